@@ -0,0 +1,107 @@
+//! Benchmarks of the hand-off of the chunks from the reader to the consumers.
+//!
+//! Compares the lock-free ring of the reader against the `deadqueue` queue it replaced, each
+//! with a single producer and up to 16 consumers:
+//!
+//! ```bash
+//! cargo bench --bench handoff
+//! ```
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use std::sync::Arc;
+
+use async_1brc::reader::ring::Ring;
+
+/// The number of items handed off per iteration.
+const ITEMS: usize = 10_000;
+
+fn bench_handoff(c: &mut Criterion) {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(16)
+        .build()
+        .unwrap();
+
+    let mut group = c.benchmark_group("handoff");
+    group.throughput(Throughput::Elements(ITEMS as u64));
+
+    for consumers in [1, 4, 8, 16] {
+        group.bench_with_input(
+            BenchmarkId::new("ring", consumers),
+            &consumers,
+            |b, &consumers| {
+                b.iter(|| {
+                    runtime.block_on(async {
+                        let ring = Arc::new(Ring::with_capacity(64));
+
+                        let tasks = (0..consumers)
+                            .map(|_| {
+                                let ring = Arc::clone(&ring);
+                                tokio::spawn(async move {
+                                    let mut received = 0;
+                                    while let Some(item) = ring.pop().await {
+                                        received += std::hint::black_box(item);
+                                    }
+                                    received
+                                })
+                            })
+                            .collect::<Vec<_>>();
+
+                        for _ in 0..ITEMS {
+                            ring.push(1usize).await;
+                        }
+                        ring.close();
+
+                        let mut received = 0;
+                        for task in tasks {
+                            received += task.await.unwrap();
+                        }
+                        assert_eq!(received, ITEMS);
+                    })
+                })
+            },
+        );
+
+        group.bench_with_input(
+            BenchmarkId::new("deadqueue", consumers),
+            &consumers,
+            |b, &consumers| {
+                b.iter(|| {
+                    runtime.block_on(async {
+                        let queue = Arc::new(deadqueue::unlimited::Queue::new());
+
+                        // The queue cannot be closed, so each consumer stops at its own marker.
+                        let tasks = (0..consumers)
+                            .map(|_| {
+                                let queue = Arc::clone(&queue);
+                                tokio::spawn(async move {
+                                    let mut received = 0;
+                                    while let Some(item) = queue.pop().await {
+                                        received += std::hint::black_box(item);
+                                    }
+                                    received
+                                })
+                            })
+                            .collect::<Vec<_>>();
+
+                        for _ in 0..ITEMS {
+                            queue.push(Some(1usize));
+                        }
+                        for _ in 0..consumers {
+                            queue.push(None);
+                        }
+
+                        let mut received = 0;
+                        for task in tasks {
+                            received += task.await.unwrap();
+                        }
+                        assert_eq!(received, ITEMS);
+                    })
+                })
+            },
+        );
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_handoff);
+criterion_main!(benches);
@@ -0,0 +1,257 @@
+//! Micro-benchmarks of the hot paths of the parser.
+//!
+//! These allow changes affecting performance to be evaluated without running the full
+//! billion rows:
+//!
+//! ```bash
+//! cargo bench --bench parser
+//! ```
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use std::hash::BuildHasher;
+
+use async_1brc::parser::{
+    engine::ParserEngine, func, hasher::SwarHasher, line, models::StationRecords, separators, sync,
+    values, LiteHashBuffer,
+};
+
+/// The station names used to generate the inputs.
+const STATIONS: [&str; 8] = [
+    "Abha",
+    "Bulawayo",
+    "Dar es Salaam",
+    "Hong Kong",
+    "Ouagadougou",
+    "Petropavlovsk-Kamchatsky",
+    "San Francisco",
+    "Zürich",
+];
+
+/// The values used to generate the inputs, covering every shape of value.
+const VALUES: [&str; 6] = ["0.0", "-1.2", "12.3", "-45.6", "99.9", "-99.9"];
+
+/// Generate `lines` lines of 1BRC input.
+fn generate_lines(lines: usize) -> Vec<u8> {
+    (0..lines)
+        .flat_map(|index| {
+            format!(
+                "{};{}\n",
+                STATIONS[index % STATIONS.len()],
+                VALUES[index % VALUES.len()]
+            )
+            .into_bytes()
+        })
+        .collect()
+}
+
+/// The number of values parsed per iteration by the async parser, to amortize the cost of
+/// entering the runtime.
+const VALUES_PER_ITERATION: usize = 1_000;
+
+fn bench_parse_value(c: &mut Criterion) {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    let mut group = c.benchmark_group("parse_value");
+    group.throughput(Throughput::Elements(VALUES_PER_ITERATION as u64));
+
+    for value in VALUES {
+        let lines = format!("{}\n", value)
+            .repeat(VALUES_PER_ITERATION)
+            .into_bytes();
+
+        group.bench_with_input(BenchmarkId::new("line", value), &lines, |b, lines| {
+            b.iter(|| {
+                runtime.block_on(async {
+                    let mut buffer = black_box(&lines[..]);
+                    for _ in 0..VALUES_PER_ITERATION {
+                        black_box(line::parse_value(&mut buffer).await);
+                    }
+                })
+            })
+        });
+
+        group.bench_with_input(BenchmarkId::new("checked", value), value, |b, value| {
+            b.iter(|| {
+                for _ in 0..VALUES_PER_ITERATION {
+                    black_box(func::parse_value_checked(black_box(value.as_bytes())));
+                }
+            })
+        });
+    }
+
+    group.finish();
+}
+
+fn bench_parse_values(c: &mut Criterion) {
+    let bytes = generate_lines(50_000);
+    let starts = bytes
+        .iter()
+        .enumerate()
+        .filter_map(|(position, &byte)| (byte == b';').then_some(position + 1))
+        .collect::<Vec<_>>();
+    let ends = starts
+        .iter()
+        .map(|&start| {
+            start
+                + bytes[start..]
+                    .iter()
+                    .position(|&byte| byte == b'\n')
+                    .unwrap()
+        })
+        .collect::<Vec<_>>();
+
+    let mut group = c.benchmark_group("parse_values");
+    group.throughput(Throughput::Elements(starts.len() as u64));
+
+    let mut values = Vec::with_capacity(starts.len());
+
+    group.bench_function("scalar", |b| {
+        b.iter(|| {
+            values.clear();
+            values.extend(
+                starts
+                    .iter()
+                    .zip(&ends)
+                    .map(|(&start, &end)| sync::parse_value(black_box(&bytes[start..end]))),
+            );
+        })
+    });
+
+    group.bench_function("swar", |b| {
+        b.iter(|| {
+            values.clear();
+            values.extend(
+                starts
+                    .iter()
+                    .map(|&start| values::parse_value_swar(black_box(&bytes), start)),
+            );
+        })
+    });
+
+    group.bench_function("batched", |b| {
+        b.iter(|| {
+            values.clear();
+            values::parse_values_batched(black_box(&bytes), &starts, &mut values);
+        })
+    });
+
+    group.finish();
+}
+
+fn bench_find_separators(c: &mut Criterion) {
+    let bytes = generate_lines(50_000);
+    let mut group = c.benchmark_group("find_separators");
+    group.throughput(Throughput::Bytes(bytes.len() as u64));
+
+    let mut positions = Vec::with_capacity(bytes.len() / 4);
+
+    group.bench_function("iter", |b| {
+        b.iter(|| {
+            positions.clear();
+            separators::find_separators_iter(black_box(&bytes), &mut positions);
+        })
+    });
+
+    group.bench_function("simd", |b| {
+        b.iter(|| {
+            positions.clear();
+            separators::find_separators_simd(black_box(&bytes), &mut positions);
+        })
+    });
+
+    group.finish();
+}
+
+fn bench_parse_chunk(c: &mut Criterion) {
+    let bytes = generate_lines(50_000);
+    let mut group = c.benchmark_group("parse_chunk");
+    group.throughput(Throughput::Bytes(bytes.len() as u64));
+
+    for engine in [
+        ParserEngine::Scalar,
+        ParserEngine::Memchr,
+        ParserEngine::Batched,
+        #[cfg(feature = "gpu")]
+        ParserEngine::Gpu,
+    ] {
+        group.bench_function(BenchmarkId::from_parameter(engine), |b| {
+            b.iter(|| {
+                let mut records = StationRecords::new();
+                engine.parse_chunk(black_box(&bytes), &mut records);
+                records
+            })
+        });
+    }
+
+    group.finish();
+}
+
+fn bench_hash_name(c: &mut Criterion) {
+    let gxhash = gxhash::GxBuildHasher::default();
+    let mut group = c.benchmark_group("hash_name");
+
+    for name in STATIONS {
+        let buffer: LiteHashBuffer = name.as_bytes().into();
+
+        group.bench_with_input(BenchmarkId::new("gxhash", name), &buffer, |b, buffer| {
+            b.iter(|| gxhash.hash_one(black_box(buffer)))
+        });
+
+        group.bench_with_input(BenchmarkId::new("swar", name), &buffer, |b, buffer| {
+            b.iter(|| SwarHasher.hash_one(black_box(buffer)))
+        });
+    }
+
+    group.finish();
+}
+
+fn bench_insert(c: &mut Criterion) {
+    let rows = (0..10_000)
+        .map(|index| {
+            (
+                LiteHashBuffer::from(STATIONS[index % STATIONS.len()].as_bytes()),
+                (index % 1999) as i16 - 999,
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let mut group = c.benchmark_group("StationRecords::insert");
+    group.throughput(Throughput::Elements(rows.len() as u64));
+
+    group.bench_function("10k rows", |b| {
+        b.iter_batched(
+            || rows.clone(),
+            |rows| {
+                let mut records = StationRecords::new();
+                rows.into_iter()
+                    .for_each(|(name, value)| records.insert(name, value));
+                records
+            },
+            criterion::BatchSize::LargeInput,
+        )
+    });
+
+    // The names borrowed from the input, as inserted by the parsers; compare the backends by
+    // running this with and without `--features raw-table`.
+    group.bench_function("10k borrowed rows", |b| {
+        b.iter(|| {
+            let mut records = StationRecords::new();
+            rows.iter()
+                .for_each(|(name, value)| records.insert_borrowed(name.as_slice(), *value));
+            records
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_parse_value,
+    bench_parse_values,
+    bench_find_separators,
+    bench_parse_chunk,
+    bench_hash_name,
+    bench_insert
+);
+criterion_main!(benches);
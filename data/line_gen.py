@@ -4,12 +4,16 @@ A quick utility to generate lines for me to test on a train without internet.
 """
 
 import argparse
+import math
 import random
+import sys
 from pathlib import Path
-from typing import Optional
+from typing import List, Optional, Tuple
 from pydantic import BaseModel, Field, field_validator
 from tqdm import tqdm
 
+from official_stations import OFFICIAL_STATIONS
+
 PRESET_TOWNS=[
     "Springfield",
     "Hogwarts",
@@ -23,12 +27,30 @@ MIN_TEMP = -100
 
 DEFAULT_ROWS = 1_000
 
+OFFICIAL_SIGMA = 10.0
+"""
+The standard deviation of the Gaussian distribution used by the official
+``create_measurements.sh`` around each station's mean temperature.
+"""
+
+OFFICIAL_MAX_TEMP = 99.9
+OFFICIAL_MIN_TEMP = -99.9
+
+CHECK_STANDARD_ERRORS = 5.0
+"""
+How many standard errors of its mean a station's mean may be away from the listed mean
+before ``--check`` reports it.
+"""
+
 class CliArgs(BaseModel):
     """
     The arguments passed in from CLI.
     """
     path: Path
     rows: int = Field(DEFAULT_ROWS)
+    seed: Optional[int] = Field(None)
+    official: bool = Field(False)
+    check: bool = Field(False)
 
     @field_validator("path", mode="before")
     @classmethod
@@ -51,6 +73,58 @@ def gen_one_line(
     temp = random.randrange(MIN_TEMP * 10, MAX_TEMP * 10, 1) / 10
     return f"{place_name};{temp:.1f}"
 
+def gen_one_official_line(
+    rng: random.Random,
+    stations: List[Tuple[str, float]] = OFFICIAL_STATIONS,
+) -> str:
+    """
+    Generate one line following the rules of the official ``CreateMeasurements.java``:
+    a station is picked uniformly at random, and its measurement is drawn from a
+    Gaussian distribution around the station's mean, rounded half up to one decimal
+    like Java's ``Math.round``.
+
+    The measurement is clamped to the range allowed by the 1BRC format.
+    """
+    place_name, mean = rng.choice(stations)
+    temp = math.floor(rng.gauss(mean, OFFICIAL_SIGMA) * 10 + 0.5) / 10
+    temp = min(max(temp, OFFICIAL_MIN_TEMP), OFFICIAL_MAX_TEMP)
+    return f"{place_name};{temp:.1f}"
+
+def check_official_means(
+    path: Path,
+    stations: List[Tuple[str, float]] = OFFICIAL_STATIONS,
+) -> List[str]:
+    """
+    Check that the mean of each station in the file at ``path`` lands near its listed
+    mean, within ``CHECK_STANDARD_ERRORS`` standard errors of its number of lines.
+
+    Returns a description of each station that does not, or that is not listed.
+    """
+    listed = dict(stations)
+    sums = {}
+    counts = {}
+    with open(path, "r", encoding="utf-8") as _f:
+        for line in _f:
+            name, temp = line.rstrip("\n").rsplit(";", 1)
+            sums[name] = sums.get(name, 0.0) + float(temp)
+            counts[name] = counts.get(name, 0) + 1
+
+    failures = []
+    for name, count in sorted(counts.items()):
+        if name not in listed:
+            failures.append(f"{name}: not an official station")
+            continue
+
+        mean = sums[name] / count
+        tolerance = CHECK_STANDARD_ERRORS * OFFICIAL_SIGMA / math.sqrt(count)
+        if abs(mean - listed[name]) > tolerance:
+            failures.append(
+                f"{name}: mean {mean:.2f} of {count} lines, "
+                f"expected {listed[name]:.2f} ± {tolerance:.2f}"
+            )
+
+    return failures
+
 def init_args() -> CliArgs:
     """
     Initialize the arguments captured.
@@ -79,6 +153,35 @@ def init_args() -> CliArgs:
         ),
         required=False,
     )
+    parser.add_argument(
+        "--seed",
+        dest="seed",
+        default=None,
+        type=int,
+        help=(
+            "Seed the random number generator, so that the same seed always "
+            "generates the same file."
+        ),
+        required=False,
+    )
+    parser.add_argument(
+        "--official",
+        dest="official",
+        action="store_true",
+        help=(
+            "Follow the distribution rules of the official 1BRC generator, using "
+            "its stations and their means instead of the preset towns."
+        ),
+    )
+    parser.add_argument(
+        "--check",
+        dest="check",
+        action="store_true",
+        help=(
+            "Instead of generating the file, check that the mean of each station "
+            "in it lands near the mean of the official station."
+        ),
+    )
 
     args = parser.parse_args()
 
@@ -86,18 +189,45 @@ def init_args() -> CliArgs:
         dict(
             path=args.file,
             rows=args.rows,
+            seed=args.seed,
+            official=args.official,
+            check=args.check,
         )
     )
 
 def gen_lines_to_file(
     path: Path,
     rows: int,
+    seed: Optional[int] = None,
+    official: bool = False,
 ):
-    with open(path, "w") as _f:
+    """
+    Generate the lines to a file.
+
+    Note that Python's random number generator differs from Java's, so the output
+    will not be byte-identical to the official generator's even with the same seed;
+    only the distribution is comparable.
+    """
+    if official:
+        rng = random.Random(seed)
+        gen = lambda: gen_one_official_line(rng)
+    else:
+        random.seed(seed)
+        gen = gen_one_line
+
+    with open(path, "w", encoding="utf-8") as _f:
         for _ in tqdm(range(rows)):
-            _f.write(gen_one_line())
+            _f.write(gen())
             _f.write("\n")
 
 if __name__ == "__main__":
     args = init_args()
-    gen_lines_to_file(args.path, args.rows)
+
+    if args.check:
+        failures = check_official_means(args.path)
+        for failure in failures:
+            print(failure)
+
+        sys.exit(1 if failures else 0)
+
+    gen_lines_to_file(args.path, args.rows, args.seed, args.official)
@@ -0,0 +1,16 @@
+//! Check that the SIMD separator search never panics, and always agrees with the scalar
+//! search, on arbitrary chunks.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use async_1brc::parser::separators;
+
+fuzz_target!(|bytes: &[u8]| {
+    let (mut simd, mut iter) = (Vec::new(), Vec::new());
+
+    separators::find_separators_simd(bytes, &mut simd);
+    separators::find_separators_iter(bytes, &mut iter);
+
+    assert_eq!(simd, iter);
+});
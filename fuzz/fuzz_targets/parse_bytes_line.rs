@@ -0,0 +1,27 @@
+//! Feed arbitrary chunks to [`parser::line::parse_bytes`].
+//!
+//! This parser expects perfect input, so it is expected to panic on malformed lines until
+//! it validates its input; the target exists to catch any panic beyond those.
+#![no_main]
+
+use std::sync::OnceLock;
+
+use libfuzzer_sys::fuzz_target;
+
+use async_1brc::parser::{line, models::StationRecords};
+
+/// A single runtime shared by every run, as building one dominates the cost of a run.
+static RUNTIME: OnceLock<tokio::runtime::Runtime> = OnceLock::new();
+
+fuzz_target!(|bytes: &[u8]| {
+    let runtime = RUNTIME.get_or_init(|| {
+        tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap()
+    });
+
+    runtime.block_on(async {
+        let mut records = StationRecords::new();
+        line::parse_bytes(bytes, &mut records).await;
+    });
+});
@@ -0,0 +1,14 @@
+//! Feed arbitrary chunks to [`parser::sync::parse_bytes`].
+//!
+//! This parser expects perfect input, so it is expected to panic on malformed lines until
+//! it validates its input; the target exists to catch any panic beyond those.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use async_1brc::parser::{models::StationRecords, sync};
+
+fuzz_target!(|bytes: &[u8]| {
+    let mut records = StationRecords::new();
+    sync::parse_bytes(bytes, &mut records);
+});
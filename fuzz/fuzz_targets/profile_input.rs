@@ -0,0 +1,15 @@
+//! Check that the input validator never panics on arbitrary chunks, and accounts for
+//! every line.
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use async_1brc::parser::profile::InputProfile;
+
+fuzz_target!(|bytes: &[u8]| {
+    let profile = InputProfile::from_bytes(bytes, 0);
+
+    let lines = bytes.iter().filter(|&&byte| byte == b'\n').count()
+        + usize::from(!bytes.is_empty() && !bytes.ends_with(b"\n"));
+    assert_eq!(profile.lines, lines);
+});
@@ -0,0 +1,62 @@
+/*
+ * A C interface to the aggregation core of async-1brc.
+ *
+ * Build the library with:
+ *
+ *     cargo rustc --release --lib --features ffi --crate-type staticlib
+ *
+ * and link against target/release/libasync_1brc.a.
+ */
+#ifndef ASYNC_1BRC_H
+#define ASYNC_1BRC_H
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct Aggregator onebrc_aggregator;
+typedef struct Results onebrc_results;
+
+/* The statistics of a single station; the values are in tenths of a degree. */
+typedef struct onebrc_station {
+    /* Not null-terminated; valid until the results are freed. */
+    const uint8_t *name;
+    size_t name_len;
+    int16_t min;
+    int16_t max;
+    int64_t sum;
+    uint64_t count;
+} onebrc_station;
+
+/* Create a new empty aggregator. */
+onebrc_aggregator *onebrc_aggregator_new(void);
+
+/* Feed bytes of 1BRC input, which may end in the middle of a line.
+ * Returns false if the input is not in the 1BRC format. */
+bool onebrc_aggregator_feed(onebrc_aggregator *aggregator, const uint8_t *bytes, size_t len);
+
+/* Consume the aggregator into results sorted by station name.
+ * Returns NULL if the last line is not in the 1BRC format. */
+onebrc_results *onebrc_aggregator_finalize(onebrc_aggregator *aggregator);
+
+/* Free an aggregator without finalizing it. */
+void onebrc_aggregator_free(onebrc_aggregator *aggregator);
+
+/* The number of stations in the results. */
+size_t onebrc_results_len(const onebrc_results *results);
+
+/* Write the station at index to station; returns false if index is out of range. */
+bool onebrc_results_get(const onebrc_results *results, size_t index, onebrc_station *station);
+
+/* Free the results, invalidating the names of their stations. */
+void onebrc_results_free(onebrc_results *results);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* ASYNC_1BRC_H */
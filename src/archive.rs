@@ -0,0 +1,250 @@
+//! Read the members of a tar archive as inputs of a batch, without extracting them.
+//!
+//! Sharded 1BRC datasets are often distributed as a single `.tar`, `.tar.gz` or `.tgz` file.
+//! [`run_archive`] reads such an archive front to back on a blocking thread, decompressing it on
+//! the fly, and streams each regular file in it through a pipe into [`run_from`], one member at
+//! a time, as a tar archive can only be read in order. The members are reported as files of the
+//! batch, at the path of the archive joined with their path within it; hidden members, e.g. the
+//! `._*` metadata of macOS, are skipped as they are in a directory.
+
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+use tokio::io::{AsyncWriteExt, DuplexStream};
+use tokio::runtime::Handle;
+use tokio::sync::mpsc;
+
+use crate::batch::{BatchError, BatchOptions, FileReport};
+use crate::{config, run_from, RunError};
+
+/// The extensions of the archives read, compressed with gzip or not.
+pub const ARCHIVE_EXTENSIONS: [&str; 3] = [".tar", ".tar.gz", ".tgz"];
+
+/// Whether `path` names a tar archive, by its extension.
+pub fn is_archive(path: impl AsRef<Path>) -> bool {
+    path.as_ref().file_name().is_some_and(|name| {
+        let name = name.as_encoded_bytes();
+        ARCHIVE_EXTENSIONS
+            .iter()
+            .any(|extension| name.ends_with(extension.as_bytes()))
+    })
+}
+
+/// Whether the archive at `path` is compressed with gzip, by its extension.
+fn is_gzip(path: &Path) -> bool {
+    path.file_name().is_some_and(|name| {
+        let name = name.as_encoded_bytes();
+        name.ends_with(b".gz") || name.ends_with(b".tgz")
+    })
+}
+
+/// A read of the archive failed after it was opened.
+fn read_error(error: io::Error) -> RunError {
+    RunError::Read {
+        error,
+        report: Box::default(),
+    }
+}
+
+/// Read the archive at `path`, sending the path and a pipe of each of its regular files in turn,
+/// and writing the member into the pipe until it is read to the end.
+///
+/// Stops without an error once the members are no longer received.
+fn stream_members(
+    path: &Path,
+    members: mpsc::Sender<(PathBuf, DuplexStream)>,
+    runtime: Handle,
+) -> Result<(), RunError> {
+    let file = BufReader::new(File::open(path).map_err(RunError::Open)?);
+    let input: Box<dyn Read> = if is_gzip(path) {
+        Box::new(flate2::read::GzDecoder::new(file))
+    } else {
+        Box::new(file)
+    };
+
+    let mut archive = tar::Archive::new(input);
+    let mut buffer = vec![0; config::CHUNK_SIZE];
+
+    for entry in archive.entries().map_err(read_error)? {
+        let mut entry = entry.map_err(read_error)?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+
+        let member = entry.path().map_err(read_error)?.into_owned();
+        if member
+            .file_name()
+            .is_none_or(|name| name.as_encoded_bytes().starts_with(b"."))
+        {
+            continue;
+        }
+
+        let (mut writer, reader) = tokio::io::duplex(config::CHUNK_SIZE);
+        if members.blocking_send((member, reader)).is_err() {
+            return Ok(());
+        }
+
+        loop {
+            let read = match entry.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(read) => read,
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                Err(error) => return Err(read_error(error)),
+            };
+
+            // The run over the member stopped reading, having failed.
+            if runtime.block_on(writer.write_all(&buffer[..read])).is_err() {
+                return Ok(());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Aggregate every member of the archive at `archive` in turn, each through its own
+/// [`run_from`] with the options of a file of `options`, returning their reports in the order
+/// of the archive.
+///
+/// The files of `options` are ignored. A member is reported at the path of the archive joined
+/// with its path within it, and its results are exported as `<member name>.out` if per-file
+/// outputs are requested.
+pub async fn run_archive(
+    archive: &Path,
+    options: &BatchOptions,
+) -> Result<Vec<FileReport>, BatchError> {
+    let (sender, mut members) = mpsc::channel(1);
+    let streaming = {
+        let (path, runtime) = (archive.to_owned(), Handle::current());
+        tokio::task::spawn_blocking(move || stream_members(&path, sender, runtime))
+    };
+
+    let mut files = Vec::new();
+    let mut failure = None;
+
+    while let Some((member, input)) = members.recv().await {
+        let file = archive.join(member);
+        let input = tokio::io::BufReader::with_capacity(config::CHUNK_SIZE, input);
+
+        match run_from(input, options.file_options(&file)).await {
+            Ok(report) => files.push(FileReport { file, report }),
+            Err(error) => {
+                failure = Some(BatchError::File { file, error });
+                break;
+            }
+        }
+    }
+
+    // Let the archive stop streaming, if a member failed.
+    drop(members);
+    let streamed = streaming
+        .await
+        .unwrap_or_else(|error| Err(read_error(io::Error::other(error))));
+
+    // A failed member is reported first, as the archive stopped streaming because of it.
+    match (failure, streamed) {
+        (Some(failure), _) => Err(failure),
+        (None, Err(error)) => Err(BatchError::File {
+            file: archive.to_owned(),
+            error,
+        }),
+        (None, Ok(())) => Ok(files),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::batch::run_batch;
+    use crate::testing::TestDir;
+    use crate::RunOptions;
+
+    /// Write a gzipped archive of `members` to `path`.
+    fn write_archive(path: &Path, members: &[(&str, &str)]) {
+        let encoder =
+            flate2::write::GzEncoder::new(File::create(path).unwrap(), flate2::Compression::fast());
+        let mut builder = tar::Builder::new(encoder);
+
+        for (name, content) in members {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, name, content.as_bytes())
+                .unwrap();
+        }
+
+        builder.into_inner().unwrap().finish().unwrap();
+    }
+
+    #[test]
+    fn archive_extensions() {
+        assert!(is_archive("data/measurements.tar"));
+        assert!(is_archive("measurements.tar.gz") && is_gzip(Path::new("measurements.tar.gz")));
+        assert!(is_archive("measurements.tgz"));
+        assert!(!is_archive("measurements.txt") && !is_archive("measurements.tar.txt"));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn aggregate_members() {
+        let dir = TestDir::new("aggregate_members");
+        let archive = dir.join("measurements.tgz");
+        write_archive(
+            &archive,
+            &[
+                ("shards/a.txt", &"jack;1.2\njill;-3.4\n".repeat(1000)),
+                ("shards/._a.txt", "not an input\n"),
+                ("shards/b.txt", "jack;5.6\nbob;0.0"),
+            ],
+        );
+
+        let output = dir.join("output.txt");
+        let options = BatchOptions::new(
+            vec![archive.clone()],
+            RunOptions::new("")
+                .with_output(&output)
+                .with_threads(2)
+                .with_chunk_sizes(64, 64 + config::MAX_LINE_LENGTH),
+        )
+        .with_per_file_outputs(dir.as_ref());
+
+        let report = run_batch(options).await.unwrap();
+        let exported = std::fs::read_to_string(&output).unwrap();
+        let b = std::fs::read_to_string(dir.join("b.txt.out")).unwrap();
+
+        assert_eq!(
+            exported,
+            "{bob=0.0/0.0/0.0, jack=1.2/1.2/5.6, jill=-3.4/-3.4/-3.4}\n"
+        );
+        assert_eq!(b, "{bob=0.0/0.0/0.0, jack=5.6/5.6/5.6}\n");
+        assert_eq!(
+            report
+                .files
+                .iter()
+                .map(|file| file.file.clone())
+                .collect::<Vec<_>>(),
+            [archive.join("shards/a.txt"), archive.join("shards/b.txt")]
+        );
+        assert_eq!(report.total.records_parsed, 2002);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn corrupt_archive() {
+        let dir = TestDir::new("corrupt_archive");
+        let path = dir.join("corrupt.tar.gz");
+        std::fs::write(&path, "not a gzip stream").unwrap();
+
+        let options = BatchOptions::new(vec![path.clone()], RunOptions::new(""));
+        let result = run_archive(&path, &options).await;
+
+        assert!(matches!(
+            result,
+            Err(BatchError::File {
+                error: RunError::Read { .. },
+                ..
+            })
+        ));
+    }
+}
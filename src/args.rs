@@ -1,12 +1,27 @@
 //! Parse command line arguments.
 
-use clap::Parser;
+use clap::{parser::ValueSource, ArgMatches, Parser};
 
 use crate::config;
+use crate::parser::{spill::SpillOptions, units::Unit};
+
+#[cfg(feature = "filter")]
+use crate::parser::filter::{NamePattern, StationFilter};
+use crate::reader::{
+    cache::Readahead,
+    recovery::{ErrorPolicy, RetryPolicy},
+    storage::StorageKind,
+};
+use crate::tune::Tuning;
+
+#[cfg(feature = "runtime")]
+use crate::{batch::BatchOptions, parser::engine::ParserEngine, RunOptions};
 
 /// Command line arguments.
 #[derive(Parser, Debug, Clone)]
 pub struct CliArgs {
+    /// The input file; a directory or a pattern with `*` and `?` wildcards in its last
+    /// component aggregates every file it names, e.g. `data/measurements-*.txt`.
     #[arg(short, long, default_value_t = config::MEASURMENTS_PATH.to_owned())]
     pub file: String,
 
@@ -17,6 +32,28 @@ pub struct CliArgs {
     #[arg(short, long, default_value_t = config::BASELINE_PATH.to_owned())]
     pub baseline: String,
 
+    /// Compare the values of the output and the baseline within this tolerance in degrees,
+    /// instead of requiring the files to be identical.
+    #[cfg(feature = "assert")]
+    #[arg(long)]
+    pub assert_tolerance: Option<f64>,
+
+    /// Compare the records against this snapshot saved by `--save-snapshot`, instead of
+    /// matching the output and the baseline files.
+    #[cfg(feature = "assert")]
+    #[arg(long)]
+    pub snapshot: Option<String>,
+
+    /// Parse the input again with the blocking scalar parser, and check that it produces
+    /// exactly the same records as this run.
+    #[cfg(all(feature = "assert", feature = "sync"))]
+    #[arg(long)]
+    pub cross_check: bool,
+
+    /// Save the records of this run as a binary snapshot to this path.
+    #[arg(long)]
+    pub save_snapshot: Option<String>,
+
     #[arg(short, long, default_value_t = config::NUMBER_OF_THREADS)]
     pub threads: usize,
 
@@ -25,4 +62,448 @@ pub struct CliArgs {
 
     #[arg(long, default_value_t = config::MAX_CHUNK_SIZE)]
     pub max_chunk_size: usize,
+
+    /// The longest station name expected in bytes; the reader keeps room for a line with such
+    /// a name at the end of each chunk.
+    #[arg(long, default_value_t = config::MAX_NAME_LENGTH)]
+    pub max_name_length: usize,
+
+    /// Validate every line, aborting the run on the first name longer than `--max-name-length`,
+    /// or malformed or out of range value.
+    #[arg(long)]
+    pub strict: bool,
+
+    /// Write the invalid lines to this file with their byte offsets, instead of aggregating
+    /// them, and carry on with the run; the number of lines rejected is reported at the end.
+    #[arg(long, conflicts_with = "strict")]
+    pub rejects: Option<String>,
+
+    /// Merge the station names which only differ by their Unicode normal form, e.g. from
+    /// datasets generated by different tools, under their NFC form.
+    #[cfg(feature = "normalize")]
+    #[arg(long)]
+    pub normalize_names: bool,
+
+    /// Only aggregate the stations matching any of these patterns: a name, `prefix:` and the
+    /// start of the names, or `regex:` and a regular expression; may be repeated.
+    #[cfg(feature = "filter")]
+    #[arg(long)]
+    pub include: Vec<NamePattern>,
+
+    /// Skip the stations matching any of these patterns, as for `--include`; may be repeated.
+    #[cfg(feature = "filter")]
+    #[arg(long)]
+    pub exclude: Vec<NamePattern>,
+
+    /// Load the number of threads and the chunk sizes found by `main tune` from this file,
+    /// if it exists, unless they are given explicitly.
+    #[arg(long, default_value_t = config::TUNING_PATH.to_owned())]
+    pub tuning: String,
+
+    /// The parser used by the consumers to parse each chunk.
+    #[cfg(feature = "runtime")]
+    #[arg(long, value_enum, default_value_t)]
+    pub engine: ParserEngine,
+
+    /// Partition the records of each consumer into this many shards by the hash of the names,
+    /// so that the end-of-run merge runs one task per shard instead of one consumer after the
+    /// other; worth it with many threads and many distinct stations.
+    #[arg(long, default_value_t = config::MERGE_SHARDS)]
+    pub merge_shards: usize,
+
+    /// Evict the input file from the page cache before each trial, to measure cold-I/O
+    /// performance.
+    #[arg(long, conflicts_with = "prewarm")]
+    pub drop_caches: bool,
+
+    /// Read the input file once before each trial to warm the page cache, to measure
+    /// hot-cache performance.
+    #[arg(long)]
+    pub prewarm: bool,
+
+    /// The hint given to the kernel to read ahead the input file.
+    #[arg(long, value_enum, default_value_t)]
+    pub readahead: Readahead,
+
+    /// The storage the input resides on, detected from the file if not given. Unless given
+    /// explicitly or tuned, the number of threads and the chunk sizes are picked to suit it.
+    #[arg(long, value_enum)]
+    pub storage: Option<StorageKind>,
+
+    /// Pick the number of threads and the chunk sizes to suit the storage detected from the
+    /// file as well, without `--storage`; the detection is only reported otherwise, as it can
+    /// be wrong, e.g. on the virtual disks of a VM.
+    #[arg(long)]
+    pub detect_storage: bool,
+
+    /// Read the input on a dedicated thread with its own runtime, so that the system calls of
+    /// the reader do not compete with the consumers for the worker threads.
+    #[arg(long)]
+    pub isolated_reader: bool,
+
+    /// The number of retries of a failed read of the input, e.g. on a flaky network mount.
+    #[arg(long, default_value_t = config::READ_RETRIES)]
+    pub read_retries: u32,
+
+    /// The wait before the first retry of a failed read in milliseconds, doubled after every
+    /// attempt.
+    #[arg(long, default_value_t = config::READ_RETRY_BACKOFF_MS)]
+    pub read_retry_backoff: u64,
+
+    /// What to do once a read of the input has failed every retry: abort the run, skip the
+    /// chunk being read, or stop and export the partial results.
+    #[arg(long, value_enum, default_value_t)]
+    pub on_error: ErrorPolicy,
+
+    /// Spill the records of each consumer to the disk whenever they grow past this estimated
+    /// size in bytes, and merge the spills into the output at the end, keeping the memory
+    /// bounded on inputs with many distinct stations.
+    #[arg(long, conflicts_with_all = ["stats_only", "save_snapshot"])]
+    pub spill_threshold: Option<usize>,
+
+    /// The directory the spill files are created in, the temporary directory by default.
+    #[arg(long, requires = "spill_threshold")]
+    pub spill_dir: Option<String>,
+
+    /// Save the records aggregated so far and the offset of the input they cover to this file
+    /// every `--checkpoint-interval` bytes, so that an interrupted run can be resumed.
+    #[arg(long, conflicts_with = "spill_threshold")]
+    pub checkpoint: Option<String>,
+
+    /// The bytes of input read between two checkpoints.
+    #[arg(long, default_value_t = config::CHECKPOINT_INTERVAL)]
+    pub checkpoint_interval: u64,
+
+    /// Resume an interrupted run from this checkpoint, seeking the input to its offset and
+    /// starting from its records; the run keeps checkpointing to it unless `--checkpoint` is
+    /// given.
+    #[arg(long, conflicts_with = "spill_threshold")]
+    pub resume: Option<String>,
+
+    /// Aggregate only about this fraction of the input, e.g. `0.01`, in windows of a chunk
+    /// spread evenly across it, and scale the counts up to the whole input, for approximate
+    /// results in a fraction of the time.
+    #[arg(long, value_parser = parse_rate, conflicts_with_all = ["checkpoint", "resume", "rejects"])]
+    pub sample: Option<f64>,
+
+    /// Group the records by the first this many `;`-separated fields of each line before the
+    /// value, e.g. `2` for `station;sensor` in `station;sensor;reading;12.3`, instead of by all
+    /// of them.
+    #[arg(
+        long,
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..),
+        conflicts_with = "spill_threshold"
+    )]
+    pub group_by: Option<usize>,
+
+    /// The unit the results are exported in; the values are converted from degrees Celsius.
+    #[arg(long, value_enum, default_value_t)]
+    pub unit: Unit,
+
+    /// With several inputs, the number of files aggregated at once, sharing `--threads`; as
+    /// many as there are threads by default.
+    #[arg(long)]
+    pub concurrent_files: Option<usize>,
+
+    /// With several inputs, export the results of each file to this directory as well, as
+    /// `<file name>.out`; the directory is created if it does not exist.
+    #[arg(long)]
+    pub per_file_output: Option<String>,
+
+    /// Time the instrumented operations and report them at exit. This is always enabled if
+    /// compiled with the `timed` feature.
+    #[arg(long)]
+    pub timed: bool,
+
+    /// Write the timings of all the instrumented operations to this path at exit; as CSV
+    /// if it ends with `.csv`, otherwise as JSON. This implies `--timed`.
+    #[arg(long)]
+    pub timings_output: Option<String>,
+
+    /// Periodically write the metrics of the pipeline to this path in the OpenMetrics text
+    /// format, e.g. for the textfile collector of the Prometheus node exporter.
+    #[cfg(feature = "metrics")]
+    #[arg(long)]
+    pub metrics_file: Option<String>,
+
+    /// The interval between two writes of the metrics file, in milliseconds.
+    #[cfg(feature = "metrics")]
+    #[arg(long, default_value_t = config::METRICS_INTERVAL_MS)]
+    pub metrics_interval: u64,
+
+    /// Keep running, and aggregate the input again whenever it changes, reusing the buffers
+    /// and the records table of the previous run.
+    #[cfg(feature = "watch")]
+    #[arg(long, conflicts_with_all = ["checkpoint", "resume"])]
+    pub watch: bool,
+
+    /// Serve the metrics of the run, and its results once complete, as JSON over HTTP on this
+    /// address, e.g. `0.0.0.0:8080`; the server keeps answering until interrupted.
+    #[cfg(feature = "serve")]
+    #[arg(long, conflicts_with = "spill_threshold")]
+    pub serve: Option<String>,
+
+    /// Sample the queue depth, bytes read, records parsed and resident set size throughout
+    /// the run, and write them to this path as CSV.
+    #[arg(long)]
+    pub timeline: Option<String>,
+
+    /// The interval between two samples of the timeline, in milliseconds.
+    #[arg(long, default_value_t = config::TIMELINE_INTERVAL_MS)]
+    pub timeline_interval: u64,
+
+    /// Print dataset-level statistics instead of exporting the 1BRC output.
+    #[arg(long)]
+    pub stats_only: bool,
+
+    /// Read the output back after exporting it, and verify it against the records: every
+    /// station in order, with the same values, and a trailing newline.
+    #[arg(long, conflicts_with = "stats_only")]
+    pub verify_output: bool,
+
+    /// Write a CPU profile of the run to this path; a flamegraph if it ends with `.svg`,
+    /// otherwise a `pprof` protobuf.
+    #[cfg(feature = "pprof")]
+    #[arg(long)]
+    pub profile: Option<String>,
+
+    /// The number of rows in each Arrow record batch written by `to_arrow`.
+    #[cfg(feature = "arrow")]
+    #[arg(long, default_value_t = config::ARROW_BATCH_SIZE)]
+    pub arrow_batch_size: usize,
+
+    /// Count the allocations made by each stage of the pipeline, and report them along with
+    /// the peak memory usage at the end of the run.
+    #[cfg(feature = "mem-stats")]
+    #[arg(long)]
+    pub mem_stats: bool,
+}
+
+/// Parse a fraction of the input, greater than 0 and at most 1.
+fn parse_rate(rate: &str) -> Result<f64, String> {
+    match rate.parse::<f64>() {
+        Ok(rate) if rate > 0.0 && rate <= 1.0 => Ok(rate),
+        Ok(_) => Err("the rate must be greater than 0 and at most 1".to_owned()),
+        Err(error) => Err(error.to_string()),
+    }
+}
+
+impl CliArgs {
+    /// The retries of a failed read of the input given on the command line.
+    pub fn retry_policy(&self) -> RetryPolicy {
+        RetryPolicy {
+            retries: self.read_retries,
+            backoff: std::time::Duration::from_millis(self.read_retry_backoff),
+        }
+    }
+
+    /// The spilling of the records given on the command line, if any.
+    pub fn spill(&self) -> Option<SpillOptions> {
+        let spill = SpillOptions::new(self.spill_threshold?);
+
+        Some(match &self.spill_dir {
+            Some(dir) => spill.with_dir(dir),
+            None => spill,
+        })
+    }
+
+    /// The stations to aggregate given on the command line, unless every station is.
+    #[cfg(feature = "filter")]
+    pub fn station_filter(&self) -> Option<StationFilter> {
+        (!self.include.is_empty() || !self.exclude.is_empty())
+            .then(|| StationFilter::new(self.include.clone(), self.exclude.clone()))
+    }
+
+    /// The options of a run of [`crate::run`] as given on the command line, exporting the
+    /// results to the output.
+    #[cfg(feature = "runtime")]
+    pub fn run_options(&self) -> RunOptions {
+        let options = RunOptions::new(&self.file)
+            .with_output(&self.output)
+            .with_threads(self.threads)
+            .with_chunk_sizes(self.chunk_size, self.max_chunk_size)
+            .with_engine(self.engine)
+            .with_merge_shards(self.merge_shards)
+            .with_max_name_length(self.max_name_length)
+            .with_strict(self.strict)
+            .with_readahead(self.readahead)
+            .with_isolated_reader(self.isolated_reader)
+            .with_retry_policy(self.retry_policy())
+            .with_error_policy(self.on_error)
+            .with_checkpoint_interval(self.checkpoint_interval)
+            .with_verify_output(self.verify_output)
+            .with_unit(self.unit);
+
+        #[cfg(feature = "normalize")]
+        let options = options.with_normalize_names(self.normalize_names);
+
+        #[cfg(feature = "filter")]
+        let options = match self.station_filter() {
+            Some(filter) => options.with_filter(filter),
+            None => options,
+        };
+
+        let options = match &self.rejects {
+            Some(rejects) => options.with_rejects(rejects),
+            None => options,
+        };
+
+        let options = match &self.checkpoint {
+            Some(checkpoint) => options.with_checkpoints(checkpoint),
+            None => options,
+        };
+
+        let options = match &self.resume {
+            Some(resume) => options.with_resume(resume),
+            None => options,
+        };
+
+        let options = match self.sample {
+            Some(rate) => options.with_sample(rate),
+            None => options,
+        };
+
+        let options = match self.group_by {
+            Some(fields) => options.with_group_by(fields),
+            None => options,
+        };
+
+        match self.spill() {
+            Some(spill) => options.with_spill(spill),
+            None => options,
+        }
+    }
+
+    /// The options of a batch of runs of [`crate::batch::run_batch`] over `files`, as given on
+    /// the command line.
+    #[cfg(feature = "runtime")]
+    pub fn batch_options(&self, files: Vec<std::path::PathBuf>) -> BatchOptions {
+        let options = BatchOptions::new(files, self.run_options());
+
+        let options = match self.concurrent_files {
+            Some(concurrent_files) => options.with_concurrent_files(concurrent_files),
+            None => options,
+        };
+
+        match &self.per_file_output {
+            Some(dir) => options.with_per_file_outputs(dir),
+            None => options,
+        }
+    }
+
+    /// Override the number of threads and the chunk sizes not given on the command line with
+    /// the tuning file, if it exists.
+    ///
+    /// Returns the tuning applied, or the error if the file exists but cannot be read.
+    pub fn apply_tuning(&mut self, matches: &ArgMatches) -> Option<std::io::Result<Tuning>> {
+        if !std::path::Path::new(&self.tuning).exists() {
+            return None;
+        }
+
+        let tuning = match Tuning::read(&self.tuning) {
+            Ok(tuning) => tuning,
+            Err(err) => return Some(Err(err)),
+        };
+        self.apply(matches, tuning);
+
+        Some(Ok(tuning))
+    }
+
+    /// Override the number of threads and the chunk sizes not given on the command line with
+    /// those suggested for the `storage` of the input.
+    ///
+    /// Returns the values overridden, e.g. `threads 8 -> 2`, to be reported.
+    pub fn apply_storage(&mut self, matches: &ArgMatches, storage: StorageKind) -> Vec<String> {
+        let Some(tuning) = storage.suggested_tuning() else {
+            return Vec::new();
+        };
+
+        let before = [self.threads, self.chunk_size, self.max_chunk_size];
+        self.apply(matches, tuning);
+        let after = [self.threads, self.chunk_size, self.max_chunk_size];
+
+        ["threads", "chunk size", "max chunk size"]
+            .into_iter()
+            .zip(before.into_iter().zip(after))
+            .filter(|(_, (before, after))| before != after)
+            .map(|(name, (before, after))| format!("{} {} -> {}", name, before, after))
+            .collect()
+    }
+
+    /// Override the values not given on the command line with `tuning`.
+    fn apply(&mut self, matches: &ArgMatches, tuning: Tuning) {
+        let is_default = |id: &str| matches.value_source(id) != Some(ValueSource::CommandLine);
+
+        if is_default("threads") {
+            self.threads = tuning.threads;
+        }
+        if is_default("chunk_size") {
+            self.chunk_size = tuning.chunk_size;
+        }
+        if is_default("max_chunk_size") {
+            self.max_chunk_size = tuning.max_chunk_size;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::TestDir;
+    use clap::{CommandFactory, FromArgMatches};
+
+    #[test]
+    fn apply_tuning_file() {
+        let dir = TestDir::new("apply_tuning_file");
+        let path = dir.join("tuning.json");
+        Tuning::new(3, 1 << 16).write(&path).unwrap();
+
+        let matches = CliArgs::command()
+            .try_get_matches_from(["main", "--threads=5", "--tuning", path.to_str().unwrap()])
+            .unwrap();
+        let mut args = CliArgs::from_arg_matches(&matches).unwrap();
+        let applied = args.apply_tuning(&matches);
+
+        assert!(matches!(applied, Some(Ok(_))));
+        assert_eq!(args.threads, 5);
+        assert_eq!(args.chunk_size, 1 << 16);
+        assert_eq!(args.max_chunk_size, Tuning::new(3, 1 << 16).max_chunk_size);
+
+        args.tuning = "/nonexistent/tuning.json".to_owned();
+        assert!(args.apply_tuning(&matches).is_none());
+    }
+
+    #[test]
+    fn apply_storage_tuning() {
+        let matches = CliArgs::command()
+            .try_get_matches_from(["main", "--chunk-size=4096"])
+            .unwrap();
+        let mut args = CliArgs::from_arg_matches(&matches).unwrap();
+
+        assert!(args
+            .apply_storage(&matches, StorageKind::RamDisk)
+            .is_empty());
+        assert_eq!(args.threads, config::NUMBER_OF_THREADS);
+
+        let suggested = StorageKind::Rotational.suggested_tuning().unwrap();
+        let overridden = args.apply_storage(&matches, StorageKind::Rotational);
+        assert_eq!(args.threads, suggested.threads);
+        assert_eq!(args.chunk_size, 4096);
+        assert_eq!(args.max_chunk_size, suggested.max_chunk_size);
+        assert_eq!(
+            overridden,
+            [
+                format!(
+                    "threads {} -> {}",
+                    config::NUMBER_OF_THREADS,
+                    suggested.threads
+                ),
+                format!(
+                    "max chunk size {} -> {}",
+                    config::MAX_CHUNK_SIZE,
+                    suggested.max_chunk_size
+                ),
+            ]
+        );
+    }
 }
@@ -0,0 +1,133 @@
+//! Compare files by their checksums, without holding them in memory.
+//!
+//! Each file is hashed in chunks of [`config::CHECKSUM_CHUNK_SIZE`] bytes, so that two
+//! identical files can be confirmed as such while reading both of them only once and
+//! concurrently. Only when the checksums differ is it worth loading the files for a
+//! detailed comparison.
+
+use std::io;
+use std::path::Path;
+
+use tokio::io::AsyncReadExt;
+
+use crate::{config, parser::hasher};
+
+/// The seed of the hash of each chunk.
+const CHECKSUM_SEED: i64 = 0x1b2c;
+
+/// The checksum of a file, made of the hash of each of its chunks.
+pub type Checksum = Vec<u128>;
+
+/// Read from `file` until `buffer` is full or the end of the file is reached, so that the
+/// chunks are split at the same positions regardless of how the reads are fragmented.
+async fn fill(file: &mut tokio::fs::File, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+
+    while filled < buffer.len() {
+        match file.read(&mut buffer[filled..]).await? {
+            0 => break,
+            read => filled += read,
+        }
+    }
+
+    Ok(filled)
+}
+
+/// Calculate the checksum of a file.
+pub async fn checksum(path: impl AsRef<Path>) -> io::Result<Checksum> {
+    let mut file = tokio::fs::File::open(path).await?;
+    let mut buffer = vec![0; config::CHECKSUM_CHUNK_SIZE];
+    let mut checksum = Checksum::new();
+
+    loop {
+        match fill(&mut file, &mut buffer).await? {
+            0 => return Ok(checksum),
+            read => checksum.push(hasher::hash128(&buffer[..read], CHECKSUM_SEED)),
+        }
+    }
+}
+
+/// Calculate the checksum of a file.
+#[cfg(feature = "sync")]
+pub fn checksum_blocking(path: impl AsRef<Path>) -> io::Result<Checksum> {
+    use std::io::Read;
+
+    let mut file = std::fs::File::open(path)?;
+    let mut buffer = Vec::with_capacity(config::CHECKSUM_CHUNK_SIZE);
+    let mut checksum = Checksum::new();
+
+    loop {
+        buffer.clear();
+        file.by_ref()
+            .take(config::CHECKSUM_CHUNK_SIZE as u64)
+            .read_to_end(&mut buffer)?;
+
+        match buffer.len() {
+            0 => return Ok(checksum),
+            _ => checksum.push(hasher::hash128(&buffer, CHECKSUM_SEED)),
+        }
+    }
+}
+
+/// Check whether two files are identical by their checksums.
+///
+/// Returns [`None`] if either file cannot be read, leaving the error to be reported by the
+/// detailed comparison.
+pub async fn checksums_match(
+    output_path: impl AsRef<Path>,
+    baseline_path: impl AsRef<Path>,
+) -> Option<bool> {
+    match tokio::join!(checksum(output_path), checksum(baseline_path)) {
+        (Ok(output), Ok(baseline)) => Some(output == baseline),
+        _ => None,
+    }
+}
+
+/// Check whether two files are identical by their checksums.
+///
+/// Returns [`None`] if either file cannot be read, leaving the error to be reported by the
+/// detailed comparison.
+#[cfg(feature = "sync")]
+pub fn checksums_match_blocking(
+    output_path: impl AsRef<Path>,
+    baseline_path: impl AsRef<Path>,
+) -> Option<bool> {
+    match (
+        checksum_blocking(output_path),
+        checksum_blocking(baseline_path),
+    ) {
+        (Ok(output), Ok(baseline)) => Some(output == baseline),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::TestDir;
+
+    #[tokio::test]
+    async fn checksum_chunks() {
+        let dir = TestDir::new("checksum_chunks");
+        let (first, second, third) = (dir.join("1.txt"), dir.join("2.txt"), dir.join("3.txt"));
+
+        let mut bytes = vec![b'a'; config::CHECKSUM_CHUNK_SIZE * 2 + 7];
+        std::fs::write(&first, &bytes).unwrap();
+        std::fs::write(&second, &bytes).unwrap();
+        *bytes.last_mut().unwrap() = b'b';
+        std::fs::write(&third, &bytes).unwrap();
+
+        let checksum_first = checksum(&first).await.unwrap();
+        let identical = checksums_match(&first, &second).await;
+        let different = checksums_match(&first, &third).await;
+        let unreadable = checksums_match(&first, "/nonexistent/baseline.txt").await;
+
+        #[cfg(feature = "sync")]
+        assert_eq!(checksum_blocking(&first).unwrap(), checksum_first);
+
+        assert_eq!(checksum_first.len(), 3);
+        assert_eq!(identical, Some(true));
+        assert_eq!(different, Some(false));
+        assert_eq!(unreadable, None);
+    }
+}
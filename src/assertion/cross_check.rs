@@ -0,0 +1,62 @@
+//! Cross-check the records of a run against the reference parser.
+//!
+//! The reference is the blocking scalar parser in [`crate::parser::sync`], run over the
+//! same input through a [`MmapReader`]. Any difference points to a bug in either parser,
+//! such as a hash collision between station names, without needing an external baseline.
+
+use crate::parser::models::StationRecords;
+use crate::reader::sync::MmapReader;
+
+use super::{records::diff_records, MismatchReport};
+
+/// Parse the input at `path` with the reference parser over `threads` chunks.
+pub fn reference_records(path: &str, threads: usize) -> StationRecords {
+    let reader = MmapReader::from_path(path).with_chunks(threads);
+
+    StationRecords::read_from_iterator(reader.iter::<b'\n'>())
+}
+
+/// Match the records against those of the reference parser on the same input at `path`,
+/// requiring the exact same integer statistics for every station.
+pub fn cross_check(
+    records: &StationRecords,
+    path: &str,
+    threads: usize,
+) -> Result<(), MismatchReport> {
+    let diffs = diff_records(records, &reference_records(path, threads));
+
+    if diffs.is_empty() {
+        Ok(())
+    } else {
+        Err(MismatchReport::CrossCheck { diffs })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::TestDir;
+
+    #[tokio::test]
+    async fn cross_check_async_parser() {
+        let dir = TestDir::new("cross_check_async_parser");
+        let path = dir.join("input.txt");
+        let bytes = b"jack;1.2\njill;-3.4\njack;56.7\nbob;0.0\njill;-99.9\n";
+        std::fs::write(&path, bytes).unwrap();
+        let path = path.to_str().unwrap();
+
+        let mut records = StationRecords::new();
+        crate::parser::line::parse_bytes(&bytes[..], &mut records).await;
+
+        let matched = cross_check(&records, path, 2);
+
+        records.insert("bob".as_bytes().into(), 1);
+        let mismatched = cross_check(&records, path, 2);
+
+        assert!(matched.is_ok());
+        assert!(matches!(
+            mismatched,
+            Err(MismatchReport::CrossCheck { ref diffs }) if diffs.len() == 1
+        ));
+    }
+}
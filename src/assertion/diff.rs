@@ -0,0 +1,294 @@
+//! Compare two outputs in the 1BRC format station by station.
+
+use std::collections::BTreeMap;
+
+/// The maximum number of differences listed by [`describe_diffs`].
+pub(crate) const MAX_LISTED_DIFFS: usize = 20;
+
+/// A statistic reported for each station in the 1BRC output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Statistic {
+    Min,
+    Mean,
+    Max,
+}
+
+impl std::fmt::Display for Statistic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Min => write!(f, "min"),
+            Self::Mean => write!(f, "mean"),
+            Self::Max => write!(f, "max"),
+        }
+    }
+}
+
+/// The statistics of a station as printed in the 1BRC output, in tenths of a degree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutputStats {
+    pub min: i32,
+    pub mean: i32,
+    pub max: i32,
+}
+
+impl OutputStats {
+    /// Get a single statistic.
+    pub fn get(&self, statistic: Statistic) -> i32 {
+        match statistic {
+            Statistic::Min => self.min,
+            Statistic::Mean => self.mean,
+            Statistic::Max => self.max,
+        }
+    }
+}
+
+/// Parse a value such as `-12.3` into tenths.
+fn parse_tenths(text: &str) -> Option<i32> {
+    text.parse::<f64>()
+        .ok()
+        .map(|value| (value * 10.0).round() as i32)
+}
+
+/// Parse a 1BRC output, i.e. `{name=min/mean/max, ...}`, into the statistics of each
+/// station.
+///
+/// Station names may contain `, ` or `/`, as the separators are located relative to the
+/// `=` of each station. Returns [`None`] if the text is not in the 1BRC format.
+pub fn parse_output(text: &str) -> Option<BTreeMap<String, OutputStats>> {
+    let mut rest = text.trim_end().strip_prefix('{')?.strip_suffix('}')?;
+    let mut stations = BTreeMap::new();
+
+    while !rest.is_empty() {
+        let (name, values) = rest.split_once('=')?;
+        let (values, remainder) = values.split_once(", ").unwrap_or((values, ""));
+
+        let mut values = values.splitn(3, '/').map(parse_tenths);
+        let stats = OutputStats {
+            min: values.next()??,
+            mean: values.next()??,
+            max: values.next()??,
+        };
+
+        stations.insert(name.to_owned(), stats);
+        rest = remainder;
+    }
+
+    Some(stations)
+}
+
+/// A difference between the output and the baseline for a single station.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StationDiff {
+    /// The station is in the baseline but not in the output.
+    MissingFromOutput(String),
+
+    /// The station is in the output but not in the baseline.
+    MissingFromBaseline(String),
+
+    /// A statistic of the station differs, in tenths of a degree.
+    Value {
+        name: String,
+        statistic: Statistic,
+        output: i32,
+        baseline: i32,
+    },
+}
+
+impl StationDiff {
+    /// Whether the difference is likely due to a different rounding strategy, i.e. the
+    /// values are only 0.1 apart.
+    pub fn is_rounding(&self) -> bool {
+        matches!(self, Self::Value { output, baseline, .. } if (output - baseline).abs() == 1)
+    }
+}
+
+impl std::fmt::Display for StationDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingFromOutput(name) => write!(f, "{}: missing from the output", name),
+            Self::MissingFromBaseline(name) => write!(f, "{}: missing from the baseline", name),
+            Self::Value {
+                name,
+                statistic,
+                output,
+                baseline,
+            } => {
+                write!(
+                    f,
+                    "{}: {} is {:.1} in the output but {:.1} in the baseline",
+                    name,
+                    statistic,
+                    *output as f64 / 10.0,
+                    *baseline as f64 / 10.0
+                )?;
+
+                if self.is_rounding() {
+                    write!(f, " (likely rounding)")?;
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Find every difference between the output and the baseline, in order of station name.
+pub fn diff_outputs(
+    output: &BTreeMap<String, OutputStats>,
+    baseline: &BTreeMap<String, OutputStats>,
+) -> Vec<StationDiff> {
+    diff_outputs_within(output, baseline, 0.0)
+}
+
+/// Find every difference between the output and the baseline larger than `tolerance`
+/// degrees, in order of station name.
+///
+/// Since every value is printed with one decimal place, the tolerance is rounded to the
+/// nearest tenth; a tolerance of `0.1` accepts values differing in the last decimal only.
+pub fn diff_outputs_within(
+    output: &BTreeMap<String, OutputStats>,
+    baseline: &BTreeMap<String, OutputStats>,
+    tolerance: f64,
+) -> Vec<StationDiff> {
+    let tolerance = (tolerance.abs() * 10.0).round() as i32;
+
+    let names = output
+        .keys()
+        .chain(baseline.keys())
+        .collect::<std::collections::BTreeSet<_>>();
+
+    names
+        .into_iter()
+        .flat_map(|name| match (output.get(name), baseline.get(name)) {
+            (Some(output), Some(baseline)) => [Statistic::Min, Statistic::Mean, Statistic::Max]
+                .into_iter()
+                .filter(|statistic| {
+                    (output.get(*statistic) - baseline.get(*statistic)).abs() > tolerance
+                })
+                .map(|statistic| StationDiff::Value {
+                    name: name.clone(),
+                    statistic,
+                    output: output.get(statistic),
+                    baseline: baseline.get(statistic),
+                })
+                .collect::<Vec<_>>(),
+            (None, _) => vec![StationDiff::MissingFromOutput(name.clone())],
+            (_, None) => vec![StationDiff::MissingFromBaseline(name.clone())],
+        })
+        .collect()
+}
+
+/// Describe a list of differences, for reporting a mismatch.
+pub fn describe_diffs(diffs: &[StationDiff]) -> String {
+    let rounding = diffs.iter().filter(|diff| diff.is_rounding()).count();
+
+    let mut description = format!(
+        "{} differences found, of which {} are likely rounding only:\n",
+        diffs.len(),
+        rounding
+    );
+
+    for diff in diffs.iter().take(MAX_LISTED_DIFFS) {
+        description += &format!("- {}\n", diff);
+    }
+    if diffs.len() > MAX_LISTED_DIFFS {
+        description += &format!("...and {} more.\n", diffs.len() - MAX_LISTED_DIFFS);
+    }
+
+    description
+}
+
+/// Find every difference between two files in the 1BRC format.
+///
+/// Returns [`None`] if either file cannot be parsed.
+pub fn diff_texts(output: &[u8], baseline: &[u8]) -> Option<Vec<StationDiff>> {
+    Some(diff_outputs(
+        &parse_output(&String::from_utf8_lossy(output))?,
+        &parse_output(&String::from_utf8_lossy(baseline))?,
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_valid_output() {
+        let stations = parse_output("{Abha=-23.0/18.0/59.2, Foo, Bar=1.0/2.5/3.0}\n").unwrap();
+
+        assert_eq!(
+            stations.get("Abha"),
+            Some(&OutputStats {
+                min: -230,
+                mean: 180,
+                max: 592
+            })
+        );
+        assert_eq!(stations.get("Foo, Bar").unwrap().mean, 25);
+        assert_eq!(stations.len(), 2);
+    }
+
+    #[test]
+    fn parse_invalid_output() {
+        assert_eq!(parse_output("Abha=-23.0/18.0/59.2"), None);
+        assert_eq!(parse_output("{Abha=-23.0/18.0}"), None);
+        assert_eq!(parse_output("{}").unwrap().len(), 0);
+    }
+
+    #[test]
+    fn diff_stations() {
+        let output = parse_output("{a=1.0/2.0/3.0, b=1.0/2.1/3.0, c=0.0/0.0/0.0}").unwrap();
+        let baseline = parse_output("{a=1.0/2.0/3.0, b=1.5/2.0/3.0, d=0.0/0.0/0.0}").unwrap();
+
+        let diffs = diff_outputs(&output, &baseline);
+
+        assert_eq!(
+            diffs,
+            vec![
+                StationDiff::Value {
+                    name: "b".to_owned(),
+                    statistic: Statistic::Min,
+                    output: 10,
+                    baseline: 15
+                },
+                StationDiff::Value {
+                    name: "b".to_owned(),
+                    statistic: Statistic::Mean,
+                    output: 21,
+                    baseline: 20
+                },
+                StationDiff::MissingFromBaseline("c".to_owned()),
+                StationDiff::MissingFromOutput("d".to_owned()),
+            ]
+        );
+        assert_eq!(
+            diffs
+                .iter()
+                .map(StationDiff::is_rounding)
+                .collect::<Vec<_>>(),
+            vec![false, true, false, false]
+        );
+        assert_eq!(
+            diffs[1].to_string(),
+            "b: mean is 2.1 in the output but 2.0 in the baseline (likely rounding)"
+        );
+    }
+
+    #[test]
+    fn diff_stations_within_tolerance() {
+        let output = parse_output("{a=1.0/2.1/3.0, b=-1.0/2.0/3.3}").unwrap();
+        let baseline = parse_output("{a=1.0/2.0/3.0, b=-1.2/2.0/3.0}").unwrap();
+
+        assert_eq!(diff_outputs(&output, &baseline).len(), 3);
+        assert_eq!(
+            diff_outputs_within(&output, &baseline, 0.2),
+            vec![StationDiff::Value {
+                name: "b".to_owned(),
+                statistic: Statistic::Max,
+                output: 33,
+                baseline: 30
+            }]
+        );
+        assert!(diff_outputs_within(&output, &baseline, 0.3).is_empty());
+    }
+}
@@ -1,91 +1,218 @@
 //! Match the output and the baseline files.
 
 use std::path::Path;
-use tokio::{
-    fs::File,
-    io::{AsyncReadExt, BufReader},
-};
 
 #[cfg(feature = "sync")]
-use memmap::Mmap;
+use crate::reader::mapped::MappedFile;
 
-/// The size of the chunk to match the files.
-const MATCH_CHUNK_SIZE: usize = 32;
+use super::{checksum, diff, MismatchReport};
 
-/// Match the output and the baseline files.
-pub async fn match_files(output_path: impl AsRef<Path>, baseline_path: impl AsRef<Path>) {
-    let output_file = File::open(output_path).await.unwrap();
-    let baseline_file = File::open(baseline_path).await.unwrap();
+/// The size of the window around the first differing byte shown in a [`MismatchReport`].
+const MATCH_CHUNK_SIZE: usize = 32;
 
-    let mut output_reader = BufReader::new(output_file);
-    let mut baseline_reader = BufReader::new(baseline_file);
+/// Compare the output and the baseline byte by byte.
+///
+/// If they differ, they are parsed in the 1BRC format to report the differences station by
+/// station, falling back to the first differing bytes if either is not in the format.
+fn match_bytes(output: &[u8], baseline: &[u8]) -> Result<(), MismatchReport> {
+    if output == baseline {
+        return Ok(());
+    }
 
-    let mut output_buffer = vec![0; MATCH_CHUNK_SIZE];
-    let mut baseline_buffer = vec![0; MATCH_CHUNK_SIZE];
+    match diff::diff_texts(output, baseline) {
+        Some(diffs) if !diffs.is_empty() => Err(MismatchReport::Stations {
+            diffs,
+            tolerance: 0.0,
+        }),
+        _ => {
+            let offset = output
+                .iter()
+                .zip(baseline)
+                .position(|(output, baseline)| output != baseline)
+                .unwrap_or(output.len().min(baseline.len()));
+            let window = |bytes: &[u8]| {
+                let start = offset.min(bytes.len());
+                String::from_utf8_lossy(&bytes[start..(start + MATCH_CHUNK_SIZE).min(bytes.len())])
+                    .into_owned()
+            };
 
-    loop {
-        let (output_bytes, baseline_bytes) = tokio::join!(
-            output_reader.read(&mut output_buffer),
-            baseline_reader.read(&mut baseline_buffer)
-        );
+            Err(MismatchReport::Bytes {
+                offset,
+                output: window(output),
+                baseline: window(baseline),
+            })
+        }
+    }
+}
 
-        match (output_bytes, baseline_bytes) {
-            (Ok(0), Ok(0)) => {
-                break;
+/// Compare the parsed output and baseline, allowing each value to differ by up to
+/// `tolerance` degrees.
+fn match_bytes_with_tolerance(
+    output: &[u8],
+    baseline: &[u8],
+    output_path: &Path,
+    baseline_path: &Path,
+    tolerance: f64,
+) -> Result<(), MismatchReport> {
+    let parse = |bytes: &[u8], path: &Path| {
+        diff::parse_output(&String::from_utf8_lossy(bytes)).ok_or_else(|| {
+            MismatchReport::Malformed {
+                path: path.to_owned(),
             }
-            (Ok(i), Ok(j)) if i == j => {
-                if output_buffer[..i] != baseline_buffer[..j] {
-                    panic!(
-                        "The files differ at the following position:\noutput:{}\nbaseline:{}",
-                        String::from_utf8_lossy(&output_buffer[..i]),
-                        String::from_utf8_lossy(&baseline_buffer[..j])
-                    )
-                }
-            }
-            (Ok(i), Ok(j)) => {
-                panic!(
-                    "The files have different sizes: {} and {};\noutput:{}\nbaseline:{}",
-                    i,
-                    j,
-                    String::from_utf8_lossy(&output_buffer[..i]),
-                    String::from_utf8_lossy(&baseline_buffer[..j])
-                );
-            }
-            _ => {
-                panic!("Error reading the files.");
-            }
-        }
+        })
+    };
+
+    let diffs = diff::diff_outputs_within(
+        &parse(output, output_path)?,
+        &parse(baseline, baseline_path)?,
+        tolerance,
+    );
+
+    if diffs.is_empty() {
+        Ok(())
+    } else {
+        Err(MismatchReport::Stations { diffs, tolerance })
     }
 }
 
+/// Read a whole file, reporting the path if it cannot be read.
+async fn read(path: &Path) -> Result<Vec<u8>, MismatchReport> {
+    tokio::fs::read(path)
+        .await
+        .map_err(|error| MismatchReport::Unreadable {
+            path: path.to_owned(),
+            error,
+        })
+}
+
+/// Memory-map a whole file, reporting the path if it cannot be read.
 #[cfg(feature = "sync")]
+fn map(path: &Path) -> Result<MappedFile, MismatchReport> {
+    MappedFile::open(path).map_err(|error| MismatchReport::Unreadable {
+        path: path.to_owned(),
+        error,
+    })
+}
+
 /// Match the output and the baseline files.
-pub fn match_files_blocking(output_path: impl AsRef<Path>, baseline_path: impl AsRef<Path>) {
-    let output_file = std::fs::File::open(output_path).unwrap();
-    let baseline_file = std::fs::File::open(baseline_path).unwrap();
-
-    let output_reader = unsafe { Mmap::map(&output_file).unwrap() };
-    let baseline_reader = unsafe { Mmap::map(&baseline_file).unwrap() };
-
-    let mut cursor = 0;
-    loop {
-        let output_chunk =
-            &output_reader[cursor..(cursor + MATCH_CHUNK_SIZE).min(output_reader.len())];
-        let baseline_chunk =
-            &baseline_reader[cursor..(cursor + MATCH_CHUNK_SIZE).min(baseline_reader.len())];
-
-        if output_chunk != baseline_chunk {
-            panic!(
-                "The files differ at the following position:\noutput:{}\nbaseline:{}",
-                String::from_utf8_lossy(output_chunk),
-                String::from_utf8_lossy(baseline_chunk)
-            )
-        }
+///
+/// The files are first compared by their checksums, so that identical files are never held
+/// in memory; they are only read in full to report the differences.
+pub async fn match_files(
+    output_path: impl AsRef<Path>,
+    baseline_path: impl AsRef<Path>,
+) -> Result<(), MismatchReport> {
+    let (output_path, baseline_path) = (output_path.as_ref(), baseline_path.as_ref());
+
+    if checksum::checksums_match(output_path, baseline_path).await == Some(true) {
+        return Ok(());
+    }
 
-        cursor += MATCH_CHUNK_SIZE;
+    let (output, baseline) = tokio::join!(read(output_path), read(baseline_path));
 
-        if cursor >= output_reader.len() {
-            break;
-        }
+    match_bytes(&output?, &baseline?)
+}
+
+/// Match the output and the baseline files, allowing each value to differ by up to
+/// `tolerance` degrees rather than requiring the files to be identical.
+///
+/// This is useful for baselines produced by other implementations, which may round the
+/// values differently.
+pub async fn match_files_with_tolerance(
+    output_path: impl AsRef<Path>,
+    baseline_path: impl AsRef<Path>,
+    tolerance: f64,
+) -> Result<(), MismatchReport> {
+    let (output_path, baseline_path) = (output_path.as_ref(), baseline_path.as_ref());
+    let (output, baseline) = tokio::join!(read(output_path), read(baseline_path));
+
+    match_bytes_with_tolerance(&output?, &baseline?, output_path, baseline_path, tolerance)
+}
+
+#[cfg(feature = "sync")]
+/// Match the output and the baseline files, allowing each value to differ by up to
+/// `tolerance` degrees rather than requiring the files to be identical.
+pub fn match_files_with_tolerance_blocking(
+    output_path: impl AsRef<Path>,
+    baseline_path: impl AsRef<Path>,
+    tolerance: f64,
+) -> Result<(), MismatchReport> {
+    let (output_path, baseline_path) = (output_path.as_ref(), baseline_path.as_ref());
+
+    match_bytes_with_tolerance(
+        map(output_path)?.bytes(),
+        map(baseline_path)?.bytes(),
+        output_path,
+        baseline_path,
+        tolerance,
+    )
+}
+
+#[cfg(feature = "sync")]
+/// Match the output and the baseline files.
+///
+/// The files are first compared by their checksums, so that identical files are never held
+/// in memory; they are only mapped in full to report the differences.
+pub fn match_files_blocking(
+    output_path: impl AsRef<Path>,
+    baseline_path: impl AsRef<Path>,
+) -> Result<(), MismatchReport> {
+    let (output_path, baseline_path) = (output_path.as_ref(), baseline_path.as_ref());
+
+    if checksum::checksums_match_blocking(output_path, baseline_path) == Some(true) {
+        return Ok(());
+    }
+
+    match_bytes(map(output_path)?.bytes(), map(baseline_path)?.bytes())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn match_identical() {
+        assert!(match_bytes(b"{a=1.0/2.0/3.0}\n", b"{a=1.0/2.0/3.0}\n").is_ok());
+    }
+
+    #[test]
+    fn match_stations() {
+        let report = match_bytes(b"{a=1.0/2.1/3.0}\n", b"{a=1.0/2.0/3.0}\n").unwrap_err();
+
+        assert!(matches!(&report, MismatchReport::Stations { diffs, .. } if diffs.len() == 1));
+        assert!(report.is_rounding_only());
+    }
+
+    #[test]
+    fn match_malformed() {
+        let report = match_bytes(b"{a=1.0/2.0/3.0}\n", b"{a=1.0/2.0/3.0}\nextra").unwrap_err();
+
+        assert!(matches!(
+            report,
+            MismatchReport::Bytes { offset: 16, ref output, ref baseline }
+                if output.is_empty() && baseline == "extra"
+        ));
+    }
+
+    #[tokio::test]
+    async fn match_unreadable() {
+        let report = match_files("/nonexistent/output.txt", "/nonexistent/baseline.txt")
+            .await
+            .unwrap_err();
+
+        assert!(matches!(report, MismatchReport::Unreadable { .. }));
+    }
+
+    #[test]
+    fn match_with_tolerance() {
+        let (output, baseline) = (b"{a=1.0/2.1/3.0}\n", b"{a=1.0/2.0/3.0}\n");
+        let paths = (Path::new("output"), Path::new("baseline"));
+
+        assert!(match_bytes_with_tolerance(output, baseline, paths.0, paths.1, 0.1).is_ok());
+        assert!(match_bytes_with_tolerance(output, baseline, paths.0, paths.1, 0.0).is_err());
+        assert!(matches!(
+            match_bytes_with_tolerance(b"garbage", baseline, paths.0, paths.1, 0.1),
+            Err(MismatchReport::Malformed { .. })
+        ));
     }
 }
@@ -1,4 +1,22 @@
 //! Utilities for checking the results.
 
+pub mod checksum;
+
+#[cfg(feature = "sync")]
+mod cross_check;
+#[cfg(feature = "sync")]
+pub use cross_check::*;
+
+pub mod diff;
+
 mod match_files;
 pub use match_files::*;
+
+mod report;
+pub use report::MismatchReport;
+
+mod records;
+pub use records::*;
+
+mod snapshot;
+pub use snapshot::*;
@@ -0,0 +1,124 @@
+//! Compare the records of a run against reference records, such as a saved snapshot.
+
+use crate::parser::models::{StationRecords, StationStats};
+use std::collections::BTreeMap;
+
+use super::diff::MAX_LISTED_DIFFS;
+
+/// A difference between the records and the reference for a single station.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RecordsDiff {
+    /// The station is in the reference but not in the records.
+    MissingFromOutput(String),
+
+    /// The station is in the records but not in the reference.
+    MissingFromReference(String),
+
+    /// The statistics of the station differ.
+    Stats {
+        name: String,
+        output: StationStats,
+        reference: StationStats,
+    },
+}
+
+impl std::fmt::Display for RecordsDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let describe = |stats: &StationStats| {
+            format!(
+                "min={} max={} sum={} count={}",
+                stats.min, stats.max, stats.sum, stats.count
+            )
+        };
+
+        match self {
+            Self::MissingFromOutput(name) => write!(f, "{}: missing from the output", name),
+            Self::MissingFromReference(name) => write!(f, "{}: missing from the reference", name),
+            Self::Stats {
+                name,
+                output,
+                reference,
+            } => write!(
+                f,
+                "{}: {} in the output but {} in the reference",
+                name,
+                describe(output),
+                describe(reference)
+            ),
+        }
+    }
+}
+
+/// Describe a list of differences, for reporting a mismatch.
+pub fn describe_records_diffs(diffs: &[RecordsDiff]) -> String {
+    let mut description = format!("{} differences found:\n", diffs.len());
+
+    for diff in diffs.iter().take(MAX_LISTED_DIFFS) {
+        description += &format!("- {}\n", diff);
+    }
+    if diffs.len() > MAX_LISTED_DIFFS {
+        description += &format!("...and {} more.\n", diffs.len() - MAX_LISTED_DIFFS);
+    }
+
+    description
+}
+
+/// Find every difference between the records and the reference, in order of station name.
+pub fn diff_records(output: &StationRecords, reference: &StationRecords) -> Vec<RecordsDiff> {
+    let mut stations = BTreeMap::<&[u8], (Option<&StationStats>, Option<&StationStats>)>::new();
+
+    output
+        .iter()
+        .for_each(|(name, stats)| stations.entry(name).or_default().0 = Some(stats));
+    reference
+        .iter()
+        .for_each(|(name, stats)| stations.entry(name).or_default().1 = Some(stats));
+
+    stations
+        .into_iter()
+        .filter_map(|(name, pair)| {
+            let name = String::from_utf8_lossy(name).into_owned();
+
+            match pair {
+                (Some(output), Some(reference)) if output == reference => None,
+                (Some(output), Some(reference)) => Some(RecordsDiff::Stats {
+                    name,
+                    output: *output,
+                    reference: *reference,
+                }),
+                (None, _) => Some(RecordsDiff::MissingFromOutput(name)),
+                (_, None) => Some(RecordsDiff::MissingFromReference(name)),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn diff_reference() {
+        let mut output = StationRecords::new();
+        output.insert("a".as_bytes().into(), 10);
+        output.insert("b".as_bytes().into(), 10);
+        output.insert("c".as_bytes().into(), 10);
+
+        let mut reference = StationRecords::new();
+        reference.insert("a".as_bytes().into(), 10);
+        reference.insert("b".as_bytes().into(), 10);
+        reference.insert("b".as_bytes().into(), 10);
+        reference.insert("d".as_bytes().into(), 10);
+
+        let diffs = diff_records(&output, &reference);
+
+        assert_eq!(diffs.len(), 3);
+        assert!(matches!(
+            &diffs[0],
+            RecordsDiff::Stats { name, output, reference }
+                if name == "b" && output.count == 1 && reference.count == 2
+        ));
+        assert_eq!(diffs[1], RecordsDiff::MissingFromReference("c".to_owned()));
+        assert_eq!(diffs[2], RecordsDiff::MissingFromOutput("d".to_owned()));
+    }
+}
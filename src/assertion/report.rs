@@ -0,0 +1,92 @@
+//! The report of a failed match between the output and the baseline.
+
+use std::path::PathBuf;
+
+use super::diff::{self, StationDiff};
+use super::records::{self, RecordsDiff};
+
+/// Why the output did not match the baseline.
+#[derive(Debug)]
+pub enum MismatchReport {
+    /// A file could not be read.
+    Unreadable {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+
+    /// A file is not in the 1BRC format, which is required for a tolerant comparison.
+    Malformed { path: PathBuf },
+
+    /// The files differ, but are not both in the 1BRC format, so only the first differing
+    /// bytes can be reported.
+    Bytes {
+        offset: usize,
+        output: String,
+        baseline: String,
+    },
+
+    /// The stations differ by more than `tolerance` degrees.
+    Stations {
+        diffs: Vec<StationDiff>,
+        tolerance: f64,
+    },
+
+    /// The records differ from the saved snapshot.
+    Snapshot { diffs: Vec<RecordsDiff> },
+
+    /// The records differ from those of the reference parser.
+    CrossCheck { diffs: Vec<RecordsDiff> },
+}
+
+impl MismatchReport {
+    /// Whether every difference is likely due to a different rounding strategy.
+    pub fn is_rounding_only(&self) -> bool {
+        matches!(self, Self::Stations { diffs, .. } if diffs.iter().all(StationDiff::is_rounding))
+    }
+}
+
+impl std::fmt::Display for MismatchReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unreadable { path, error } => write!(f, "Could not read {:?}: {}", path, error),
+            Self::Malformed { path } => write!(f, "{:?} is not in the 1BRC format.", path),
+            Self::Bytes {
+                offset,
+                output,
+                baseline,
+            } => write!(
+                f,
+                "The files differ at byte offset {}:\noutput:{}\nbaseline:{}",
+                offset, output, baseline
+            ),
+            Self::Stations { diffs, tolerance } => {
+                if *tolerance > 0.0 {
+                    write!(f, "The files differ beyond a tolerance of {}; ", tolerance)?;
+                } else {
+                    write!(f, "The files differ; ")?;
+                }
+
+                write!(f, "{}", diff::describe_diffs(diffs))
+            }
+            Self::Snapshot { diffs } => write!(
+                f,
+                "The records differ from the snapshot; {}",
+                records::describe_records_diffs(diffs)
+            ),
+            Self::CrossCheck { diffs } => write!(
+                f,
+                "The records differ from those of the reference parser; {}",
+                records::describe_records_diffs(diffs)
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MismatchReport {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Unreadable { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
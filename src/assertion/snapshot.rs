@@ -0,0 +1,64 @@
+//! Compare the records of a run against a saved snapshot.
+
+use std::path::Path;
+
+use crate::parser::models::StationRecords;
+
+use super::{records::diff_records, MismatchReport};
+
+/// Match the records against the snapshot file, requiring the exact same integer
+/// statistics for every station.
+///
+/// This is stricter than matching the output files, which only compare the rounded
+/// values, and avoids formatting and parsing any text.
+pub fn match_snapshot(
+    records: &StationRecords,
+    snapshot_path: impl AsRef<Path>,
+) -> Result<(), MismatchReport> {
+    let snapshot_path = snapshot_path.as_ref();
+    let snapshot = StationRecords::read_snapshot(snapshot_path).map_err(|error| {
+        MismatchReport::Unreadable {
+            path: snapshot_path.to_owned(),
+            error,
+        }
+    })?;
+
+    let diffs = diff_records(records, &snapshot);
+
+    if diffs.is_empty() {
+        Ok(())
+    } else {
+        Err(MismatchReport::Snapshot { diffs })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::TestDir;
+
+    #[test]
+    fn match_snapshot_file() {
+        let dir = TestDir::new("match_snapshot_file");
+        let path = dir.join("snapshot.bin");
+
+        let mut records = StationRecords::new();
+        records.insert("a".as_bytes().into(), -5);
+        records.write_snapshot(&path).unwrap();
+
+        let matched = match_snapshot(&records, &path);
+
+        records.insert("a".as_bytes().into(), 5);
+        let mismatched = match_snapshot(&records, &path);
+
+        assert!(matched.is_ok());
+        assert!(matches!(
+            mismatched,
+            Err(MismatchReport::Snapshot { ref diffs }) if diffs.len() == 1
+        ));
+        assert!(matches!(
+            match_snapshot(&records, "/nonexistent/snapshot.bin"),
+            Err(MismatchReport::Unreadable { .. })
+        ));
+    }
+}
@@ -0,0 +1,504 @@
+//! Aggregate several 1BRC inputs at once, e.g. the daily files of a dataset.
+//!
+//! [`run_batch`] runs [`run`] over each file through its own reader and consumers, a few files
+//! at a time, sharing the threads of the options between the runs in flight. The records of the
+//! files are merged into the aggregate results, exported to the output of the options; the
+//! report of each file is kept with its throughput, and its results can be exported on their
+//! own as well.
+//!
+//! The inputs are given as a directory, taking every file in it, or as a pattern with `*` and
+//! `?` wildcards in its last component, e.g. `data/measurements-*.txt`; see [`resolve_inputs`].
+//! With the `tar` feature, an input may also be a tar archive, whose members are aggregated in
+//! turn as files of the batch; see [`crate::archive`].
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Instant;
+
+use tokio::sync::Semaphore;
+
+use crate::run::{consumer_failure, export};
+use crate::{run, RunError, RunOptions, RunReport};
+
+/// The extension appended to the name of each input for its own results.
+pub const PER_FILE_EXTENSION: &str = "out";
+
+/// Whether `path` names several inputs, i.e. is a directory, an archive with the `tar`
+/// feature, or has wildcards in its last component.
+pub fn is_batch(path: impl AsRef<Path>) -> bool {
+    let path = path.as_ref();
+
+    #[cfg(feature = "tar")]
+    if crate::archive::is_archive(path) {
+        return true;
+    }
+
+    path.is_dir()
+        || path
+            .file_name()
+            .is_some_and(|name| has_wildcards(name.as_encoded_bytes()))
+}
+
+/// Whether `pattern` has any wildcard.
+fn has_wildcards(pattern: &[u8]) -> bool {
+    pattern.iter().any(|&byte| byte == b'*' || byte == b'?')
+}
+
+/// Whether `name` matches `pattern`, where `*` matches any number of bytes, and `?` any
+/// single byte.
+fn matches(pattern: &[u8], name: &[u8]) -> bool {
+    let (mut p, mut n) = (0, 0);
+    // The position after the last `*` in the pattern, and where it started matching in the name.
+    let mut star = None;
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                star = Some((p + 1, n));
+                p += 1;
+            }
+            Some(&byte) if byte == b'?' || byte == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            // Let the last `*` match one more byte, and try again from there.
+            _ => match star {
+                Some((after, start)) => {
+                    star = Some((after, start + 1));
+                    p = after;
+                    n = start + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&byte| byte == b'*')
+}
+
+/// The files named by `path`, sorted by name: every file of a directory but the hidden ones,
+/// or the files matching the wildcards in the last component, or `path` itself otherwise.
+pub fn resolve_inputs(path: impl AsRef<Path>) -> std::io::Result<Vec<PathBuf>> {
+    let path = path.as_ref();
+
+    let (dir, pattern) = if path.is_dir() {
+        (path, None)
+    } else {
+        match path.file_name() {
+            Some(name) if has_wildcards(name.as_encoded_bytes()) => (
+                path.parent()
+                    .filter(|parent| !parent.as_os_str().is_empty())
+                    .unwrap_or(Path::new(".")),
+                Some(name.as_encoded_bytes()),
+            ),
+            _ => return Ok(vec![path.to_owned()]),
+        }
+    };
+
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let name = name.as_encoded_bytes();
+
+        let included = match pattern {
+            Some(pattern) => matches(pattern, name),
+            None => !name.starts_with(b"."),
+        };
+        if included && entry.file_type()?.is_file() {
+            files.push(entry.path());
+        }
+    }
+
+    if files.is_empty() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("no input found at {:?}", path),
+        ));
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+/// The options of a batch of runs of [`run_batch`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatchOptions {
+    /// The input files, in the order they are started and reported in.
+    pub files: Vec<PathBuf>,
+
+    /// The options of the runs: `threads` is shared between the runs in flight, while
+    /// `output`, and the verification and baseline matching of it, apply to the aggregate
+    /// results; `file` is ignored.
+    pub run: RunOptions,
+
+    /// The number of files aggregated at once.
+    pub concurrent_files: usize,
+
+    /// The directory the results of each file are exported to, as `<file name>.out`, if any.
+    pub per_file_outputs: Option<PathBuf>,
+}
+
+impl BatchOptions {
+    /// Create a new [`BatchOptions`] aggregating `files` with the options of `run`, as many
+    /// files at once as there are threads.
+    pub fn new(files: Vec<PathBuf>, run: RunOptions) -> Self {
+        Self {
+            concurrent_files: run.threads.min(files.len()).max(1),
+            files,
+            run,
+            per_file_outputs: None,
+        }
+    }
+
+    /// Set the number of files aggregated at once.
+    pub fn with_concurrent_files(mut self, concurrent_files: usize) -> Self {
+        self.concurrent_files = concurrent_files;
+        self
+    }
+
+    /// Export the results of each file to `dir` as well, as `<file name>.out`; [`run_batch`]
+    /// creates `dir` if it does not exist.
+    pub fn with_per_file_outputs(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.per_file_outputs = Some(dir.into());
+        self
+    }
+
+    /// The number of consumers of each run, sharing the threads between the runs in flight.
+    pub fn threads_per_file(&self) -> usize {
+        (self.run.threads / self.concurrent_files.max(1)).max(1)
+    }
+
+    /// The path the results of `file` are exported to, if any.
+    pub fn per_file_output(&self, file: &Path) -> Option<PathBuf> {
+        let dir = self.per_file_outputs.as_ref()?;
+        let mut name = file.file_name()?.to_owned();
+        name.push(".");
+        name.push(PER_FILE_EXTENSION);
+
+        Some(dir.join(name))
+    }
+
+    /// The options of the run over `file`.
+    pub(crate) fn file_options(&self, file: &Path) -> RunOptions {
+        let mut options = self.run.clone().with_threads(self.threads_per_file());
+        options.file = file.to_owned();
+        options.output = self.per_file_output(file);
+
+        #[cfg(feature = "assert")]
+        {
+            options.baseline = None;
+        }
+
+        options
+    }
+
+    /// The first option of `run` which names a single file, and so cannot be shared between
+    /// the runs, if any.
+    fn unsupported(&self) -> Option<&'static str> {
+        if self.run.rejects.is_some() {
+            Some("the rejects file")
+        } else if self.run.checkpoint.is_some() || self.run.resume.is_some() {
+            Some("the checkpoints")
+        } else if self.run.spill.is_some() {
+            // The spills are exported straight to the output, instead of being merged.
+            Some("spilling")
+        } else {
+            None
+        }
+    }
+}
+
+/// The report of the run over one of the files of a batch.
+#[derive(Debug, Clone)]
+pub struct FileReport {
+    /// The path of the file.
+    pub file: PathBuf,
+
+    /// The report of its run; its records are merged into the aggregate results, and left
+    /// empty.
+    pub report: RunReport,
+}
+
+/// The summary of a batch of runs.
+#[derive(Debug, Clone, Default)]
+pub struct BatchReport {
+    /// The aggregate records, the sums of the counters of the files, and the time taken by the
+    /// whole batch.
+    pub total: RunReport,
+
+    /// The report of each file, in the order of the inputs.
+    pub files: Vec<FileReport>,
+}
+
+/// Why a batch of runs failed.
+#[derive(Debug)]
+pub enum BatchError {
+    /// An option of the runs names a single file, which several runs cannot share.
+    Unsupported(&'static str),
+
+    /// The directory of the per-file outputs could not be created; no run is started.
+    Outputs { dir: PathBuf, error: std::io::Error },
+
+    /// The run over a file failed; no more runs are started once one has failed.
+    File { file: PathBuf, error: RunError },
+
+    /// The aggregate results could not be exported, or do not match; the report of the error
+    /// is the total of the batch.
+    Export(RunError),
+}
+
+impl std::fmt::Display for BatchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unsupported(option) => {
+                write!(f, "{} cannot be used with several inputs", option)
+            }
+            Self::Outputs { dir, error } => {
+                write!(
+                    f,
+                    "Could not create the output directory {:?}: {}",
+                    dir, error
+                )
+            }
+            Self::File { file, error } => write!(f, "{:?}: {}", file, error),
+            Self::Export(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for BatchError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Unsupported(_) => None,
+            Self::Outputs { error, .. } => Some(error),
+            Self::File { error, .. } | Self::Export(error) => Some(error),
+        }
+    }
+}
+
+impl From<BatchError> for std::io::Error {
+    fn from(error: BatchError) -> Self {
+        match error {
+            BatchError::Unsupported(_) => {
+                std::io::Error::new(std::io::ErrorKind::InvalidInput, error.to_string())
+            }
+            BatchError::Outputs { error, .. } => error,
+            BatchError::File { error, .. } | BatchError::Export(error) => error.into(),
+        }
+    }
+}
+
+/// Add the counters and the records of `report` to `total`, leaving the records of `report`
+/// empty.
+pub(crate) fn accumulate(total: &mut RunReport, report: &mut RunReport) {
+    total.records += std::mem::take(&mut report.records);
+    total.bytes_read += report.bytes_read;
+    total.chunks += report.chunks;
+    total.records_parsed += report.records_parsed;
+    total.read_retries += report.read_retries;
+    total.chunks_skipped += report.chunks_skipped;
+    total.lines_rejected += report.lines_rejected;
+    total.partial |= report.partial;
+}
+
+/// The reports of the runs over `file`: its own, or those of its members if it is an archive.
+async fn run_file(file: PathBuf, options: &BatchOptions) -> Result<Vec<FileReport>, BatchError> {
+    #[cfg(feature = "tar")]
+    if crate::archive::is_archive(&file) {
+        return crate::archive::run_archive(&file, options).await;
+    }
+
+    match run(options.file_options(&file)).await {
+        Ok(report) => Ok(vec![FileReport { file, report }]),
+        Err(error) => Err(BatchError::File { file, error }),
+    }
+}
+
+/// Aggregate every file of `options`, each through its own [`run`], exporting the aggregate
+/// results and the results of each file if requested.
+///
+/// The runs are spawned onto the current tokio runtime, which should be multi-threaded for
+/// them to run in parallel.
+pub async fn run_batch(options: BatchOptions) -> Result<BatchReport, BatchError> {
+    if let Some(option) = options.unsupported() {
+        return Err(BatchError::Unsupported(option));
+    }
+
+    if let Some(dir) = &options.per_file_outputs {
+        std::fs::create_dir_all(dir).map_err(|error| BatchError::Outputs {
+            dir: dir.clone(),
+            error,
+        })?;
+    }
+
+    let start = Instant::now();
+    let options = Arc::new(options);
+    let permits = Arc::new(Semaphore::new(options.concurrent_files.max(1)));
+    let mut runs = Vec::with_capacity(options.files.len());
+
+    for file in &options.files {
+        // A failed run closes the semaphore, so that no more runs are started.
+        let Ok(permit) = Arc::clone(&permits).acquire_owned().await else {
+            break;
+        };
+
+        let (file, options) = (file.clone(), Arc::clone(&options));
+        let permits = Arc::clone(&permits);
+        runs.push(tokio::spawn(async move {
+            let result = run_file(file, &options).await;
+            if result.is_err() {
+                permits.close();
+            }
+
+            drop(permit);
+            result
+        }));
+    }
+
+    let mut report = BatchReport::default();
+    let mut failure = None;
+
+    for (file, handle) in options.files.iter().zip(runs) {
+        let result = handle.await.unwrap_or_else(|error| {
+            Err(BatchError::File {
+                file: file.clone(),
+                error: RunError::Consumer {
+                    message: consumer_failure(error),
+                    report: Box::default(),
+                },
+            })
+        });
+
+        match result {
+            Ok(files) => {
+                for mut file_report in files {
+                    accumulate(&mut report.total, &mut file_report.report);
+                    report.files.push(file_report);
+                }
+            }
+            // The first failure in the order of the inputs is reported.
+            Err(error) if failure.is_none() => failure = Some(error),
+            Err(_) => {}
+        }
+    }
+
+    if let Some(failure) = failure {
+        return Err(failure);
+    }
+
+    let total = export(Box::new(report.total), None, &options.run)
+        .await
+        .map_err(BatchError::Export)?;
+    report.total = *total;
+    report.total.elapsed = start.elapsed();
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config;
+    use crate::testing::TestDir;
+
+    #[test]
+    fn match_wildcards() {
+        assert!(matches(b"*.txt", b"measurements.txt"));
+        assert!(matches(b"measurements-?.txt", b"measurements-1.txt"));
+        assert!(matches(b"*-*.txt", b"a-b-c.txt"));
+        assert!(matches(b"*", b""));
+        assert!(!matches(b"*.txt", b"measurements.txt.out"));
+        assert!(!matches(b"measurements-?.txt", b"measurements-10.txt"));
+        assert!(!matches(b"?", b""));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn aggregate_files() {
+        let dir = TestDir::new("aggregate_files");
+        // The directory of the per-file outputs does not exist yet.
+        let outputs = dir.join("outputs/nested");
+        for (name, lines) in [
+            ("a.txt", "jack;1.2\njill;-3.4\n"),
+            ("b.txt", "jack;5.6\n"),
+            ("c.txt", "bob;0.0\njill;3.4\n"),
+            ("notes.md", "not an input\n"),
+        ] {
+            std::fs::write(dir.join(name), lines.repeat(100)).unwrap();
+        }
+
+        let files = resolve_inputs(dir.join("*.txt")).unwrap();
+        assert_eq!(files.len(), 3);
+        assert!(is_batch(dir.join("*.txt")) && is_batch(&dir));
+        assert!(!is_batch(dir.join("a.txt")));
+
+        let output = dir.join("output.txt");
+        let options = BatchOptions::new(
+            files,
+            RunOptions::new("")
+                .with_output(&output)
+                .with_verify_output(true)
+                .with_threads(4)
+                .with_chunk_sizes(64, 64 + config::MAX_LINE_LENGTH),
+        )
+        .with_concurrent_files(2)
+        .with_per_file_outputs(&outputs);
+        assert_eq!(options.threads_per_file(), 2);
+
+        let report = run_batch(options).await.unwrap();
+        let exported = std::fs::read_to_string(&output).unwrap();
+        let b = std::fs::read_to_string(outputs.join("b.txt.out")).unwrap();
+
+        assert_eq!(
+            exported,
+            "{bob=0.0/0.0/0.0, jack=1.2/3.4/5.6, jill=-3.4/0.0/3.4}\n"
+        );
+        assert_eq!(b, "{jack=5.6/5.6/5.6}\n");
+        assert_eq!(report.files.len(), 3);
+        assert_eq!(report.total.records_parsed, 500);
+        assert_eq!(
+            report.total.bytes_read,
+            report
+                .files
+                .iter()
+                .map(|file| file.report.bytes_read)
+                .sum::<u64>()
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn stop_on_failure() {
+        let options = BatchOptions::new(
+            vec!["/nonexistent/a.txt".into(), "/nonexistent/b.txt".into()],
+            RunOptions::new("").with_threads(2),
+        )
+        .with_concurrent_files(1);
+
+        match run_batch(options).await {
+            Err(BatchError::File { file, error }) => {
+                assert_eq!(file, PathBuf::from("/nonexistent/a.txt"));
+                assert!(matches!(error, RunError::Open(_)));
+            }
+            result => panic!("expected the first file to fail, got {:?}", result),
+        }
+
+        let options = BatchOptions::new(
+            vec!["a.txt".into()],
+            RunOptions::new("").with_rejects("rejects.txt"),
+        );
+        assert!(matches!(
+            run_batch(options).await,
+            Err(BatchError::Unsupported(_))
+        ));
+
+        // No run is started once the per-file outputs cannot be created.
+        let dir = TestDir::new("stop_on_failure");
+        std::fs::write(dir.join("file"), "").unwrap();
+        let options = BatchOptions::new(vec!["/nonexistent/a.txt".into()], RunOptions::new(""))
+            .with_per_file_outputs(dir.join("file"));
+        assert!(matches!(
+            run_batch(options).await,
+            Err(BatchError::Outputs { .. })
+        ));
+    }
+}
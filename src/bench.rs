@@ -0,0 +1,392 @@
+//! Record the performance of the runs, and detect regressions against earlier recordings.
+//!
+//! `main bench` runs the whole pipeline a few times with the same arguments as a normal run.
+//! With `--record`, the fastest pass is appended as a [`Recording`] to a history file, one JSON
+//! object per line, along with the git revision and the configuration; with `--compare`, its
+//! throughput is compared against the previous recording of the same input, and the command
+//! fails if it dropped by more than a threshold:
+//!
+//! ```bash
+//! cargo run --release --bin main -- --file=measurements.txt bench --record
+//! cargo run --release --bin main -- --file=measurements.txt bench --compare --record
+//! ```
+
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::config;
+
+/// The options of the `bench` subcommand of `main`.
+#[derive(clap::Args, Debug, Clone)]
+pub struct BenchArgs {
+    /// Append the results to the history file.
+    #[arg(long)]
+    pub record: bool,
+
+    /// Compare the results against the previous recording of the same input in the history
+    /// file, failing if the throughput dropped by more than `--threshold`.
+    #[arg(long)]
+    pub compare: bool,
+
+    /// The history file of the recordings, one JSON object per line.
+    #[arg(long, default_value_t = config::BENCH_HISTORY_PATH.to_owned())]
+    pub history: String,
+
+    /// The number of passes over the input, keeping the fastest.
+    #[arg(long, default_value_t = config::BENCH_REPEATS)]
+    pub repeats: usize,
+
+    /// The drop of the throughput in percent beyond which a comparison is a regression.
+    #[arg(long, default_value_t = config::BENCH_REGRESSION_THRESHOLD)]
+    pub threshold: f64,
+}
+
+/// The results of a benchmark, and what they were measured with.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recording {
+    /// The time of the recording, in seconds since the Unix epoch.
+    pub timestamp: u64,
+
+    /// The git revision of the working tree, suffixed with `-dirty` if it has changes, or
+    /// `unknown` outside of a repository.
+    pub git_rev: String,
+
+    /// The path of the input.
+    pub file: String,
+
+    /// The size of the input in bytes.
+    pub bytes: u64,
+
+    /// The number of records in the input.
+    pub records: u64,
+
+    pub threads: usize,
+    pub chunk_size: usize,
+    pub max_chunk_size: usize,
+    pub engine: String,
+
+    /// The enabled features, as given by [`crate::features::describe`].
+    pub features: String,
+
+    /// The elapsed time of each pass.
+    pub timings: Vec<Duration>,
+}
+
+impl Recording {
+    /// The fastest pass.
+    pub fn best(&self) -> Duration {
+        self.timings.iter().copied().min().unwrap_or_default()
+    }
+
+    /// The throughput of the fastest pass in bytes per second.
+    pub fn throughput(&self) -> f64 {
+        self.bytes as f64 / self.best().as_secs_f64()
+    }
+
+    /// Describe the configuration the recording was measured with.
+    pub fn configuration(&self) -> String {
+        format!(
+            "{} threads, chunk size {}, max chunk size {}, {} engine, features {}",
+            self.threads, self.chunk_size, self.max_chunk_size, self.engine, self.features
+        )
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "timestamp": self.timestamp,
+            "git_rev": self.git_rev,
+            "file": self.file,
+            "bytes": self.bytes,
+            "records": self.records,
+            "threads": self.threads,
+            "chunk_size": self.chunk_size,
+            "max_chunk_size": self.max_chunk_size,
+            "engine": self.engine,
+            "features": self.features,
+            "timings_ms": self
+                .timings
+                .iter()
+                .map(|timing| timing.as_secs_f64() * 1000.0)
+                .collect::<Vec<_>>(),
+            "best_ms": self.best().as_secs_f64() * 1000.0,
+            "throughput_mb_s": self.throughput() / 1e6,
+        })
+    }
+
+    fn from_json(value: &serde_json::Value) -> Option<Self> {
+        let integer = |name: &str| value[name].as_u64();
+        let string = |name: &str| value[name].as_str().map(str::to_owned);
+
+        Some(Self {
+            timestamp: integer("timestamp")?,
+            git_rev: string("git_rev")?,
+            file: string("file")?,
+            bytes: integer("bytes")?,
+            records: integer("records")?,
+            threads: integer("threads")? as usize,
+            chunk_size: integer("chunk_size")? as usize,
+            max_chunk_size: integer("max_chunk_size")? as usize,
+            engine: string("engine")?,
+            features: string("features")?,
+            timings: value["timings_ms"]
+                .as_array()?
+                .iter()
+                .map(|timing| {
+                    timing
+                        .as_f64()
+                        .map(|timing| Duration::from_secs_f64(timing / 1000.0))
+                })
+                .collect::<Option<_>>()?,
+        })
+    }
+}
+
+impl std::fmt::Display for Recording {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:?} at {:.1} MB/s over {} bytes ({})",
+            self.best(),
+            self.throughput() / 1e6,
+            self.bytes,
+            self.git_rev
+        )
+    }
+}
+
+/// Load every recording of the history file, oldest first; a missing file has none.
+pub fn read_history(path: impl AsRef<Path>) -> io::Result<Vec<Recording>> {
+    let history = match std::fs::read_to_string(path) {
+        Ok(history) => history,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+
+    history
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            serde_json::from_str(line)
+                .ok()
+                .as_ref()
+                .and_then(Recording::from_json)
+                .ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!(
+                            "Line {} of the history file is not a valid recording.",
+                            index + 1
+                        ),
+                    )
+                })
+        })
+        .collect()
+}
+
+/// Append `recording` to the history file, creating it if needed.
+pub fn append_history(path: impl AsRef<Path>, recording: &Recording) -> io::Result<()> {
+    if let Some(parent) = path.as_ref().parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?;
+
+    writeln!(file, "{}", recording.to_json())
+}
+
+/// The latest recording of the same input as `recording` in `history`.
+pub fn previous<'a>(history: &'a [Recording], recording: &Recording) -> Option<&'a Recording> {
+    history
+        .iter()
+        .rev()
+        .find(|previous| previous.file == recording.file)
+}
+
+/// The change of the throughput from a previous recording.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Comparison {
+    /// The change of the throughput in percent, negative if slower.
+    pub change: f64,
+
+    /// Whether the throughput dropped by more than the threshold.
+    pub regression: bool,
+}
+
+impl Comparison {
+    /// Compare `current` against `previous`, with a regression beyond a drop of `threshold`
+    /// percent.
+    pub fn new(previous: &Recording, current: &Recording, threshold: f64) -> Self {
+        let change = (current.throughput() / previous.throughput() - 1.0) * 100.0;
+
+        Self {
+            change,
+            regression: change < -threshold,
+        }
+    }
+}
+
+impl std::fmt::Display for Comparison {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:+.1}% throughput{}",
+            self.change,
+            if self.regression { ": regression" } else { "" }
+        )
+    }
+}
+
+/// The git revision of the current directory, suffixed with `-dirty` if it has uncommitted
+/// changes, or `unknown` if it cannot be found.
+pub fn git_rev() -> String {
+    let git = |args: &[&str]| {
+        std::process::Command::new("git")
+            .args(args)
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_owned())
+    };
+
+    match git(&["rev-parse", "--short", "HEAD"]) {
+        Some(rev) => match git(&["status", "--porcelain", "--untracked-files=no"]) {
+            Some(status) if !status.is_empty() => format!("{}-dirty", rev),
+            _ => rev,
+        },
+        None => "unknown".to_owned(),
+    }
+}
+
+/// Run the pipeline over the input of `options` `repeats` times, calling `before_pass` before
+/// each one, e.g. to prepare the page cache, and record the timings of every pass.
+#[cfg(feature = "runtime")]
+pub async fn measure(
+    options: &crate::RunOptions,
+    repeats: usize,
+    mut before_pass: impl FnMut(),
+) -> Result<Recording, crate::RunError> {
+    let mut timings = Vec::new();
+    let mut report = crate::RunReport::default();
+
+    for _ in 0..repeats.max(1) {
+        before_pass();
+        report = crate::run(options.clone()).await?;
+        timings.push(report.elapsed);
+    }
+
+    Ok(Recording {
+        timestamp: std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs()),
+        git_rev: git_rev(),
+        file: options.file.display().to_string(),
+        bytes: report.bytes_read,
+        records: report.records_parsed,
+        threads: options.threads,
+        chunk_size: options.chunk_size,
+        max_chunk_size: options.max_chunk_size,
+        engine: options.engine.to_string(),
+        features: crate::features::describe(),
+        timings,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::TestDir;
+
+    fn recording(file: &str, best_ms: u64) -> Recording {
+        Recording {
+            timestamp: 1_700_000_000,
+            git_rev: "abc1234".to_owned(),
+            file: file.to_owned(),
+            bytes: 1_000_000,
+            records: 70_000,
+            threads: 4,
+            chunk_size: 1 << 16,
+            max_chunk_size: (1 << 20) + config::MAX_LINE_LENGTH,
+            engine: "scalar".to_owned(),
+            features: "runtime".to_owned(),
+            timings: vec![
+                Duration::from_millis(best_ms + 5),
+                Duration::from_millis(best_ms),
+            ],
+        }
+    }
+
+    #[test]
+    fn history_roundtrip() {
+        let dir = TestDir::new("history_roundtrip");
+        let path = dir.join("history.jsonl");
+
+        let empty = read_history(&path);
+        append_history(&path, &recording("a.txt", 100)).unwrap();
+        append_history(&path, &recording("b.txt", 200)).unwrap();
+        let history = read_history(&path);
+        std::fs::write(&path, "{\"timestamp\": 1}\n").unwrap();
+        let invalid = read_history(&path);
+
+        assert!(empty.unwrap().is_empty());
+        assert_eq!(
+            history.unwrap(),
+            vec![recording("a.txt", 100), recording("b.txt", 200)]
+        );
+        assert_eq!(invalid.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn compare_recordings() {
+        let history = [
+            recording("a.txt", 100),
+            recording("b.txt", 100),
+            recording("a.txt", 110),
+        ];
+
+        assert_eq!(
+            previous(&history, &recording("a.txt", 0)),
+            Some(&history[2])
+        );
+        assert_eq!(previous(&history, &recording("c.txt", 0)), None);
+
+        let faster = Comparison::new(&history[2], &recording("a.txt", 100), 5.0);
+        assert!(faster.change > 9.9 && !faster.regression);
+
+        let slower = Comparison::new(&history[0], &recording("a.txt", 104), 5.0);
+        assert!(slower.change < 0.0 && !slower.regression);
+
+        let regressed = Comparison::new(&history[0], &recording("a.txt", 110), 5.0);
+        assert!(regressed.regression);
+        assert_eq!(regressed.to_string(), "-9.1% throughput: regression");
+    }
+
+    #[cfg(feature = "runtime")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn measure_passes() {
+        let dir = TestDir::new("measure_passes");
+        let path = dir.join("input.txt");
+        std::fs::write(&path, "jack;1.2\njill;-3.4\njack;5.6\n".repeat(1000)).unwrap();
+
+        let mut passes = 0;
+        let recording = measure(
+            &crate::RunOptions::new(&path)
+                .with_threads(2)
+                .with_chunk_sizes(256, 256 + config::MAX_LINE_LENGTH),
+            3,
+            || passes += 1,
+        )
+        .await;
+
+        let recording = recording.unwrap();
+        assert_eq!(passes, 3);
+        assert_eq!(recording.timings.len(), 3);
+        assert_eq!(recording.bytes, 28_000);
+        assert_eq!(recording.records, 3000);
+        assert_eq!(recording.threads, 2);
+    }
+}
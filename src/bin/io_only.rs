@@ -9,6 +9,8 @@ use clap::Parser;
 
 use async_1brc::{reader, CliArgs};
 
+use async_1brc::timed;
+
 /// The number of trials to run the benchmark.
 const TRIALS: usize = 8;
 
@@ -24,9 +26,15 @@ async fn main() {
         args.file, args.chunk_size, args.max_chunk_size
     );
 
+    if args.timed || args.timings_output.is_some() {
+        timed::enable();
+    }
+
     let mut trials = Vec::with_capacity(TRIALS);
 
     for trial in 0..TRIALS {
+        reader::cache::prepare(&args.file, args.drop_caches, args.prewarm);
+
         #[cfg(feature = "debug")]
         println!("Starting the reader coroutine.");
 
@@ -35,14 +43,19 @@ async fn main() {
 
         let reader = reader::RowsReader::with_chunk_sizes(args.chunk_size, args.max_chunk_size);
 
-        let file = tokio::fs::File::open(&args.file).await.unwrap();
+        let file = reader::cache::open(&args.file, args.readahead).unwrap();
+        if let Err(err) = reader::cache::advise_file(&file, args.readahead) {
+            println!("Could not advise the kernel to read ahead: {}", err);
+        }
+
+        let file = tokio::fs::File::from_std(file);
         let bufreader = tokio::io::BufReader::with_capacity(args.chunk_size, file);
 
         let mut count = 0;
         tokio::select! {
-            _ = reader.read(bufreader) => {},
+            read = reader.read(bufreader) => read.expect("Failed to read the file."),
             _ = async {
-                let mut buffer = Vec::with_capacity(args.max_chunk_size);
+                let mut buffer = reader::func::allocate_buffer(args.max_chunk_size);
                 while let Some(bytes) = reader.fill(buffer).await {
                     buffer = bytes;
                     count += 1;
@@ -70,4 +83,14 @@ async fn main() {
     println!("- Mean elapsed time: {:?}", mean);
     println!("- Max elapsed time: {:?}", max);
     println!("- Min elapsed time: {:?}", min);
+
+    if timed::is_enabled() {
+        println!("\nReporting the total time spent in the operations...");
+        timed::report_all();
+
+        if let Some(path) = &args.timings_output {
+            timed::export::export(path, timed::registered());
+            println!("Timings written to {:?}.", path);
+        }
+    }
 }
@@ -1,5 +1,5 @@
-use clap::Parser;
-use std::sync::Arc;
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
+use std::sync::{Arc, Mutex};
 
 #[cfg(feature = "bench")]
 use tokio::time::Instant;
@@ -7,11 +7,511 @@ use tokio::time::Instant;
 #[cfg(feature = "assert")]
 use async_1brc::assertion;
 
-use async_1brc::{parser, reader, CliArgs};
+use async_1brc::timed;
+
+#[cfg(feature = "pprof")]
+use async_1brc::cpu_profile::CpuProfiler;
+
+#[cfg(feature = "mem-stats")]
+use async_1brc::mem_stats;
+
+#[cfg(feature = "metrics")]
+use async_1brc::metrics;
+
+#[cfg(feature = "serve")]
+use async_1brc::serve::ResultsServer;
+
+#[cfg(feature = "watch")]
+use async_1brc::watch::{WatchSession, Watcher};
+
+use async_1brc::{
+    batch, bench, compare, config, distributed, features, parser, reader, timeline::Timeline, tune,
+    CliArgs,
+};
+
+/// The arguments of `main`, which aggregates the input unless a subcommand is given.
+#[derive(Parser, Debug)]
+struct MainArgs {
+    #[command(flatten)]
+    args: CliArgs,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Find the number of threads and the chunk sizes best suited to this machine, over a
+    /// prefix of the input, and save them to the `--tuning` file for later runs.
+    Tune(tune::TuneArgs),
+
+    /// Run the whole pipeline a few times, and record the fastest pass to the history file, or
+    /// compare it against the previous recording to detect regressions.
+    Bench(bench::BenchArgs),
+
+    /// Run every engine available in this build with each of the thread counts, verify that
+    /// they agree, and print a Markdown table of their times and throughput.
+    Compare(compare::CompareArgs),
+
+    /// Aggregate an input of `station;timestamp;temp` lines per station and time window, and
+    /// export the windows as CSV.
+    Windows(parser::windows::WindowArgs),
+
+    /// Aggregate the shards of the input asked for by a coordinator over TCP, with the options
+    /// of this machine, and send the records back.
+    ServeWorker(distributed::WorkerArgs),
+
+    /// Split the input into one shard per worker, each aggregating its shard of the file at
+    /// the same path on its machine, then merge and export their records.
+    Coordinator(distributed::CoordinatorArgs),
+}
+
+/// Calibrate over a prefix of the input, and save the fastest configuration.
+async fn run_tune(args: &CliArgs, options: &tune::TuneArgs) {
+    println!(
+        "Calibrating over the first {} bytes of {}...",
+        options.prefix_size, args.file
+    );
+
+    let trials = tune::calibrate(&args.file, options, |trial| {
+        println!("- {}: {:?}", trial.tuning, trial.elapsed)
+    })
+    .await
+    .unwrap_or_else(|err| {
+        println!("Could not calibrate over {:?}: {}", args.file, err);
+        std::process::exit(config::FAILURE_EXIT_CODE);
+    });
+
+    match tune::fastest(&trials) {
+        Some(tuning) => match tuning.write(&args.tuning) {
+            Ok(()) => println!("Fastest: {}; written to {:?}.", tuning, args.tuning),
+            Err(err) => println!("Could not write the tuning to {:?}: {}", args.tuning, err),
+        },
+        None => println!("No configuration was tried."),
+    }
+}
+
+/// Compare every engine over the input, exiting with a failure if any of them disagrees.
+async fn run_compare(args: &CliArgs, options: &compare::CompareArgs) {
+    println!(
+        "Comparing the engines over {} with {:?} threads...",
+        args.file, options.thread_counts
+    );
+
+    let trials = compare::compare(&args.run_options(), options, |trial| {
+        println!(
+            "- {} with {} threads: {:?}",
+            trial.contender, trial.threads, trial.elapsed
+        )
+    })
+    .await
+    .unwrap_or_else(|err| {
+        println!("The comparison failed: {}", err);
+        std::process::exit(config::FAILURE_EXIT_CODE);
+    });
+
+    let table = compare::markdown_table(&trials);
+    println!("\n{}", table);
+
+    if let Some(path) = &options.table {
+        match std::fs::write(path, &table) {
+            Ok(()) => println!("Table written to {:?}.", path),
+            Err(err) => println!("Could not write the table to {:?}: {}", path, err),
+        }
+    }
+
+    if trials.iter().any(|trial| !trial.matches) {
+        println!("Some engines disagree with the first one.");
+        std::process::exit(config::MISMATCH_EXIT_CODE);
+    }
+}
+
+/// Aggregate the input per station and time window, and export the windows as CSV.
+fn run_windows(args: &CliArgs, options: &parser::windows::WindowArgs) {
+    println!(
+        "Aggregating {} per {} with {} threads...",
+        args.file, options.window, args.threads
+    );
+
+    let start = std::time::Instant::now();
+    let records = parser::windows::read_file(&args.file, options.window, args.threads)
+        .unwrap_or_else(|err| {
+            println!("Could not read {:?}: {}", args.file, err);
+            std::process::exit(config::FAILURE_EXIT_CODE);
+        });
+    println!(
+        "Aggregated {} windows of {} stations in {:?}.",
+        records.windows(),
+        records.stations(),
+        start.elapsed()
+    );
+
+    match records.export_csv(&options.csv) {
+        Ok(()) => println!("Windows written to {:?}.", options.csv),
+        Err(err) => {
+            println!("Could not write the windows to {:?}: {}", options.csv, err);
+            std::process::exit(config::FAILURE_EXIT_CODE);
+        }
+    }
+}
+
+/// Benchmark the pipeline, then compare and record the results as requested.
+async fn run_bench(args: &CliArgs, options: &bench::BenchArgs) {
+    println!(
+        "Benchmarking {} passes over {} with {} threads, chunk size {}...",
+        options.repeats, args.file, args.threads, args.chunk_size
+    );
+
+    let recording = bench::measure(&args.run_options(), options.repeats, || {
+        reader::cache::prepare(&args.file, args.drop_caches, args.prewarm)
+    })
+    .await
+    .unwrap_or_else(|err| {
+        println!("The benchmark failed: {}", err);
+        std::process::exit(err.exit_code());
+    });
+    println!("Fastest: {}.", recording);
+
+    let comparison = if options.compare {
+        let history = bench::read_history(&options.history).unwrap_or_else(|err| {
+            println!("Could not read the history {:?}: {}", options.history, err);
+            std::process::exit(config::FAILURE_EXIT_CODE);
+        });
+
+        match bench::previous(&history, &recording) {
+            Some(previous) => {
+                let comparison = bench::Comparison::new(previous, &recording, options.threshold);
+
+                println!("Previous: {}.", previous);
+                if previous.configuration() != recording.configuration() {
+                    println!(
+                        "The configurations differ: {} before, {} now.",
+                        previous.configuration(),
+                        recording.configuration()
+                    );
+                }
+                println!("Change: {}.", comparison);
+
+                Some(comparison)
+            }
+            None => {
+                println!("No previous recording of {} to compare against.", args.file);
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    if options.record {
+        match bench::append_history(&options.history, &recording) {
+            Ok(()) => println!("Recorded to {:?}.", options.history),
+            Err(err) => println!("Could not record to {:?}: {}", options.history, err),
+        }
+    }
+
+    if comparison.is_some_and(|comparison| comparison.regression) {
+        println!(
+            "The throughput regressed by more than {}%.",
+            options.threshold
+        );
+        std::process::exit(config::REGRESSION_EXIT_CODE);
+    }
+}
+
+/// Answer the requests of the coordinators, until interrupted.
+async fn run_worker(args: &CliArgs, options: &distributed::WorkerArgs) {
+    let listener = tokio::net::TcpListener::bind(&options.listen)
+        .await
+        .unwrap_or_else(|err| {
+            println!("Could not listen on {}: {}", options.listen, err);
+            std::process::exit(config::FAILURE_EXIT_CODE);
+        });
+    let root = options
+        .root
+        .clone()
+        .unwrap_or_else(|| args.file.clone().into());
+    println!(
+        "Serving shards of {} on {} with {} threads, chunk size {}...",
+        root.display(),
+        options.listen,
+        args.threads,
+        args.chunk_size
+    );
+
+    let served =
+        distributed::serve_worker(listener, &root, args.run_options(), |request, result| {
+            match result {
+                Ok(report) => println!(
+                    "- Shard {} of {} of {}: {} bytes in {:?} at {:.1} MB/s",
+                    request.shard + 1,
+                    request.shards,
+                    request.path.display(),
+                    report.bytes_read,
+                    report.elapsed,
+                    report.throughput() / 1e6
+                ),
+                Err(err) => println!(
+                    "- Shard {} of {} of {} failed: {}",
+                    request.shard + 1,
+                    request.shards,
+                    request.path.display(),
+                    err
+                ),
+            }
+        })
+        .await;
+
+    if let Err(err) = served {
+        println!("Stopped serving on {}: {}", options.listen, err);
+        std::process::exit(config::FAILURE_EXIT_CODE);
+    }
+}
+
+/// Aggregate the input on the workers, reporting the throughput of each.
+async fn run_coordinator(args: &CliArgs, options: &distributed::CoordinatorArgs) {
+    println!(
+        "Aggregating {} on {} workers...",
+        args.file,
+        options.workers.len()
+    );
+
+    let report = distributed::coordinate(&options.workers, &args.file, &args.run_options())
+        .await
+        .unwrap_or_else(|err| {
+            println!("The distributed run failed: {}", err);
+            std::process::exit(match &err {
+                distributed::DistributedError::Export(err) => err.exit_code(),
+                _ => config::FAILURE_EXIT_CODE,
+            });
+        });
+
+    for worker in &report.workers {
+        println!(
+            "- {}: {} bytes in {:?} at {:.1} MB/s",
+            worker.worker,
+            worker.report.bytes_read,
+            worker.report.elapsed,
+            worker.report.throughput() / 1e6
+        );
+    }
+    println!(
+        "Aggregated {} bytes in {:?} at {:.1} MB/s to {:?}.",
+        report.total.bytes_read,
+        report.total.elapsed,
+        report.total.throughput() / 1e6,
+        args.output
+    );
+    if report.total.partial {
+        println!("Some shards were not read completely; the results are partial.");
+    }
+}
+
+/// Aggregate the input again whenever it changes, until interrupted.
+#[cfg(feature = "watch")]
+async fn run_watch(args: &CliArgs) {
+    if batch::is_batch(&args.file) {
+        println!("Cannot watch several inputs: {}", args.file);
+        std::process::exit(config::FAILURE_EXIT_CODE);
+    }
+
+    let mut watcher = Watcher::new(&args.file).unwrap_or_else(|err| {
+        println!("Could not watch {}: {}", args.file, err);
+        std::process::exit(config::FAILURE_EXIT_CODE);
+    });
+    let mut session = WatchSession::new(args.run_options());
+
+    for pass in 1.. {
+        match session.run().await {
+            Ok(report) => println!(
+                "Run #{}: {} bytes in {:?} at {:.1} MB/s, {} stations exported to {:?}.",
+                pass,
+                report.bytes_read,
+                report.elapsed,
+                report.throughput() / 1e6,
+                report.records.stations(),
+                args.output
+            ),
+            // A half-written input fails the run, which is retried on the next change.
+            Err(err) => println!("Run #{} failed: {}", pass, err),
+        }
+
+        println!("Watching {} for changes...", args.file);
+        if let Err(err) = watcher.changed().await {
+            println!("Could not watch {} any more: {}", args.file, err);
+            std::process::exit(config::FAILURE_EXIT_CODE);
+        }
+    }
+}
+
+/// Aggregate every file named by the input, reporting the throughput of each.
+async fn run_batch(args: &CliArgs) {
+    let files = batch::resolve_inputs(&args.file).unwrap_or_else(|err| {
+        println!("Could not list the inputs {:?}: {}", args.file, err);
+        std::process::exit(config::FAILURE_EXIT_CODE);
+    });
+
+    let options = args.batch_options(files);
+    println!(
+        "Aggregating {} inputs, {} at a time with {} threads each...",
+        options.files.len(),
+        options.concurrent_files,
+        options.threads_per_file()
+    );
+
+    let report = batch::run_batch(options).await.unwrap_or_else(|err| {
+        println!("The batch failed: {}", err);
+        std::process::exit(match &err {
+            batch::BatchError::File { error, .. } | batch::BatchError::Export(error) => {
+                error.exit_code()
+            }
+            batch::BatchError::Unsupported(_) | batch::BatchError::Outputs { .. } => {
+                config::FAILURE_EXIT_CODE
+            }
+        });
+    });
+
+    for file in &report.files {
+        println!(
+            "- {}: {} bytes in {:?} at {:.1} MB/s{}",
+            file.file.display(),
+            file.report.bytes_read,
+            file.report.elapsed,
+            file.report.throughput() / 1e6,
+            if file.report.partial {
+                " (partial)"
+            } else {
+                ""
+            }
+        );
+    }
+    println!(
+        "Aggregated {} bytes in {:?} at {:.1} MB/s to {:?}.",
+        report.total.bytes_read,
+        report.total.elapsed,
+        report.total.throughput() / 1e6,
+        args.output
+    );
+    if report.total.partial {
+        println!("Some inputs were not read completely; the results are partial.");
+    }
+}
+
+/// What watches the reader of the run while it goes on, started by the hook of the run once
+/// the reader is created.
+#[derive(Default)]
+struct Observers {
+    /// The reader of the run, kept past its end for the final reports.
+    reader: Option<Arc<reader::RowsReader>>,
+
+    #[cfg(feature = "serve")]
+    server: Option<ResultsServer>,
+
+    timeline: Option<Timeline>,
+
+    #[cfg(feature = "metrics")]
+    metrics_task: Option<tokio::task::JoinHandle<()>>,
+}
+
+impl Observers {
+    /// Start watching `reader` as requested by `args`.
+    fn start(&mut self, reader: &Arc<reader::RowsReader>, args: &CliArgs) {
+        self.reader = Some(Arc::clone(reader));
+
+        #[cfg(feature = "serve")]
+        if let Some(server) = &self.server {
+            server.watch(Arc::clone(reader));
+        }
+
+        self.timeline = args.timeline.as_ref().map(|path| {
+            Timeline::start(
+                Arc::clone(reader),
+                path,
+                std::time::Duration::from_millis(args.timeline_interval),
+            )
+        });
+
+        #[cfg(feature = "metrics")]
+        {
+            self.metrics_task = args.metrics_file.as_ref().map(|path| {
+                tokio::spawn(metrics::export_periodically(
+                    Arc::clone(reader),
+                    path.clone(),
+                    std::time::Duration::from_millis(args.metrics_interval),
+                ))
+            });
+        }
+    }
+}
 
 #[tokio::main]
 async fn main() {
-    let args = CliArgs::parse();
+    #[cfg(feature = "console")]
+    console_subscriber::init();
+
+    let matches = MainArgs::command().get_matches();
+    let MainArgs { mut args, command } =
+        MainArgs::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+
+    if let Some(Command::Tune(options)) = command {
+        return run_tune(&args, &options).await;
+    }
+
+    // The input is read by the workers, with their own tuning.
+    if let Some(Command::Coordinator(options)) = &command {
+        return run_coordinator(&args, options).await;
+    }
+
+    let detected = args.storage.is_none();
+    let storage = args
+        .storage
+        .unwrap_or_else(|| reader::storage::detect(&args.file));
+
+    let mut overridden = Vec::new();
+    match args.apply_tuning(&matches) {
+        Some(Ok(tuning)) => println!("Loaded the tuning from {:?}: {}.", args.tuning, tuning),
+        Some(Err(err)) => println!("Could not load the tuning from {:?}: {}", args.tuning, err),
+        // The detection can be wrong, so it only tunes the run when asked to.
+        None if detected && !args.detect_storage => {}
+        None => overridden = args.apply_storage(&matches, storage),
+    }
+
+    let storage = match (detected, overridden.is_empty()) {
+        (true, true) => format!("{} (detected)", storage),
+        (true, false) => format!(
+            "{} (detected), overriding {}",
+            storage,
+            overridden.join(", ")
+        ),
+        (false, true) => storage.to_string(),
+        (false, false) => format!("{}, overriding {}", storage, overridden.join(", ")),
+    };
+
+    // The benchmark runs with the tuning applied, as a normal run would.
+    if let Some(Command::Bench(options)) = command {
+        return run_bench(&args, &options).await;
+    }
+
+    if let Some(Command::Compare(options)) = command {
+        return run_compare(&args, &options).await;
+    }
+
+    if let Some(Command::Windows(options)) = command {
+        return run_windows(&args, &options);
+    }
+
+    if let Some(Command::ServeWorker(options)) = command {
+        return run_worker(&args, &options).await;
+    }
+
+    #[cfg(feature = "watch")]
+    if args.watch {
+        return run_watch(&args).await;
+    }
+
+    if batch::is_batch(&args.file) {
+        return run_batch(&args).await;
+    }
 
     println!(
         "Parameters:\n\
@@ -19,87 +519,260 @@ async fn main() {
         - Output: {}\n\
         - Threads: {}\n\
         - Chunk size: {}\n\
-        - Max chunk size: {}\n",
-        args.file, args.output, args.threads, args.chunk_size, args.max_chunk_size
+        - Max chunk size: {}\n\
+        - Max name length: {}{}\n\
+        - Engine: {}\n\
+        - Storage: {}\n\
+        - Isolated reader: {}\n\
+        - On error: {} (after {} retries)\n\
+        - Features: {}\n",
+        args.file,
+        args.output,
+        args.threads,
+        args.chunk_size,
+        args.max_chunk_size,
+        args.max_name_length,
+        if args.strict { " (strict)" } else { "" },
+        args.engine,
+        storage,
+        args.isolated_reader,
+        args.on_error,
+        args.read_retries,
+        features::describe()
     );
 
+    #[cfg(feature = "gpu")]
+    if args.engine == parser::engine::ParserEngine::Gpu {
+        match parser::gpu::adapter_name() {
+            Some(name) => println!("Parsing on the GPU: {}.", name),
+            None => println!("No GPU found; parsing on the CPU instead."),
+        }
+    }
+
+    #[cfg(feature = "serve")]
+    let server = match &args.serve {
+        Some(address) => match ResultsServer::bind(address).await {
+            Ok(server) => {
+                println!(
+                    "Serving the metrics and results on http://{}.",
+                    server.address()
+                );
+                Some(server)
+            }
+            Err(err) => {
+                println!("Could not serve on {}: {}", address, err);
+                std::process::exit(config::FAILURE_EXIT_CODE);
+            }
+        },
+        None => None,
+    };
+
     #[cfg(feature = "debug")]
     println!("Starting the reader coroutine.");
 
+    if args.timed || args.timings_output.is_some() {
+        timed::enable();
+    }
+
+    #[cfg(feature = "mem-stats")]
+    if args.mem_stats {
+        mem_stats::enable();
+    }
+
+    reader::cache::prepare(&args.file, args.drop_caches, args.prewarm);
+
+    let observers = Arc::new(Mutex::new(Observers {
+        #[cfg(feature = "serve")]
+        server,
+        ..Default::default()
+    }));
+
+    let mut options = args.run_options().with_reader_hook({
+        let (observers, args) = (Arc::clone(&observers), args.clone());
+        move |reader| observers.lock().unwrap().start(reader, &args)
+    });
+    if args.stats_only {
+        options.output = None;
+    }
+
+    #[cfg(feature = "pprof")]
+    let profiler = args.profile.as_ref().map(CpuProfiler::start);
+
     #[cfg(feature = "bench")]
     let start = Instant::now();
 
-    let reader = Arc::new(
-        reader::RowsReader::with_chunk_sizes(args.chunk_size, args.max_chunk_size)
-            .with_additional_buffers(8),
-    );
+    let report = match async_1brc::run(options).await {
+        Ok(report) => report,
+        Err(err) => {
+            println!("{}", err);
+            if let Some(report) = err.report() {
+                println!(
+                    "The run stopped after {} bytes and {} records in {:?}.",
+                    report.bytes_read, report.records_parsed, report.elapsed
+                );
+            }
+            std::process::exit(err.exit_code());
+        }
+    };
+    let records = &report.records;
 
-    let (_, records) = tokio::join!(
-        async {
-            let file = tokio::fs::File::open(&args.file).await.unwrap();
-            let buffer = tokio::io::BufReader::with_capacity(args.chunk_size, file);
+    if report.resumed_from > 0 {
+        println!("Resumed from offset {}.", report.resumed_from);
+    }
 
-            reader.read(buffer).await
-        },
-        parser::task::read_from_reader(Arc::clone(&reader), args.threads, args.max_chunk_size),
-    );
+    if let Some(fraction) = report.sampled {
+        println!(
+            "Sampled {:.2}% of the input, scaling the counts up; the results are approximate.",
+            fraction * 100.0
+        );
+    }
 
-    records.export_file(&args.output).await;
+    if args.stats_only {
+        print!("{}", records.dataset_stats());
+    } else if let Some(stations) = report.verified {
+        println!("The output matches the {} stations.", stations);
+    }
+
+    if report.read_retries > 0 || report.partial {
+        println!(
+            "Recovered from the errors of the input: {} reads retried, {} chunks skipped.",
+            report.read_retries, report.chunks_skipped
+        );
+    }
+    if report.partial {
+        println!("The input was not read completely; the results are partial.");
+    }
+    if let Some(path) = &args.rejects {
+        println!(
+            "Rejected {} invalid lines to {:?}.",
+            report.lines_rejected, path
+        );
+    }
+    if let Some(path) = args.checkpoint.as_ref().or(args.resume.as_ref()) {
+        println!("Saved {} checkpoints to {:?}.", report.checkpoints, path);
+    }
+    if args.spill().is_some() {
+        println!("Spilled the records {} times.", report.spills);
+    }
+
+    if let Some(path) = &args.save_snapshot {
+        match records.write_snapshot(path) {
+            Ok(()) => println!("Snapshot written to {:?}.", path),
+            Err(err) => println!("Could not write the snapshot to {:?}: {}", path, err),
+        }
+    }
 
     #[cfg(feature = "bench")]
     println!("Elapsed time: {:?}", start.elapsed());
 
-    #[cfg(feature = "timed")]
-    '_timed: {
-        println!("Reporting the total time spent in the operations...");
-        if let Some(ops) = reader::READER_READ_TIMED.get() {
-            ops.report()
-        }
-        if let Some(ops) = reader::READER_LINE_TIMED.get() {
-            ops.report()
-        }
-        if let Some(ops) = reader::READER_LOCK_TIMED.get() {
-            ops.report()
+    // The buffers are still held by the reader kept by the observers at this point.
+    #[cfg(feature = "hugepages")]
+    reader::huge_pages::report();
+
+    let observers = std::mem::take(&mut *observers.lock().unwrap());
+
+    if let (Some(timeline), Some(path)) = (observers.timeline, &args.timeline) {
+        match timeline.finish() {
+            Ok(()) => println!("Timeline written to {:?}.", path),
+            Err(err) => println!("Could not write the timeline to {:?}: {}", path, err),
         }
-        if let Some(ops) = reader::func::CLONE_BUFFER_TIMED.get() {
-            ops.report()
+    }
+
+    #[cfg(feature = "metrics")]
+    if let (Some(task), Some(reader), Some(path)) = (
+        observers.metrics_task,
+        &observers.reader,
+        &args.metrics_file,
+    ) {
+        task.abort();
+
+        // Write a final snapshot, so that the file reflects the completed run.
+        match metrics::write(reader, path) {
+            Ok(()) => println!("Metrics written to {:?}.", path),
+            Err(err) => println!("Could not write the metrics to {:?}: {}", path, err),
         }
-        if let Some(ops) = reader::func::MEM_SWAP_TIMED.get() {
-            ops.report()
+    }
+
+    #[cfg(feature = "serve")]
+    if let Some(server) = &observers.server {
+        server.publish(records);
+    }
+
+    #[cfg(feature = "pprof")]
+    if let Some(profiler) = profiler {
+        profiler.finish();
+    }
+
+    #[cfg(feature = "mem-stats")]
+    if args.mem_stats {
+        mem_stats::report();
+    }
+
+    if timed::is_enabled() {
+        println!("Reporting the total time spent in the operations...");
+        timed::report_all();
+
+        if let Some(path) = &args.timings_output {
+            timed::export::export(path, timed::registered());
+            println!("Timings written to {:?}.", path);
         }
-        #[cfg(feature = "timed-extreme")]
-        {
-            if let Some(ops) = parser::line::PARSE_NAME_TIMED.get() {
-                ops.report()
-            }
-            if let Some(ops) = parser::line::PARSE_VALUE_TIMED.get() {
-                ops.report()
-            }
-            if let Some(ops) = parser::models::HASH_INSERT_TIMED.get() {
-                ops.report()
-            }
+    }
+
+    #[cfg(all(feature = "assert", feature = "sync"))]
+    if args.cross_check {
+        println!("Cross-checking the records against the reference parser...");
+
+        if let Err(report) = assertion::cross_check(records, &args.file, args.threads) {
+            println!("{}", report);
+            std::process::exit(config::MISMATCH_EXIT_CODE);
         }
+
+        println!("The records match those of the reference parser.");
     }
 
     #[cfg(feature = "assert")]
-    '_assertion: {
-        if cfg!(any(
-            feature = "noparse",
-            feature = "noparse-name",
-            feature = "noparse-value"
-        )) {
-            println!("Cannot perform assertions when parsing is partially/fully disabled. Assertion aborted.");
-            return;
+    'assertion: {
+        if args.stats_only && args.snapshot.is_none() {
+            println!("Cannot perform assertions in stats-only mode as no output was exported. Assertion aborted.");
+            break 'assertion;
         }
 
-        println!("Checking the number of records...");
-        let output_len = records.len();
-        println!("The number of records: {}", output_len);
-        assert_eq!(output_len, 1_000_000_000);
+        // The spilled records are only merged into the output, so they cannot be counted.
+        if args.spill().is_none() {
+            println!("Checking the number of records...");
+            let output_len = records.len();
+            println!("The number of records: {}", output_len);
+            assert_eq!(output_len, 1_000_000_000);
+        }
+
+        let matched = if let Some(snapshot) = &args.snapshot {
+            println!("Matching the records and the snapshot...");
+            assertion::match_snapshot(records, snapshot)
+        } else {
+            println!("Matching the output and the baseline files...");
+            match args.assert_tolerance {
+                Some(tolerance) => {
+                    assertion::match_files_with_tolerance(&args.output, &args.baseline, tolerance)
+                        .await
+                }
+                None => assertion::match_files(&args.output, &args.baseline).await,
+            }
+        };
 
-        println!("Matching the output and the baseline files...");
-        assertion::match_files(&args.output, &args.baseline).await;
+        if let Err(report) = matched {
+            println!("{}", report);
+            std::process::exit(config::MISMATCH_EXIT_CODE);
+        }
 
         println!("All assertions passed.")
     }
+
+    #[cfg(feature = "serve")]
+    if let Some(server) = observers.server {
+        println!(
+            "Serving the results on http://{} until interrupted.",
+            server.address()
+        );
+        server.serve_forever().await;
+    }
 }
@@ -1,4 +1,4 @@
-//! A simple implementation using [`memmap::Mmap`] as well as [`rayon::iter::ParallelIterator`]
+//! A simple implementation using [`MmapReader`] as well as [`rayon::iter::ParallelIterator`]
 //! to read the file and parse the records in parallel.
 //!
 //! The file is sliced into given number of chunks, equal to the number of threads, then
@@ -10,42 +10,75 @@
 //! usage, and have limited scalability. This also does not support a streaming input
 //! as the async implementation does.
 use clap::Parser;
+
+#[cfg(feature = "bench")]
 use std::time::Instant;
 
-use async_1brc::{parser::models::StationRecords, reader::sync::*, CliArgs};
+use async_1brc::{features, prelude::*, reader, CliArgs};
 
 #[cfg(feature = "assert")]
-use async_1brc::assertion;
+use async_1brc::{assertion, config};
+
+#[cfg(feature = "pprof")]
+use async_1brc::cpu_profile::CpuProfiler;
 
 fn main() {
     let args = CliArgs::parse();
 
     println!(
         "Parameters:\n\
-        - File: {}",
-        args.file
+        - File: {}\n\
+        - Features: {}",
+        args.file,
+        features::describe()
     );
 
+    reader::cache::prepare(&args.file, args.drop_caches, args.prewarm);
+
+    #[cfg(feature = "pprof")]
+    let profiler = args.profile.as_ref().map(CpuProfiler::start);
+
     #[cfg(feature = "bench")]
     let start = Instant::now();
 
-    let reader = MmapReader::from_path(&args.file).with_chunks(args.threads);
+    let mut reader = MmapReader::from_path(&args.file).with_chunks(args.threads);
+    if let Err(err) = reader.advise(args.readahead) {
+        println!("Could not advise the kernel to read ahead: {}", err);
+    }
+
+    #[cfg(feature = "hugepages")]
+    let reader = reader.with_huge_pages();
 
     let records = StationRecords::read_from_iterator(reader.iter::<b'\n'>());
 
-    records.export_file_blocking(&args.output);
+    if args.stats_only {
+        print!("{}", records.dataset_stats());
+    } else {
+        records.export_file_blocking(&args.output);
+    }
+
+    if let Some(path) = &args.save_snapshot {
+        match records.write_snapshot(path) {
+            Ok(()) => println!("Snapshot written to {:?}.", path),
+            Err(err) => println!("Could not write the snapshot to {:?}: {}", path, err),
+        }
+    }
 
     #[cfg(feature = "bench")]
     println!("elapsed time: {:?}", start.elapsed());
 
+    #[cfg(feature = "hugepages")]
+    reader::huge_pages::report();
+
+    #[cfg(feature = "pprof")]
+    if let Some(profiler) = profiler {
+        profiler.finish();
+    }
+
     #[cfg(feature = "assert")]
     '_assertion: {
-        if cfg!(any(
-            feature = "noparse",
-            feature = "noparse-name",
-            feature = "noparse-value"
-        )) {
-            println!("Cannot perform assertions when parsing is partially/fully disabled. Assertion aborted.");
+        if args.stats_only && args.snapshot.is_none() {
+            println!("Cannot perform assertions in stats-only mode as no output was exported. Assertion aborted.");
             return;
         }
 
@@ -54,8 +87,25 @@ fn main() {
         println!("The number of records: {}", output_len);
         assert_eq!(output_len, 1_000_000_000);
 
-        println!("Matching the output and the baseline files...");
-        assertion::match_files_blocking(&args.output, &args.baseline);
+        let matched = if let Some(snapshot) = &args.snapshot {
+            println!("Matching the records and the snapshot...");
+            assertion::match_snapshot(&records, snapshot)
+        } else {
+            println!("Matching the output and the baseline files...");
+            match args.assert_tolerance {
+                Some(tolerance) => assertion::match_files_with_tolerance_blocking(
+                    &args.output,
+                    &args.baseline,
+                    tolerance,
+                ),
+                None => assertion::match_files_blocking(&args.output, &args.baseline),
+            }
+        };
+
+        if let Err(report) = matched {
+            println!("{}", report);
+            std::process::exit(config::MISMATCH_EXIT_CODE);
+        }
 
         println!("All assertions passed.")
     }
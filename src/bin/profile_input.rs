@@ -0,0 +1,47 @@
+//! Profile a measurements file before benchmarking against it.
+//!
+//! The file is memory-mapped and sliced into chunks using [`MmapReader`], then each chunk is
+//! scanned in parallel for separators, validating every line against the 1BRC grammar. The
+//! resulting [`InputProfile`] reports the line count, the number of distinct stations, a
+//! histogram of the station name lengths, the range of values, and the byte offsets of any
+//! invalid lines.
+use clap::Parser;
+use rayon::prelude::*;
+use std::time::Instant;
+
+use async_1brc::{parser::profile::InputProfile, reader::MmapReader, CliArgs};
+
+fn main() {
+    let args = CliArgs::parse();
+
+    println!(
+        "Parameters:\n\
+        - File: {}\n\
+        - Threads: {}\n",
+        args.file, args.threads
+    );
+
+    let start = Instant::now();
+
+    let reader = MmapReader::from_path(&args.file).with_chunks(args.threads);
+
+    let profile = reader
+        .iter::<b'\n'>()
+        .scan(0, |offset, chunk| {
+            let chunk_offset = *offset;
+            *offset += chunk.len();
+            Some((chunk_offset, chunk))
+        })
+        .par_bridge()
+        .map(|(offset, chunk)| InputProfile::from_bytes(&chunk, offset))
+        .reduce(InputProfile::new, |profile, chunk_profile| {
+            profile + chunk_profile
+        });
+
+    print!("{}", profile);
+    println!("Elapsed time: {:?}", start.elapsed());
+
+    if !profile.is_valid() {
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,64 @@
+//! Convert a measurements file into an Arrow IPC stream, keeping every record.
+//!
+//! Instead of aggregating the stations, the consumers batch the parsed records into Arrow
+//! record batches, which are written to the output path by a blocking writer.
+use std::sync::Arc;
+use std::time::Instant;
+
+use clap::Parser;
+
+use async_1brc::{config, parser::batches, reader, CliArgs};
+
+/// The number of batches buffered between the consumers and the writer.
+const CHANNEL_CAPACITY: usize = 16;
+
+#[tokio::main]
+async fn main() {
+    let args = CliArgs::parse();
+
+    println!(
+        "Parameters:\n\
+        - File: {}\n\
+        - Output: {}\n\
+        - Threads: {}\n\
+        - Batch size: {}\n",
+        args.file, args.output, args.threads, args.arrow_batch_size
+    );
+
+    let start = Instant::now();
+
+    let reader = Arc::new(
+        reader::RowsReader::with_chunk_sizes(args.chunk_size, args.max_chunk_size)
+            .with_additional_buffers(config::ADDITIONAL_BUFFERS),
+    );
+    let (sender, receiver) = tokio::sync::mpsc::channel(CHANNEL_CAPACITY);
+
+    let output = args.output.clone();
+    let writer = tokio::task::spawn_blocking(move || batches::write_ipc_stream(output, receiver));
+
+    let file = tokio::fs::File::open(&args.file)
+        .await
+        .expect("Failed to open the file.");
+    let bufreader = tokio::io::BufReader::with_capacity(args.chunk_size, file);
+
+    let (read, parsed) = tokio::join!(
+        reader.read(bufreader),
+        batches::batches_from_reader(
+            Arc::clone(&reader),
+            args.threads,
+            args.max_chunk_size,
+            args.arrow_batch_size,
+            sender,
+        ),
+    );
+
+    read.expect("Failed to read the file.");
+    let written = writer
+        .await
+        .unwrap()
+        .expect("Failed to write the Arrow stream.");
+
+    println!("Records parsed: {}", parsed);
+    println!("Rows written to {}: {}", args.output, written);
+    println!("Elapsed time: {:?}", start.elapsed());
+}
@@ -0,0 +1,37 @@
+//! Validate that a measurements file is well-formed before running against it.
+//!
+//! The parsers used in the hot paths assume a perfect input, and behave unpredictably when
+//! a line does not match the 1BRC grammar. This streams the whole file through
+//! [`InputProfile::from_reader`] without memory-mapping it, and reports the byte offsets of
+//! any invalid lines, exiting with a non-zero code if there are any.
+use clap::Parser;
+use std::time::Instant;
+
+use async_1brc::{parser::profile::InputProfile, CliArgs};
+
+fn main() {
+    let args = CliArgs::parse();
+
+    println!(
+        "Parameters:\n\
+        - File: {}\n\
+        - Chunk size: {}\n",
+        args.file, args.chunk_size
+    );
+
+    let start = Instant::now();
+
+    let file = std::fs::File::open(&args.file).expect("Failed to open the file.");
+    let profile =
+        InputProfile::from_reader(file, args.chunk_size).expect("Failed to read the file.");
+
+    print!("{}", profile);
+    println!("Elapsed time: {:?}", start.elapsed());
+
+    if profile.is_valid() {
+        println!("The input is well-formed.");
+    } else {
+        println!("The input is NOT well-formed.");
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,287 @@
+//! Compare every engine available in this build over the same input.
+//!
+//! `main compare` runs each [`Contender`] with every number of threads of the grid, verifies
+//! that they all aggregate the same records as the first one, and prints a Markdown table of
+//! the fastest time and throughput of each:
+//!
+//! ```bash
+//! cargo run --release --features sync --bin main -- --file=measurements.txt compare
+//! ```
+//!
+//! This takes the place of running `main` and `mmap_baseline` one after the other by hand, and
+//! diffing their outputs.
+
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use crate::config;
+use crate::parser::engine::ParserEngine;
+use crate::parser::models::StationRecords;
+use crate::parser::sync;
+use crate::RunOptions;
+
+/// The options of the `compare` subcommand of `main`.
+#[derive(clap::Args, Debug, Clone)]
+pub struct CompareArgs {
+    /// The numbers of threads to run every engine with, separated by commas.
+    #[arg(long, value_delimiter = ',', default_values_t = config::COMPARE_THREADS)]
+    pub thread_counts: Vec<usize>,
+
+    /// The number of passes of each engine and number of threads, keeping the fastest.
+    #[arg(long, default_value_t = config::COMPARE_REPEATS)]
+    pub repeats: usize,
+
+    /// Also write the Markdown table to this path.
+    #[arg(long)]
+    pub table: Option<PathBuf>,
+}
+
+/// A way of aggregating the input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Contender {
+    /// The async reader and consumers of [`run`](crate::run), parsing with the engine.
+    Async(ParserEngine),
+
+    /// The whole input read into memory, split at the lines into one slice per thread, and
+    /// parsed by [`sync::parse_bytes`] on scoped threads.
+    SyncSplit,
+
+    /// The memory-mapped reader with a rayon pool, as in the `mmap_baseline` binary.
+    #[cfg(feature = "sync")]
+    MmapRayon,
+}
+
+impl std::fmt::Display for Contender {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Async(engine) => write!(f, "async {}", engine),
+            Self::SyncSplit => write!(f, "sync split"),
+            #[cfg(feature = "sync")]
+            Self::MmapRayon => write!(f, "mmap rayon"),
+        }
+    }
+}
+
+/// Every contender available in this build, the async engines first.
+pub fn contenders() -> Vec<Contender> {
+    use clap::ValueEnum;
+
+    let mut contenders: Vec<_> = ParserEngine::value_variants()
+        .iter()
+        .copied()
+        .map(Contender::Async)
+        .collect();
+
+    contenders.push(Contender::SyncSplit);
+    #[cfg(feature = "sync")]
+    contenders.push(Contender::MmapRayon);
+
+    contenders
+}
+
+impl Contender {
+    /// Aggregate the input of `options` with `threads` threads, without exporting it.
+    pub async fn run(self, options: &RunOptions, threads: usize) -> io::Result<StationRecords> {
+        let path = options.file.clone();
+
+        match self {
+            Self::Async(engine) => {
+                let options = options.clone().with_threads(threads).with_engine(engine);
+                Ok(crate::run(options).await?.records)
+            }
+            Self::SyncSplit => blocking(move || parse_split(&path, threads)).await,
+            #[cfg(feature = "sync")]
+            Self::MmapRayon => blocking(move || parse_mapped(&path, threads)).await,
+        }
+    }
+}
+
+/// Run a blocking contender off the runtime.
+async fn blocking(
+    run: impl FnOnce() -> io::Result<StationRecords> + Send + 'static,
+) -> io::Result<StationRecords> {
+    tokio::task::spawn_blocking(run)
+        .await
+        .unwrap_or_else(|error| Err(io::Error::other(error)))
+}
+
+/// Read the file at `path` into memory, and parse it on `threads` scoped threads.
+fn parse_split(path: &Path, threads: usize) -> io::Result<StationRecords> {
+    let bytes = std::fs::read(path)?;
+
+    Ok(std::thread::scope(|scope| {
+        let handles: Vec<_> = split_at_lines(&bytes, threads)
+            .map(|slice| {
+                scope.spawn(move || {
+                    let mut records = StationRecords::new();
+                    sync::parse_bytes(slice, &mut records);
+                    records
+                })
+            })
+            .collect();
+
+        handles
+            .into_iter()
+            .map(|handle| handle.join().expect("a parsing thread panicked"))
+            .fold(StationRecords::new(), |total, records| total + records)
+    }))
+}
+
+/// Split `bytes` into up to `parts` slices of about the same length, each ending at a line.
+fn split_at_lines(bytes: &[u8], parts: usize) -> impl Iterator<Item = &[u8]> {
+    let parts = parts.max(1);
+    let mut start = 0;
+
+    (1..=parts).filter_map(move |part| {
+        let target = (bytes.len() * part / parts).max(start);
+        let end = match memchr::memchr(b'\n', &bytes[target..]) {
+            Some(newline) if part < parts => target + newline + 1,
+            _ => bytes.len(),
+        };
+
+        let slice = &bytes[start..end];
+        start = end;
+        (!slice.is_empty()).then_some(slice)
+    })
+}
+
+/// Parse the file at `path` through [`MmapReader`](crate::reader::MmapReader) chunks on a
+/// rayon pool of `threads` threads.
+#[cfg(feature = "sync")]
+fn parse_mapped(path: &Path, threads: usize) -> io::Result<StationRecords> {
+    let reader = crate::reader::MmapReader::open(path)?.with_chunks(threads);
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(threads)
+        .build()
+        .map_err(io::Error::other)?;
+
+    Ok(pool.install(|| StationRecords::read_from_iterator(reader.iter::<b'\n'>())))
+}
+
+/// The fastest pass of a contender with a number of threads.
+#[derive(Debug, Clone, Copy)]
+pub struct Trial {
+    pub contender: Contender,
+    pub threads: usize,
+    pub elapsed: Duration,
+    /// The size of the input.
+    pub bytes: u64,
+    /// Whether the records are the same as those of the first trial.
+    pub matches: bool,
+}
+
+impl Trial {
+    /// The bytes of input aggregated per second.
+    pub fn throughput(&self) -> f64 {
+        self.bytes as f64 / self.elapsed.as_secs_f64()
+    }
+}
+
+/// Run every contender with every number of threads of `args` over the input of `options`,
+/// returning the fastest pass of each in the order tried; `on_trial` is called after each,
+/// e.g. to report the progress.
+///
+/// The records of every trial are compared to those of the first one. The output of
+/// `options` is ignored, and nothing is exported.
+pub async fn compare(
+    options: &RunOptions,
+    args: &CompareArgs,
+    mut on_trial: impl FnMut(&Trial),
+) -> io::Result<Vec<Trial>> {
+    let mut options = options.clone();
+    options.output = None;
+
+    let bytes = std::fs::metadata(&options.file)?.len();
+    let mut reference = None;
+    let mut trials = Vec::new();
+
+    for contender in contenders() {
+        for &threads in &args.thread_counts {
+            let mut elapsed = Duration::MAX;
+            let mut records = StationRecords::new();
+
+            for _ in 0..args.repeats.max(1) {
+                let start = Instant::now();
+                records = contender.run(&options, threads).await?;
+                elapsed = elapsed.min(start.elapsed());
+            }
+
+            let trial = Trial {
+                contender,
+                threads,
+                elapsed,
+                bytes,
+                matches: *reference.get_or_insert_with(|| records.clone()) == records,
+            };
+            on_trial(&trial);
+            trials.push(trial);
+        }
+    }
+
+    Ok(trials)
+}
+
+/// Render the trials as a Markdown table.
+pub fn markdown_table(trials: &[Trial]) -> String {
+    let mut table = "| Engine | Threads | Time | Throughput | Matches |\n\
+        | --- | ---: | ---: | ---: | :---: |\n"
+        .to_owned();
+
+    for trial in trials {
+        table += &format!(
+            "| {} | {} | {:.3} s | {:.1} MB/s | {} |\n",
+            trial.contender,
+            trial.threads,
+            trial.elapsed.as_secs_f64(),
+            trial.throughput() / 1e6,
+            if trial.matches { "yes" } else { "**no**" }
+        );
+    }
+
+    table
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::TestDir;
+
+    #[test]
+    fn split_into_lines() {
+        let bytes = b"a;1.0\nbb;2.0\nccc;3.0\n";
+
+        let slices: Vec<_> = split_at_lines(bytes, 2).collect();
+        assert_eq!(slices, [&b"a;1.0\nbb;2.0\n"[..], &b"ccc;3.0\n"[..]]);
+
+        // More parts than lines leave no empty slice.
+        assert_eq!(split_at_lines(bytes, 10).count(), 3);
+        assert_eq!(
+            split_at_lines(bytes, 10).collect::<Vec<_>>().concat(),
+            bytes
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn contenders_agree() {
+        let dir = TestDir::new("contenders_agree");
+        let path = dir.join("input.txt");
+        let input: String = (0..2000)
+            .map(|line| format!("station{};{}.{}\n", line % 17, line % 40 - 20, line % 10))
+            .collect();
+        std::fs::write(&path, input).unwrap();
+
+        let args = CompareArgs {
+            thread_counts: vec![1, 3],
+            repeats: 1,
+            table: None,
+        };
+        let options = RunOptions::new(&path).with_chunk_sizes(256, 256 + config::MAX_LINE_LENGTH);
+        let trials = compare(&options, &args, |_| {}).await;
+
+        let trials = trials.unwrap();
+        assert_eq!(trials.len(), contenders().len() * 2);
+        assert!(trials.iter().all(|trial| trial.matches));
+        assert_eq!(markdown_table(&trials).lines().count(), trials.len() + 2);
+    }
+}
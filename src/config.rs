@@ -1,16 +1,142 @@
 //! Configuration for the reader.
 
-pub const MAX_LINE_LENGTH: usize = 30;
+/// The maximum length of a station name in bytes allowed by the 1BRC rules.
+pub const MAX_NAME_LENGTH: usize = 100;
+
+/// The length of the longest value of a 1BRC line, i.e. `-99.9`.
+pub const MAX_VALUE_LENGTH: usize = 5;
+
+/// The maximum length of a line with a name of up to `max_name_length` bytes, including its
+/// `;` and newline.
+pub const fn max_line_length(max_name_length: usize) -> usize {
+    max_name_length + 1 + MAX_VALUE_LENGTH + 1
+}
+
+/// The maximum length of a line allowed by the 1BRC rules, kept spare in each chunk for the
+/// end of its last line.
+pub const MAX_LINE_LENGTH: usize = max_line_length(MAX_NAME_LENGTH);
 
 pub const CHUNK_SIZE: usize = 65536 * 8; // Max buffer capacity 2097152 - higher does not change anything.
 
 pub const MAX_CHUNK_SIZE: usize = CHUNK_SIZE * 16 + MAX_LINE_LENGTH;
 
+/// The size of the windows of the file memory-mapped at a time by the blocking reader, small
+/// enough to fit in the address space of 32-bit targets.
+pub const MMAP_WINDOW_SIZE: usize = 1 << 30;
+
+/// The bytes of input read between two checkpoints of a run.
+pub const CHECKPOINT_INTERVAL: u64 = 1 << 30;
+
+/// The number of shards the records of the consumers are merged in, in parallel; with a
+/// single shard, they are merged one consumer after the other.
+pub const MERGE_SHARDS: usize = 1;
+
 pub const NUMBER_OF_THREADS: usize = 8;
 
+/// The address `main serve-worker` listens for the coordinator on by default.
+pub const WORKER_ADDRESS: &str = "127.0.0.1:7878";
+
+/// How long `main serve-worker` waits for a coordinator to send its request before dropping
+/// the connection.
+pub const WORKER_REQUEST_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// The number of buffers allocated upfront in the input queue of the reader.
+pub const ADDITIONAL_BUFFERS: usize = 8;
+
+/// The number of slots of the rings handing the chunks and the spent buffers between the
+/// reader and the consumers; rounded up to a power of two.
+pub const RING_CAPACITY: usize = 64;
+
+/// The number of retries of a failed read of the input.
+pub const READ_RETRIES: u32 = 3;
+
+/// The wait before the first retry of a failed read, in milliseconds; doubled after every
+/// attempt.
+pub const READ_RETRY_BACKOFF_MS: u64 = 50;
+
+/// The default input: on the RAM disk created by `make ramdisk_macos` on macOS, in the shared
+/// memory of `/dev/shm` on Linux, or in the `1brc` repository next to this one elsewhere.
+#[cfg(target_os = "macos")]
 pub const MEASURMENTS_PATH: &str = "/Volumes/RAMDisk/measurements.txt";
+#[cfg(target_os = "linux")]
+pub const MEASURMENTS_PATH: &str = "/dev/shm/measurements.txt";
+#[cfg(not(any(target_os = "macos", target_os = "linux")))]
+pub const MEASURMENTS_PATH: &str = "../1brc/measurements.txt";
 
 pub const OUTPUT_PATH: &str = "data/output.txt";
 
+/// The file of the configuration found by `main tune`, loaded by later runs if it exists.
+pub const TUNING_PATH: &str = "data/tuning.json";
+
+/// The default number of bytes at the start of the input calibrated with by `main tune`.
+pub const TUNE_PREFIX_SIZE: usize = 256 << 20;
+
+/// The default chunk sizes tried by `main tune`.
+pub const TUNE_CHUNK_SIZES: [usize; 6] = [1 << 16, 1 << 17, 1 << 18, 1 << 19, 1 << 20, 1 << 21];
+
+/// The default numbers of threads tried by `main tune`.
+pub const TUNE_THREADS: [usize; 4] = [2, 4, 8, 16];
+
+/// The default number of passes of each configuration tried by `main tune`.
+pub const TUNE_REPEATS: usize = 3;
+
+/// The default numbers of threads every engine is run with by `main compare`.
+pub const COMPARE_THREADS: [usize; 4] = [1, 2, 4, 8];
+
+/// The default number of passes of each engine and number of threads run by `main compare`.
+pub const COMPARE_REPEATS: usize = 1;
+
+/// The default CSV file the windows of `main windows` are exported to.
+pub const WINDOWS_OUTPUT_PATH: &str = "data/windows.csv";
+
+/// The history file of the recordings of `main bench --record`, one JSON object per line.
+pub const BENCH_HISTORY_PATH: &str = "data/bench_history.jsonl";
+
+/// The number of passes of `main bench`, keeping the fastest.
+pub const BENCH_REPEATS: usize = 3;
+
+/// The drop of the throughput in percent beyond which `main bench --compare` fails.
+pub const BENCH_REGRESSION_THRESHOLD: f64 = 5.0;
+
 #[cfg(feature = "assert")]
 pub const BASELINE_PATH: &str = "../1brc/out_expected.txt";
+
+/// The exit code of the binaries when the run fails, e.g. the input cannot be read.
+pub const FAILURE_EXIT_CODE: i32 = 1;
+
+/// The exit code of the binaries when the output does not match the baseline, or when the
+/// engines compared by `main compare` disagree.
+pub const MISMATCH_EXIT_CODE: i32 = 2;
+
+/// The exit code of `main bench --compare` when the throughput regressed.
+pub const REGRESSION_EXIT_CODE: i32 = 3;
+
+/// The size of the chunks hashed when comparing the output and the baseline by checksum.
+#[cfg(feature = "assert")]
+pub const CHECKSUM_CHUNK_SIZE: usize = 1 << 20;
+
+/// The default number of rows in each Arrow record batch.
+#[cfg(feature = "arrow")]
+pub const ARROW_BATCH_SIZE: usize = 8192;
+
+/// The maximum number of invalid line offsets kept for reporting by the input profiler.
+pub const MAX_REPORTED_INVALID_LINES: usize = 100;
+
+/// The sampling frequency of the CPU profiler, in Hz.
+#[cfg(feature = "pprof")]
+pub const PROFILE_FREQUENCY: i32 = 997;
+
+/// How long the directory of the input must be quiet after a change before `--watch` runs
+/// again, in milliseconds.
+#[cfg(feature = "watch")]
+pub const WATCH_DEBOUNCE_MS: u64 = 200;
+
+/// The default interval between two writes of the metrics file, in milliseconds.
+#[cfg(feature = "metrics")]
+pub const METRICS_INTERVAL_MS: u64 = 1000;
+
+/// The number of slowest calls kept by the timed operations of the reader.
+pub const TIMED_OUTLIERS: usize = 5;
+
+/// The default interval between two samples of the timeline, in milliseconds.
+pub const TIMELINE_INTERVAL_MS: u64 = 100;
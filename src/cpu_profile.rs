@@ -0,0 +1,76 @@
+//! Sample the CPU usage of a run using [`pprof`].
+
+use std::path::{Path, PathBuf};
+
+use pprof::protos::Message;
+
+use crate::config;
+
+/// Libraries excluded from the samples, as unwinding through them is unreliable.
+const BLOCKLIST: &[&str] = &["libc", "libgcc", "pthread", "vdso"];
+
+/// A running CPU profiler, writing its report to a file upon [`CpuProfiler::finish`].
+///
+/// The format of the report is decided by the extension of the path: `.svg` produces a
+/// flamegraph, anything else produces a `pprof` protobuf, which can be opened with
+/// `go tool pprof` or similar.
+pub struct CpuProfiler {
+    guard: pprof::ProfilerGuard<'static>,
+    path: PathBuf,
+}
+
+impl CpuProfiler {
+    /// Start sampling the CPU usage of all threads in the process.
+    pub fn start(path: impl AsRef<Path>) -> Self {
+        let guard = pprof::ProfilerGuardBuilder::default()
+            .frequency(config::PROFILE_FREQUENCY)
+            .blocklist(BLOCKLIST)
+            .build()
+            .expect("Failed to start the CPU profiler.");
+
+        Self {
+            guard,
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+
+    /// Whether the report will be written as a flamegraph.
+    pub fn is_flamegraph(&self) -> bool {
+        self.path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("svg"))
+    }
+
+    /// Stop sampling, and write the report to the path given at [`CpuProfiler::start`].
+    pub fn finish(self) {
+        let report = self
+            .guard
+            .report()
+            .build()
+            .expect("Failed to build the CPU profile report.");
+
+        let file = std::fs::File::create(&self.path)
+            .unwrap_or_else(|_| panic!("Could not create the profile at {:?}.", self.path));
+
+        if self.is_flamegraph() {
+            report
+                .flamegraph(file)
+                .expect("Failed to write the flamegraph.");
+        } else {
+            use std::io::Write;
+
+            let mut content = Vec::new();
+            report
+                .pprof()
+                .expect("Failed to convert the report to pprof format.")
+                .encode(&mut content)
+                .expect("Failed to encode the pprof profile.");
+
+            std::io::BufWriter::new(file)
+                .write_all(&content)
+                .expect("Failed to write the pprof profile.");
+        }
+
+        println!("CPU profile written to {:?}.", self.path);
+    }
+}
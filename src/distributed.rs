@@ -0,0 +1,566 @@
+//! Aggregate an input across several machines over TCP.
+//!
+//! `main serve-worker` listens for a coordinator, and aggregates the shards it is asked for
+//! with its own options, e.g. its own number of threads and chunk sizes. `main coordinator`
+//! splits the input into one shard per worker, asks each worker for its shard of the file at
+//! the same path on its machine, and merges the records sent back before exporting them.
+//!
+//! The protocol is not authenticated: a worker only serves the shards of its own input, a file
+//! or the files under a directory, and should only listen on a trusted network.
+//!
+//! A shard is a range of bytes of the file, which the worker works out from the length of the
+//! file: shard `i` of `n` starts at the first line starting at or after `len * i / n`, and
+//! ends where shard `i + 1` starts, so that every line belongs to exactly one shard.
+//!
+//! The requests and responses are little-endian throughout:
+//!
+//! ```text
+//! request:
+//!     magic: [u8; 8] = b"1BRCWORK"
+//!     version: u32
+//!     shard: u32
+//!     shards: u32
+//!     path length: u32
+//!     path: [u8; path length], in UTF-8
+//!
+//! response:
+//!     status: u8, 0 if the shard was aggregated
+//!     if aggregated:
+//!         bytes read: u64
+//!         chunks: u64
+//!         records parsed: u64
+//!         lines rejected: u64
+//!         partial: u8
+//!         elapsed: u64, in nanoseconds
+//!         snapshot length: u64
+//!         snapshot: the records as written by `StationRecords::encode_snapshot`
+//!     otherwise:
+//!         message length: u32
+//!         message: [u8; message length], in UTF-8
+//! ```
+
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+use crate::batch::accumulate;
+use crate::config;
+use crate::parser::models::StationRecords;
+use crate::run::export;
+use crate::{run_from, RunError, RunOptions, RunReport};
+
+/// The bytes at the start of every request.
+pub const REQUEST_MAGIC: &[u8; 8] = b"1BRCWORK";
+
+/// The version of the protocol.
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// The longest path a request may carry, so that a bogus length is not allocated.
+const MAX_PATH_LENGTH: u32 = 1 << 16;
+
+/// The longest error message a response may carry, so that a bogus length is not allocated.
+const MAX_MESSAGE_LENGTH: u32 = 1 << 16;
+
+/// The options of the `serve-worker` subcommand of `main`.
+#[derive(clap::Args, Debug, Clone)]
+pub struct WorkerArgs {
+    /// The address to listen for the coordinator on.
+    #[arg(long, default_value_t = config::WORKER_ADDRESS.to_owned())]
+    pub listen: String,
+
+    /// The file, or the directory of the files, the coordinators may ask for shards of; the
+    /// `--file` of the worker by default.
+    #[arg(long)]
+    pub root: Option<PathBuf>,
+}
+
+/// The options of the `coordinator` subcommand of `main`.
+#[derive(clap::Args, Debug, Clone)]
+pub struct CoordinatorArgs {
+    /// The addresses of the workers, separated by commas; the input is split into one shard
+    /// per worker.
+    #[arg(long, value_delimiter = ',', required = true)]
+    pub workers: Vec<String>,
+}
+
+/// A request for a shard of an input.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShardRequest {
+    /// The path of the input on the worker.
+    pub path: PathBuf,
+
+    /// The index of the shard.
+    pub shard: u32,
+
+    /// The number of shards the input is split into.
+    pub shards: u32,
+}
+
+impl ShardRequest {
+    /// The range of bytes of the shard in an input of `len` bytes, before it is aligned to
+    /// the lines.
+    pub fn range(&self, len: u64) -> (u64, u64) {
+        let offset = |shard: u32| (len as u128 * shard as u128 / self.shards.max(1) as u128) as u64;
+
+        (offset(self.shard), offset(self.shard + 1))
+    }
+
+    /// Send the request to `writer`.
+    pub async fn write_to(&self, writer: &mut (impl AsyncWrite + Unpin)) -> io::Result<()> {
+        let path = self
+            .path
+            .to_str()
+            .ok_or_else(|| invalid(format!("The path {:?} is not valid UTF-8.", self.path)))?;
+
+        writer.write_all(REQUEST_MAGIC).await?;
+        writer.write_u32_le(PROTOCOL_VERSION).await?;
+        writer.write_u32_le(self.shard).await?;
+        writer.write_u32_le(self.shards).await?;
+        writer.write_u32_le(path.len() as u32).await?;
+        writer.write_all(path.as_bytes()).await?;
+        writer.flush().await
+    }
+
+    /// Receive a request from `reader`.
+    pub async fn read_from(reader: &mut (impl AsyncRead + Unpin)) -> io::Result<Self> {
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic).await?;
+        if &magic != REQUEST_MAGIC {
+            return Err(invalid("Not a request for a shard."));
+        }
+
+        let version = reader.read_u32_le().await?;
+        if version != PROTOCOL_VERSION {
+            return Err(invalid(format!(
+                "Unsupported protocol version {}, expected {}.",
+                version, PROTOCOL_VERSION
+            )));
+        }
+
+        let (shard, shards) = (reader.read_u32_le().await?, reader.read_u32_le().await?);
+        if shard >= shards {
+            return Err(invalid(format!("No shard {} of {}.", shard, shards)));
+        }
+
+        let path_len = reader.read_u32_le().await?;
+        if path_len > MAX_PATH_LENGTH {
+            return Err(invalid(format!("Path of {} bytes is too long.", path_len)));
+        }
+        let mut path = vec![0; path_len as usize];
+        reader.read_exact(&mut path).await?;
+        let path = String::from_utf8(path).map_err(|_| invalid("The path is not valid UTF-8."))?;
+
+        Ok(Self {
+            path: path.into(),
+            shard,
+            shards,
+        })
+    }
+}
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+/// The start of the first line starting at or after `offset` in `file` of `len` bytes.
+pub fn align_to_line(file: &mut std::fs::File, offset: u64, len: u64) -> io::Result<u64> {
+    if offset == 0 || offset >= len {
+        return Ok(offset.min(len));
+    }
+
+    // A line starts at `offset` itself if the byte before it ends a line.
+    file.seek(SeekFrom::Start(offset - 1))?;
+    let mut position = offset - 1;
+    let mut buffer = [0; 4096];
+
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            return Ok(len);
+        }
+
+        if let Some(newline) = memchr::memchr(b'\n', &buffer[..read]) {
+            return Ok(position + newline as u64 + 1);
+        }
+        position += read as u64;
+    }
+}
+
+/// The canonical path of the input of `request`, if it is `root` or a file under it, which
+/// must be canonical itself.
+pub fn authorize(request: &ShardRequest, root: &Path) -> io::Result<PathBuf> {
+    let path = request.path.canonicalize()?;
+    if !path.starts_with(root) {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("{} is not served by this worker.", request.path.display()),
+        ));
+    }
+
+    Ok(path)
+}
+
+/// Aggregate the shard of `request` with `options`, without exporting it.
+pub async fn aggregate_shard(
+    request: &ShardRequest,
+    mut options: RunOptions,
+) -> Result<RunReport, RunError> {
+    // The coordinator exports the merged records instead.
+    options.output = None;
+
+    let mut file = std::fs::File::open(&request.path).map_err(RunError::Open)?;
+    let range = file.metadata().and_then(|metadata| {
+        let len = metadata.len();
+        let (start, end) = request.range(len);
+        let (start, end) = (
+            align_to_line(&mut file, start, len)?,
+            align_to_line(&mut file, end, len)?,
+        );
+
+        file.seek(SeekFrom::Start(start))?;
+        Ok(end - start)
+    });
+    let range = range.map_err(RunError::Open)?;
+
+    let input = tokio::fs::File::from_std(file).take(range);
+    run_from(
+        tokio::io::BufReader::with_capacity(options.chunk_size, input),
+        options,
+    )
+    .await
+}
+
+/// Send the result of a shard to `writer`.
+async fn write_response(
+    writer: &mut (impl AsyncWrite + Unpin),
+    result: &Result<RunReport, RunError>,
+) -> io::Result<()> {
+    match result {
+        Ok(report) => {
+            let mut snapshot = Vec::new();
+            report.records.encode_snapshot(&mut snapshot)?;
+
+            writer.write_u8(0).await?;
+            writer.write_u64_le(report.bytes_read).await?;
+            writer.write_u64_le(report.chunks as u64).await?;
+            writer.write_u64_le(report.records_parsed).await?;
+            writer.write_u64_le(report.lines_rejected).await?;
+            writer.write_u8(report.partial as u8).await?;
+            writer
+                .write_u64_le(report.elapsed.as_nanos() as u64)
+                .await?;
+            writer.write_u64_le(snapshot.len() as u64).await?;
+            writer.write_all(&snapshot).await?;
+        }
+        Err(error) => {
+            let message = error.to_string();
+
+            writer.write_u8(1).await?;
+            writer.write_u32_le(message.len() as u32).await?;
+            writer.write_all(message.as_bytes()).await?;
+        }
+    }
+
+    writer.flush().await
+}
+
+/// Receive the result of a shard from `reader`; the error of the worker is returned as
+/// [`io::ErrorKind::Other`].
+async fn read_response(reader: &mut (impl AsyncRead + Unpin)) -> io::Result<RunReport> {
+    if reader.read_u8().await? != 0 {
+        let message_len = reader.read_u32_le().await?;
+        if message_len > MAX_MESSAGE_LENGTH {
+            return Err(invalid(format!(
+                "Error message of {} bytes is too long.",
+                message_len
+            )));
+        }
+        let mut message = vec![0; message_len as usize];
+        reader.read_exact(&mut message).await?;
+        return Err(io::Error::other(String::from_utf8_lossy(&message)));
+    }
+
+    let bytes_read = reader.read_u64_le().await?;
+    let chunks = reader.read_u64_le().await? as usize;
+    let records_parsed = reader.read_u64_le().await?;
+    let lines_rejected = reader.read_u64_le().await?;
+    let partial = reader.read_u8().await? != 0;
+    let elapsed = Duration::from_nanos(reader.read_u64_le().await?);
+
+    let mut snapshot = Vec::new();
+    let snapshot_len = reader.read_u64_le().await?;
+    reader.take(snapshot_len).read_to_end(&mut snapshot).await?;
+    let records = StationRecords::decode_snapshot(&mut snapshot.as_slice())?;
+
+    Ok(RunReport {
+        records,
+        elapsed,
+        bytes_read,
+        chunks,
+        records_parsed,
+        lines_rejected,
+        partial,
+        ..Default::default()
+    })
+}
+
+/// Answer the requests for shards coming to `listener`, each on its own task, aggregating them
+/// with `options`, forever; `on_request` is called with each request and its result, e.g. to
+/// report them.
+///
+/// Only the shards of `root`, a file or the files under a directory, are aggregated; the
+/// requests for other paths are answered with an error. A request that is not received within
+/// [`config::WORKER_REQUEST_TIMEOUT`], or cannot be read or answered, is dropped, and the
+/// worker carries on.
+pub async fn serve_worker(
+    listener: TcpListener,
+    root: impl AsRef<Path>,
+    options: RunOptions,
+    on_request: impl Fn(&ShardRequest, &Result<RunReport, RunError>) + Send + Sync + 'static,
+) -> io::Result<()> {
+    let root: Arc<Path> = root.as_ref().canonicalize()?.into();
+    let on_request = Arc::new(on_request);
+
+    loop {
+        let (mut stream, _) = listener.accept().await?;
+        let (root, options, on_request) = (root.clone(), options.clone(), on_request.clone());
+
+        tokio::spawn(async move {
+            let request = tokio::time::timeout(
+                config::WORKER_REQUEST_TIMEOUT,
+                ShardRequest::read_from(&mut stream),
+            );
+            let Ok(Ok(request)) = request.await else {
+                return;
+            };
+
+            let result = match authorize(&request, &root) {
+                Ok(path) => {
+                    let request = ShardRequest {
+                        path,
+                        ..request.clone()
+                    };
+                    aggregate_shard(&request, options).await
+                }
+                Err(error) => Err(RunError::Open(error)),
+            };
+            on_request(&request, &result);
+
+            let _ = write_response(&mut stream, &result).await;
+        });
+    }
+}
+
+/// Ask the worker at `address` for the shard of `request`.
+pub async fn request_shard(address: &str, request: &ShardRequest) -> io::Result<RunReport> {
+    let mut stream = TcpStream::connect(address).await?;
+    request.write_to(&mut stream).await?;
+
+    read_response(&mut stream).await
+}
+
+/// The report of the shard aggregated by one of the workers.
+#[derive(Debug, Clone)]
+pub struct WorkerReport {
+    /// The address of the worker.
+    pub worker: String,
+
+    /// The report of its shard, as sent back by the worker; its records are merged into the
+    /// aggregate results, and left empty.
+    pub report: RunReport,
+}
+
+/// The summary of an input aggregated by the workers.
+#[derive(Debug, Clone, Default)]
+pub struct DistributedReport {
+    /// The merged records, the sums of the counters of the workers, and the time taken by the
+    /// whole run as seen by the coordinator.
+    pub total: RunReport,
+
+    /// The report of each worker, in the order of the shards.
+    pub workers: Vec<WorkerReport>,
+}
+
+/// Why an input could not be aggregated by the workers.
+#[derive(Debug)]
+pub enum DistributedError {
+    /// A worker could not be reached, or failed to aggregate its shard.
+    Worker { worker: String, error: io::Error },
+
+    /// The merged results could not be exported, or do not match.
+    Export(RunError),
+}
+
+impl std::fmt::Display for DistributedError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Worker { worker, error } => write!(f, "Worker {}: {}", worker, error),
+            Self::Export(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for DistributedError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Worker { error, .. } => Some(error),
+            Self::Export(error) => Some(error),
+        }
+    }
+}
+
+impl From<DistributedError> for io::Error {
+    fn from(error: DistributedError) -> Self {
+        match error {
+            DistributedError::Worker { error, .. } => error,
+            DistributedError::Export(error) => error.into(),
+        }
+    }
+}
+
+/// Split the input at `path` on the `workers` into one shard each, and merge their records,
+/// exporting them to the output of `options` if any.
+pub async fn coordinate(
+    workers: &[String],
+    path: impl AsRef<Path>,
+    options: &RunOptions,
+) -> Result<DistributedReport, DistributedError> {
+    let start = Instant::now();
+    let shards = workers.len() as u32;
+
+    let handles: Vec<_> = workers
+        .iter()
+        .enumerate()
+        .map(|(shard, worker)| {
+            let request = ShardRequest {
+                path: path.as_ref().to_owned(),
+                shard: shard as u32,
+                shards,
+            };
+            let worker = worker.clone();
+
+            tokio::spawn(async move { request_shard(&worker, &request).await })
+        })
+        .collect();
+
+    let mut report = DistributedReport::default();
+    let mut failure = None;
+
+    for (worker, handle) in workers.iter().zip(handles) {
+        match handle
+            .await
+            .unwrap_or_else(|error| Err(io::Error::other(error)))
+        {
+            Ok(mut worker_report) => {
+                accumulate(&mut report.total, &mut worker_report);
+                report.workers.push(WorkerReport {
+                    worker: worker.clone(),
+                    report: worker_report,
+                });
+            }
+            // The first failure in the order of the shards is reported.
+            Err(error) if failure.is_none() => {
+                failure = Some(DistributedError::Worker {
+                    worker: worker.clone(),
+                    error,
+                });
+            }
+            Err(_) => {}
+        }
+    }
+
+    if let Some(failure) = failure {
+        return Err(failure);
+    }
+
+    let total = export(Box::new(report.total), None, options)
+        .await
+        .map_err(DistributedError::Export)?;
+    report.total = *total;
+    report.total.elapsed = start.elapsed();
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::TestDir;
+
+    #[test]
+    fn align_shards_to_lines() {
+        let dir = TestDir::new("align_shards_to_lines");
+        let path = dir.join("input.txt");
+        std::fs::write(&path, "jack;1.2\njill;-3.4\njack;5.6\n").unwrap();
+        let mut file = std::fs::File::open(&path).unwrap();
+
+        let aligned = [0, 1, 8, 9, 10, 19, 28, 40]
+            .map(|offset| align_to_line(&mut file, offset, 28).unwrap());
+
+        assert_eq!(aligned, [0, 9, 9, 9, 19, 19, 28, 28]);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn coordinate_workers() {
+        let dir = TestDir::new("coordinate_workers");
+        let (input, output) = (dir.join("input.txt"), dir.join("output.txt"));
+        let bytes: String = (0..3000)
+            .map(|line| format!("station{};{}.{}\n", line % 13, line % 50, line % 10))
+            .collect();
+        std::fs::write(&input, &bytes).unwrap();
+
+        let options = RunOptions::new(&input)
+            .with_threads(2)
+            .with_chunk_sizes(256, 256 + config::MAX_LINE_LENGTH);
+        let expected = crate::run(options.clone()).await.unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let worker = tokio::spawn(serve_worker(
+            listener,
+            dir.as_ref().to_owned(),
+            options.clone(),
+            |_, _| {},
+        ));
+
+        // A client that never sends its request does not hold up the others.
+        let _silent = TcpStream::connect(&address).await.unwrap();
+
+        // The same worker answers every shard.
+        let workers = vec![address.clone(); 3];
+        let report = coordinate(&workers, &input, &options.with_output(&output))
+            .await
+            .unwrap();
+        let exported = std::fs::read_to_string(&output).unwrap();
+
+        let missing = coordinate(
+            &workers[..1],
+            "/nonexistent/measurements.txt",
+            &RunOptions::new(""),
+        )
+        .await;
+        let outside_dir = TestDir::new("coordinate_workers_outside");
+        let outside = outside_dir.join("input.txt");
+        std::fs::write(&outside, &bytes).unwrap();
+        let forbidden = coordinate(&workers[..1], &outside, &RunOptions::new("")).await;
+        worker.abort();
+
+        assert_eq!(report.total.records, expected.records);
+        assert_eq!(exported, expected.records.export_text());
+        assert_eq!(report.total.bytes_read, bytes.len() as u64);
+        assert_eq!(report.total.records_parsed, 3000);
+        assert_eq!(report.workers.len(), 3);
+        assert!(matches!(
+            missing,
+            Err(DistributedError::Worker { error, .. }) if error.to_string().contains("open")
+        ));
+        assert!(matches!(
+            forbidden,
+            Err(DistributedError::Worker { error, .. }) if error.to_string().contains("not served")
+        ));
+    }
+}
@@ -0,0 +1,61 @@
+//! The Cargo features the crate was built with, for the binaries to report.
+
+/// Every feature of the crate, and whether it is enabled in this build.
+const FEATURES: &[(&str, bool)] = &[
+    ("runtime", cfg!(feature = "runtime")),
+    ("sync", cfg!(feature = "sync")),
+    ("arrow", cfg!(feature = "arrow")),
+    ("codec", cfg!(feature = "codec")),
+    ("ffi", cfg!(feature = "ffi")),
+    ("polars", cfg!(feature = "polars")),
+    ("wasm", cfg!(feature = "wasm")),
+    ("debug", cfg!(feature = "debug")),
+    ("bench", cfg!(feature = "bench")),
+    ("assert", cfg!(feature = "assert")),
+    ("timed", cfg!(feature = "timed")),
+    ("timed-extreme", cfg!(feature = "timed-extreme")),
+    ("nohash", cfg!(feature = "nohash")),
+    ("portable-hash", cfg!(feature = "portable-hash")),
+    ("raw-table", cfg!(feature = "raw-table")),
+    ("normalize", cfg!(feature = "normalize")),
+    ("filter", cfg!(feature = "filter")),
+    ("noparse-name", cfg!(feature = "noparse-name")),
+    ("noparse-value", cfg!(feature = "noparse-value")),
+    ("pprof", cfg!(feature = "pprof")),
+    ("hugepages", cfg!(feature = "hugepages")),
+    ("mem-stats", cfg!(feature = "mem-stats")),
+    ("metrics", cfg!(feature = "metrics")),
+    ("serve", cfg!(feature = "serve")),
+    ("watch", cfg!(feature = "watch")),
+    ("tar", cfg!(feature = "tar")),
+    ("gpu", cfg!(feature = "gpu")),
+    ("console", cfg!(feature = "console")),
+];
+
+/// The names of the features enabled in this build.
+pub fn enabled() -> Vec<&'static str> {
+    FEATURES
+        .iter()
+        .filter(|(_, enabled)| *enabled)
+        .map(|(name, _)| *name)
+        .collect()
+}
+
+/// Describe the enabled features as a comma-separated list, e.g. `runtime, assert`.
+pub fn describe() -> String {
+    match enabled().join(", ") {
+        features if features.is_empty() => "none".to_owned(),
+        features => features,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn enabled_features() {
+        assert_eq!(enabled().contains(&"runtime"), cfg!(feature = "runtime"));
+        assert!(!describe().is_empty());
+    }
+}
@@ -0,0 +1,290 @@
+//! A C interface to the aggregation core, for linking the parser into C/C++ harnesses.
+//!
+//! The interface is declared in `include/async_1brc.h`; build a library exposing it with:
+//!
+//! ```bash
+//! cargo rustc --release --lib --features ffi --crate-type staticlib
+//! ```
+//!
+//! An aggregator is fed chunks of input in any size, parsed with the blocking parser, then
+//! finalized into results sorted by station name:
+//!
+//! ```c
+//! onebrc_aggregator *aggregator = onebrc_aggregator_new();
+//! onebrc_aggregator_feed(aggregator, chunk, chunk_len);
+//! onebrc_results *results = onebrc_aggregator_finalize(aggregator);
+//!
+//! onebrc_station station;
+//! for (size_t i = 0; onebrc_results_get(results, i, &station); i++) { ... }
+//!
+//! onebrc_results_free(results);
+//! ```
+
+use std::panic::{catch_unwind, AssertUnwindSafe};
+
+use crate::parser::{
+    models::{StationRecords, StationStats},
+    sync, LiteHashBuffer,
+};
+
+/// The records being aggregated, and the incomplete line at the end of the last chunk.
+pub struct Aggregator {
+    records: StationRecords,
+    remainder: Vec<u8>,
+}
+
+impl Aggregator {
+    /// Parse the complete lines of `bytes`, keeping any incomplete line for the next call.
+    fn feed(&mut self, bytes: &[u8]) {
+        match bytes.iter().rposition(|&byte| byte == b'\n') {
+            Some(end) => {
+                if self.remainder.is_empty() {
+                    sync::parse_bytes(&bytes[..=end], &mut self.records);
+                } else {
+                    self.remainder.extend_from_slice(&bytes[..=end]);
+                    sync::parse_bytes(&self.remainder, &mut self.records);
+                    self.remainder.clear();
+                }
+                self.remainder.extend_from_slice(&bytes[end + 1..]);
+            }
+            None => self.remainder.extend_from_slice(bytes),
+        }
+    }
+
+    /// Parse the last line, even without a newline, and sort the stations by name.
+    fn finalize(mut self) -> Results {
+        sync::parse_bytes(&self.remainder, &mut self.records);
+
+        let mut stations = self.records.into_iter().collect::<Vec<_>>();
+        stations.sort_unstable_by(|(lhs, _), (rhs, _)| lhs.cmp(rhs));
+
+        Results { stations }
+    }
+}
+
+/// The statistics of a single station; the values are in tenths of a degree.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Station {
+    /// The name of the station, which is not null-terminated; valid until the results are
+    /// freed.
+    pub name: *const u8,
+    pub name_len: usize,
+    pub min: i16,
+    pub max: i16,
+    pub sum: i64,
+    pub count: u64,
+}
+
+/// The finalized results of an [`Aggregator`], sorted by station name.
+pub struct Results {
+    stations: Vec<(LiteHashBuffer, StationStats)>,
+}
+
+/// Create a new empty aggregator, to be freed by [`onebrc_aggregator_finalize`] or
+/// [`onebrc_aggregator_free`].
+#[no_mangle]
+pub extern "C" fn onebrc_aggregator_new() -> *mut Aggregator {
+    Box::into_raw(Box::new(Aggregator {
+        records: StationRecords::new(),
+        remainder: Vec::new(),
+    }))
+}
+
+/// Feed `len` bytes of 1BRC input to the aggregator. The bytes may end in the middle of a
+/// line, which is completed by the next call.
+///
+/// Returns `false` if the input is not in the 1BRC format, after which the state of the
+/// aggregator is unspecified.
+///
+/// # Safety
+///
+/// `aggregator` must have been created by [`onebrc_aggregator_new`] and not freed, and
+/// `bytes` must be valid for reads of `len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn onebrc_aggregator_feed(
+    aggregator: *mut Aggregator,
+    bytes: *const u8,
+    len: usize,
+) -> bool {
+    let (aggregator, bytes) = match (aggregator.as_mut(), bytes.is_null()) {
+        (Some(aggregator), false) => (aggregator, std::slice::from_raw_parts(bytes, len)),
+        (Some(_), true) if len == 0 => return true,
+        _ => return false,
+    };
+
+    // The parser panics on malformed lines, which must not unwind into C.
+    catch_unwind(AssertUnwindSafe(|| aggregator.feed(bytes))).is_ok()
+}
+
+/// Finalize the aggregator into its results, consuming the aggregator.
+///
+/// Returns a null pointer if the last line is not in the 1BRC format.
+///
+/// # Safety
+///
+/// `aggregator` must have been created by [`onebrc_aggregator_new`] and not freed; it must
+/// not be used after this call.
+#[no_mangle]
+pub unsafe extern "C" fn onebrc_aggregator_finalize(aggregator: *mut Aggregator) -> *mut Results {
+    if aggregator.is_null() {
+        return std::ptr::null_mut();
+    }
+
+    let aggregator = Box::from_raw(aggregator);
+
+    match catch_unwind(AssertUnwindSafe(|| aggregator.finalize())) {
+        Ok(results) => Box::into_raw(Box::new(results)),
+        Err(_) => std::ptr::null_mut(),
+    }
+}
+
+/// Free an aggregator without finalizing it.
+///
+/// # Safety
+///
+/// `aggregator` must be null, or have been created by [`onebrc_aggregator_new`] and not
+/// freed.
+#[no_mangle]
+pub unsafe extern "C" fn onebrc_aggregator_free(aggregator: *mut Aggregator) {
+    if !aggregator.is_null() {
+        drop(Box::from_raw(aggregator));
+    }
+}
+
+/// The number of stations in the results.
+///
+/// # Safety
+///
+/// `results` must have been returned by [`onebrc_aggregator_finalize`] and not freed.
+#[no_mangle]
+pub unsafe extern "C" fn onebrc_results_len(results: *const Results) -> usize {
+    results.as_ref().map_or(0, |results| results.stations.len())
+}
+
+/// Write the station at `index` to `station`, returning `false` if `index` is out of range.
+///
+/// # Safety
+///
+/// `results` must have been returned by [`onebrc_aggregator_finalize`] and not freed, and
+/// `station` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn onebrc_results_get(
+    results: *const Results,
+    index: usize,
+    station: *mut Station,
+) -> bool {
+    match (
+        results
+            .as_ref()
+            .and_then(|results| results.stations.get(index)),
+        station.is_null(),
+    ) {
+        (Some((name, stats)), false) => {
+            station.write(Station {
+                name: name.as_ptr(),
+                name_len: name.len(),
+                min: stats.min,
+                max: stats.max,
+                sum: stats.sum as i64,
+                count: stats.count as u64,
+            });
+            true
+        }
+        _ => false,
+    }
+}
+
+/// Free the results, invalidating the names of their stations.
+///
+/// # Safety
+///
+/// `results` must be null, or have been returned by [`onebrc_aggregator_finalize`] and not
+/// freed.
+#[no_mangle]
+pub unsafe extern "C" fn onebrc_results_free(results: *mut Results) {
+    if !results.is_null() {
+        drop(Box::from_raw(results));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Collect the results as `(name, min, max, sum, count)`.
+    unsafe fn collect(results: *const Results) -> Vec<(String, i16, i16, i64, u64)> {
+        let mut station = std::mem::MaybeUninit::<Station>::uninit();
+
+        (0..)
+            .map_while(|index| {
+                onebrc_results_get(results, index, station.as_mut_ptr()).then(|| {
+                    let station = station.assume_init();
+                    let name = std::slice::from_raw_parts(station.name, station.name_len);
+
+                    (
+                        String::from_utf8_lossy(name).into_owned(),
+                        station.min,
+                        station.max,
+                        station.sum,
+                        station.count,
+                    )
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn aggregate_chunks() {
+        let input = b"jack;1.2\njill;-3.4\njack;5.6\nbob;0.0";
+
+        unsafe {
+            let aggregator = onebrc_aggregator_new();
+
+            // Split the input in the middle of lines.
+            for chunk in input.chunks(5) {
+                assert!(onebrc_aggregator_feed(
+                    aggregator,
+                    chunk.as_ptr(),
+                    chunk.len()
+                ));
+            }
+
+            let results = onebrc_aggregator_finalize(aggregator);
+            assert_eq!(onebrc_results_len(results), 3);
+            assert_eq!(
+                collect(results),
+                vec![
+                    ("bob".to_owned(), 0, 0, 0, 1),
+                    ("jack".to_owned(), 12, 56, 68, 2),
+                    ("jill".to_owned(), -34, -34, -34, 1),
+                ]
+            );
+
+            onebrc_results_free(results);
+        }
+    }
+
+    #[test]
+    fn aggregate_malformed() {
+        let input = b"jack;1.2;3.4\n";
+
+        unsafe {
+            let aggregator = onebrc_aggregator_new();
+
+            assert!(!onebrc_aggregator_feed(
+                aggregator,
+                input.as_ptr(),
+                input.len()
+            ));
+            assert!(!onebrc_aggregator_feed(
+                std::ptr::null_mut(),
+                input.as_ptr(),
+                1
+            ));
+
+            onebrc_aggregator_free(aggregator);
+            assert!(onebrc_aggregator_finalize(std::ptr::null_mut()).is_null());
+        }
+    }
+}
@@ -1,12 +1,82 @@
+#[cfg(all(target_family = "wasm", not(feature = "nohash")))]
+compile_error!("GxHash is not available on WebAssembly; build with the `wasm` feature instead.");
+
+#[cfg(all(feature = "nohash", feature = "portable-hash"))]
+compile_error!(
+    "The `nohash` feature replaces the hasher selected by `portable-hash`; enable only one of them."
+);
+
+#[cfg(all(feature = "nohash", feature = "raw-table"))]
+compile_error!("The `raw-table` feature hashes the station names, which `nohash` does not; enable only one of them.");
+
+#[cfg(all(
+    feature = "assert",
+    any(
+        feature = "noparse",
+        feature = "noparse-name",
+        feature = "noparse-value"
+    )
+))]
+compile_error!("The output cannot be asserted when parsing is disabled by the `noparse` features.");
+
+#[cfg(all(feature = "console", not(tokio_unstable)))]
+compile_error!("The `console` feature requires `RUSTFLAGS=\"--cfg tokio_unstable\"`.");
+
+pub mod bench;
 pub mod config;
+pub mod features;
 pub mod parser;
+pub mod prelude;
+pub mod tune;
+
 pub mod reader;
 
+#[cfg(feature = "runtime")]
+pub mod timeline;
+
 mod args;
 pub use args::CliArgs;
 
+#[cfg(test)]
+mod testing;
+
+#[cfg(feature = "runtime")]
+mod run;
+#[cfg(feature = "runtime")]
+pub use run::{run, run_from, ReaderHook, RunError, RunOptions, RunReport};
+
+#[cfg(feature = "runtime")]
+pub mod batch;
+
+#[cfg(feature = "tar")]
+pub mod archive;
+
+#[cfg(feature = "runtime")]
+pub mod distributed;
+
+#[cfg(feature = "runtime")]
+pub mod compare;
+
 #[cfg(feature = "assert")]
 pub mod assertion;
 
-#[cfg(feature = "timed")]
+#[cfg(feature = "runtime")]
 pub mod timed;
+
+#[cfg(feature = "ffi")]
+pub mod ffi;
+
+#[cfg(feature = "pprof")]
+pub mod cpu_profile;
+
+#[cfg(feature = "mem-stats")]
+pub mod mem_stats;
+
+#[cfg(feature = "metrics")]
+pub mod metrics;
+
+#[cfg(feature = "serve")]
+pub mod serve;
+
+#[cfg(feature = "watch")]
+pub mod watch;
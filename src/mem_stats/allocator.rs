@@ -0,0 +1,212 @@
+//! A global allocator counting the allocations made by each [`Stage`].
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use super::Stage;
+
+/// Counters of a single [`Stage`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct StageStats {
+    /// The number of allocations made.
+    pub allocations: usize,
+    /// The total number of bytes allocated, not accounting for deallocations.
+    pub bytes: usize,
+}
+
+/// A snapshot of the counters of a [`CountingAllocator`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct MemStats {
+    /// The highest number of bytes in use at any point.
+    pub peak_bytes: usize,
+    /// The number of bytes currently in use.
+    pub current_bytes: usize,
+    /// The counters of each [`Stage`].
+    pub stages: [StageStats; Stage::COUNT],
+}
+
+impl MemStats {
+    /// Get the counters of a single [`Stage`].
+    pub fn stage(&self, stage: Stage) -> StageStats {
+        self.stages[stage.index()]
+    }
+
+    /// The total number of allocations across all stages.
+    pub fn total_allocations(&self) -> usize {
+        self.stages.iter().map(|stats| stats.allocations).sum()
+    }
+
+    /// The total number of bytes allocated across all stages.
+    pub fn total_bytes(&self) -> usize {
+        self.stages.iter().map(|stats| stats.bytes).sum()
+    }
+}
+
+/// A wrapper around the [`System`] allocator that counts allocations once enabled.
+///
+/// Reallocations are counted as an allocation of the new size.
+pub struct CountingAllocator {
+    enabled: AtomicBool,
+    current: AtomicUsize,
+    peak: AtomicUsize,
+    allocations: [AtomicUsize; Stage::COUNT],
+    bytes: [AtomicUsize; Stage::COUNT],
+}
+
+impl Default for CountingAllocator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CountingAllocator {
+    /// Create a new allocator, which does not count anything until enabled.
+    pub const fn new() -> Self {
+        Self {
+            enabled: AtomicBool::new(false),
+            current: AtomicUsize::new(0),
+            peak: AtomicUsize::new(0),
+            allocations: [const { AtomicUsize::new(0) }; Stage::COUNT],
+            bytes: [const { AtomicUsize::new(0) }; Stage::COUNT],
+        }
+    }
+
+    /// Start counting allocations.
+    pub fn enable(&self) {
+        self.enabled.store(true, Ordering::Relaxed)
+    }
+
+    /// Whether the allocator is counting allocations.
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Get a snapshot of the counters.
+    pub fn snapshot(&self) -> MemStats {
+        let mut stats = MemStats {
+            peak_bytes: self.peak.load(Ordering::Relaxed),
+            current_bytes: self.current.load(Ordering::Relaxed),
+            ..Default::default()
+        };
+
+        Stage::ALL.iter().for_each(|stage| {
+            stats.stages[stage.index()] = StageStats {
+                allocations: self.allocations[stage.index()].load(Ordering::Relaxed),
+                bytes: self.bytes[stage.index()].load(Ordering::Relaxed),
+            };
+        });
+
+        stats
+    }
+
+    /// Record an allocation of `size` bytes.
+    fn record_alloc(&self, size: usize) {
+        if !self.is_enabled() {
+            return;
+        }
+
+        let stage = Stage::current().index();
+        self.allocations[stage].fetch_add(1, Ordering::Relaxed);
+        self.bytes[stage].fetch_add(size, Ordering::Relaxed);
+
+        let current = self.current.fetch_add(size, Ordering::Relaxed) + size;
+        self.peak.fetch_max(current, Ordering::Relaxed);
+    }
+
+    /// Record a deallocation of `size` bytes.
+    fn record_dealloc(&self, size: usize) {
+        if !self.is_enabled() {
+            return;
+        }
+
+        // Memory allocated before the allocator was enabled may be freed afterwards.
+        let _ = self
+            .current
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |current| {
+                Some(current.saturating_sub(size))
+            });
+    }
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            self.record_alloc(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc_zeroed(layout);
+        if !ptr.is_null() {
+            self.record_alloc(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        self.record_dealloc(layout.size());
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = System.realloc(ptr, layout, new_size);
+        if !new_ptr.is_null() {
+            self.record_dealloc(layout.size());
+            self.record_alloc(new_size);
+        }
+        new_ptr
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn counting_allocator_disabled() {
+        let allocator = CountingAllocator::new();
+        allocator.record_alloc(100);
+
+        assert_eq!(allocator.snapshot(), MemStats::default());
+    }
+
+    #[test]
+    fn counting_allocator_stages() {
+        let allocator = CountingAllocator::new();
+        allocator.enable();
+
+        {
+            let _stage = Stage::Reader.enter();
+            allocator.record_alloc(100);
+            allocator.record_alloc(50);
+        }
+        allocator.record_dealloc(100);
+        {
+            let _stage = Stage::Parser.enter();
+            allocator.record_alloc(20);
+        }
+
+        let stats = allocator.snapshot();
+        assert_eq!(
+            stats.stage(Stage::Reader),
+            StageStats {
+                allocations: 2,
+                bytes: 150
+            }
+        );
+        assert_eq!(
+            stats.stage(Stage::Parser),
+            StageStats {
+                allocations: 1,
+                bytes: 20
+            }
+        );
+        assert_eq!(stats.stage(Stage::Other), StageStats::default());
+        assert_eq!(stats.peak_bytes, 150);
+        assert_eq!(stats.current_bytes, 70);
+        assert_eq!(stats.total_allocations(), 3);
+        assert_eq!(Stage::current(), Stage::Other);
+    }
+}
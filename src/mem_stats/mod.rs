@@ -0,0 +1,82 @@
+//! Memory usage statistics of the pipeline.
+//!
+//! When the `mem-stats` feature is enabled, a [`CountingAllocator`] is installed as the
+//! global allocator. Once [`enable`] is called, every allocation is attributed to the
+//! [`Stage`] of the pipeline that is running on the current thread, which can be set with
+//! [`Stage::enter`] for synchronous code, or [`in_stage`] for futures.
+
+mod allocator;
+pub use allocator::*;
+
+mod stage;
+pub use stage::*;
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator::new();
+
+/// Start counting allocations.
+pub fn enable() {
+    ALLOCATOR.enable()
+}
+
+/// Get the statistics collected by the global allocator so far.
+pub fn snapshot() -> MemStats {
+    ALLOCATOR.snapshot()
+}
+
+/// Get the peak resident set size of the process in bytes, if available on this platform.
+#[cfg(unix)]
+pub fn peak_rss() -> Option<usize> {
+    let mut usage = std::mem::MaybeUninit::<libc::rusage>::uninit();
+
+    // SAFETY: `getrusage` only writes to the provided struct.
+    let usage = unsafe {
+        if libc::getrusage(libc::RUSAGE_SELF, usage.as_mut_ptr()) != 0 {
+            return None;
+        }
+        usage.assume_init()
+    };
+
+    // Linux reports in kilobytes, macOS in bytes.
+    let multiplier = if cfg!(target_os = "macos") { 1 } else { 1024 };
+
+    Some(usage.ru_maxrss as usize * multiplier)
+}
+
+/// Get the peak resident set size of the process in bytes, if available on this platform.
+#[cfg(not(unix))]
+pub fn peak_rss() -> Option<usize> {
+    None
+}
+
+/// Print the memory statistics collected so far.
+pub fn report() {
+    let stats = snapshot();
+
+    println!("Memory statistics:");
+    match peak_rss() {
+        Some(rss) => println!("- Peak resident set size: {}", format_bytes(rss)),
+        None => println!("- Peak resident set size: N/A"),
+    }
+    println!("- Peak heap usage: {}", format_bytes(stats.peak_bytes));
+    println!(
+        "- Total allocations: {} ({})",
+        stats.total_allocations(),
+        format_bytes(stats.total_bytes())
+    );
+
+    for stage in Stage::ALL {
+        let stage_stats = stats.stage(stage);
+        println!(
+            "    {}: {} allocations ({})",
+            stage,
+            stage_stats.allocations,
+            format_bytes(stage_stats.bytes)
+        );
+    }
+}
+
+/// Format a number of bytes in MiB.
+fn format_bytes(bytes: usize) -> String {
+    format!("{:.2} MiB", bytes as f64 / 1024.0 / 1024.0)
+}
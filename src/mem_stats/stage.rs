@@ -0,0 +1,112 @@
+//! Attribute allocations to the stages of the pipeline.
+
+use std::cell::Cell;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+thread_local! {
+    static CURRENT_STAGE: Cell<Stage> = const { Cell::new(Stage::Other) };
+}
+
+/// A stage of the pipeline that allocations are attributed to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stage {
+    /// Anything not explicitly attributed to a stage.
+    Other,
+    /// Reading the input into chunks.
+    Reader,
+    /// Parsing the chunks into [`crate::parser::models::StationRecords`].
+    Parser,
+    /// Merging the records from the consumers.
+    Merge,
+    /// Exporting the records to the output.
+    Export,
+}
+
+impl Stage {
+    /// All the stages, in the order they are reported.
+    pub const ALL: [Self; 5] = [
+        Self::Reader,
+        Self::Parser,
+        Self::Merge,
+        Self::Export,
+        Self::Other,
+    ];
+
+    /// The number of stages.
+    pub const COUNT: usize = Self::ALL.len();
+
+    /// The index of the stage for the counters.
+    pub(crate) fn index(self) -> usize {
+        self as usize
+    }
+
+    /// Get the stage of the current thread.
+    ///
+    /// This returns [`Stage::Other`] if the thread is being torn down.
+    pub fn current() -> Self {
+        CURRENT_STAGE
+            .try_with(|stage| stage.get())
+            .unwrap_or(Self::Other)
+    }
+
+    /// Attribute allocations on the current thread to this stage until the returned guard
+    /// is dropped.
+    ///
+    /// The guard must not be held across an `.await`, as the task may resume on a different
+    /// thread; use [`in_stage`] for futures instead.
+    pub fn enter(self) -> StageGuard {
+        StageGuard {
+            previous: CURRENT_STAGE.replace(self),
+        }
+    }
+}
+
+impl std::fmt::Display for Stage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Other => "Other",
+            Self::Reader => "Reader",
+            Self::Parser => "Parser",
+            Self::Merge => "Merge",
+            Self::Export => "Export",
+        };
+
+        f.write_str(name)
+    }
+}
+
+/// Restores the previous [`Stage`] of the current thread when dropped.
+pub struct StageGuard {
+    previous: Stage,
+}
+
+impl Drop for StageGuard {
+    fn drop(&mut self) {
+        CURRENT_STAGE.set(self.previous)
+    }
+}
+
+/// A future that attributes all allocations made while it is polled to a [`Stage`].
+pub struct InStage<F: Future> {
+    stage: Stage,
+    future: Pin<Box<F>>,
+}
+
+impl<F: Future> Future for InStage<F> {
+    type Output = F::Output;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let _guard = self.stage.enter();
+        self.future.as_mut().poll(cx)
+    }
+}
+
+/// Attribute all allocations made while polling the future to the given [`Stage`].
+pub fn in_stage<F: Future>(stage: Stage, future: F) -> InStage<F> {
+    InStage {
+        stage,
+        future: Box::pin(future),
+    }
+}
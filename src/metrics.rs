@@ -0,0 +1,181 @@
+//! Export the state of a running pipeline in the OpenMetrics text format.
+//!
+//! The metrics are written to a file rather than served, so that they can be picked up by
+//! the textfile collector of the Prometheus node exporter, or any tooling that can scrape a
+//! file, without this crate having to run a server.
+
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::reader::RowsReader;
+
+use crate::timed;
+
+/// The prefix of all the metric names.
+const PREFIX: &str = "async_1brc";
+
+/// Append a single metric without labels.
+fn write_metric(
+    text: &mut String,
+    name: &str,
+    kind: &str,
+    help: &str,
+    value: impl std::fmt::Display,
+) {
+    let suffix = if kind == "counter" { "_total" } else { "" };
+
+    writeln!(text, "# TYPE {PREFIX}_{name} {kind}").unwrap();
+    writeln!(text, "# HELP {PREFIX}_{name} {help}").unwrap();
+    writeln!(text, "{PREFIX}_{name}{suffix} {value}").unwrap();
+}
+
+/// Render the metrics of the reader, and of the timed operations if enabled.
+pub fn render(reader: &RowsReader) -> String {
+    let mut text = String::new();
+
+    write_metric(
+        &mut text,
+        "reader_bytes_read",
+        "counter",
+        "Bytes read from the input.",
+        reader.bytes_read(),
+    );
+    write_metric(
+        &mut text,
+        "reader_chunks",
+        "counter",
+        "Chunks pushed to the queue by the reader.",
+        reader.chunks_exported(),
+    );
+    write_metric(
+        &mut text,
+        "reader_read_retries",
+        "counter",
+        "Failed reads of the input retried by the reader.",
+        reader.read_retries(),
+    );
+    write_metric(
+        &mut text,
+        "reader_chunks_skipped",
+        "counter",
+        "Chunks dropped by the reader after a failed read.",
+        reader.chunks_skipped(),
+    );
+    write_metric(
+        &mut text,
+        "reader_queue_depth",
+        "gauge",
+        "Chunks waiting in the queue for a consumer.",
+        reader.queue_depth(),
+    );
+    write_metric(
+        &mut text,
+        "parser_records",
+        "counter",
+        "Records parsed by the consumers.",
+        reader.records_parsed(),
+    );
+
+    if timed::is_enabled() {
+        let operations = timed::registered();
+
+        writeln!(text, "# TYPE {PREFIX}_operation_calls counter").unwrap();
+        writeln!(
+            text,
+            "# HELP {PREFIX}_operation_calls Calls made to the operation."
+        )
+        .unwrap();
+        for op in operations.iter() {
+            writeln!(
+                text,
+                "{PREFIX}_operation_calls_total{{operation=\"{}\"}} {}",
+                escape_label(op.name()),
+                op.count()
+            )
+            .unwrap();
+        }
+
+        writeln!(text, "# TYPE {PREFIX}_operation_seconds counter").unwrap();
+        writeln!(text, "# UNIT {PREFIX}_operation_seconds seconds").unwrap();
+        writeln!(
+            text,
+            "# HELP {PREFIX}_operation_seconds Time spent in the operation."
+        )
+        .unwrap();
+        for op in operations.iter() {
+            writeln!(
+                text,
+                "{PREFIX}_operation_seconds_total{{operation=\"{}\"}} {}",
+                escape_label(op.name()),
+                op.duration().as_secs_f64()
+            )
+            .unwrap();
+        }
+    }
+
+    text.push_str("# EOF\n");
+    text
+}
+
+/// Escape a label value for the OpenMetrics text format.
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Write the metrics to the file atomically, so that a scraper never reads a partial file.
+pub fn write(reader: &RowsReader, path: impl AsRef<Path>) -> std::io::Result<()> {
+    let path = path.as_ref();
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(".tmp");
+    let temp_path = PathBuf::from(temp_path);
+
+    std::fs::write(&temp_path, render(reader))?;
+    std::fs::rename(&temp_path, path)
+}
+
+/// Write the metrics to the file every `interval`, until the returned future is dropped.
+///
+/// Failures to write are reported but do not stop the exporter, since the run itself is
+/// unaffected.
+pub async fn export_periodically(
+    reader: Arc<RowsReader>,
+    path: impl AsRef<Path>,
+    interval: Duration,
+) {
+    let mut ticker = tokio::time::interval(interval);
+
+    loop {
+        ticker.tick().await;
+
+        if let Err(err) = write(&reader, &path) {
+            println!(
+                "Could not write the metrics to {:?}: {}",
+                path.as_ref(),
+                err
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn render_reader_metrics() {
+        let reader = RowsReader::new();
+        reader.add_records_parsed(42);
+
+        let text = render(&reader);
+
+        assert!(text.contains("\nasync_1brc_reader_bytes_read_total 0\n"));
+        assert!(text.contains("\nasync_1brc_reader_queue_depth 0\n"));
+        assert!(text.contains("\nasync_1brc_parser_records_total 42\n"));
+        assert!(text.ends_with("# EOF\n"));
+    }
+}
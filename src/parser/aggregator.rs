@@ -0,0 +1,115 @@
+//! The statistics kept for each station by [`StationRecords`](super::models::StationRecords).
+//!
+//! [`StationStats`] keeps the min/mean/max required by the 1BRC, but any [`Aggregator`] can be
+//! plugged into the same records and parsers, e.g. count-distinct sketches or last-value
+//! semantics.
+
+use super::models::StationStats;
+
+/// Aggregate the values of a single station.
+pub trait Aggregator: Default {
+    /// The result emitted for each station.
+    type Output;
+
+    /// Create a new aggregator from the first value of a station.
+    fn from_value(value: i16) -> Self {
+        let mut aggregator = Self::default();
+        aggregator.observe(value);
+        aggregator
+    }
+
+    /// Observe a single value, in tenths of a degree.
+    fn observe(&mut self, value: i16);
+
+    /// Merge the values observed by another aggregator of the same station, e.g. from
+    /// another consumer.
+    fn merge(&mut self, other: Self);
+
+    /// Emit the result for the values observed so far.
+    fn emit(&self) -> Self::Output;
+}
+
+/// The min, mean and max of a station in degrees, displayed in the 1BRC format.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StationSummary {
+    pub min: f32,
+    pub mean: f32,
+    pub max: f32,
+}
+
+impl std::fmt::Display for StationSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:.1}/{:.1}/{:.1}", self.min, self.mean, self.max)
+    }
+}
+
+impl Aggregator for StationStats {
+    type Output = StationSummary;
+
+    fn from_value(value: i16) -> Self {
+        Self::new(value)
+    }
+
+    fn observe(&mut self, value: i16) {
+        self.extend(value);
+    }
+
+    fn merge(&mut self, other: Self) {
+        *self += other;
+    }
+
+    fn emit(&self) -> Self::Output {
+        StationSummary {
+            min: self.min as f32 / 10.0,
+            mean: self.sum as f32 / self.count as f32 / 10.0,
+            max: self.max as f32 / 10.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::{models::StationRecords, sync};
+
+    /// Keep the last value of each station only.
+    #[derive(Debug, Default, Clone, PartialEq)]
+    struct LastValue(i16);
+
+    impl Aggregator for LastValue {
+        type Output = f32;
+
+        fn observe(&mut self, value: i16) {
+            self.0 = value;
+        }
+
+        fn merge(&mut self, other: Self) {
+            *self = other;
+        }
+
+        fn emit(&self) -> Self::Output {
+            self.0 as f32 / 10.0
+        }
+    }
+
+    #[test]
+    fn station_stats_emit() {
+        let mut stats = StationStats::from_value(-12);
+        stats.observe(35);
+        stats.merge(StationStats::new(10));
+
+        assert_eq!(stats.emit().to_string(), "-1.2/1.1/3.5");
+    }
+
+    #[test]
+    fn custom_aggregator() {
+        let mut records = StationRecords::<LastValue>::default();
+        sync::parse_bytes(b"jack;1.2\njill;-3.4\njack;5.6\n", &mut records);
+
+        let mut other = StationRecords::<LastValue>::default();
+        other.insert("jill".as_bytes().into(), 78);
+        records += other;
+
+        assert_eq!(records.export_text(), "{jack=5.6, jill=7.8}\n");
+    }
+}
@@ -0,0 +1,124 @@
+//! Write the outputs atomically.
+//!
+//! An output is written to a temporary file next to it, e.g. `output.txt.tmp`, which is only
+//! renamed over the output once it is complete. A crash or a Ctrl-C during the export thus
+//! leaves the previous output, if any, and a stray temporary file, but never a truncated
+//! output that the assertions or any downstream consumer could mistake for a complete one.
+//!
+//! The temporary file is not synced to the disk before the rename, as the outputs are only
+//! kept until the next run; the rename is atomic, but not durable across a power loss.
+
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// The suffix appended to the name of an output while it is written.
+pub const TEMPORARY_SUFFIX: &str = ".tmp";
+
+/// The temporary file an output at `path` is written to, e.g. `output.txt.tmp`.
+pub fn temporary_path(path: impl AsRef<Path>) -> PathBuf {
+    let mut temporary = path.as_ref().as_os_str().to_owned();
+    temporary.push(TEMPORARY_SUFFIX);
+    temporary.into()
+}
+
+/// Write the output at `path` with `write`, through its temporary file.
+///
+/// The temporary file is removed if `write` fails, leaving any previous output untouched.
+pub fn write_blocking(
+    path: impl AsRef<Path>,
+    write: impl FnOnce(&mut io::BufWriter<std::fs::File>) -> io::Result<()>,
+) -> io::Result<()> {
+    let (path, temporary) = (path.as_ref(), temporary_path(&path));
+
+    let result = std::fs::File::create(&temporary).and_then(|file| {
+        let mut file = io::BufWriter::new(file);
+        write(&mut file)?;
+        file.flush()
+    });
+
+    match result.and_then(|()| std::fs::rename(&temporary, path)) {
+        Ok(()) => Ok(()),
+        Err(error) => {
+            // The temporary file may not have been created at all.
+            let _ = std::fs::remove_file(&temporary);
+            Err(error)
+        }
+    }
+}
+
+/// Write `bytes` as the output at `path`, through its temporary file.
+///
+/// The temporary file is removed if the write fails, leaving any previous output untouched.
+#[cfg(feature = "runtime")]
+pub async fn write(path: impl AsRef<Path>, bytes: &[u8]) -> io::Result<()> {
+    use tokio::io::AsyncWriteExt;
+
+    let (path, temporary) = (path.as_ref(), temporary_path(&path));
+
+    let result = async {
+        let mut file = tokio::fs::File::create(&temporary).await?;
+        file.write_all(bytes).await?;
+
+        // A tokio `File` completes the write in the background; flush it so that the file is
+        // complete before it is renamed.
+        file.flush().await?;
+        tokio::fs::rename(&temporary, path).await
+    }
+    .await;
+
+    if result.is_err() {
+        let _ = tokio::fs::remove_file(&temporary).await;
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::TestDir;
+
+    #[test]
+    fn temporary_next_to_output() {
+        assert_eq!(
+            temporary_path("data/output.txt"),
+            PathBuf::from("data/output.txt.tmp")
+        );
+    }
+
+    #[test]
+    fn write_blocking_renames() {
+        let dir = TestDir::new("write_blocking_renames");
+        let path = dir.join("output.txt");
+
+        write_blocking(&path, |file| file.write_all(b"{}\n")).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"{}\n");
+        assert!(!temporary_path(&path).exists());
+
+        // A failed write leaves the previous output untouched.
+        let error = write_blocking(&path, |file| {
+            file.write_all(b"{jack=")?;
+            Err(io::Error::other("interrupted"))
+        });
+        assert!(error.is_err());
+        assert_eq!(std::fs::read(&path).unwrap(), b"{}\n");
+        assert!(!temporary_path(&path).exists());
+    }
+
+    #[cfg(feature = "runtime")]
+    #[tokio::test]
+    async fn write_renames() {
+        let dir = TestDir::new("write_renames");
+        let path = dir.join("output.txt");
+
+        write(&path, b"{jack=1.2/1.2/1.2}\n").await.unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), b"{jack=1.2/1.2/1.2}\n");
+        assert!(!temporary_path(&path).exists());
+
+        // The output cannot be renamed over a directory.
+        let directory = dir.join("directory");
+        std::fs::create_dir_all(&directory).unwrap();
+        assert!(write(&directory, b"{}\n").await.is_err());
+        assert!(!temporary_path(&directory).exists());
+    }
+}
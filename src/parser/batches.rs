@@ -0,0 +1,278 @@
+//! Convert the parsed records into Arrow [`RecordBatch`]es instead of aggregating them.
+//!
+//! This turns the reader into an ingestion front-end for query engines: every
+//! `(station, temperature)` pair is kept, with the station names dictionary-encoded within
+//! each batch. The batches are sent over a channel by [`batches_from_reader`], and can be
+//! written to a file in the Arrow IPC stream format by [`write_ipc_stream`].
+
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+
+use arrow::array::{ArrayBuilder, Float64Builder, RecordBatch, StringDictionaryBuilder};
+use arrow::datatypes::{DataType, Field, Int32Type, Schema, SchemaRef};
+use arrow::error::ArrowError;
+use arrow::ipc::writer::StreamWriter;
+use tokio::sync::mpsc;
+
+use super::{func, separators, sync};
+use crate::reader::{func::allocate_buffer, RowsReader};
+
+/// The schema of every batch: `station` as a dictionary of strings, and `temperature` in
+/// degrees.
+pub fn schema() -> SchemaRef {
+    static SCHEMA: OnceLock<SchemaRef> = OnceLock::new();
+
+    Arc::clone(SCHEMA.get_or_init(|| {
+        Arc::new(Schema::new(vec![
+            Field::new(
+                "station",
+                DataType::Dictionary(Box::new(DataType::Int32), Box::new(DataType::Utf8)),
+                false,
+            ),
+            Field::new("temperature", DataType::Float64, false),
+        ]))
+    }))
+}
+
+/// Accumulate records into [`RecordBatch`]es of a fixed number of rows.
+pub struct BatchBuilder {
+    batch_size: usize,
+    stations: StringDictionaryBuilder<Int32Type>,
+    temperatures: Float64Builder,
+    positions: Vec<usize>,
+}
+
+impl BatchBuilder {
+    /// Create a new [`BatchBuilder`] emitting batches of `batch_size` rows.
+    pub fn new(batch_size: usize) -> Self {
+        Self {
+            batch_size: batch_size.max(1),
+            stations: StringDictionaryBuilder::new(),
+            temperatures: Float64Builder::with_capacity(batch_size),
+            positions: Vec::new(),
+        }
+    }
+
+    /// The number of rows not yet emitted in a batch.
+    pub fn len(&self) -> usize {
+        self.temperatures.len()
+    }
+
+    /// Check if there are no rows waiting to be emitted.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Add a single record, with the value in tenths, returning a batch if it is full.
+    pub fn push(&mut self, name: &[u8], value: i16) -> Option<RecordBatch> {
+        self.stations.append_value(func::bytes_to_string(name));
+        self.temperatures.append_value(value as f64 / 10.0);
+
+        (self.len() >= self.batch_size).then(|| self.finish_batch())
+    }
+
+    /// Parse a chunk of complete lines, calling `emit` with every batch that fills up.
+    ///
+    /// Like the other parsers used in the hot paths, this expects perfect input. Returns the
+    /// number of records parsed.
+    pub fn extend_from_bytes(&mut self, bytes: &[u8], mut emit: impl FnMut(RecordBatch)) -> usize {
+        let mut positions = std::mem::take(&mut self.positions);
+        positions.clear();
+        separators::find_separators_simd(bytes, &mut positions);
+
+        let mut start = 0;
+        for pair in positions.chunks_exact(2) {
+            let (semicolon, newline) = (pair[0], pair[1]);
+
+            if let Some(batch) = self.push(
+                &bytes[start..semicolon],
+                sync::parse_value(&bytes[semicolon + 1..newline]),
+            ) {
+                emit(batch);
+            }
+
+            start = newline + 1;
+        }
+
+        let records = positions.len() / 2;
+        self.positions = positions;
+        records
+    }
+
+    /// Emit the remaining rows as a final, possibly smaller, batch.
+    pub fn finish(&mut self) -> Option<RecordBatch> {
+        (!self.is_empty()).then(|| self.finish_batch())
+    }
+
+    /// Build a batch out of the rows accumulated so far.
+    fn finish_batch(&mut self) -> RecordBatch {
+        RecordBatch::try_new(
+            schema(),
+            vec![
+                Arc::new(self.stations.finish()),
+                Arc::new(self.temperatures.finish()),
+            ],
+        )
+        .expect("The columns always match the schema.")
+    }
+}
+
+/// Read from a [`RowsReader`] with `threads` concurrent consumers, sending the records in
+/// batches of `batch_size` rows over `sender`.
+///
+/// Returns the total number of records parsed. The consumers stop early if the receiver is
+/// dropped.
+pub async fn batches_from_reader(
+    reader: Arc<RowsReader>,
+    threads: usize,
+    max_chunk_size: usize,
+    batch_size: usize,
+    sender: mpsc::Sender<RecordBatch>,
+) -> usize {
+    let handles = (0..threads.max(1))
+        .map(|_| {
+            let reader = Arc::clone(&reader);
+            let sender = sender.clone();
+
+            tokio::spawn(async move {
+                let mut builder = BatchBuilder::new(batch_size);
+                let mut buffer = allocate_buffer(max_chunk_size);
+                let mut batches = Vec::new();
+                let mut count = 0;
+
+                while let Some(bytes) = reader.fill(buffer).await {
+                    let parsed = builder.extend_from_bytes(&bytes, |batch| batches.push(batch));
+                    reader.add_records_parsed(parsed);
+                    count += parsed;
+
+                    for batch in batches.drain(..) {
+                        if sender.send(batch).await.is_err() {
+                            return count;
+                        }
+                    }
+
+                    buffer = bytes;
+                }
+
+                if let Some(batch) = builder.finish() {
+                    let _ = sender.send(batch).await;
+                }
+
+                count
+            })
+        })
+        .collect::<Vec<_>>();
+
+    // Close the channel once every consumer is done.
+    drop(sender);
+
+    let mut count = 0;
+    for handle in handles {
+        count += handle.await.unwrap();
+    }
+
+    count
+}
+
+/// Write every batch received from `receiver` to a new file at `path` in the Arrow IPC
+/// stream format, returning the number of rows written.
+///
+/// The stream format is used rather than the file format, as each batch carries its own
+/// dictionary of station names. This blocks the current thread, so should be called from
+/// [`tokio::task::spawn_blocking`].
+pub fn write_ipc_stream(
+    path: impl AsRef<Path>,
+    mut receiver: mpsc::Receiver<RecordBatch>,
+) -> Result<usize, ArrowError> {
+    let file = std::fs::File::create(path)?;
+    let mut writer = StreamWriter::try_new_buffered(file, &schema())?;
+    let mut rows = 0;
+
+    while let Some(batch) = receiver.blocking_recv() {
+        rows += batch.num_rows();
+        writer.write(&batch)?;
+    }
+
+    writer.finish()?;
+    Ok(rows)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::TestDir;
+    use arrow::array::{AsArray, DictionaryArray};
+    use arrow::datatypes::Float64Type;
+
+    #[test]
+    fn build_batches() {
+        let mut builder = BatchBuilder::new(3);
+        let mut batches = Vec::new();
+
+        let parsed = builder
+            .extend_from_bytes(b"jack;1.2\njill;-3.4\njack;56.7\nbob;0.0\n", |batch| {
+                batches.push(batch)
+            });
+        batches.extend(builder.finish());
+
+        assert_eq!(parsed, 4);
+        assert_eq!(
+            batches
+                .iter()
+                .map(RecordBatch::num_rows)
+                .collect::<Vec<_>>(),
+            vec![3, 1]
+        );
+
+        let stations = batches[0]
+            .column(0)
+            .as_any()
+            .downcast_ref::<DictionaryArray<Int32Type>>()
+            .unwrap();
+        assert_eq!(stations.values().len(), 2);
+        assert_eq!(
+            stations
+                .downcast_dict::<arrow::array::StringArray>()
+                .unwrap()
+                .into_iter()
+                .collect::<Vec<_>>(),
+            vec![Some("jack"), Some("jill"), Some("jack")]
+        );
+        assert_eq!(
+            batches[0].column(1).as_primitive::<Float64Type>().values(),
+            &[1.2, -3.4, 56.7]
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn stream_batches_to_ipc() {
+        let dir = TestDir::new("stream_batches_to_ipc");
+        let path = dir.join("batches.arrows");
+        let input = "jack;1.2\njill;-3.4\njack;56.7\nbob;0.0\n".repeat(1000);
+
+        let reader = Arc::new(RowsReader::with_chunk_sizes(256, 512).with_additional_buffers(4));
+        let (sender, receiver) = mpsc::channel(4);
+
+        let writer = tokio::task::spawn_blocking({
+            let path = path.clone();
+            move || write_ipc_stream(path, receiver)
+        });
+
+        let (read, parsed) = tokio::join!(
+            reader.read(input.as_bytes()),
+            batches_from_reader(Arc::clone(&reader), 3, 512, 100, sender),
+        );
+        let written = writer.await.unwrap().unwrap();
+        read.unwrap();
+
+        let file = std::fs::File::open(&path).unwrap();
+        let rows = arrow::ipc::reader::StreamReader::try_new(file, None)
+            .unwrap()
+            .map(|batch| batch.unwrap().num_rows())
+            .sum::<usize>();
+
+        assert_eq!(parsed, 4000);
+        assert_eq!(written, 4000);
+        assert_eq!(rows, 4000);
+    }
+}
@@ -0,0 +1,389 @@
+//! Checkpoint the records aggregated so far, so that an interrupted run can be resumed.
+//!
+//! Every `interval` bytes of input, the reader stops handing out chunks until the consumers
+//! have merged the records of every chunk already handed out, then saves them along with the
+//! offset of the input they cover. A run resumed from the checkpoint seeks the input to that
+//! offset, and starts from the saved records instead of empty ones.
+//!
+//! The consumers merge their records after every chunk, instead of once at the end, so that
+//! the records are always ready to be saved; this costs a lock per chunk, which is negligible
+//! next to reading from the slow media long runs are checkpointed on.
+//!
+//! The format is little-endian throughout, followed by the records as a snapshot:
+//!
+//! ```text
+//! magic: [u8; 8] = b"1BRCCKPT"
+//! version: u32
+//! offset: u64
+//! input length: u64
+//! records: the snapshot written by `StationRecords::encode_snapshot`
+//! ```
+//!
+//! Each checkpoint replaces the previous one atomically, through [`atomic::write_blocking`].
+
+use std::fs::File;
+use std::io::{self, Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Mutex, PoisonError};
+
+use tokio::sync::Notify;
+
+use super::atomic;
+use super::models::StationRecords;
+
+/// The bytes at the start of every checkpoint.
+pub const CHECKPOINT_MAGIC: &[u8; 8] = b"1BRCCKPT";
+
+/// The version of the checkpoint format.
+pub const CHECKPOINT_VERSION: u32 = 1;
+
+/// Create an error for a checkpoint that cannot be decoded or resumed from.
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+/// Read a fixed number of bytes.
+fn read_array<const N: usize>(reader: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// The records of the first `offset` bytes of an input of `input_len` bytes.
+#[derive(Debug, Default)]
+pub struct Checkpoint {
+    /// The offset of the input up to which the records were aggregated.
+    pub offset: u64,
+
+    /// The length of the input, to tell it apart from another input when resuming.
+    pub input_len: u64,
+
+    /// The records of the input up to `offset`.
+    pub records: StationRecords,
+}
+
+impl Checkpoint {
+    /// Save the checkpoint to `path`, replacing any previous one.
+    pub fn write(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        write(path, self.offset, self.input_len, &self.records)
+    }
+
+    /// Load the checkpoint at `path`.
+    pub fn read(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut reader = io::BufReader::new(File::open(path)?);
+
+        if &read_array::<8>(&mut reader)? != CHECKPOINT_MAGIC {
+            return Err(invalid("Not a checkpoint of a run."));
+        }
+
+        let version = u32::from_le_bytes(read_array(&mut reader)?);
+        if version != CHECKPOINT_VERSION {
+            return Err(invalid(format!(
+                "Unsupported checkpoint version {}; expected {}.",
+                version, CHECKPOINT_VERSION
+            )));
+        }
+
+        Ok(Self {
+            offset: u64::from_le_bytes(read_array(&mut reader)?),
+            input_len: u64::from_le_bytes(read_array(&mut reader)?),
+            records: StationRecords::decode_snapshot(&mut reader)?,
+        })
+    }
+}
+
+/// Save the `records` of the first `offset` bytes of an input of `input_len` bytes to `path`.
+fn write(
+    path: impl AsRef<Path>,
+    offset: u64,
+    input_len: u64,
+    records: &StationRecords,
+) -> io::Result<()> {
+    atomic::write_blocking(path, |file| {
+        file.write_all(CHECKPOINT_MAGIC)?;
+        file.write_all(&CHECKPOINT_VERSION.to_le_bytes())?;
+        file.write_all(&offset.to_le_bytes())?;
+        file.write_all(&input_len.to_le_bytes())?;
+        records.encode_snapshot(file)
+    })
+}
+
+/// The checkpoints of a run, holding the records merged by the consumers so far.
+#[derive(Debug)]
+pub struct Checkpoints {
+    path: PathBuf,
+    interval: u64,
+    input_len: u64,
+    /// The offset of the input the run started from.
+    start: u64,
+    /// The offset of the input from which the next checkpoint is due.
+    next: AtomicU64,
+    records: Mutex<StationRecords>,
+    chunks_merged: AtomicUsize,
+    /// Notified whenever a consumer has merged a chunk, or has stopped.
+    merged: Notify,
+    /// Whether a consumer stopped before the end of the input, so that the chunks it held are
+    /// never merged, and no more checkpoints can be taken.
+    failed: AtomicBool,
+    written: AtomicUsize,
+    error: Mutex<Option<io::Error>>,
+}
+
+impl Checkpoints {
+    /// Checkpoint a run over an input of `input_len` bytes to `path`, every `interval` bytes.
+    pub fn new(path: impl Into<PathBuf>, interval: u64, input_len: u64) -> Self {
+        Self {
+            path: path.into(),
+            interval: interval.max(1),
+            input_len,
+            start: 0,
+            next: AtomicU64::new(interval.max(1)),
+            records: Mutex::default(),
+            chunks_merged: AtomicUsize::default(),
+            merged: Notify::new(),
+            failed: AtomicBool::new(false),
+            written: AtomicUsize::default(),
+            error: Mutex::default(),
+        }
+    }
+
+    /// Resume the run from the checkpoint at `path`, seeking `input` to its offset and
+    /// starting from its records.
+    ///
+    /// Fails if the checkpoint was taken over an input of another length.
+    pub fn resume_from(mut self, path: impl AsRef<Path>, input: &mut File) -> io::Result<Self> {
+        let checkpoint = Checkpoint::read(path)?;
+
+        if checkpoint.input_len != self.input_len {
+            return Err(invalid(format!(
+                "The checkpoint was taken over an input of {} bytes, not {} bytes.",
+                checkpoint.input_len, self.input_len
+            )));
+        } else if checkpoint.offset > self.input_len {
+            return Err(invalid(format!(
+                "The checkpoint is at offset {}, past the end of the input.",
+                checkpoint.offset
+            )));
+        }
+
+        input.seek(io::SeekFrom::Start(checkpoint.offset))?;
+
+        self.start = checkpoint.offset;
+        self.next = AtomicU64::new(checkpoint.offset + self.interval);
+        self.records = Mutex::new(checkpoint.records);
+        Ok(self)
+    }
+
+    /// The path the checkpoints are saved to.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The offset of the input the run started from, 0 unless it was resumed.
+    pub fn start(&self) -> u64 {
+        self.start
+    }
+
+    /// The number of checkpoints saved so far.
+    pub fn written(&self) -> usize {
+        self.written.load(Ordering::Relaxed)
+    }
+
+    /// Merge the `records` of a consumer after a chunk, leaving them empty.
+    pub fn merge(&self, records: &mut StationRecords) {
+        *self.records.lock().unwrap_or_else(PoisonError::into_inner) += std::mem::take(records);
+        self.chunks_merged.fetch_add(1, Ordering::Release);
+        self.merged.notify_waiters();
+    }
+
+    /// Register a consumer merging its records, until the returned guard is finished.
+    pub fn consumer(&self) -> Consumer<'_> {
+        Consumer {
+            checkpoints: self,
+            finished: false,
+        }
+    }
+
+    /// Save a checkpoint at `offset` if one is due, once the records of the `chunks` handed
+    /// out so far are merged.
+    ///
+    /// A failed write is kept for [`Checkpoints::finish`], so that the run is not interrupted.
+    pub async fn checkpoint_if_due(&self, offset: u64, chunks: usize) {
+        if offset < self.next.load(Ordering::Relaxed) {
+            return;
+        }
+
+        loop {
+            let notified = self.merged.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+
+            if self.failed.load(Ordering::Relaxed) {
+                return;
+            } else if self.chunks_merged.load(Ordering::Acquire) >= chunks {
+                break;
+            }
+
+            notified.await;
+        }
+
+        let records = self.records.lock().unwrap_or_else(PoisonError::into_inner);
+        match write(&self.path, offset, self.input_len, &records) {
+            Ok(()) => {
+                self.written.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(error) => {
+                self.error
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .get_or_insert(error);
+            }
+        }
+
+        self.next.store(offset + self.interval, Ordering::Relaxed);
+    }
+
+    /// A copy of the records merged so far, e.g. to report them while the run goes on.
+    pub fn snapshot(&self) -> StationRecords {
+        self.records
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Take the records merged so far.
+    pub fn take_records(&self) -> StationRecords {
+        std::mem::take(&mut *self.records.lock().unwrap_or_else(PoisonError::into_inner))
+    }
+
+    /// Return the number of checkpoints saved, or the first error saving one.
+    pub fn finish(&self) -> io::Result<usize> {
+        match self
+            .error
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take()
+        {
+            Some(error) => Err(error),
+            None => Ok(self.written()),
+        }
+    }
+}
+
+/// A consumer merging its records into the [`Checkpoints`].
+///
+/// If dropped before [`Consumer::finish`], e.g. as the consumer panicked, the checkpoints stop,
+/// as the chunk the consumer held is never merged.
+pub struct Consumer<'c> {
+    checkpoints: &'c Checkpoints,
+    finished: bool,
+}
+
+impl Consumer<'_> {
+    /// Mark the consumer as having merged every chunk it took.
+    pub fn finish(mut self) {
+        self.finished = true;
+    }
+}
+
+impl Drop for Consumer<'_> {
+    fn drop(&mut self) {
+        if !self.finished {
+            self.checkpoints.failed.store(true, Ordering::Relaxed);
+            self.checkpoints.merged.notify_waiters();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::sync;
+    use crate::testing::TestDir;
+
+    fn records(input: &str) -> StationRecords {
+        let mut records = StationRecords::new();
+        sync::parse_bytes(input.as_bytes(), &mut records);
+        records
+    }
+
+    #[test]
+    fn write_and_read() {
+        let dir = TestDir::new("write_and_read");
+        let path = dir.join("checkpoint.bin");
+        let checkpoint = Checkpoint {
+            offset: 19,
+            input_len: 28,
+            records: records("jack;1.2\njill;-3.4\n"),
+        };
+
+        checkpoint.write(&path).unwrap();
+        let read = Checkpoint::read(&path).unwrap();
+        assert_eq!((read.offset, read.input_len), (19, 28));
+        assert_eq!(read.records.export_text(), checkpoint.records.export_text());
+    }
+
+    #[test]
+    fn resume_seeks_the_input() {
+        let input = "jack;1.2\njill;-3.4\njack;5.6\n";
+        let dir = TestDir::new("resume_seeks_the_input");
+        let (path, input_path) = (dir.join("checkpoint.bin"), dir.join("input.txt"));
+        std::fs::write(&input_path, input).unwrap();
+
+        Checkpoint {
+            offset: 19,
+            input_len: input.len() as u64,
+            records: records(&input[..19]),
+        }
+        .write(&path)
+        .unwrap();
+
+        let mut file = File::open(&input_path).unwrap();
+        let checkpoints = Checkpoints::new(&path, 1, input.len() as u64)
+            .resume_from(&path, &mut file)
+            .unwrap();
+        assert_eq!(checkpoints.start(), 19);
+        assert_eq!(checkpoints.take_records().iter().count(), 2);
+
+        let mut rest = String::new();
+        file.read_to_string(&mut rest).unwrap();
+        assert_eq!(rest, "jack;5.6\n");
+
+        // A checkpoint over another input is refused.
+        let error = Checkpoints::new(&path, 1, input.len() as u64 + 1)
+            .resume_from(&path, &mut file)
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[tokio::test]
+    async fn checkpoint_once_merged() {
+        let dir = TestDir::new("checkpoint_once_merged");
+        let path = dir.join("checkpoint.bin");
+        let checkpoints = Checkpoints::new(&path, 10, 28);
+
+        // Not due yet.
+        checkpoints.checkpoint_if_due(9, 1).await;
+        assert_eq!(checkpoints.written(), 0);
+
+        let consumer = checkpoints.consumer();
+        let mut chunk = records("jack;1.2\njill;-3.4\n");
+        let merging = async {
+            tokio::task::yield_now().await;
+            checkpoints.merge(&mut chunk);
+        };
+        tokio::join!(checkpoints.checkpoint_if_due(19, 1), merging);
+        consumer.finish();
+
+        assert_eq!(checkpoints.finish().unwrap(), 1);
+        let checkpoint = Checkpoint::read(&path).unwrap();
+        assert_eq!(checkpoint.offset, 19);
+        assert_eq!(checkpoint.records.iter().count(), 2);
+
+        // A consumer stopping early stops the checkpoints instead of waiting forever.
+        drop(checkpoints.consumer());
+        checkpoints.checkpoint_if_due(28, 2).await;
+        assert_eq!(checkpoints.written(), 1);
+    }
+}
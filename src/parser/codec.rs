@@ -0,0 +1,169 @@
+//! Decode 1BRC records from any [`AsyncRead`](tokio::io::AsyncRead) with
+//! [`FramedRead`](tokio_util::codec::FramedRead).
+//!
+//! Unlike the parsers used in the hot paths, every line is validated against the 1BRC
+//! grammar, so this is suitable for untrusted sources such as sockets or pipes:
+//!
+//! ```no_run
+//! use futures::StreamExt;
+//! use tokio_util::codec::FramedRead;
+//!
+//! use async_1brc::parser::codec::OneBrcCodec;
+//!
+//! # async fn example() -> std::io::Result<()> {
+//! let file = tokio::fs::File::open("measurements.txt").await?;
+//! let mut records = FramedRead::new(file, OneBrcCodec::new());
+//!
+//! while let Some((name, value)) = records.next().await.transpose()? {
+//!     println!("{:?}: {}", name, value);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::io;
+
+use bytes::{Bytes, BytesMut};
+use tokio_util::codec::Decoder;
+
+use super::func;
+use crate::config;
+
+/// The longest valid line, without its newline: a name of [`config::MAX_NAME_LENGTH`]
+/// bytes, the `;`, and a value such as `-99.9`.
+pub const MAX_RECORD_LENGTH: usize = config::MAX_NAME_LENGTH + 1 + 5;
+
+/// A [`Decoder`] of 1BRC lines into `(name, value)` frames, with the value in tenths.
+#[derive(Debug, Clone, Default)]
+pub struct OneBrcCodec {
+    /// The number of bytes at the start of the buffer known not to contain a newline.
+    searched: usize,
+}
+
+impl OneBrcCodec {
+    /// Create a new [`OneBrcCodec`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse a single line without its newline.
+    fn parse_line(line: Bytes) -> io::Result<(Bytes, i16)> {
+        let invalid = || {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid 1BRC line: {:?}", func::bytes_to_string(&line)),
+            )
+        };
+
+        let semicolon = line
+            .iter()
+            .position(|&byte| byte == b';')
+            .ok_or_else(invalid)?;
+
+        if semicolon == 0 || semicolon > config::MAX_NAME_LENGTH {
+            return Err(invalid());
+        }
+
+        let value = func::parse_value_checked(&line[semicolon + 1..]).ok_or_else(invalid)?;
+
+        Ok((line.slice(..semicolon), value))
+    }
+}
+
+impl Decoder for OneBrcCodec {
+    type Item = (Bytes, i16);
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> io::Result<Option<Self::Item>> {
+        let Some(newline) = src[self.searched..]
+            .iter()
+            .position(|&byte| byte == b'\n')
+            .map(|pos| self.searched + pos)
+        else {
+            self.searched = src.len();
+
+            if src.len() > MAX_RECORD_LENGTH {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("No newline found in {} bytes.", src.len()),
+                ));
+            }
+
+            return Ok(None);
+        };
+
+        self.searched = 0;
+        let line = src.split_to(newline + 1).freeze();
+
+        Self::parse_line(line.slice(..newline)).map(Some)
+    }
+
+    fn decode_eof(&mut self, src: &mut BytesMut) -> io::Result<Option<Self::Item>> {
+        match self.decode(src)? {
+            Some(frame) => Ok(Some(frame)),
+            // Accept a last line without a newline.
+            None if !src.is_empty() => {
+                self.searched = 0;
+                Self::parse_line(src.split().freeze()).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures::StreamExt;
+    use tokio_util::codec::FramedRead;
+
+    #[tokio::test]
+    async fn decode_lines() {
+        let input = b"jack;1.2\njill;-3.4\nZ\xc3\xbcrich;56.7\nbob;0.0".as_ref();
+
+        let frames = FramedRead::new(input, OneBrcCodec::new())
+            .map(Result::unwrap)
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(
+            frames,
+            vec![
+                (Bytes::from("jack"), 12),
+                (Bytes::from("jill"), -34),
+                (Bytes::from("Zürich"), 567),
+                (Bytes::from("bob"), 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn decode_partial_lines() {
+        let mut codec = OneBrcCodec::new();
+        let mut buffer = BytesMut::from("jack;1");
+
+        assert_eq!(codec.decode(&mut buffer).unwrap(), None);
+
+        buffer.extend_from_slice(b".2\njill;");
+        assert_eq!(
+            codec.decode(&mut buffer).unwrap(),
+            Some((Bytes::from("jack"), 12))
+        );
+        assert_eq!(codec.decode(&mut buffer).unwrap(), None);
+        assert_eq!(&buffer[..], b"jill;");
+    }
+
+    #[test]
+    fn decode_invalid_lines() {
+        for line in ["jack;1.23\n", ";1.0\n", "jack\n", "jack;1.0;2.0\n"] {
+            let error = OneBrcCodec::new()
+                .decode(&mut BytesMut::from(line))
+                .unwrap_err();
+
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData, "{:?}", line);
+        }
+
+        let mut buffer = BytesMut::from("x".repeat(MAX_RECORD_LENGTH + 1).as_str());
+        assert!(OneBrcCodec::new().decode(&mut buffer).is_err());
+    }
+}
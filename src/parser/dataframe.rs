@@ -0,0 +1,86 @@
+//! Convert [`StationRecords`] into a Polars [`DataFrame`].
+
+use polars::prelude::*;
+
+use super::{func, models::StationRecords};
+
+impl StationRecords {
+    /// Convert the records into a [`DataFrame`] with one row per station, in order of name.
+    ///
+    /// The columns are `name`, `min`, `mean`, `max` in degrees, and `count`. Unlike the 1BRC
+    /// output, the mean is not rounded.
+    pub fn to_dataframe(&self) -> PolarsResult<DataFrame> {
+        let stations = self.iter_sorted().collect::<Vec<_>>();
+        let len = stations.len();
+        let mut names = Vec::with_capacity(len);
+        let mut mins = Vec::with_capacity(len);
+        let mut means = Vec::with_capacity(len);
+        let mut maxs = Vec::with_capacity(len);
+        let mut counts = Vec::with_capacity(len);
+
+        for (name, stats) in stations {
+            names.push(func::bytes_to_string(name).into_owned());
+            mins.push(stats.min as f64 / 10.0);
+            means.push(stats.sum as f64 / stats.count as f64 / 10.0);
+            maxs.push(stats.max as f64 / 10.0);
+            counts.push(stats.count as u64);
+        }
+
+        DataFrame::new(vec![
+            Column::new("name".into(), names),
+            Column::new("min".into(), mins),
+            Column::new("mean".into(), means),
+            Column::new("max".into(), maxs),
+            Column::new("count".into(), counts),
+        ])
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn records_to_dataframe() {
+        let mut records = StationRecords::new();
+        records.insert("jill".as_bytes().into(), -34);
+        records.insert("jack".as_bytes().into(), 12);
+        records.insert("jack".as_bytes().into(), 57);
+
+        let df = records.to_dataframe().unwrap();
+
+        assert_eq!(
+            df.get_column_names(),
+            ["name", "min", "mean", "max", "count"]
+        );
+        assert_eq!(df.height(), 2);
+        assert_eq!(
+            df.column("name")
+                .unwrap()
+                .str()
+                .unwrap()
+                .into_no_null_iter()
+                .collect::<Vec<_>>(),
+            vec!["jack", "jill"]
+        );
+        assert_eq!(
+            df.column("mean")
+                .unwrap()
+                .f64()
+                .unwrap()
+                .into_no_null_iter()
+                .collect::<Vec<_>>(),
+            vec![3.45, -3.4]
+        );
+        assert_eq!(
+            df.column("count")
+                .unwrap()
+                .u64()
+                .unwrap()
+                .into_no_null_iter()
+                .collect::<Vec<_>>(),
+            vec![2, 1]
+        );
+        assert_eq!(StationRecords::new().to_dataframe().unwrap().height(), 0);
+    }
+}
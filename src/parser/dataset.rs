@@ -0,0 +1,144 @@
+//! Dataset-level statistics across all stations.
+
+use super::func;
+use super::models::StationRecords;
+
+/// The distribution of the number of rows per station.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RowsDistribution {
+    pub min: usize,
+    pub median: usize,
+    pub mean: f64,
+    pub max: usize,
+}
+
+/// Statistics describing a whole dataset rather than individual stations.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DatasetStats {
+    /// The total number of rows.
+    pub rows: usize,
+
+    /// The number of distinct stations.
+    pub stations: usize,
+
+    /// The station with the highest single reading, and that reading.
+    pub hottest: Option<(Vec<u8>, i16)>,
+
+    /// The station with the lowest single reading, and that reading.
+    pub coldest: Option<(Vec<u8>, i16)>,
+
+    /// The distribution of the number of rows per station.
+    pub rows_per_station: RowsDistribution,
+}
+
+impl DatasetStats {
+    /// Summarize the [`StationRecords`] into a [`DatasetStats`].
+    ///
+    /// Ties for the hottest and coldest readings are resolved in favour of the station name
+    /// that sorts first.
+    pub fn from_records(records: &StationRecords) -> Self {
+        let mut stats = Self::default();
+        let mut counts = Vec::new();
+
+        for (name, station) in records.iter_sorted() {
+            stats.rows += station.count;
+            stats.stations += 1;
+            counts.push(station.count);
+
+            if stats
+                .hottest
+                .as_ref()
+                .is_none_or(|(_, max)| station.max > *max)
+            {
+                stats.hottest = Some((name.to_vec(), station.max));
+            }
+            if stats
+                .coldest
+                .as_ref()
+                .is_none_or(|(_, min)| station.min < *min)
+            {
+                stats.coldest = Some((name.to_vec(), station.min));
+            }
+        }
+
+        if !counts.is_empty() {
+            counts.sort_unstable();
+
+            stats.rows_per_station = RowsDistribution {
+                min: counts[0],
+                median: counts[counts.len() / 2],
+                mean: stats.rows as f64 / counts.len() as f64,
+                max: counts[counts.len() - 1],
+            };
+        }
+
+        stats
+    }
+}
+
+impl std::fmt::Display for DatasetStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let format_reading = |reading: &Option<(Vec<u8>, i16)>| {
+            reading.as_ref().map_or("N/A".to_owned(), |(name, value)| {
+                format!(
+                    "{:.1} at {}",
+                    *value as f32 / 10.0,
+                    func::bytes_to_string(name)
+                )
+            })
+        };
+
+        writeln!(f, "Dataset statistics:")?;
+        writeln!(f, "- Total rows: {}", self.rows)?;
+        writeln!(f, "- Distinct stations: {}", self.stations)?;
+        writeln!(f, "- Hottest reading: {}", format_reading(&self.hottest))?;
+        writeln!(f, "- Coldest reading: {}", format_reading(&self.coldest))?;
+        writeln!(
+            f,
+            "- Rows per station: min {}, median {}, mean {:.1}, max {}",
+            self.rows_per_station.min,
+            self.rows_per_station.median,
+            self.rows_per_station.mean,
+            self.rows_per_station.max
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn dataset_stats_empty() {
+        let stats = DatasetStats::from_records(&StationRecords::new());
+
+        assert_eq!(stats, DatasetStats::default());
+    }
+
+    #[test]
+    fn dataset_stats_from_records() {
+        let mut records = StationRecords::new();
+        records.insert(b"jack".into(), 12);
+        records.insert(b"jack".into(), -34);
+        records.insert(b"jill".into(), 567);
+        records.insert(b"jill".into(), 0);
+        records.insert(b"jill".into(), 1);
+        records.insert(b"bob".into(), -34);
+
+        let stats = DatasetStats::from_records(&records);
+
+        assert_eq!(stats.rows, 6);
+        assert_eq!(stats.stations, 3);
+        assert_eq!(stats.hottest, Some((b"jill".to_vec(), 567)));
+        assert_eq!(stats.coldest, Some((b"bob".to_vec(), -34)));
+        assert_eq!(
+            stats.rows_per_station,
+            RowsDistribution {
+                min: 1,
+                median: 2,
+                mean: 2.0,
+                max: 3,
+            }
+        );
+    }
+}
@@ -0,0 +1,109 @@
+//! Select the function parsing each chunk read by the [`RowsReader`](crate::reader::RowsReader).
+
+use std::hash::BuildHasher;
+
+use super::{aggregator::Aggregator, line, models::StationRecords, scratch::ScratchSpace, sync};
+
+/// The parser used by the consumers to parse each chunk of complete lines.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ParserEngine {
+    /// Split the lines and look for the `;` one byte at a time, i.e. [`line::parse_chunk`].
+    #[default]
+    Scalar,
+
+    /// Find the `;` and `\n` with the vectorized search of the `memchr` crate, i.e.
+    /// [`sync::parse_bytes_memchr`].
+    Memchr,
+
+    /// Find every separator with the SWAR scan of [`separators`](super::separators), then
+    /// convert the values in batches, i.e. [`sync::parse_bytes_batched`].
+    Batched,
+
+    /// Find the records with a compute shader on the GPU, and aggregate them on the CPU, i.e.
+    /// [`gpu::parse_chunk`](super::gpu::parse_chunk); an experiment, falling back to
+    /// [`ParserEngine::Batched`] without a GPU.
+    #[cfg(feature = "gpu")]
+    Gpu,
+}
+
+impl ParserEngine {
+    /// Parse a chunk of complete lines into `records`, returning the number of records parsed.
+    #[inline]
+    pub fn parse_chunk<A: Aggregator, S: BuildHasher>(
+        self,
+        bytes: &[u8],
+        records: &mut StationRecords<A, S>,
+    ) -> usize {
+        match self {
+            Self::Scalar => line::parse_chunk(bytes, records),
+            Self::Memchr => sync::parse_bytes_memchr(bytes, records),
+            Self::Batched => sync::parse_bytes_batched(bytes, records),
+            #[cfg(feature = "gpu")]
+            Self::Gpu => super::gpu::parse_chunk(bytes, records),
+        }
+    }
+
+    /// Parse a chunk like [`ParserEngine::parse_chunk`], with the buffers of `scratch`, which
+    /// the caller reuses across chunks.
+    #[inline]
+    pub fn parse_chunk_with<A: Aggregator, S: BuildHasher>(
+        self,
+        bytes: &[u8],
+        records: &mut StationRecords<A, S>,
+        scratch: &mut ScratchSpace,
+    ) -> usize {
+        match self {
+            Self::Batched => sync::parse_bytes_batched_with(bytes, records, scratch),
+            _ => self.parse_chunk(bytes, records),
+        }
+    }
+}
+
+impl std::fmt::Display for ParserEngine {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Scalar => write!(f, "scalar"),
+            Self::Memchr => write!(f, "memchr"),
+            Self::Batched => write!(f, "batched"),
+            #[cfg(feature = "gpu")]
+            Self::Gpu => write!(f, "gpu"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn engines_agree() {
+        let bytes = "jack;1.2\njill;-3.4\n\njack;5.6\nZürich;-0.1\n".repeat(100);
+
+        let mut scalar = StationRecords::new();
+        assert_eq!(
+            ParserEngine::Scalar.parse_chunk(bytes.as_bytes(), &mut scalar),
+            400
+        );
+
+        for engine in [ParserEngine::Memchr, ParserEngine::Batched] {
+            let mut records = StationRecords::new();
+
+            assert_eq!(engine.parse_chunk(bytes.as_bytes(), &mut records), 400);
+            assert_eq!(records, scalar, "{}", engine);
+        }
+    }
+
+    #[test]
+    fn reuse_scratch_across_chunks() {
+        let bytes = "jack;1.2\njill;-3.4\n".repeat(1000);
+        let (mut records, mut scratch) = (StationRecords::new(), ScratchSpace::new());
+
+        ParserEngine::Batched.parse_chunk_with(bytes.as_bytes(), &mut records, &mut scratch);
+        let allocated = scratch.allocated_bytes();
+        ParserEngine::Batched.parse_chunk_with(bytes.as_bytes(), &mut records, &mut scratch);
+
+        assert!(allocated > 0);
+        assert_eq!(scratch.allocated_bytes(), allocated);
+        assert_eq!(records.get(&b"jill".as_slice().into()).unwrap().count, 2000);
+    }
+}
@@ -0,0 +1,209 @@
+//! Only aggregate the stations whose names match some patterns.
+//!
+//! A run focused on a few stations would otherwise insert every record of the input into the
+//! records only to drop most of them at the end. The consumers instead parse each chunk with
+//! [`StationFilter::parse_chunk`], which matches the name of each line right after finding its
+//! `;`, and skips the value and the insertion of any station filtered out.
+//!
+//! A pattern is a station name to match exactly, `prefix:` followed by the start of the names
+//! to match, or `regex:` followed by a regular expression matched anywhere in the names, e.g.
+//! `regex:^(Abha|Accra)$`; `exact:` can be given explicitly for a name starting with `prefix:`
+//! or `regex:`.
+
+use std::hash::BuildHasher;
+use std::str::FromStr;
+
+use super::{aggregator::Aggregator, func, models::StationRecords, sync::parse_value};
+
+/// A pattern matched against the station names.
+#[derive(Debug, Clone)]
+pub enum NamePattern {
+    /// The whole name.
+    Exact(Vec<u8>),
+
+    /// The start of the name.
+    Prefix(Vec<u8>),
+
+    /// A regular expression matched anywhere in the name.
+    Regex(regex::bytes::Regex),
+}
+
+impl NamePattern {
+    /// Whether `name` matches the pattern.
+    #[inline]
+    pub fn matches(&self, name: &[u8]) -> bool {
+        match self {
+            Self::Exact(exact) => name == exact.as_slice(),
+            Self::Prefix(prefix) => name.starts_with(prefix),
+            Self::Regex(regex) => regex.is_match(name),
+        }
+    }
+
+    /// The bytes any matching name starts with, unless it can start with any byte.
+    fn first_byte(&self) -> Option<Option<u8>> {
+        match self {
+            Self::Exact(bytes) | Self::Prefix(bytes) => Some(bytes.first().copied()),
+            Self::Regex(_) => None,
+        }
+    }
+}
+
+impl FromStr for NamePattern {
+    type Err = String;
+
+    fn from_str(pattern: &str) -> Result<Self, Self::Err> {
+        if let Some(prefix) = pattern.strip_prefix("prefix:") {
+            Ok(Self::Prefix(prefix.as_bytes().to_vec()))
+        } else if let Some(regex) = pattern.strip_prefix("regex:") {
+            regex::bytes::Regex::new(regex)
+                .map(Self::Regex)
+                .map_err(|error| error.to_string())
+        } else {
+            let exact = pattern.strip_prefix("exact:").unwrap_or(pattern);
+            Ok(Self::Exact(exact.as_bytes().to_vec()))
+        }
+    }
+}
+
+impl std::fmt::Display for NamePattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Exact(exact) => write!(f, "exact:{}", func::bytes_to_string(exact)),
+            Self::Prefix(prefix) => write!(f, "prefix:{}", func::bytes_to_string(prefix)),
+            Self::Regex(regex) => write!(f, "regex:{}", regex.as_str()),
+        }
+    }
+}
+
+impl PartialEq for NamePattern {
+    fn eq(&self, other: &Self) -> bool {
+        self.to_string() == other.to_string()
+    }
+}
+
+/// The stations to aggregate: those matching any of the included patterns, or every station if
+/// there are none, unless they match any of the excluded patterns.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StationFilter {
+    include: Vec<NamePattern>,
+    exclude: Vec<NamePattern>,
+    /// Whether a name starting with each byte may match an included pattern, so that most
+    /// names are rejected on their first byte; every byte may if there is no included pattern,
+    /// or an included regex.
+    first_bytes: [bool; 256],
+}
+
+impl StationFilter {
+    /// Aggregate the stations matching any of `include`, or every station if empty, and none
+    /// matching any of `exclude`.
+    pub fn new(include: Vec<NamePattern>, exclude: Vec<NamePattern>) -> Self {
+        let mut first_bytes = [include.is_empty(); 256];
+
+        for pattern in &include {
+            match pattern.first_byte() {
+                Some(Some(byte)) => first_bytes[byte as usize] = true,
+                // An empty prefix or name, or a regex, may start with anything.
+                Some(None) | None => first_bytes = [true; 256],
+            }
+        }
+
+        Self {
+            include,
+            exclude,
+            first_bytes,
+        }
+    }
+
+    /// Whether the station `name` is aggregated.
+    #[inline]
+    pub fn matches(&self, name: &[u8]) -> bool {
+        let first = name
+            .first()
+            .is_none_or(|&byte| self.first_bytes[byte as usize]);
+
+        first
+            && (self.include.is_empty() || self.include.iter().any(|pattern| pattern.matches(name)))
+            && !self.exclude.iter().any(|pattern| pattern.matches(name))
+    }
+
+    /// Parse a chunk of complete lines into `records` like
+    /// [`line::parse_chunk`](super::line::parse_chunk), only parsing the values of and
+    /// inserting the stations matching the filter; returns the number of records aggregated.
+    pub fn parse_chunk<A: Aggregator, S: BuildHasher>(
+        &self,
+        bytes: &[u8],
+        records: &mut StationRecords<A, S>,
+    ) -> usize {
+        let mut count = 0;
+
+        for line in bytes
+            .split(|&byte| byte == b'\n')
+            .filter(|line| !line.is_empty())
+        {
+            let semicolon = func::find_semicolon(line).unwrap_or_else(|| {
+                panic!(
+                    "StationFilter::parse_chunk() found an invalid line: {:?}",
+                    func::bytes_to_string(line)
+                )
+            });
+
+            let name = &line[..semicolon];
+            if self.matches(name) {
+                records.insert_borrowed(name, parse_value(&line[semicolon + 1..]));
+                count += 1;
+            }
+        }
+
+        count
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn patterns(patterns: &[&str]) -> Vec<NamePattern> {
+        patterns
+            .iter()
+            .map(|pattern| pattern.parse().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn parse_patterns() {
+        assert_eq!(
+            patterns(&["Abha", "prefix:Ab", "regex:^A.a$", "exact:prefix:x"]),
+            [
+                NamePattern::Exact(b"Abha".to_vec()),
+                NamePattern::Prefix(b"Ab".to_vec()),
+                NamePattern::Regex(regex::bytes::Regex::new("^A.a$").unwrap()),
+                NamePattern::Exact(b"prefix:x".to_vec()),
+            ]
+        );
+        assert!("regex:(".parse::<NamePattern>().is_err());
+    }
+
+    #[test]
+    fn match_names() {
+        let filter = StationFilter::new(patterns(&["Abha", "prefix:Zu"]), patterns(&["Zug"]));
+
+        assert!(filter.matches(b"Abha") && filter.matches(b"Zurich"));
+        assert!(!filter.matches(b"Abhaa") && !filter.matches(b"Zug") && !filter.matches(b"Lyon"));
+
+        let exclude_only = StationFilter::new(vec![], patterns(&["regex:^L"]));
+        assert!(exclude_only.matches(b"Abha") && !exclude_only.matches(b"Lyon"));
+
+        let regex = StationFilter::new(patterns(&["regex:a$"]), vec![]);
+        assert!(regex.matches(b"Abha") && regex.matches(b"Accra") && !regex.matches(b"Zug"));
+    }
+
+    #[test]
+    fn parse_filtered_chunk() {
+        let bytes = b"jack;1.2\njill;-3.4\njack;5.6\nbob;0.0\n";
+        let filter = StationFilter::new(patterns(&["prefix:j"]), patterns(&["jill"]));
+
+        let mut records = StationRecords::new();
+        assert_eq!(filter.parse_chunk(bytes, &mut records), 2);
+        assert_eq!(records.export_text(), "{jack=1.2/3.4/5.6}\n");
+    }
+}
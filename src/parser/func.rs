@@ -9,3 +9,276 @@ pub fn u8_to_digit(byte: u8) -> u8 {
 pub fn bytes_to_string(bytes: &[u8]) -> std::borrow::Cow<'_, str> {
     String::from_utf8_lossy(bytes)
 }
+
+/// The length of the shortest value of a 1BRC line, e.g. `1.2`.
+const MIN_VALUE_LENGTH: usize = 3;
+
+/// The length of the longest value of a 1BRC line, e.g. `-12.3`.
+const MAX_VALUE_LENGTH: usize = 5;
+
+/// Find the position of the `;` of a line without its newline.
+///
+/// The value is 3 to 5 bytes long, so the `;` can only be 4 to 6 bytes from the end of the
+/// line; those positions are probed first, whatever the length of the name. If the value does
+/// not have a valid length, this falls back to a backward scan for the last `;`, which gives
+/// the same result for any line with a valid value.
+#[inline]
+pub fn find_semicolon(line: &[u8]) -> Option<usize> {
+    (MIN_VALUE_LENGTH..=MAX_VALUE_LENGTH)
+        .filter_map(|value_length| line.len().checked_sub(value_length + 1))
+        .find(|&position| line[position] == b';')
+        .or_else(|| line.iter().rposition(|&byte| byte == b';'))
+}
+
+/// Why a line is rejected by [`check_line`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvalidLine {
+    /// The line has no `;`.
+    MissingSemicolon,
+    /// The station name is empty.
+    EmptyName,
+    /// The station name is longer than the maximum length.
+    LongName,
+    /// The value is empty.
+    EmptyValue,
+    /// The value does not match `-?\d+\.\d`, e.g. with multiple signs, multiple `;` or other
+    /// characters.
+    MalformedValue,
+    /// The value is well-formed but has more than 2 integer digits, which is out of the range
+    /// of the 1BRC values, and may overflow an [`i16`] in the parsers of the hot paths.
+    OutOfRange,
+}
+
+impl std::fmt::Display for InvalidLine {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingSemicolon => write!(f, "missing `;`"),
+            Self::EmptyName => write!(f, "empty station name"),
+            Self::LongName => write!(f, "station name too long"),
+            Self::EmptyValue => write!(f, "empty value"),
+            Self::MalformedValue => write!(f, "malformed value"),
+            Self::OutOfRange => write!(f, "value out of range"),
+        }
+    }
+}
+
+/// Strictly validate a line without its newline, with a name of up to `max_name_length`
+/// bytes, returning its name and value.
+///
+/// Like [`parse_value_checked`], this is not meant for the hot paths.
+pub fn check_line(line: &[u8], max_name_length: usize) -> Result<(&[u8], i16), InvalidLine> {
+    let semicolon = memchr::memchr(b';', line).ok_or(InvalidLine::MissingSemicolon)?;
+    let (name, value) = (&line[..semicolon], &line[semicolon + 1..]);
+
+    if name.is_empty() {
+        return Err(InvalidLine::EmptyName);
+    } else if name.len() > max_name_length {
+        return Err(InvalidLine::LongName);
+    }
+
+    match parse_value_checked(value) {
+        Some(value) => Ok((name, value)),
+        None if value.is_empty() => Err(InvalidLine::EmptyValue),
+        None => {
+            let digits = value.strip_prefix(b"-").unwrap_or(value);
+
+            match digits {
+                [integer @ .., b'.', decimal]
+                    if integer.len() > 2
+                        && decimal.is_ascii_digit()
+                        && integer.iter().all(u8::is_ascii_digit) =>
+                {
+                    Err(InvalidLine::OutOfRange)
+                }
+                _ => Err(InvalidLine::MalformedValue),
+            }
+        }
+    }
+}
+
+/// Find the first invalid line of `bytes` according to [`check_line`], returning the 1-based
+/// number of the line within `bytes`, the reason, and the line without its newline.
+///
+/// Empty lines are skipped, like the parsers do.
+pub fn find_invalid_line(
+    bytes: &[u8],
+    max_name_length: usize,
+) -> Option<(usize, InvalidLine, &[u8])> {
+    bytes
+        .split(|&byte| byte == b'\n')
+        .enumerate()
+        .filter(|(_, line)| !line.is_empty())
+        .find_map(|(index, line)| {
+            check_line(line, max_name_length)
+                .err()
+                .map(|reason| (index + 1, reason, line))
+        })
+}
+
+/// Parse a 1BRC value, returning [`None`] if it does not match `-?\d{1,2}\.\d`.
+///
+/// Unlike the parsers used in the hot paths, this strictly validates the input, and is
+/// meant for inspecting untrusted data.
+pub fn parse_value_checked(bytes: &[u8]) -> Option<i16> {
+    let (multiplier, digits) = match bytes.split_first() {
+        Some((b'-', digits)) => (-1, digits),
+        _ => (1, bytes),
+    };
+
+    match digits {
+        [integer @ .., b'.', decimal]
+            if (1..=2).contains(&integer.len())
+                && decimal.is_ascii_digit()
+                && integer.iter().all(u8::is_ascii_digit) =>
+        {
+            Some(
+                integer
+                    .iter()
+                    .chain(std::iter::once(decimal))
+                    .fold(0, |acc, &digit| acc * 10 + u8_to_digit(digit) as i16)
+                    * multiplier,
+            )
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    macro_rules! expand_parse_value_checked_tests {
+        ($((
+            $name:ident,
+            $input:expr,
+            $expected:expr
+        )),*$(,)?) => {
+            $(
+                #[test]
+                fn $name() {
+                    assert_eq!(parse_value_checked($input.as_bytes()), $expected);
+                }
+            )*
+        };
+    }
+
+    #[test]
+    fn find_semicolon_of_every_shape() {
+        for name in ["", "A", "Abha", "Petropavlovsk-Kamchatsky"] {
+            for value in ["1.2", "-1.2", "12.3", "-12.3"] {
+                let line = format!("{};{}", name, value);
+
+                assert_eq!(
+                    find_semicolon(line.as_bytes()),
+                    Some(name.len()),
+                    "{}",
+                    line
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn find_semicolon_fallback() {
+        assert_eq!(find_semicolon(b"Abha;123.45"), Some(4));
+        assert_eq!(find_semicolon(b"Abha;1"), Some(4));
+        assert_eq!(find_semicolon(b";"), Some(0));
+        assert_eq!(find_semicolon(b"Abha"), None);
+        assert_eq!(find_semicolon(b""), None);
+    }
+
+    macro_rules! expand_check_line_tests {
+        ($((
+            $name:ident,
+            $input:expr,
+            $expected:expr
+        )),*$(,)?) => {
+            $(
+                #[test]
+                fn $name() {
+                    assert_eq!(check_line($input.as_bytes(), 8), $expected);
+                }
+            )*
+        };
+    }
+
+    expand_check_line_tests!(
+        (
+            check_line_valid,
+            "Abha;-12.3",
+            Ok((b"Abha".as_slice(), -123))
+        ),
+        (
+            check_line_missing_semicolon,
+            "Abha 12.3",
+            Err(InvalidLine::MissingSemicolon)
+        ),
+        (check_line_empty_name, ";12.3", Err(InvalidLine::EmptyName)),
+        (
+            check_line_long_name,
+            "Petropavlovsk;12.3",
+            Err(InvalidLine::LongName)
+        ),
+        (
+            check_line_empty_value,
+            "Abha;",
+            Err(InvalidLine::EmptyValue)
+        ),
+        (
+            check_line_double_sign,
+            "Abha;--1.2",
+            Err(InvalidLine::MalformedValue)
+        ),
+        (
+            check_line_double_semicolon,
+            "Abha;;1.2",
+            Err(InvalidLine::MalformedValue)
+        ),
+        (
+            check_line_no_decimal,
+            "Abha;12",
+            Err(InvalidLine::MalformedValue)
+        ),
+        (
+            check_line_out_of_range,
+            "Abha;123.4",
+            Err(InvalidLine::OutOfRange)
+        ),
+        (
+            check_line_overflow,
+            "Abha;-99999.9",
+            Err(InvalidLine::OutOfRange)
+        ),
+    );
+
+    #[test]
+    fn find_invalid_line_of_chunk() {
+        let chunk = b"Abha;1.2\n\nTokyo;-12.3\nTokyo;1-2.3\nAbha;5.6\n";
+
+        assert_eq!(
+            find_invalid_line(chunk, 8),
+            Some((4, InvalidLine::MalformedValue, b"Tokyo;1-2.3".as_slice()))
+        );
+        assert_eq!(find_invalid_line(&chunk[..22], 8), None);
+        assert_eq!(
+            find_invalid_line(chunk, 4),
+            Some((3, InvalidLine::LongName, b"Tokyo;-12.3".as_slice()))
+        );
+    }
+
+    expand_parse_value_checked_tests!(
+        (parse_value_checked_0, "0.0", Some(0)),
+        (parse_value_checked_12, "1.2", Some(12)),
+        (parse_value_checked_999, "99.9", Some(999)),
+        (parse_value_checked_neg_0, "-0.0", Some(0)),
+        (parse_value_checked_neg_999, "-99.9", Some(-999)),
+        (parse_value_checked_empty, "", None),
+        (parse_value_checked_sign_only, "-", None),
+        (parse_value_checked_no_decimal, "12", None),
+        (parse_value_checked_two_decimals, "1.23", None),
+        (parse_value_checked_three_digits, "123.4", None),
+        (parse_value_checked_double_sign, "--1.2", None),
+        (parse_value_checked_letters, "1a.2", None),
+        (parse_value_checked_trailing_newline, "1.2\n", None),
+    );
+}
@@ -0,0 +1,315 @@
+//! Parse the chunks on the GPU, as an experiment against the SIMD CPU path.
+//!
+//! Each chunk is uploaded to a wgpu compute shader, `gpu.wgsl`, which runs one invocation per
+//! byte: the invocation of each `;` finds the start of its name and converts its value, and
+//! appends the record as the offset and length of the name and the value. The records are read
+//! back, put in order of offset, and aggregated on the CPU, which still hashes every name.
+//!
+//! The GPU is set up once, on the first chunk, with the default adapter of the system. A
+//! single set of buffers is kept, so the consumers take turns on the GPU; the chunks are parsed
+//! on the CPU by [`sync::parse_bytes_batched`] instead if no adapter is found, or if a chunk
+//! does not fit the buffers the device allows.
+
+use std::hash::BuildHasher;
+use std::sync::{Mutex, OnceLock, PoisonError};
+
+use super::aggregator::Aggregator;
+use super::models::StationRecords;
+use super::sync;
+
+/// The source of the compute shader.
+const SHADER: &str = include_str!("gpu.wgsl");
+
+/// The number of invocations of a workgroup, as declared by the shader.
+const WORKGROUP_SIZE: u32 = 256;
+
+/// The number of words of each record found by the shader.
+const RECORD_WORDS: usize = 3;
+
+/// The fewest bytes a line with a record can take, i.e. `;`, a digit and `\n`; the records
+/// buffer has room for one record per this many bytes of the chunk.
+const MIN_LINE_LENGTH: usize = 3;
+
+/// The GPU, set up on first use; `None` if no adapter could be found.
+static GPU: OnceLock<Option<Gpu>> = OnceLock::new();
+
+/// The buffers of the shader, sized for the largest chunk so far.
+struct Buffers {
+    /// The length of the largest chunk the buffers have room for.
+    len: usize,
+    params: wgpu::Buffer,
+    chunk: wgpu::Buffer,
+    records: wgpu::Buffer,
+    found: wgpu::Buffer,
+    /// The buffer the records and their count are copied to, to be read back.
+    staging: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+/// A device running the compute shader.
+struct Gpu {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    pipeline: wgpu::ComputePipeline,
+    /// The name and backend of the adapter.
+    name: String,
+    buffers: Mutex<Option<Buffers>>,
+}
+
+/// The name and backend of the GPU the chunks are parsed on, or `None` if no adapter could be
+/// found, and the chunks are parsed on the CPU.
+pub fn adapter_name() -> Option<&'static str> {
+    gpu().map(|gpu| gpu.name.as_str())
+}
+
+/// Get the GPU, setting it up on the first call.
+fn gpu() -> Option<&'static Gpu> {
+    GPU.get_or_init(|| pollster::block_on(Gpu::new())).as_ref()
+}
+
+/// Parse a chunk of complete lines into `records` on the GPU, returning the number of records
+/// parsed; on the CPU if no GPU is available, or if the chunk is too large for it.
+pub fn parse_chunk<A: Aggregator, S: BuildHasher>(
+    bytes: &[u8],
+    records: &mut StationRecords<A, S>,
+) -> usize {
+    let Some(found) = gpu().and_then(|gpu| gpu.find_records(bytes)) else {
+        return sync::parse_bytes_batched(bytes, records);
+    };
+
+    for &[start, len, value] in &found {
+        let (start, len) = (start as usize, len as usize);
+        records.insert_borrowed(&bytes[start..start + len], value as i32 as i16);
+    }
+
+    found.len()
+}
+
+impl Gpu {
+    /// Set up the default adapter of the system, if any.
+    async fn new() -> Option<Self> {
+        let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::default());
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::HighPerformance,
+                ..Default::default()
+            })
+            .await
+            .ok()?;
+        let info = adapter.get_info();
+
+        let (device, queue) = adapter
+            .request_device(&wgpu::DeviceDescriptor {
+                label: Some("async-1brc"),
+                required_limits: adapter.limits(),
+                ..Default::default()
+            })
+            .await
+            .ok()?;
+
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("gpu.wgsl"),
+            source: wgpu::ShaderSource::Wgsl(SHADER.into()),
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("find_records"),
+            layout: None,
+            module: &module,
+            entry_point: Some("main"),
+            compilation_options: Default::default(),
+            cache: None,
+        });
+
+        Some(Self {
+            device,
+            queue,
+            pipeline,
+            name: format!("{} ({:?})", info.name, info.backend),
+            buffers: Mutex::new(None),
+        })
+    }
+
+    /// Create the buffers for chunks of up to `len` bytes, or `None` if they would exceed the
+    /// limits of the device.
+    fn create_buffers(&self, len: usize) -> Option<Buffers> {
+        let limits = self.device.limits();
+        let chunk_size = len.next_multiple_of(4) as u64;
+        let records_size = (capacity(len) * RECORD_WORDS * 4) as u64;
+
+        let max_binding = limits.max_storage_buffer_binding_size as u64;
+        if chunk_size > max_binding || records_size > max_binding {
+            return None;
+        }
+
+        let buffer = |label, size, usage| {
+            self.device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(label),
+                size,
+                usage,
+                mapped_at_creation: false,
+            })
+        };
+        let storage = wgpu::BufferUsages::STORAGE;
+        let copy = wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::COPY_DST;
+
+        let params = buffer("params", 16, wgpu::BufferUsages::UNIFORM | copy);
+        let chunk = buffer("chunk", chunk_size, storage | copy);
+        let records = buffer("records", records_size, storage | copy);
+        let found = buffer("found", 4, storage | copy);
+        let staging = buffer(
+            "staging",
+            records_size + 4,
+            wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+        );
+
+        let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("find_records"),
+            layout: &self.pipeline.get_bind_group_layout(0),
+            entries: &[&params, &chunk, &records, &found]
+                .into_iter()
+                .enumerate()
+                .map(|(binding, buffer)| wgpu::BindGroupEntry {
+                    binding: binding as u32,
+                    resource: buffer.as_entire_binding(),
+                })
+                .collect::<Vec<_>>(),
+        });
+
+        Some(Buffers {
+            len,
+            params,
+            chunk,
+            records,
+            found,
+            staging,
+            bind_group,
+        })
+    }
+
+    /// Find the records of `bytes` with the shader, as the offset and length of each name and
+    /// its value, in order of offset; `None` if the chunk does not fit the device, or has more
+    /// `;` than lines can hold.
+    fn find_records(&self, bytes: &[u8]) -> Option<Vec<[u32; 3]>> {
+        if bytes.is_empty() {
+            return Some(Vec::new());
+        }
+
+        let len = u32::try_from(bytes.len()).ok()?;
+        let mut buffers = self.buffers.lock().unwrap_or_else(PoisonError::into_inner);
+        if buffers
+            .as_ref()
+            .is_none_or(|buffers| buffers.len < bytes.len())
+        {
+            *buffers = Some(self.create_buffers(bytes.len())?);
+        }
+        let buffers = buffers.as_ref()?;
+
+        // The buffers are written in whole words, so the last few bytes are padded.
+        let aligned = bytes.len() / 4 * 4;
+        let mut tail = [0; 4];
+        tail[..bytes.len() - aligned].copy_from_slice(&bytes[aligned..]);
+
+        let groups = len.div_ceil(WORKGROUP_SIZE);
+        let max_groups = self.device.limits().max_compute_workgroups_per_dimension;
+        let (columns, rows) = (groups.min(max_groups), groups.div_ceil(max_groups));
+        let capacity = capacity(bytes.len()) as u32;
+
+        let params = [len, capacity, columns * WORKGROUP_SIZE, 0];
+        self.queue
+            .write_buffer(&buffers.params, 0, &words_to_bytes(&params));
+        if aligned > 0 {
+            self.queue
+                .write_buffer(&buffers.chunk, 0, &bytes[..aligned]);
+        }
+        if aligned < bytes.len() {
+            self.queue
+                .write_buffer(&buffers.chunk, aligned as u64, &tail);
+        }
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        encoder.clear_buffer(&buffers.found, 0, None);
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor::default());
+            pass.set_pipeline(&self.pipeline);
+            pass.set_bind_group(0, &buffers.bind_group, &[]);
+            pass.dispatch_workgroups(columns, rows, 1);
+        }
+
+        let records_size = capacity as u64 * (RECORD_WORDS * 4) as u64;
+        encoder.copy_buffer_to_buffer(&buffers.found, 0, &buffers.staging, 0, 4);
+        encoder.copy_buffer_to_buffer(&buffers.records, 0, &buffers.staging, 4, records_size);
+        self.queue.submit([encoder.finish()]);
+
+        let slice = buffers.staging.slice(..4 + records_size);
+        slice.map_async(wgpu::MapMode::Read, |_| {});
+        self.device.poll(wgpu::PollType::Wait).ok()?;
+
+        let found = {
+            let mapped = slice.get_mapped_range();
+            let words = bytes_to_words(&mapped);
+
+            match words[0] {
+                found if found > capacity => None,
+                found => {
+                    let mut records = words[1..][..found as usize * RECORD_WORDS]
+                        .chunks_exact(RECORD_WORDS)
+                        .map(|record| [record[0], record[1], record[2]])
+                        .collect::<Vec<_>>();
+
+                    records.sort_unstable_by_key(|record| record[0]);
+                    Some(records)
+                }
+            }
+        };
+        buffers.staging.unmap();
+
+        found
+    }
+}
+
+/// The number of records the buffers have room for in a chunk of `len` bytes.
+fn capacity(len: usize) -> usize {
+    len / MIN_LINE_LENGTH + 1
+}
+
+/// The little-endian bytes of `words`.
+fn words_to_bytes(words: &[u32]) -> Vec<u8> {
+    words.iter().flat_map(|word| word.to_le_bytes()).collect()
+}
+
+/// The little-endian words of `bytes`.
+fn bytes_to_words(bytes: &[u8]) -> Vec<u32> {
+    bytes
+        .chunks_exact(4)
+        .map(|word| u32::from_le_bytes(word.try_into().unwrap()))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn agree_with_cpu() {
+        let bytes = "jack;1.2\njill;-3.4\n\njack;5.6\nZürich;-0.1\n".repeat(1000);
+
+        let (mut gpu, mut cpu) = (StationRecords::new(), StationRecords::new());
+        assert_eq!(parse_chunk(bytes.as_bytes(), &mut gpu), 4000);
+        sync::parse_bytes_batched(bytes.as_bytes(), &mut cpu);
+
+        // Without an adapter, this only checks the fallback to the CPU.
+        assert_eq!(gpu, cpu, "on {:?}", adapter_name());
+    }
+
+    #[test]
+    fn pad_the_last_word() {
+        // The length is not a multiple of 4, and the chunk ends without a newline.
+        let bytes = b"a;1.0\nbb;-2.5";
+
+        let mut records = StationRecords::new();
+        assert_eq!(parse_chunk(bytes, &mut records), 2);
+        assert_eq!(records.get(&b"bb".into()).unwrap().min, -25);
+    }
+}
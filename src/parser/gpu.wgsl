@@ -0,0 +1,72 @@
+// Find the records of a chunk of 1BRC lines, one invocation per byte.
+//
+// The invocation of each `;` scans back to the start of its line for the name, and forward to
+// the end of its line for the value, then appends the record as 3 words: the offset of the
+// name, its length, and the value in tenths of a degree. The records are appended in no
+// particular order; the CPU puts them back in order of offset.
+
+struct Params {
+    // The number of bytes of the chunk.
+    len: u32,
+    // The number of records `records` has room for.
+    capacity: u32,
+    // The number of invocations in a row of workgroups.
+    row: u32,
+    _padding: u32,
+}
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var<storage, read> chunk: array<u32>;
+@group(0) @binding(2) var<storage, read_write> records: array<u32>;
+@group(0) @binding(3) var<storage, read_write> found: atomic<u32>;
+
+const SEMICOLON: u32 = 0x3bu;
+const NEWLINE: u32 = 0x0au;
+const MINUS: u32 = 0x2du;
+const ZERO: u32 = 0x30u;
+const NINE: u32 = 0x39u;
+
+// The byte at `index` of the chunk, packed 4 to a little-endian word.
+fn byte_at(index: u32) -> u32 {
+    return (chunk[index >> 2u] >> ((index & 3u) * 8u)) & 0xffu;
+}
+
+@compute @workgroup_size(256)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let separator = id.x + id.y * params.row;
+    if (separator >= params.len || byte_at(separator) != SEMICOLON) {
+        return;
+    }
+
+    var start = separator;
+    while (start > 0u && byte_at(start - 1u) != NEWLINE) {
+        start -= 1u;
+    }
+
+    // The digits are read as a fixed-point number, skipping the decimal point.
+    var value = 0i;
+    var negative = false;
+    var index = separator + 1u;
+    while (index < params.len) {
+        let byte = byte_at(index);
+        if (byte == NEWLINE) {
+            break;
+        } else if (byte == MINUS) {
+            negative = true;
+        } else if (byte >= ZERO && byte <= NINE) {
+            value = value * 10i + i32(byte - ZERO);
+        }
+        index += 1u;
+    }
+    if (negative) {
+        value = -value;
+    }
+
+    // Records past the capacity are only counted, so that the CPU can tell they were lost.
+    let slot = atomicAdd(&found, 1u);
+    if (slot < params.capacity) {
+        records[slot * 3u] = start;
+        records[slot * 3u + 1u] = separator - start;
+        records[slot * 3u + 2u] = bitcast<u32>(value);
+    }
+}
@@ -1,78 +1,132 @@
-//! A [`u8`] buffer that just use its first 7 characters as the hash.
+//! A [`u8`] buffer keeping short station names inline.
 
-#[cfg(not(feature = "nohash"))]
-pub type LiteHashBuffer = Vec<u8>;
+use smallvec::SmallVec;
 
-#[cfg(feature = "nohash")]
-pub use _nohash_buffer::LiteHashBuffer;
+/// The number of bytes kept inline by a [`LiteHashBuffer`] before allocating on the heap;
+/// this covers the vast majority of the station names.
+pub const INLINE_CAPACITY: usize = 24;
 
-#[cfg(feature = "nohash")]
-mod _nohash_buffer {
-    /// A [`u8`] buffer that just use its first 7 characters as the hash.
-    ///
-    /// This will cause hash collisions if two identically sized buffer contains identical
-    /// first 7 characters; however this is considered not a problem for the purpose of this
-    /// crate.
-    #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
-    pub struct LiteHashBuffer {
-        buffer: Vec<u8>,
-    }
+/// A [`u8`] buffer used as the key of the station names.
+///
+/// Names of up to [`INLINE_CAPACITY`] bytes are stored inline, so that the keys of the common
+/// case live entirely in the table entries without a heap allocation.
+///
+/// With the `nohash` feature, this just uses its first 7 characters as the hash. This will
+/// cause hash collisions if two identically sized buffer contains identical first 7
+/// characters; however this is considered not a problem for the purpose of this crate.
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct LiteHashBuffer {
+    buffer: SmallVec<[u8; INLINE_CAPACITY]>,
+}
 
-    impl LiteHashBuffer {
-        /// Create a new instance with a buffer.
-        pub fn new(buffer: Vec<u8>) -> Self {
-            Self { buffer }
+impl LiteHashBuffer {
+    /// Create a new instance with a buffer.
+    pub fn new(buffer: Vec<u8>) -> Self {
+        Self {
+            buffer: SmallVec::from_vec(buffer),
         }
     }
 
-    impl<T> From<T> for LiteHashBuffer
-    where
-        T: Into<Vec<u8>>,
-    {
-        // Create a new instance with a buffer.
-        fn from(buffer: T) -> Self {
-            Self::new(buffer.into())
-        }
+    /// Get the bytes of the buffer.
+    pub fn as_slice(&self) -> &[u8] {
+        &self.buffer
     }
 
-    #[cfg(not(feature = "nohash"))]
-    impl std::hash::Hash for LiteHashBuffer {
-        // Hash the buffer using the [`GxHash`] algorithm.
-        fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-            self.buffer.hash(state)
-        }
+    /// Check if the buffer is stored inline, without a heap allocation.
+    pub fn is_inline(&self) -> bool {
+        !self.buffer.spilled()
     }
+}
 
-    #[cfg(feature = "nohash")]
-    impl std::hash::Hash for LiteHashBuffer {
-        // Invoke write_u64() on the length of the buffer followed by the first 7 bytes of
-        // the buffer.
-        //
-        // This allows the buffer to be hashed with [`nohash`] without actually hashing the
-        // buffer.
-        //
-        // This however did not appear to be as fast as GxHash in itself.
-        fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-            state.write_u64(
-                self.buffer
-                    .iter()
-                    .take(7)
-                    .enumerate()
-                    .fold(self.buffer.len() as u64, |acc, (pos, &byte)| {
-                        acc | ((byte as u64) << (pos * 8))
-                    }),
-            )
+impl From<&[u8]> for LiteHashBuffer {
+    fn from(buffer: &[u8]) -> Self {
+        Self {
+            buffer: SmallVec::from_slice(buffer),
         }
     }
+}
 
-    #[cfg(feature = "nohash")]
-    impl nohash::IsEnabled for LiteHashBuffer {}
+impl<const N: usize> From<&[u8; N]> for LiteHashBuffer {
+    fn from(buffer: &[u8; N]) -> Self {
+        Self::from(&buffer[..])
+    }
+}
 
-    impl std::ops::Deref for LiteHashBuffer {
-        type Target = Vec<u8>;
+impl From<Vec<u8>> for LiteHashBuffer {
+    fn from(buffer: Vec<u8>) -> Self {
+        Self::new(buffer)
+    }
+}
 
-        fn deref(&self) -> &Self::Target {
-            &self.buffer
-        }
+impl From<LiteHashBuffer> for Vec<u8> {
+    fn from(buffer: LiteHashBuffer) -> Self {
+        buffer.buffer.into_vec()
+    }
+}
+
+#[cfg(not(feature = "nohash"))]
+impl std::hash::Hash for LiteHashBuffer {
+    // Hash the bytes the same way as a `[u8]`.
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.as_slice().hash(state)
+    }
+}
+
+#[cfg(feature = "nohash")]
+impl std::hash::Hash for LiteHashBuffer {
+    // Invoke write_u64() on the length of the buffer followed by the first 7 bytes of
+    // the buffer.
+    //
+    // This allows the buffer to be hashed with [`nohash`] without actually hashing the
+    // buffer.
+    //
+    // This however did not appear to be as fast as GxHash in itself.
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        state.write_u64(
+            self.buffer
+                .iter()
+                .take(7)
+                .enumerate()
+                .fold(self.buffer.len() as u64, |acc, (pos, &byte)| {
+                    acc | ((byte as u64) << (pos * 8))
+                }),
+        )
+    }
+}
+
+#[cfg(feature = "nohash")]
+impl nohash::IsEnabled for LiteHashBuffer {}
+
+impl std::ops::Deref for LiteHashBuffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        &self.buffer
+    }
+}
+
+// The hash of `nohash` differs from that of a `[u8]`, so the keys cannot be looked up by
+// their bytes with it.
+#[cfg(not(feature = "nohash"))]
+impl std::borrow::Borrow<[u8]> for LiteHashBuffer {
+    fn borrow(&self) -> &[u8] {
+        &self.buffer
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn inline_short_names() {
+        let short = LiteHashBuffer::from("Zürich".as_bytes());
+        let long = LiteHashBuffer::from(&[b'a'; INLINE_CAPACITY + 1]);
+
+        assert!(short.is_inline());
+        assert!(!long.is_inline());
+        assert_eq!(short.as_slice(), "Zürich".as_bytes());
+        assert_eq!(Vec::from(long.clone()), vec![b'a'; INLINE_CAPACITY + 1]);
+        assert!(LiteHashBuffer::new(b"Zug".to_vec()) < short);
     }
 }
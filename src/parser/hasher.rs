@@ -0,0 +1,297 @@
+//! The hasher of the station names.
+//!
+//! The names are hashed by default with [`SwarHasher`], which reads the first 8, and if needed
+//! the next 8, bytes of a name as words and mixes them with a single multiply, rather than
+//! running a general-purpose hasher over every byte. Names sharing their first 16 bytes and
+//! their length collide, which the map resolves by comparing the keys.
+//!
+//! GxHash is the fastest general-purpose hasher for the short station names, but it relies on
+//! the AES instructions of the CPU. With the `portable-hash` feature, the [`StationHasher`] can
+//! also be switched to GxHash where the hardware supports it, detected at runtime, or to
+//! FoldHash everywhere else.
+//!
+//! The GxHash crate still requires the `aes` target feature at compile time, so such a binary
+//! has to be built for a baseline CPU with AES enabled, rather than with
+//! `-C target-cpu=native`:
+//!
+//! ```bash
+//! RUSTFLAGS="-C target-feature=+aes,+sse2" \
+//!     cargo build --release --features portable-hash
+//! ```
+
+#[cfg(not(feature = "portable-hash"))]
+/// The [`std::hash::BuildHasher`] of the station names.
+pub type StationHasher = SwarHasher;
+
+pub use swar::{SwarHasher, SwarHasherState};
+
+#[cfg(feature = "portable-hash")]
+pub use portable::{StationHasher, StationHasherState};
+
+/// Check if the CPU supports the instructions GxHash relies on.
+pub fn is_accelerated() -> bool {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    return std::arch::is_x86_feature_detected!("aes")
+        && std::arch::is_x86_feature_detected!("sse2");
+
+    #[cfg(target_arch = "aarch64")]
+    return std::arch::is_aarch64_feature_detected!("aes")
+        && std::arch::is_aarch64_feature_detected!("neon");
+
+    #[allow(unreachable_code)]
+    false
+}
+
+/// Hash a chunk of bytes into 128 bits with a fixed seed, e.g. for checksums.
+pub fn hash128(bytes: &[u8], seed: i64) -> u128 {
+    #[cfg(feature = "portable-hash")]
+    if !is_accelerated() {
+        use std::hash::BuildHasher;
+
+        let hash = |seed: u64| foldhash::fast::FixedState::with_seed(seed).hash_one(bytes);
+        return (hash(seed as u64) as u128) << 64 | hash(!(seed as u64)) as u128;
+    }
+
+    gxhash::gxhash128(bytes, seed)
+}
+
+mod swar {
+    use std::hash::{BuildHasher, Hasher};
+
+    /// The initial state of every [`SwarHasherState`].
+    const SEED: u64 = 0x243F_6A88_85A3_08D3;
+
+    /// The odd multiplier of the final mix, i.e. 2^64 divided by the golden ratio.
+    const MULTIPLIER: u64 = 0x9E37_79B9_7F4A_7C15;
+
+    /// The rotation between two words, so that identical words do not cancel out.
+    const ROTATION: u32 = 23;
+
+    /// Read 8 bytes at `offset` as a little-endian word.
+    #[inline]
+    fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+        u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+    }
+
+    /// Read 4 bytes at `offset` as a little-endian word.
+    #[inline]
+    fn read_u32(bytes: &[u8], offset: usize) -> u64 {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap()) as u64
+    }
+
+    /// Read up to the first 8 bytes as a single word.
+    ///
+    /// Shorter names are read with two overlapping loads rather than copied into a padded
+    /// word, which would cost a call to `memcpy`; the length is mixed in separately.
+    #[inline]
+    fn load(bytes: &[u8]) -> u64 {
+        match bytes.len() {
+            8.. => read_u64(bytes, 0),
+            len @ 4.. => read_u32(bytes, 0) | read_u32(bytes, len - 4) << 32,
+            len @ 1.. => {
+                (bytes[0] as u64) << 16 | (bytes[len / 2] as u64) << 8 | bytes[len - 1] as u64
+            }
+            0 => 0,
+        }
+    }
+
+    /// Multiply into 128 bits and fold the halves, so that both the low bits used to pick the
+    /// bucket and the high bits used by the control bytes depend on every input bit.
+    #[inline]
+    fn folded_multiply(lhs: u64, rhs: u64) -> u64 {
+        let full = lhs as u128 * rhs as u128;
+        full as u64 ^ (full >> 64) as u64
+    }
+
+    /// The [`BuildHasher`] hashing the station names by their first 16 bytes and length.
+    ///
+    /// The hash is not seeded randomly, and so offers no protection against crafted inputs.
+    #[derive(Debug, Clone, Copy, Default)]
+    pub struct SwarHasher;
+
+    impl BuildHasher for SwarHasher {
+        type Hasher = SwarHasherState;
+
+        #[inline]
+        fn build_hasher(&self) -> Self::Hasher {
+            SwarHasherState { hash: SEED }
+        }
+    }
+
+    /// The [`Hasher`] built by [`SwarHasher`].
+    #[derive(Debug, Clone, Copy)]
+    pub struct SwarHasherState {
+        hash: u64,
+    }
+
+    impl SwarHasherState {
+        #[inline]
+        fn mix(&mut self, word: u64) {
+            self.hash = (self.hash ^ word).rotate_left(ROTATION);
+        }
+    }
+
+    impl Hasher for SwarHasherState {
+        #[inline]
+        fn write(&mut self, bytes: &[u8]) {
+            self.mix(load(bytes));
+
+            // The next 8 bytes overlap the first 8 for names shorter than 16 bytes.
+            if bytes.len() > 8 {
+                self.mix(read_u64(bytes, bytes.len().min(16) - 8));
+            }
+        }
+
+        // Called with the length of the name before its bytes.
+        #[inline]
+        fn write_usize(&mut self, value: usize) {
+            self.mix(value as u64);
+        }
+
+        #[inline]
+        fn finish(&self) -> u64 {
+            folded_multiply(self.hash, MULTIPLIER)
+        }
+    }
+
+    #[cfg(test)]
+    mod test {
+        use super::*;
+
+        #[test]
+        fn hash_names() {
+            let names: [&[u8]; 8] = [
+                b"",
+                b"a",
+                b"a\0",
+                b"Abha",
+                b"Abhb",
+                b"San Francisco",
+                b"San Franciscp",
+                "Zürich".as_bytes(),
+            ];
+
+            let hashes = names
+                .iter()
+                .map(|name| SwarHasher.hash_one(name))
+                .collect::<std::collections::HashSet<_>>();
+
+            assert_eq!(hashes.len(), names.len());
+            assert_eq!(
+                SwarHasher.hash_one(b"Abha".as_slice()),
+                SwarHasher.hash_one(b"Abha".to_vec())
+            );
+        }
+    }
+}
+
+#[cfg(feature = "portable-hash")]
+mod portable {
+    use std::hash::{BuildHasher, Hasher};
+
+    use super::SwarHasher;
+
+    /// The [`BuildHasher`] of the station names, choosing the [`SwarHasher`] by default, or
+    /// GxHash or FoldHash depending on the CPU.
+    #[derive(Debug, Clone)]
+    pub enum StationHasher {
+        Swar(SwarHasher),
+        Gx(gxhash::GxBuildHasher),
+        Fold(foldhash::fast::RandomState),
+    }
+
+    impl StationHasher {
+        /// Use the [`SwarHasher`], regardless of the CPU.
+        pub fn swar() -> Self {
+            Self::Swar(SwarHasher)
+        }
+
+        /// Use GxHash, if the CPU supports it.
+        pub fn accelerated() -> Option<Self> {
+            super::is_accelerated().then(|| Self::Gx(Default::default()))
+        }
+
+        /// Use FoldHash, regardless of the CPU.
+        pub fn portable() -> Self {
+            Self::Fold(Default::default())
+        }
+    }
+
+    impl Default for StationHasher {
+        fn default() -> Self {
+            Self::swar()
+        }
+    }
+
+    impl BuildHasher for StationHasher {
+        type Hasher = StationHasherState;
+
+        fn build_hasher(&self) -> Self::Hasher {
+            match self {
+                Self::Swar(state) => StationHasherState::Swar(state.build_hasher()),
+                Self::Gx(state) => StationHasherState::Gx(state.build_hasher()),
+                Self::Fold(state) => StationHasherState::Fold(state.build_hasher()),
+            }
+        }
+    }
+
+    /// The [`Hasher`] built by [`StationHasher`].
+    pub enum StationHasherState {
+        Swar(super::SwarHasherState),
+        Gx(gxhash::GxHasher),
+        Fold(foldhash::fast::FoldHasher),
+    }
+
+    impl Hasher for StationHasherState {
+        #[inline]
+        fn write(&mut self, bytes: &[u8]) {
+            match self {
+                Self::Swar(hasher) => hasher.write(bytes),
+                Self::Gx(hasher) => hasher.write(bytes),
+                Self::Fold(hasher) => hasher.write(bytes),
+            }
+        }
+
+        #[inline]
+        fn write_usize(&mut self, value: usize) {
+            match self {
+                Self::Swar(hasher) => hasher.write_usize(value),
+                Self::Gx(hasher) => hasher.write_usize(value),
+                Self::Fold(hasher) => hasher.write_usize(value),
+            }
+        }
+
+        #[inline]
+        fn finish(&self) -> u64 {
+            match self {
+                Self::Swar(hasher) => hasher.finish(),
+                Self::Gx(hasher) => hasher.finish(),
+                Self::Fold(hasher) => hasher.finish(),
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod test {
+        use super::*;
+        use crate::parser::models::{StationRecords, StationStats};
+
+        #[test]
+        fn hashers_agree() {
+            let input = b"jack;1.2\njill;-3.4\njack;5.6\n";
+            let parse = |hasher: StationHasher| {
+                let mut records = StationRecords::<StationStats>::with_hasher(hasher);
+                crate::parser::sync::parse_bytes(input, &mut records);
+                records.export_text()
+            };
+
+            let portable = parse(StationHasher::portable());
+
+            assert_eq!(portable, "{jack=1.2/3.4/5.6, jill=-3.4/-3.4/-3.4}\n");
+            assert_eq!(parse(StationHasher::swar()), portable);
+            if let Some(accelerated) = StationHasher::accelerated() {
+                assert_eq!(parse(accelerated), portable);
+            }
+        }
+    }
+}
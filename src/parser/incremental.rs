@@ -0,0 +1,226 @@
+//! Aggregate an input incrementally, as data is appended to it, e.g. a file growing daily.
+//!
+//! [`StationRecords::ingest`] parses the complete lines of any reader into existing records, so
+//! that it can be called again on the data appended since. [`Incremental`] keeps the records
+//! along with the offset of the input they cover, so that only the new bytes of a file are read
+//! on each update, and can be saved and reloaded between the updates.
+//!
+//! The state is saved in a format of its own, little-endian throughout, followed by the
+//! records as a snapshot:
+//!
+//! ```text
+//! magic: [u8; 8] = b"1BRCINCR"
+//! version: u32
+//! offset: u64
+//! records: the snapshot written by `StationRecords::encode_snapshot`
+//! ```
+
+use std::hash::BuildHasher;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use super::aggregator::Aggregator;
+use super::atomic;
+use super::models::StationRecords;
+use super::sync;
+use crate::config;
+
+/// The bytes at the start of every saved state.
+pub const INCREMENTAL_MAGIC: &[u8; 8] = b"1BRCINCR";
+
+/// The version of the format written by [`Incremental::save`].
+pub const INCREMENTAL_VERSION: u32 = 1;
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+impl<A: Aggregator, S: BuildHasher> StationRecords<A, S> {
+    /// Parse the complete lines of `reader` into the records, until the end of its data,
+    /// returning the number of bytes consumed.
+    ///
+    /// An incomplete line at the end, e.g. one still being appended, is read but not parsed,
+    /// nor counted as consumed; the next call should start from the returned number of bytes,
+    /// as [`Incremental::ingest_file`] does.
+    pub fn ingest(&mut self, mut reader: impl Read) -> io::Result<u64> {
+        let mut buffer = Vec::with_capacity(config::CHUNK_SIZE + config::MAX_LINE_LENGTH);
+        let mut consumed = 0;
+
+        loop {
+            let filled = buffer.len();
+            buffer.resize(filled + config::CHUNK_SIZE, 0);
+
+            let read = loop {
+                match reader.read(&mut buffer[filled..]) {
+                    Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                    read => break read?,
+                }
+            };
+
+            buffer.truncate(filled + read);
+            if read == 0 {
+                return Ok(consumed);
+            }
+
+            if let Some(end) = memchr::memrchr(b'\n', &buffer) {
+                sync::parse_bytes_batched(&buffer[..=end], self);
+
+                consumed += end as u64 + 1;
+                buffer.drain(..=end);
+            }
+        }
+    }
+}
+
+/// The records of an input aggregated so far, and the offset of the input they cover.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Incremental {
+    records: StationRecords,
+    offset: u64,
+}
+
+impl Incremental {
+    /// Start from no records, at the start of the input.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The records aggregated so far.
+    pub fn records(&self) -> &StationRecords {
+        &self.records
+    }
+
+    /// Move the records out.
+    pub fn into_records(self) -> StationRecords {
+        self.records
+    }
+
+    /// The offset of the input the records cover, i.e. the bytes consumed so far.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Parse the complete lines of `reader`, the input from the current offset, into the
+    /// records, returning the number of bytes consumed.
+    pub fn ingest(&mut self, reader: impl Read) -> io::Result<u64> {
+        let consumed = self.records.ingest(reader)?;
+        self.offset += consumed;
+
+        Ok(consumed)
+    }
+
+    /// Parse the complete lines appended to the file at `path` since the last update,
+    /// returning the number of bytes consumed.
+    ///
+    /// Fails if the file is now shorter than the offset already covered, as it was truncated or
+    /// replaced, and the records no longer describe it.
+    pub fn ingest_file(&mut self, path: impl AsRef<Path>) -> io::Result<u64> {
+        let mut file = std::fs::File::open(path)?;
+
+        let len = file.metadata()?.len();
+        if len < self.offset {
+            return Err(invalid(format!(
+                "The input of {} bytes is shorter than the {} bytes already ingested.",
+                len, self.offset
+            )));
+        }
+
+        file.seek(SeekFrom::Start(self.offset))?;
+        self.ingest(file)
+    }
+
+    /// Save the records and the offset to `path`, replacing any previous state atomically.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        atomic::write_blocking(path, |file| {
+            file.write_all(INCREMENTAL_MAGIC)?;
+            file.write_all(&INCREMENTAL_VERSION.to_le_bytes())?;
+            file.write_all(&self.offset.to_le_bytes())?;
+            self.records.encode_snapshot(file)
+        })
+    }
+
+    /// Load the records and the offset saved at `path`.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut file = io::BufReader::new(std::fs::File::open(path)?);
+
+        let mut header = [0; 20];
+        file.read_exact(&mut header)?;
+        if &header[..8] != INCREMENTAL_MAGIC {
+            return Err(invalid("Not a saved incremental state."));
+        }
+
+        let version = u32::from_le_bytes(header[8..12].try_into().unwrap());
+        if version != INCREMENTAL_VERSION {
+            return Err(invalid(format!(
+                "Unsupported incremental state version {}, expected {}.",
+                version, INCREMENTAL_VERSION
+            )));
+        }
+
+        Ok(Self {
+            offset: u64::from_le_bytes(header[12..].try_into().unwrap()),
+            records: StationRecords::decode_snapshot(&mut file)?,
+        })
+    }
+
+    /// Load the state saved at `path`, or start a new one if there is none yet.
+    pub fn load_or_new(path: impl AsRef<Path>) -> io::Result<Self> {
+        match Self::load(path) {
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Ok(Self::new()),
+            loaded => loaded,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::TestDir;
+
+    #[test]
+    fn ingest_complete_lines_only() {
+        let mut records = StationRecords::new();
+
+        let consumed = records
+            .ingest(&b"jack;1.2\njill;-3.4\njack;5."[..])
+            .unwrap();
+        assert_eq!(consumed, 19);
+        assert_eq!(
+            records.export_text(),
+            "{jack=1.2/1.2/1.2, jill=-3.4/-3.4/-3.4}\n"
+        );
+
+        records.ingest(&b"jack;5.6\n"[..]).unwrap();
+        assert_eq!(records.get(&b"jack".into()).unwrap().count, 2);
+    }
+
+    #[test]
+    fn update_save_and_reload() {
+        let dir = TestDir::new("update_save_and_reload");
+        let (input, state) = (dir.join("input.txt"), dir.join("state.bin"));
+
+        let lines = "jack;1.2\njill;-3.4\njack;5.6\nbob;0.0\n";
+        std::fs::write(&input, &lines[..24]).unwrap();
+
+        let mut incremental = Incremental::load_or_new(&state).unwrap();
+        assert_eq!(incremental.ingest_file(&input).unwrap(), 19);
+        incremental.save(&state).unwrap();
+
+        // The rest of the line being appended, and another day of data.
+        std::fs::write(&input, lines).unwrap();
+        let mut incremental = Incremental::load(&state).unwrap();
+        assert_eq!(incremental.offset(), 19);
+        incremental.ingest_file(&input).unwrap();
+
+        let mut full = StationRecords::new();
+        full.ingest(lines.as_bytes()).unwrap();
+        assert_eq!(incremental.offset(), lines.len() as u64);
+        assert_eq!(incremental.records(), &full);
+
+        // A truncated input is no longer described by the records.
+        std::fs::write(&input, &lines[..10]).unwrap();
+        let truncated = incremental.ingest_file(&input);
+
+        assert_eq!(truncated.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
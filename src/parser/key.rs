@@ -0,0 +1,187 @@
+//! Derive the grouping key of each line of the input.
+//!
+//! The 1BRC groups the records by the station name, i.e. the bytes before the `;`. A
+//! [`KeyExtractor`] derives the key from the fields before the value instead, so that the same
+//! [`StationRecords`](super::models::StationRecords) can group records by a prefix of the
+//! name, a case-folded name, or a column of a line with multiple fields.
+//!
+//! The keys can also be derived after the parsing with [`StationRecords::regroup`], once per
+//! distinct fields rather than per line, e.g. to group `station;sensor;reading;12.3` by its
+//! compound `station;sensor` key with [`Fields`], leaving the hot paths untouched.
+
+use std::hash::BuildHasher;
+
+use super::{aggregator::Aggregator, func, models::StationRecords, LiteHashBuffer};
+
+/// Derive the grouping key of a line.
+pub trait KeyExtractor {
+    /// Derive the key from `fields`, i.e. the bytes of a line before its last `;`.
+    ///
+    /// Returns [`None`] if the key cannot be derived from the fields.
+    fn key(&self, fields: &[u8]) -> Option<LiteHashBuffer>;
+}
+
+impl<F> KeyExtractor for F
+where
+    F: Fn(&[u8]) -> Option<LiteHashBuffer>,
+{
+    fn key(&self, fields: &[u8]) -> Option<LiteHashBuffer> {
+        self(fields)
+    }
+}
+
+/// Group by the station name as is; the default of the 1BRC.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StationName;
+
+impl KeyExtractor for StationName {
+    fn key(&self, fields: &[u8]) -> Option<LiteHashBuffer> {
+        Some(fields.into())
+    }
+}
+
+/// Group by the first characters of the station name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NamePrefix(pub usize);
+
+impl KeyExtractor for NamePrefix {
+    fn key(&self, fields: &[u8]) -> Option<LiteHashBuffer> {
+        let prefix = func::bytes_to_string(fields)
+            .chars()
+            .take(self.0)
+            .collect::<String>();
+
+        Some(prefix.as_bytes().into())
+    }
+}
+
+/// Group by the lowercase station name, so that names differing only by case are merged.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CaseFolded;
+
+impl KeyExtractor for CaseFolded {
+    fn key(&self, fields: &[u8]) -> Option<LiteHashBuffer> {
+        Some(
+            func::bytes_to_string(fields)
+                .to_lowercase()
+                .as_bytes()
+                .into(),
+        )
+    }
+}
+
+/// Group by the column at a zero-based index of a line with multiple `;`-separated fields
+/// before the value, e.g. `1` for `country` in `city;country;12.3`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Column(pub usize);
+
+impl KeyExtractor for Column {
+    fn key(&self, fields: &[u8]) -> Option<LiteHashBuffer> {
+        fields
+            .split(|&byte| byte == b';')
+            .nth(self.0)
+            .map(|column| column.into())
+    }
+}
+
+/// Group by the first fields of a line with multiple `;`-separated fields before the value,
+/// joined by their `;`, e.g. `2` for `station;sensor` in `station;sensor;reading;12.3`; a line
+/// with fewer fields is grouped by all of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fields(pub usize);
+
+impl KeyExtractor for Fields {
+    fn key(&self, fields: &[u8]) -> Option<LiteHashBuffer> {
+        let end = match self.0 {
+            0 => return None,
+            count => memchr::memchr_iter(b';', fields)
+                .nth(count - 1)
+                .unwrap_or(fields.len()),
+        };
+
+        Some(fields[..end].into())
+    }
+}
+
+impl<A: Aggregator, S: BuildHasher> StationRecords<A, S> {
+    /// Merge the records under the key derived by `extractor` from their names, as if the
+    /// input had been parsed with it; the records whose key cannot be derived are kept as is.
+    pub fn regroup(&mut self, extractor: &impl KeyExtractor) {
+        let records = self.drain().collect::<Vec<_>>();
+
+        for (name, stats) in records {
+            let key = extractor.key(&name).unwrap_or(name);
+            self.merge(key, stats);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::{models::StationRecords, sync};
+
+    /// Parse the input with the extractor, returning the export.
+    fn export(input: &[u8], extractor: impl KeyExtractor) -> String {
+        let mut records = StationRecords::new();
+        sync::parse_bytes_with_key(input, &mut records, &extractor);
+        records.export_text()
+    }
+
+    #[test]
+    fn extract_keys() {
+        let input = "Zürich;1.0\nzürich;3.0\nZug;-2.0\n".as_bytes();
+
+        assert_eq!(
+            export(input, StationName),
+            "{Zug=-2.0/-2.0/-2.0, Zürich=1.0/1.0/1.0, zürich=3.0/3.0/3.0}\n"
+        );
+        assert_eq!(
+            export(input, NamePrefix(2)),
+            "{Zu=-2.0/-2.0/-2.0, Zü=1.0/1.0/1.0, zü=3.0/3.0/3.0}\n"
+        );
+        assert_eq!(
+            export(input, CaseFolded),
+            "{zug=-2.0/-2.0/-2.0, zürich=1.0/2.0/3.0}\n"
+        );
+        assert_eq!(
+            export(input, |fields: &[u8]| Some(fields[..1].into())),
+            "{Z=-2.0/-0.5/1.0, z=3.0/3.0/3.0}\n"
+        );
+    }
+
+    #[test]
+    fn extract_columns() {
+        let input = b"Zurich;CH;1.0\nGeneva;CH;3.0\nLyon;FR;-2.0\n";
+
+        assert_eq!(
+            export(input, Column(1)),
+            "{CH=1.0/2.0/3.0, FR=-2.0/-2.0/-2.0}\n"
+        );
+        assert_eq!(Column(2).key(b"Zurich;CH"), None);
+    }
+
+    #[test]
+    fn extract_fields() {
+        let input = b"Zurich;s1;a;1.0\nZurich;s1;b;3.0\nZurich;s2;a;-2.0\nLyon;0.5\n";
+
+        assert_eq!(
+            export(input, Fields(2)),
+            "{Lyon=0.5/0.5/0.5, Zurich;s1=1.0/2.0/3.0, Zurich;s2=-2.0/-2.0/-2.0}\n"
+        );
+        assert_eq!(export(input, Fields(4)), export(input, StationName));
+        assert_eq!(Fields(0).key(b"Zurich;s1"), None);
+
+        // Regrouping the parsed records gives the same as parsing with the extractor.
+        let mut records = StationRecords::new();
+        sync::parse_bytes_with_key(input, &mut records, &StationName);
+        records.regroup(&Fields(1));
+        assert_eq!(records.export_text(), export(input, Fields(1)));
+    }
+
+    #[test]
+    #[should_panic]
+    fn extract_missing_column() {
+        export(b"Zurich;CH;1.0\n", Column(2));
+    }
+}
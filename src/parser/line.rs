@@ -1,9 +1,13 @@
 //! Parsing a 1BRC line.
 
-use tokio::io::{AsyncBufReadExt, AsyncReadExt};
+use std::hash::BuildHasher;
+use std::pin::Pin;
+use std::task::{ready, Poll};
+
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt};
 
 use super::super::config;
-use super::{func, models, LiteHashBuffer};
+use super::{aggregator::Aggregator, func, models};
 
 #[cfg(feature = "timed-extreme")]
 use super::super::timed::TimedOperation;
@@ -23,31 +27,92 @@ pub static PARSE_VALUE_TIMED: std::sync::OnceLock<std::sync::Arc<TimedOperation>
 ///
 /// These parsing functions expect perfect input; if the input is not perfect, the behavior is
 /// undefined.
+///
+/// Each name is read into a single reused buffer and inserted through the borrowed-key path,
+/// so a name is only copied into a key the first time its station is seen.
+///
+/// Returns the number of records parsed.
 #[allow(unreachable_code, unused_variables, unused_mut)]
 // Unused mut is used to prevent warnings when the `nohash` feature is disabled.
-pub async fn parse_bytes<R>(mut bytes: R, records: &mut models::StationRecords)
+pub async fn parse_bytes<R, A, S>(mut bytes: R, records: &mut models::StationRecords<A, S>) -> usize
 where
     R: AsyncReadExt + AsyncBufReadExt + Unpin,
+    A: Aggregator,
+    S: BuildHasher,
 {
     #[cfg(feature = "noparse")]
     {
         // This will prevent any parsing from being done at all; all data will be discarded.
         // This is just for testing purposes.
         records.insert("some place".as_bytes().into(), 0);
-        return;
+        return 1;
     }
 
     let mut name = Vec::with_capacity(config::MAX_LINE_LENGTH);
-    let mut digits = Vec::with_capacity(5);
+    let mut count = 0;
 
-    while let Some(name) = parse_name(&mut bytes, &mut name).await {
-        let value = parse_value(&mut bytes, &mut digits).await;
+    loop {
+        name.clear();
+
+        let Some(station) = parse_name(&mut bytes, &mut name).await else {
+            break;
+        };
+        let value = parse_value(&mut bytes).await;
 
         // #[cfg(feature="debug")]
-        // println!("parse_bytes() found: {} {}", func::bytes_to_string(&name), value);
+        // println!("parse_bytes() found: {} {}", func::bytes_to_string(station), value);
+
+        records.insert_borrowed(station, value);
+        count += 1;
+    }
+
+    count
+}
+
+/// Parse a chunk of complete lines into a [`models::StationRecords`] in place.
+///
+/// Unlike [`parse_bytes`], the names and values are borrowed from the chunk instead of being
+/// copied out line by line; a name is only copied into a key the first time its station is
+/// seen by `records`.
+///
+/// Like [`parse_bytes`], this expects perfect input, and panics on a line without a `;`.
+///
+/// Returns the number of records parsed.
+#[allow(unreachable_code, unused_variables)]
+pub fn parse_chunk<A: Aggregator, S: BuildHasher>(
+    bytes: &[u8],
+    records: &mut models::StationRecords<A, S>,
+) -> usize {
+    #[cfg(feature = "noparse")]
+    {
+        // This will prevent any parsing from being done at all; all data will be discarded.
+        // This is just for testing purposes.
+        records.insert("some place".as_bytes().into(), 0);
+        return 1;
+    }
+
+    let mut count = 0;
 
-        records.insert(name, value)
+    for line in bytes
+        .split(|&byte| byte == b'\n')
+        .filter(|line| !line.is_empty())
+    {
+        // The value is much shorter than the name, so look for the semicolon from the end.
+        let semicolon = func::find_semicolon(line).unwrap_or_else(|| {
+            panic!(
+                "parse_chunk() found an invalid line: {:?}",
+                func::bytes_to_string(line)
+            )
+        });
+
+        records.insert_borrowed(
+            &line[..semicolon],
+            super::sync::parse_value(&line[semicolon + 1..]),
+        );
+        count += 1;
     }
+
+    count
 }
 
 /// Parse name.
@@ -55,7 +120,10 @@ where
 /// This expects the buffer to be at the start of the name, and ends at the semicolon.
 /// No other characters are allowed to terminate the name; if the buffer ends before the semicolon,
 /// the behavior is undefined.
-pub async fn parse_name<R>(buffer: &mut R, name: &mut Vec<u8>) -> Option<LiteHashBuffer>
+///
+/// The name is appended to `name`, which should be cleared between lines, and returned as a
+/// slice of it without the semicolon, so that no allocation is made per line.
+pub async fn parse_name<'n, R>(buffer: &mut R, name: &'n mut Vec<u8>) -> Option<&'n [u8]>
 where
     R: AsyncBufReadExt + Unpin,
 {
@@ -71,13 +139,7 @@ where
         .start();
 
     match buffer.read_until(b';', name).await {
-        Ok(count) if count > 0 => Some({
-            let mut name_with_semicolon = name.split_off(0);
-            name_with_semicolon.pop();
-            // `into` is used here to convert the `Vec<u8>` into a `LiteHashBuffer`...
-            // ...or just to shutup rust analyzer.
-            name_with_semicolon
-        }),
+        Ok(count) if count > 0 => Some(&name[..name.len() - 1]),
         Ok(_) => {
             #[cfg(feature = "debug")]
             println!("parse_name() had an EOF.");
@@ -100,15 +162,18 @@ where
 ///
 /// If the value contains more than 1 decimal point, the behavior is undefined.
 ///
+/// The digits are folded into the value straight from the slices of [`AsyncBufRead::poll_fill_buf`]
+/// in a single pass, without being copied out, and the state carries over if the value spans
+/// two slices.
+///
 /// # Warning
 ///
-/// This function expects each line to be terminated with a newline character.
-/// It will always drop the last character - which is expected to be a newline -
-/// regardless of what it actually is. This requires strict conformance to the
-/// input format.
-pub async fn parse_value<R>(buffer: &mut R, digits: &mut Vec<u8>) -> i16
+/// This function expects each line to be terminated with a newline character, and stops at the
+/// end of the buffer otherwise. Any character other than the digits and a leading `-` is
+/// skipped, which requires strict conformance to the input format.
+pub async fn parse_value<R>(buffer: &mut R) -> i16
 where
-    R: AsyncBufReadExt + Unpin,
+    R: AsyncBufRead + Unpin,
 {
     // #[cfg(feature = "noparse-value")]
     // {
@@ -116,30 +181,54 @@ where
     //     return 0;
     // }
 
-    let mut multiplier: i16 = 1;
+    let mut negative = false;
+    let mut value: i16 = 0;
 
     #[cfg(feature = "timed-extreme")]
     let _counter = PARSE_VALUE_TIMED
         .get_or_init(|| TimedOperation::new("parse_value()"))
         .start();
 
-    let len = buffer.read_until(b'\n', digits).await.expect(
-        "parse_value() failed to read until newline; this should never happen, as measurement.txt is \
-        guaranteed to have a newline.",
-    );
+    std::future::poll_fn(|cx| loop {
+        let mut buffer = Pin::new(&mut *buffer);
+        let bytes = ready!(buffer.as_mut().poll_fill_buf(cx)).expect(
+            "parse_value() failed to read until newline; this should never happen, as \
+            measurement.txt is guaranteed to have a newline.",
+        );
 
-    if digits[0] == b'-' {
-        multiplier = -1;
-    }
+        if bytes.is_empty() {
+            return Poll::Ready(());
+        }
+
+        let mut consumed = (bytes.len(), false);
+
+        for (index, &byte) in bytes.iter().enumerate() {
+            match byte {
+                b'\n' => {
+                    consumed = (index + 1, true);
+                    break;
+                }
+                b'-' => negative = true,
+                digit if digit.is_ascii_digit() => {
+                    value = value * 10 + func::u8_to_digit(digit) as i16
+                }
+                _ => {}
+            }
+        }
+
+        buffer.consume(consumed.0);
+
+        if consumed.1 {
+            return Poll::Ready(());
+        }
+    })
+    .await;
 
-    digits
-        .drain(..)
-        .take(len - 1)
-        .fold(0, |acc, digit| match digit {
-            i if i.is_ascii_digit() => acc * 10 + func::u8_to_digit(i) as i16,
-            _ => acc,
-        })
-        * multiplier
+    if negative {
+        -value
+    } else {
+        value
+    }
 }
 
 #[cfg(test)]
@@ -157,12 +246,11 @@ mod test {
                 async fn $name() {
                     let mut bytes = $input.as_bytes().to_vec();
                     bytes.push(b'\n');
-                    let mut digits = Vec::with_capacity(5);
 
                     let mut buffer = &bytes[..];
 
                     assert_eq!(
-                        parse_value(&mut buffer, &mut digits).await,
+                        parse_value(&mut buffer).await,
                         $expected
                     );
                 }
@@ -185,6 +273,17 @@ mod test {
         (parse_value_neg_5354_newline, "-535.4\n", -5354),
     );
 
+    #[tokio::test]
+    async fn parse_value_across_slices() {
+        // Each `fill_buf()` returns at most 2 bytes, so every value spans several slices.
+        let mut buffer = tokio::io::BufReader::with_capacity(2, "-12.3\n45.6\n7.8".as_bytes());
+
+        assert_eq!(parse_value(&mut buffer).await, -123);
+        assert_eq!(parse_value(&mut buffer).await, 456);
+        assert_eq!(parse_value(&mut buffer).await, 78);
+        assert_eq!(parse_value(&mut buffer).await, 0);
+    }
+
     macro_rules! expand_parse_name_tests {
         ($((
             $name:ident,
@@ -200,7 +299,7 @@ mod test {
 
                     assert_eq!(
                         parse_name(&mut buffer, &mut name).await,
-                        $expected.map(|text| text.as_bytes().to_vec().into())
+                        $expected.map(str::as_bytes)
                     );
                 }
             )*
@@ -242,12 +341,31 @@ mod test {
                     let bytes = $input.as_bytes().to_vec();
                     let buffer = &bytes[..];
 
-                    parse_bytes(buffer, &mut records).await;
+                    let count = parse_bytes(buffer, &mut records).await;
 
+                    assert_eq!(count, $input.lines().count());
                     assert_eq!(
                         records.get(&$expected.0.to_vec().into()).unwrap().sum,
                         $expected.1
                     );
+
+                    let mut chunk_records = models::StationRecords::new();
+                    assert_eq!(parse_chunk(buffer, &mut chunk_records), count);
+                    assert_eq!(chunk_records, records);
+
+                    let mut memchr_records = models::StationRecords::new();
+                    assert_eq!(
+                        super::super::sync::parse_bytes_memchr(buffer, &mut memchr_records),
+                        count
+                    );
+                    assert_eq!(memchr_records, records);
+
+                    let mut batched_records = models::StationRecords::new();
+                    assert_eq!(
+                        super::super::sync::parse_bytes_batched(buffer, &mut batched_records),
+                        count
+                    );
+                    assert_eq!(batched_records, records);
                 }
             )*
         };
@@ -1,15 +1,78 @@
 //! Parse 1BRC lines.
 
+pub mod aggregator;
+
+pub mod atomic;
+
+#[cfg(feature = "runtime")]
+pub mod checkpoint;
+
+#[cfg(feature = "arrow")]
+pub mod batches;
+
+#[cfg(feature = "codec")]
+pub mod codec;
+
+#[cfg(feature = "polars")]
+pub mod dataframe;
+
+pub mod dataset;
+
+#[cfg(feature = "filter")]
+pub mod filter;
+
+#[cfg(feature = "runtime")]
+pub mod engine;
+
 pub mod func;
 
+pub mod incremental;
+
+#[cfg(feature = "gpu")]
+pub mod gpu;
+
+#[cfg(not(target_family = "wasm"))]
+pub mod hasher;
+
+pub mod key;
+
+#[cfg(feature = "runtime")]
 pub mod line;
 
 pub mod models;
 
-#[cfg(feature = "sync")]
+#[cfg(feature = "normalize")]
+pub mod normalize;
+
+pub mod scratch;
+
+pub mod profile;
+
+pub mod rejects;
+
+pub mod separators;
+
+pub mod sharded;
+
+pub mod snapshot;
+
+pub mod spill;
+
+pub mod units;
+
 pub mod sync;
 
+#[cfg(feature = "raw-table")]
+pub mod table;
+
+#[cfg(feature = "runtime")]
 pub mod task;
 
+pub mod values;
+
+pub mod verify;
+
+pub mod windows;
+
 mod hashable_buffer;
 pub use hashable_buffer::LiteHashBuffer;
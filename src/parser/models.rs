@@ -1,16 +1,39 @@
 //! Definitions of type aliases.
 
+use itertools::Itertools;
+
+#[cfg(any(feature = "runtime", feature = "sync"))]
 use std::path::Path;
 
-use itertools::Itertools;
-use tokio::{fs::File, io::AsyncWriteExt};
+use super::{aggregator::Aggregator, dataset::DatasetStats, func, LiteHashBuffer};
+
+#[cfg(any(feature = "runtime", feature = "sync"))]
+use super::atomic;
+
+#[cfg(feature = "runtime")]
+use super::{engine::ParserEngine, scratch::ScratchSpace};
+
+#[cfg(feature = "runtime")]
+use crate::reader::{func::allocate_buffer, RowsReader};
+
+#[cfg(feature = "runtime")]
+use super::super::timed::{ThroughputCounter, TimedOperation};
+
+#[cfg(feature = "runtime")]
+pub static READ_FROM_READER_TIMED: std::sync::OnceLock<std::sync::Arc<TimedOperation>> =
+    std::sync::OnceLock::new();
 
-use super::{func, line, LiteHashBuffer};
+#[cfg(feature = "runtime")]
+pub static PARSE_CHUNK_TIMED: std::sync::OnceLock<std::sync::Arc<TimedOperation>> =
+    std::sync::OnceLock::new();
 
-use crate::reader::RowsReader;
+#[cfg(feature = "runtime")]
+pub static PARSE_THROUGHPUT: std::sync::OnceLock<std::sync::Arc<ThroughputCounter>> =
+    std::sync::OnceLock::new();
 
-#[cfg(feature = "timed")]
-use super::super::timed::TimedOperation;
+#[cfg(feature = "runtime")]
+pub static EXPORT_FILE_TIMED: std::sync::OnceLock<std::sync::Arc<TimedOperation>> =
+    std::sync::OnceLock::new();
 
 #[cfg(feature = "timed-extreme")]
 pub static HASH_INSERT_TIMED: std::sync::OnceLock<std::sync::Arc<TimedOperation>> =
@@ -19,7 +42,23 @@ pub static HASH_INSERT_TIMED: std::sync::OnceLock<std::sync::Arc<TimedOperation>
 #[cfg(feature = "nohash")]
 pub use std::hash::BuildHasherDefault;
 
-#[cfg(feature = "sync")]
+use std::hash::BuildHasher;
+
+#[cfg(not(feature = "nohash"))]
+use super::hasher::StationHasher;
+
+/// The [`BuildHasher`] of the station names of a [`StationRecords`] unless another is given.
+#[cfg(not(feature = "nohash"))]
+pub type DefaultBuildHasher = StationHasher;
+
+/// The [`BuildHasher`] of the station names of a [`StationRecords`] unless another is given,
+/// passing the precomputed hash of each [`LiteHashBuffer`] through.
+#[cfg(feature = "nohash")]
+pub type DefaultBuildHasher = BuildHasherDefault<nohash::NoHashHasher<u64>>;
+
+#[cfg(feature = "raw-table")]
+use super::table::StationTable;
+
 pub use super::sync;
 
 #[cfg(feature = "sync")]
@@ -71,13 +110,7 @@ impl StationStats {
 
     /// Export the stats to a 1BRC format string.
     pub fn export_text(&self, name: &[u8]) -> String {
-        format!(
-            "{name}={min:.1}/{avg:.1}/{max:.1}",
-            name = func::bytes_to_string(name),
-            min = self.min as f32 / 10.0,
-            avg = self.sum as f32 / self.count as f32 / 10.0,
-            max = self.max as f32 / 10.0,
-        )
+        format!("{}={}", func::bytes_to_string(name), self.emit())
     }
 }
 
@@ -119,53 +152,149 @@ impl std::ops::AddAssign<Option<Self>> for StationStats {
     }
 }
 
-/// Records of multiple stations.
+/// Records of multiple stations, aggregated by `A`, with the names hashed by `S`.
 /// This internally uses a HashMap to keep the stats.
 /// This used to have a BTreeSet to keep the names in order, but it was removed for
 /// performance reasons.
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub struct StationRecords {
-    #[cfg(not(feature = "nohash"))]
-    stats: gxhash::GxHashMap<LiteHashBuffer, StationStats>,
-
-    #[cfg(feature = "nohash")]
-    stats: std::collections::HashMap<
-        LiteHashBuffer,
-        StationStats,
-        BuildHasherDefault<nohash::NoHashHasher<u64>>,
-    >,
+#[derive(Clone)]
+pub struct StationRecords<A = StationStats, S = DefaultBuildHasher> {
+    #[cfg(not(feature = "raw-table"))]
+    stats: std::collections::HashMap<LiteHashBuffer, A, S>,
+
+    #[cfg(feature = "raw-table")]
+    stats: StationTable<A, S>,
 }
 
-impl Default for StationRecords {
-    #[cfg(not(feature = "nohash"))]
+/// An iterator over the station names of a [`StationRecords`], in an arbitrary order.
+#[cfg(not(feature = "raw-table"))]
+pub type Keys<'a, A> = std::collections::hash_map::Keys<'a, LiteHashBuffer, A>;
+
+/// A draining iterator over the records of a [`StationRecords`].
+#[cfg(not(feature = "raw-table"))]
+pub type Drain<'a, A> = std::collections::hash_map::Drain<'a, LiteHashBuffer, A>;
+
+/// An owning iterator over the records of a [`StationRecords`].
+#[cfg(not(feature = "raw-table"))]
+pub type IntoIter<A> = std::collections::hash_map::IntoIter<LiteHashBuffer, A>;
+
+#[cfg(feature = "raw-table")]
+pub use super::table::{Drain, IntoIter, Keys};
+
+impl<A, S: BuildHasher + Default> Default for StationRecords<A, S> {
     fn default() -> Self {
+        Self::with_hasher(S::default())
+    }
+}
+
+impl<A: std::fmt::Debug, S> std::fmt::Debug for StationRecords<A, S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StationRecords")
+            .field("stats", &self.stats)
+            .finish()
+    }
+}
+
+impl<A: PartialEq, S: BuildHasher> PartialEq for StationRecords<A, S> {
+    fn eq(&self, other: &Self) -> bool {
+        self.stats == other.stats
+    }
+}
+
+impl<A: Eq, S: BuildHasher> Eq for StationRecords<A, S> {}
+
+impl<A, S: BuildHasher> StationRecords<A, S> {
+    /// Create a new empty [`StationRecords`] hashing the station names with `hasher`, e.g. to
+    /// seed it, or to replace the [`DefaultBuildHasher`] altogether.
+    ///
+    /// With the `nohash` feature, the names are hashed once by [`LiteHashBuffer`], and `hasher`
+    /// only sees the precomputed hash.
+    pub fn with_hasher(hasher: S) -> Self {
         Self {
             // The actual number of stations is 400-ish.
-            stats: std::collections::HashMap::with_capacity_and_hasher(
-                500,
-                gxhash::GxBuildHasher::default(),
-            ),
+            #[cfg(not(feature = "raw-table"))]
+            stats: std::collections::HashMap::with_capacity_and_hasher(500, hasher),
+            #[cfg(feature = "raw-table")]
+            stats: StationTable::with_capacity_and_hasher(500, hasher),
         }
     }
+}
 
-    #[cfg(feature = "nohash")]
-    fn default() -> Self {
-        Self {
-            // The actual number of stations is 400-ish.
-            stats: std::collections::HashMap::with_capacity_and_hasher(
-                500,
-                BuildHasherDefault::default(),
-            ),
+#[cfg(feature = "raw-table")]
+impl<A: Aggregator, S: BuildHasher> StationRecords<A, S> {
+    /// Hash a station name the same way as the records, for [`StationRecords::insert_hashed`].
+    #[inline]
+    pub fn hash_name(&self, name: &[u8]) -> u64 {
+        self.stats.hash(name)
+    }
+
+    /// Insert a new record by a name borrowed from the input and its `hash` from
+    /// [`StationRecords::hash_name`], so that the name is hashed once in the hot path, and the
+    /// hash can be computed ahead of the insertion.
+    #[inline]
+    pub fn insert_hashed(&mut self, hash: u64, name: &[u8], value: i16) {
+        match self.stats.find_mut(hash, name) {
+            Some(stats) => stats.observe(value),
+            None => self
+                .stats
+                .insert_unique(hash, name.into(), A::from_value(value)),
         }
     }
 }
 
 impl StationRecords {
-    /// Create a new empty [`StationRecords`].
+    /// Create a new empty [`StationRecords`] of [`StationStats`].
+    ///
+    /// Records of other aggregators are created with [`StationRecords::default`].
     pub fn new() -> Self {
         Self::default()
     }
 
+    /// Summarize the records into dataset-level statistics.
+    pub fn dataset_stats(&self) -> DatasetStats {
+        DatasetStats::from_records(self)
+    }
+}
+
+impl<S: BuildHasher> StationRecords<StationStats, S> {
+    /// Calculate the length of the records.
+    #[cfg(feature = "assert")]
+    pub fn len(&self) -> usize {
+        self.stats.values().map(|stats| stats.count).sum()
+    }
+
+    /// Check if the records are empty.
+    #[cfg(feature = "assert")]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Empty the stats of every station, keeping the names and the table, so that the records
+    /// of another run can be merged in without growing the table or copying the names again.
+    ///
+    /// The stations missing from the other run are left without any value, until removed by
+    /// [`StationRecords::remove_unobserved`].
+    pub fn reset(&mut self) {
+        self.stats
+            .values_mut()
+            .for_each(|stats| *stats = StationStats::default());
+    }
+
+    /// Remove the stations without any value, e.g. left behind by [`StationRecords::reset`].
+    pub fn remove_unobserved(&mut self) {
+        self.stats.retain(|_, stats| stats.count > 0);
+    }
+
+    /// Multiply the counts and the sums of every station by `factor`, e.g. to estimate those
+    /// of a whole input from a sample of it; the means are kept, and so are the extremes.
+    pub fn scale(&mut self, factor: f64) {
+        self.stats.values_mut().for_each(|stats| {
+            stats.count = (stats.count as f64 * factor).round() as usize;
+            stats.sum = (stats.sum as f64 * factor).round() as i32;
+        });
+    }
+}
+
+impl<A: Aggregator, S: BuildHasher> StationRecords<A, S> {
     /// Insert a new record by mutating the [`StationRecords`] in place.
     pub fn insert(&mut self, name: LiteHashBuffer, value: i16) {
         #[cfg(feature = "timed-extreme")]
@@ -174,47 +303,102 @@ impl StationRecords {
             .start();
 
         // Since we hold a mutable reference, this is essentially a mutex around both fields.
+        #[cfg(not(feature = "raw-table"))]
         self.stats
             .entry(name)
-            .and_modify(|stats| stats.extend(value))
-            .or_insert(StationStats {
-                min: value,
-                max: value,
-                sum: value as i32,
-                count: 1,
-            });
+            .and_modify(|stats| stats.observe(value))
+            .or_insert_with(|| A::from_value(value));
+
+        #[cfg(feature = "raw-table")]
+        {
+            let hash = self.stats.hash(name.as_slice());
+
+            match self.stats.find_mut(hash, name.as_slice()) {
+                Some(stats) => stats.observe(value),
+                None => self.stats.insert_unique(hash, name, A::from_value(value)),
+            }
+        }
     }
 
-    /// Get the stats of a single station.
-    pub fn get(&self, name: &LiteHashBuffer) -> Option<&StationStats> {
-        self.stats.get(name)
+    /// Insert a new record by a name borrowed from the input, only copying the name into a
+    /// key the first time the station is seen.
+    pub fn insert_borrowed(&mut self, name: &[u8], value: i16) {
+        #[cfg(not(feature = "nohash"))]
+        {
+            #[cfg(feature = "timed-extreme")]
+            let _counter = HASH_INSERT_TIMED
+                .get_or_init(|| TimedOperation::new("StationRecords::insert()"))
+                .start();
+
+            #[cfg(not(feature = "raw-table"))]
+            match self.stats.get_mut(name) {
+                Some(stats) => stats.observe(value),
+                None => {
+                    self.stats.insert(name.into(), A::from_value(value));
+                }
+            }
+
+            #[cfg(feature = "raw-table")]
+            self.insert_hashed(self.stats.hash(name), name, value);
+        }
+
+        // Keys cannot be looked up by their bytes with `nohash`.
+        #[cfg(feature = "nohash")]
+        self.insert(name.into(), value);
     }
 
-    /// Calculate the length of the records.
-    #[cfg(feature = "assert")]
-    pub fn len(&self) -> usize {
-        self.stats.values().map(|stats| stats.count).sum()
+    /// Merge the stats of a station aggregated elsewhere, e.g. by another consumer, into the
+    /// records.
+    pub fn merge(&mut self, name: LiteHashBuffer, stats: A) {
+        #[cfg(not(feature = "raw-table"))]
+        match self.stats.entry(name) {
+            std::collections::hash_map::Entry::Occupied(mut entry) => entry.get_mut().merge(stats),
+            std::collections::hash_map::Entry::Vacant(entry) => {
+                entry.insert(stats);
+            }
+        }
+
+        #[cfg(feature = "raw-table")]
+        {
+            let hash = self.stats.hash(name.as_slice());
+
+            match self.stats.find_mut(hash, name.as_slice()) {
+                Some(entry) => entry.merge(stats),
+                None => self.stats.insert_unique(hash, name, stats),
+            }
+        }
     }
 
-    /// Check if the records are empty.
-    #[cfg(feature = "assert")]
-    pub fn is_empty(&self) -> bool {
-        self.len() == 0
+    /// The number of stations in the records.
+    pub fn stations(&self) -> usize {
+        self.stats.len()
+    }
+
+    /// Get the stats of a single station.
+    pub fn get(&self, name: &LiteHashBuffer) -> Option<&A> {
+        #[cfg(not(feature = "raw-table"))]
+        return self.stats.get(name);
+
+        #[cfg(feature = "raw-table")]
+        self.stats.get(name.as_slice())
     }
 
     /// Iterate through the records in an arbitrary order.
     #[allow(dead_code)]
-    pub fn iter(
-        &self,
-    ) -> IterStationRecords<std::collections::hash_map::Keys<LiteHashBuffer, StationStats>> {
+    pub fn iter(&self) -> IterStationRecords<'_, Keys<'_, A>, A, S> {
         IterStationRecords {
             iter: self.stats.keys(),
             records: self,
         }
     }
 
+    /// Remove every record, moving them out in an arbitrary order.
+    pub fn drain(&mut self) -> Drain<'_, A> {
+        self.stats.drain()
+    }
+
     /// Iterate through the records in an alphabetical order of the station names.
-    pub fn iter_sorted(&self) -> IterStationRecords<std::vec::IntoIter<&LiteHashBuffer>> {
+    pub fn iter_sorted(&self) -> IterStationRecords<'_, std::vec::IntoIter<&LiteHashBuffer>, A, S> {
         let mut names = self.stats.keys().collect_vec();
         names.sort();
 
@@ -224,59 +408,165 @@ impl StationRecords {
         }
     }
 
-    /// Export the results to a text in the 1BRC format.
+    /// Export the results to a text in the 1BRC format, i.e. `{name=output, ...}` with the
+    /// output emitted by each aggregator.
     #[allow(dead_code)]
-    pub fn export_text(&self) -> String {
+    pub fn export_text(&self) -> String
+    where
+        A::Output: std::fmt::Display,
+    {
         "{".to_owned()
             + &itertools::join(
-                self.iter_sorted()
-                    .map(|(name, stats)| stats.export_text(name)),
+                self.iter_sorted().map(|(name, stats)| {
+                    format!("{}={}", func::bytes_to_string(name), stats.emit())
+                }),
                 ", ",
             )
             + "}\n"
     }
 
+    #[cfg(feature = "runtime")]
     /// Export the results to a file in the 1BRC format.
-    pub async fn export_file(&self, path: impl AsRef<Path>) {
-        #[cfg(feature = "timed")]
-        let _ops = TimedOperation::new("StationRecords::export_file()");
-        #[cfg(feature = "timed")]
-        let _counter = _ops.start();
-
-        let mut file = File::create(path).await.unwrap();
-
-        file.write_all(self.export_text().as_bytes()).await.unwrap();
-    }
-
-    /// The main asynchronous function to read from a [`RowsReader`] and parse the data into itself.
-    pub async fn read_from_reader(reader: &RowsReader, max_chunk_size: usize) -> Self {
-        let mut records = Self::new();
-
-        let mut buffer = Vec::with_capacity(max_chunk_size);
+    pub async fn export_file(&self, path: impl AsRef<Path>)
+    where
+        A::Output: std::fmt::Display,
+    {
+        self.try_export_file(path).await.unwrap()
+    }
+
+    #[cfg(feature = "runtime")]
+    /// Export the results to a file in the 1BRC format, returning any error instead of
+    /// panicking.
+    ///
+    /// The results are written to a temporary file, renamed over `path` once complete; see
+    /// [`atomic`].
+    pub async fn try_export_file(&self, path: impl AsRef<Path>) -> std::io::Result<()>
+    where
+        A::Output: std::fmt::Display,
+    {
+        let _counter = EXPORT_FILE_TIMED
+            .get_or_init(|| TimedOperation::new("StationRecords::export_file()"))
+            .start();
 
-        while let Some(bytes) = reader.fill(buffer).await {
+        atomic::write(path, self.export_text().as_bytes()).await
+    }
+
+    #[cfg(feature = "runtime")]
+    /// The main asynchronous function to read from a [`RowsReader`] and parse the data into itself,
+    /// parsing each chunk with `engine`.
+    ///
+    /// The only await is [`RowsReader::fill`] between two chunks; each chunk is parsed by a
+    /// plain synchronous loop, so the billion lines carry no future state. The async
+    /// [`line::parse_bytes`](super::line::parse_bytes) is kept for readers that are not split
+    /// into chunks.
+    pub async fn read_from_reader(
+        reader: &RowsReader,
+        max_chunk_size: usize,
+        engine: ParserEngine,
+    ) -> Self
+    where
+        S: Default,
+    {
+        Self::read_from_reader_with(reader, max_chunk_size, engine, |_| {}).await
+    }
+
+    #[cfg(feature = "runtime")]
+    /// Like [`StationRecords::read_from_reader`], calling `after_chunk` with the records after
+    /// each chunk is parsed, e.g. to spill them to the disk once they grow too large.
+    pub async fn read_from_reader_with(
+        reader: &RowsReader,
+        max_chunk_size: usize,
+        engine: ParserEngine,
+        mut after_chunk: impl FnMut(&mut Self),
+    ) -> Self
+    where
+        S: Default,
+    {
+        let _span = READ_FROM_READER_TIMED
+            .get_or_init(|| TimedOperation::new("StationRecords::read_from_reader()"))
+            .span();
+
+        let mut records = Self::default();
+
+        let mut buffer = allocate_buffer(max_chunk_size);
+        let mut scratch = ScratchSpace::new();
+        let strict_name_length = reader.strict_name_length();
+        // The valid lines of a chunk with rejected lines.
+        let mut valid = Vec::new();
+
+        while let Some((offset, bytes)) = reader.fill_with_offset(buffer).await {
             #[cfg(feature = "debug")]
             println!(
                 "read_from_reader() found {len} bytes of data.",
                 len = bytes.len()
             );
 
-            line::parse_bytes(&bytes[..], &mut records).await;
+            if let Some((number, reason, line)) =
+                strict_name_length.and_then(|max| func::find_invalid_line(&bytes, max))
+            {
+                panic!(
+                    "read_from_reader() found an invalid line ({}) at line {} of a chunk of {} \
+                    bytes: {:?}",
+                    reason,
+                    number,
+                    bytes.len(),
+                    func::bytes_to_string(line)
+                );
+            }
 
+            {
+                let _child = _span.child(
+                    PARSE_CHUNK_TIMED
+                        .get_or_init(|| TimedOperation::new("ParserEngine::parse_chunk()[chunk]")),
+                );
+
+                let mut throughput = PARSE_THROUGHPUT
+                    .get_or_init(|| ThroughputCounter::new("ParserEngine::parse_chunk()"))
+                    .start();
+
+                let lines = match reader.rejects() {
+                    Some(rejects) => {
+                        rejects.filter(offset, &bytes, reader.max_name_length(), &mut valid)
+                    }
+                    None => &bytes,
+                };
+
+                #[cfg(feature = "filter")]
+                let parsed = match reader.filter() {
+                    Some(filter) => filter.parse_chunk(lines, &mut records),
+                    None => engine.parse_chunk_with(lines, &mut records, &mut scratch),
+                };
+                #[cfg(not(feature = "filter"))]
+                let parsed = engine.parse_chunk_with(lines, &mut records, &mut scratch);
+                reader.add_records_parsed(parsed);
+                throughput.add(bytes.len(), parsed);
+            }
+
+            after_chunk(&mut records);
             buffer = bytes;
         }
 
         #[cfg(feature = "debug")]
         println!("read_from_reader() finished.");
 
+        #[cfg(feature = "normalize")]
+        if reader.normalize_names() {
+            records.normalize_names();
+        }
+
         records
     }
 
-    /// The main synchronous function to read from a [`Mmap`] and parse the data into itself.
+    /// The main synchronous function to read from a
+    /// [`MmapReader`](crate::reader::MmapReader) and parse the data into itself.
     #[cfg(feature = "sync")]
-    pub fn read_from_iterator<'m>(
-        chunks: impl Iterator<Item = &'m [u8]> + ParallelBridge + Send,
-    ) -> Self {
+    pub fn read_from_iterator<C: std::ops::Deref<Target = [u8]> + Send>(
+        chunks: impl Iterator<Item = C> + ParallelBridge + Send,
+    ) -> Self
+    where
+        A: Send,
+        S: Default + Send,
+    {
         chunks
             // Inefficient bridge to parallelize the parsing; we will consider making this
             // a native [`rayon::iter::ParallelIterator`] in the future.
@@ -288,48 +578,44 @@ impl StationRecords {
                     len = chunk.len()
                 );
 
-                let mut records = Self::new();
-                sync::parse_bytes(chunk, &mut records);
+                let mut records = Self::default();
+                sync::parse_bytes(&chunk, &mut records);
                 records
             })
-            .reduce(Self::new, |mut records, chunk_records| {
+            .reduce(Self::default, |mut records, chunk_records| {
                 records += chunk_records;
                 records
             })
     }
 
     #[cfg(feature = "sync")]
-    /// Export the results to a file in the 1BRC format.
-    pub fn export_file_blocking(&self, path: impl AsRef<Path>) {
+    /// Export the results to a file in the 1BRC format, through a temporary file renamed over
+    /// it once complete.
+    pub fn export_file_blocking(&self, path: impl AsRef<Path>)
+    where
+        A::Output: std::fmt::Display,
+    {
         use std::io::Write;
 
-        #[cfg(feature = "timed")]
-        let _ops = TimedOperation::new("StationRecords::export_file()");
-        #[cfg(feature = "timed")]
-        let _counter = _ops.start();
-
-        let mut file = std::fs::File::create(path).expect("Failed to create the file.");
+        #[cfg(feature = "runtime")]
+        let _counter = EXPORT_FILE_TIMED
+            .get_or_init(|| TimedOperation::new("StationRecords::export_file()"))
+            .start();
 
-        file.write(self.export_text().as_bytes())
-            .expect(&format!("Failed to write to the file",));
+        atomic::write_blocking(path, |file| file.write_all(self.export_text().as_bytes()))
+            .expect("Failed to write to the file");
     }
 }
 
-impl std::ops::AddAssign for StationRecords {
+impl<A: Aggregator, S: BuildHasher> std::ops::AddAssign for StationRecords<A, S> {
     fn add_assign(&mut self, mut rhs: Self) {
-        rhs.stats.drain().for_each(|(name, rhs_stats)| {
-            self.stats
-                .entry(name.clone())
-                .and_modify(|lhs_stats| *lhs_stats += rhs_stats)
-                .or_insert_with(
-                    // This is safe because we know that the name exists in either BTreeSet.
-                    || rhs_stats,
-                );
-        });
+        rhs.stats
+            .drain()
+            .for_each(|(name, rhs_stats)| self.merge(name, rhs_stats));
     }
 }
 
-impl std::ops::Add for StationRecords {
+impl<A: Aggregator, S: BuildHasher> std::ops::Add for StationRecords<A, S> {
     type Output = Self;
 
     fn add(mut self, rhs: Self) -> Self::Output {
@@ -338,26 +624,46 @@ impl std::ops::Add for StationRecords {
     }
 }
 
-impl std::iter::Sum for StationRecords {
+impl<A: Aggregator, S: BuildHasher + Default> std::iter::Sum for StationRecords<A, S> {
     fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
         iter.reduce(|a, b| a + b).unwrap_or_default()
     }
 }
 
+impl<A, S: BuildHasher + Default> std::iter::FromIterator<(LiteHashBuffer, A)>
+    for StationRecords<A, S>
+{
+    fn from_iter<I: IntoIterator<Item = (LiteHashBuffer, A)>>(iter: I) -> Self {
+        let mut records = Self::default();
+        records.stats.extend(iter);
+        records
+    }
+}
+
+impl<A, S> IntoIterator for StationRecords<A, S> {
+    type Item = (LiteHashBuffer, A);
+    type IntoIter = IntoIter<A>;
+
+    /// Move the records out in an arbitrary order.
+    fn into_iter(self) -> Self::IntoIter {
+        self.stats.into_iter()
+    }
+}
+
 /// An iterator over the records of a [`StationRecords`].
-pub struct IterStationRecords<'a, T>
+pub struct IterStationRecords<'a, T, A = StationStats, S = DefaultBuildHasher>
 where
     T: Iterator<Item = &'a LiteHashBuffer>,
 {
     iter: T,
-    records: &'a StationRecords,
+    records: &'a StationRecords<A, S>,
 }
 
-impl<'a, T> std::iter::Iterator for IterStationRecords<'a, T>
+impl<'a, T, A: Aggregator, S: BuildHasher> std::iter::Iterator for IterStationRecords<'a, T, A, S>
 where
     T: Iterator<Item = &'a LiteHashBuffer>,
 {
-    type Item = (&'a [u8], &'a StationStats);
+    type Item = (&'a [u8], &'a A);
 
     fn next(&mut self) -> Option<Self::Item> {
         self.iter
@@ -432,6 +738,31 @@ mod test {
         assert!(records.get(&b"station3".into()).is_none());
     }
 
+    #[test]
+    fn station_records_custom_hasher() {
+        use std::collections::hash_map::RandomState;
+
+        let input = b"jack;1.2\njill;-3.4\njack;5.6\n";
+
+        let mut records = StationRecords::new();
+        sync::parse_bytes(input, &mut records);
+
+        let mut custom =
+            StationRecords::<StationStats, RandomState>::with_hasher(RandomState::new());
+        sync::parse_bytes(input, &mut custom);
+        custom += custom.clone();
+
+        assert_eq!(custom.stations(), 2);
+        assert_eq!(
+            custom.get(&b"jack".into()).map(|stats| stats.count),
+            Some(4)
+        );
+        assert_eq!(
+            custom.export_text(),
+            (records.clone() + records).export_text()
+        );
+    }
+
     #[test]
     fn station_records_add() {
         let mut records1 = StationRecords::new();
@@ -497,6 +828,56 @@ mod test {
         assert_eq!(iter.next(), None);
     }
 
+    #[test]
+    fn station_records_into_iter() {
+        let mut records = StationRecords::new();
+        records.insert(b"this".into(), 4);
+        records.insert(b"that".into(), 5);
+        records.insert(b"this".into(), -4);
+
+        let mut drained = records.clone().drain().collect_vec();
+        drained.sort_by(|(lhs, _), (rhs, _)| lhs.cmp(rhs));
+
+        let mut moved = records.into_iter().collect_vec();
+        moved.sort_by(|(lhs, _), (rhs, _)| lhs.cmp(rhs));
+
+        assert_eq!(drained, moved);
+        assert_eq!(
+            moved,
+            vec![
+                (b"that".into(), StationStats::new(5)),
+                (
+                    b"this".into(),
+                    StationStats {
+                        min: -4,
+                        max: 4,
+                        sum: 0,
+                        count: 2
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[cfg(feature = "raw-table")]
+    #[test]
+    fn station_records_insert_hashed() {
+        let mut hashed = StationRecords::new();
+        let mut borrowed = StationRecords::new();
+
+        for (name, value) in [("this", 4), ("that", 5), ("this", -4)] {
+            let hash = hashed.hash_name(name.as_bytes());
+            hashed.insert_hashed(hash, name.as_bytes(), value);
+            borrowed.insert_borrowed(name.as_bytes(), value);
+        }
+
+        assert_eq!(hashed, borrowed);
+        assert_eq!(
+            hashed.export_text(),
+            "{that=0.5/0.5/0.5, this=-0.4/0.0/0.4}\n"
+        );
+    }
+
     #[test]
     fn station_records_export() {
         let mut records = StationRecords::new();
@@ -0,0 +1,76 @@
+//! Merge the station names which only differ by their Unicode normal form.
+//!
+//! Datasets generated by different tools may spell the same city in different normal forms,
+//! e.g. `é` as the single code point U+00E9 in NFC, or as `e` followed by the combining
+//! U+0301 in NFD, which the parsers would otherwise keep apart as two stations.
+//!
+//! Each name is interned as a single key of the [`StationRecords`], so the names are
+//! normalized once per station after the parsing, and never per line: the hot paths are left
+//! untouched, and a name already in NFC, such as any ASCII name, is only checked.
+
+use std::hash::BuildHasher;
+
+use unicode_normalization::{is_nfc_quick, IsNormalized, UnicodeNormalization};
+
+use super::{aggregator::Aggregator, models::StationRecords, LiteHashBuffer};
+
+/// The NFC form of `name`, or [`None`] if it is already in NFC, or not valid UTF-8 and thus
+/// kept as is.
+pub fn nfc(name: &[u8]) -> Option<LiteHashBuffer> {
+    let name = std::str::from_utf8(name).ok()?;
+
+    if is_nfc_quick(name.chars()) == IsNormalized::Yes {
+        return None;
+    }
+
+    let normalized = name.nfc().collect::<String>();
+    (normalized != name).then(|| normalized.as_bytes().into())
+}
+
+impl<A: Aggregator, S: BuildHasher> StationRecords<A, S> {
+    /// Merge the stations whose names only differ by their Unicode normal form under their
+    /// NFC name.
+    pub fn normalize_names(&mut self) {
+        if self.iter().all(|(name, _)| nfc(name).is_none()) {
+            return;
+        }
+
+        let records = self.drain().collect::<Vec<_>>();
+        for (name, stats) in records {
+            self.merge(nfc(&name).unwrap_or(name), stats);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::sync;
+
+    const NFC: &str = "Z\u{fc}rich";
+    const NFD: &str = "Zu\u{308}rich";
+
+    #[test]
+    fn normalize_to_nfc() {
+        assert_eq!(nfc(NFC.as_bytes()), None);
+        assert_eq!(nfc(NFD.as_bytes()), Some(NFC.as_bytes().into()));
+        assert_eq!(nfc(b"Abha"), None);
+        assert_eq!(nfc(b"\xff\xfe"), None);
+    }
+
+    #[test]
+    fn merge_normal_forms() {
+        let input = format!("{};1.0\n{};3.0\nZug;-2.0\n", NFC, NFD);
+        let mut records = StationRecords::new();
+        sync::parse_bytes(input.as_bytes(), &mut records);
+
+        assert_eq!(records.iter().count(), 3);
+
+        records.normalize_names();
+
+        assert_eq!(
+            records.export_text(),
+            format!("{{Zug=-2.0/-2.0/-2.0, {}=1.0/2.0/3.0}}\n", NFC)
+        );
+    }
+}
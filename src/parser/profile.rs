@@ -0,0 +1,332 @@
+//! Characterize a 1BRC input without aggregating it.
+//!
+//! This walks through the separators found by [`separators::find_separators_simd`] and
+//! validates each line against the 1BRC grammar, collecting statistics about the dataset
+//! along the way.
+//!
+//! A line is valid if it has a non-empty station name of at most [`config::MAX_NAME_LENGTH`]
+//! bytes, exactly one `;`, and a value matching `-?\d{1,2}\.\d`, followed by a newline.
+
+use std::collections::BTreeMap;
+use std::io::Read;
+
+use super::super::config;
+use super::{func, separators};
+
+#[cfg(not(target_family = "wasm"))]
+type StationSet = std::collections::HashSet<Vec<u8>, super::hasher::StationHasher>;
+
+#[cfg(target_family = "wasm")]
+type StationSet = std::collections::HashSet<Vec<u8>>;
+
+/// Statistics describing a 1BRC input, used to validate a dataset before benchmarking.
+#[derive(Debug, Clone, Default)]
+pub struct InputProfile {
+    /// The total number of lines, valid or not.
+    pub lines: usize,
+
+    /// The distinct station names found in the valid lines.
+    pub stations: StationSet,
+
+    /// The lowest value found in the valid lines.
+    pub min_value: Option<i16>,
+
+    /// The highest value found in the valid lines.
+    pub max_value: Option<i16>,
+
+    /// The total number of invalid lines.
+    pub invalid_lines: usize,
+
+    /// The byte offsets of the first [`config::MAX_REPORTED_INVALID_LINES`] invalid lines.
+    pub invalid_offsets: Vec<usize>,
+}
+
+impl InputProfile {
+    /// Create a new empty [`InputProfile`].
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Profile a chunk of bytes, starting at `offset` bytes into the input.
+    pub fn from_bytes(bytes: &[u8], offset: usize) -> Self {
+        let mut profile = Self::new();
+        profile.scan(bytes, offset, &mut Vec::new());
+        profile
+    }
+
+    /// Profile a whole input by streaming it from `reader`, `chunk_size` bytes at a time.
+    ///
+    /// Only the lines of the current chunk are kept in memory, so this can validate inputs
+    /// much larger than the available memory. Lines straddling two chunks are carried over
+    /// to the next one.
+    pub fn from_reader(mut reader: impl Read, chunk_size: usize) -> std::io::Result<Self> {
+        let mut profile = Self::new();
+        let mut positions = Vec::new();
+        let mut buffer = Vec::with_capacity(chunk_size);
+        let mut offset = 0;
+
+        loop {
+            let filled = buffer.len();
+            buffer.resize(filled + chunk_size, 0);
+
+            let read = reader.read(&mut buffer[filled..]);
+            buffer.truncate(filled + *read.as_ref().unwrap_or(&0));
+
+            let read = match read {
+                Ok(read) => read,
+                Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            };
+
+            if read == 0 {
+                // The end of the input; anything left is a line without a newline.
+                profile.scan(&buffer, offset, &mut positions);
+                return Ok(profile);
+            }
+
+            if let Some(end) = buffer[filled..]
+                .iter()
+                .rposition(|&byte| byte == b'\n')
+                .map(|pos| filled + pos)
+            {
+                profile.scan(&buffer[..=end], offset, &mut positions);
+                offset += end + 1;
+                buffer.drain(..=end);
+            }
+        }
+    }
+
+    /// Profile a chunk of bytes by mutating the [`InputProfile`] in place.
+    ///
+    /// `offset` is the position of the chunk within the whole input, used for reporting the
+    /// locations of invalid lines. `positions` is a scratch buffer for the separators, which
+    /// will be cleared before use.
+    ///
+    /// A chunk that does not end with a newline will have its last line reported as invalid.
+    pub fn scan(&mut self, bytes: &[u8], offset: usize, positions: &mut Vec<usize>) {
+        positions.clear();
+        separators::find_separators_simd(bytes, positions);
+
+        let mut line_start = 0;
+        let mut semicolon: Option<usize> = None;
+        let mut extra_semicolons = false;
+
+        for &pos in positions.iter() {
+            match bytes[pos] {
+                b';' if semicolon.is_some() => extra_semicolons = true,
+                b';' => semicolon = Some(pos),
+                _ => {
+                    let line = &bytes[line_start..pos];
+                    let split = semicolon.filter(|_| !extra_semicolons);
+                    self.observe_line(line, split.map(|pos| pos - line_start), offset + line_start);
+
+                    line_start = pos + 1;
+                    semicolon = None;
+                    extra_semicolons = false;
+                }
+            }
+        }
+
+        if line_start < bytes.len() {
+            self.observe_invalid(offset + line_start);
+        }
+    }
+
+    /// Record a single line without its newline; `semicolon` is the position of its only
+    /// semicolon, or [`None`] if the line has zero or multiple semicolons.
+    fn observe_line(&mut self, line: &[u8], semicolon: Option<usize>, offset: usize) {
+        let parsed = semicolon.and_then(|pos| {
+            let (name, value) = (&line[..pos], &line[pos + 1..]);
+
+            (!name.is_empty() && name.len() <= config::MAX_NAME_LENGTH)
+                .then(|| func::parse_value_checked(value))
+                .flatten()
+                .map(|value| (name, value))
+        });
+
+        match parsed {
+            Some((name, value)) => {
+                self.lines += 1;
+
+                if !self.stations.contains(name) {
+                    self.stations.insert(name.to_vec());
+                }
+
+                self.min_value = Some(self.min_value.map_or(value, |min| min.min(value)));
+                self.max_value = Some(self.max_value.map_or(value, |max| max.max(value)));
+            }
+            None => self.observe_invalid(offset),
+        }
+    }
+
+    /// Record an invalid line at the given byte offset.
+    fn observe_invalid(&mut self, offset: usize) {
+        self.lines += 1;
+        self.invalid_lines += 1;
+
+        if self.invalid_offsets.len() < config::MAX_REPORTED_INVALID_LINES {
+            self.invalid_offsets.push(offset);
+        }
+    }
+
+    /// The number of distinct stations.
+    pub fn station_count(&self) -> usize {
+        self.stations.len()
+    }
+
+    /// The number of distinct stations for each name length in bytes.
+    pub fn name_length_histogram(&self) -> BTreeMap<usize, usize> {
+        self.stations
+            .iter()
+            .fold(BTreeMap::new(), |mut histogram, name| {
+                *histogram.entry(name.len()).or_insert(0) += 1;
+                histogram
+            })
+    }
+
+    /// Whether every line in the input is valid.
+    pub fn is_valid(&self) -> bool {
+        self.invalid_lines == 0
+    }
+}
+
+impl std::ops::AddAssign for InputProfile {
+    /// Combine two [`InputProfile`]s together.
+    ///
+    /// The invalid offsets are kept in ascending order, regardless of the order in which
+    /// the chunks were profiled.
+    fn add_assign(&mut self, rhs: Self) {
+        self.lines += rhs.lines;
+        self.stations.extend(rhs.stations);
+        self.min_value = self.min_value.into_iter().chain(rhs.min_value).min();
+        self.max_value = self.max_value.into_iter().chain(rhs.max_value).max();
+        self.invalid_lines += rhs.invalid_lines;
+
+        self.invalid_offsets.extend(rhs.invalid_offsets);
+        self.invalid_offsets.sort_unstable();
+        self.invalid_offsets
+            .truncate(config::MAX_REPORTED_INVALID_LINES);
+    }
+}
+
+impl std::ops::Add for InputProfile {
+    type Output = Self;
+
+    /// Combine two [`InputProfile`]s together.
+    fn add(mut self, rhs: Self) -> Self::Output {
+        self += rhs;
+        self
+    }
+}
+
+impl std::fmt::Display for InputProfile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let format_value = |value: Option<i16>| {
+            value.map_or("N/A".to_owned(), |value| {
+                format!("{:.1}", value as f32 / 10.0)
+            })
+        };
+
+        writeln!(f, "Input profile:")?;
+        writeln!(f, "- Lines: {}", self.lines)?;
+        writeln!(f, "- Distinct stations: {}", self.station_count())?;
+        writeln!(
+            f,
+            "- Value range: {} to {}",
+            format_value(self.min_value),
+            format_value(self.max_value)
+        )?;
+
+        writeln!(f, "- Name length histogram (bytes: stations):")?;
+        for (length, count) in self.name_length_histogram() {
+            writeln!(f, "    {:>3}: {}", length, count)?;
+        }
+
+        writeln!(f, "- Invalid lines: {}", self.invalid_lines)?;
+        for offset in self.invalid_offsets.iter() {
+            writeln!(f, "    at byte offset {}", offset)?;
+        }
+        if self.invalid_lines > self.invalid_offsets.len() {
+            writeln!(
+                f,
+                "    ...and {} more.",
+                self.invalid_lines - self.invalid_offsets.len()
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn profile_valid_input() {
+        let profile =
+            InputProfile::from_bytes(b"jack;1.2\njill;-3.4\njack;56.7\nbob;0.0\n".as_ref(), 0);
+
+        assert_eq!(profile.lines, 4);
+        assert_eq!(profile.station_count(), 3);
+        assert_eq!(profile.min_value, Some(-34));
+        assert_eq!(profile.max_value, Some(567));
+        assert!(profile.is_valid());
+        assert_eq!(
+            profile.name_length_histogram(),
+            BTreeMap::from([(3, 1), (4, 2)])
+        );
+    }
+
+    #[test]
+    fn profile_invalid_input() {
+        let bytes = b"jack;1.2\njill;3.4;5.6\n;1.0\nbob\njane;123.4\n\nfoo;1.0";
+        let profile = InputProfile::from_bytes(bytes.as_ref(), 100);
+
+        assert_eq!(profile.lines, 7);
+        assert_eq!(profile.station_count(), 1);
+        assert_eq!(profile.invalid_lines, 6);
+        assert_eq!(profile.invalid_offsets, vec![109, 122, 127, 131, 142, 143]);
+    }
+
+    #[test]
+    fn profile_long_name() {
+        let name = "a".repeat(config::MAX_NAME_LENGTH);
+        let bytes = format!("{name};1.0\n{name}b;1.0\n");
+        let profile = InputProfile::from_bytes(bytes.as_bytes(), 0);
+
+        assert_eq!(profile.lines, 2);
+        assert_eq!(profile.invalid_offsets, vec![config::MAX_NAME_LENGTH + 5]);
+    }
+
+    #[test]
+    fn profile_from_reader() {
+        let bytes = b"jack;1.2\njill;3.4;5.6\n;1.0\nbob\njane;123.4\n\nfoo;1.0\nbar;-2.0";
+        let expected = InputProfile::from_bytes(bytes.as_ref(), 0);
+
+        for chunk_size in [1, 3, 7, 64] {
+            let profile = InputProfile::from_reader(bytes.as_ref(), chunk_size).unwrap();
+
+            assert_eq!(profile.lines, expected.lines);
+            assert_eq!(profile.stations, expected.stations);
+            assert_eq!(profile.invalid_offsets, expected.invalid_offsets);
+        }
+
+        assert_eq!(expected.invalid_offsets, vec![9, 22, 27, 31, 42, 51]);
+    }
+
+    #[test]
+    fn profile_add() {
+        let first = b"jack;1.2\nbad\n";
+        let second = b"jill;-9.9\njack;2.0\n";
+
+        let profile = InputProfile::from_bytes(second.as_ref(), first.len())
+            + InputProfile::from_bytes(first.as_ref(), 0);
+
+        assert_eq!(profile.lines, 4);
+        assert_eq!(profile.station_count(), 2);
+        assert_eq!(profile.min_value, Some(-99));
+        assert_eq!(profile.max_value, Some(20));
+        assert_eq!(profile.invalid_offsets, vec![9]);
+    }
+}
@@ -0,0 +1,161 @@
+//! Route the invalid lines of the input to a side file, instead of aggregating them.
+//!
+//! Outside of strict mode, the parsers of the hot paths trust the input, and aggregate
+//! whatever an invalid line happens to parse into. With [`Rejects`], the consumers check every
+//! line of a chunk with [`check_line`](func::check_line) first, and only parse the valid ones;
+//! each invalid line is written to the rejects file with its byte offset in the input and the
+//! reason, and the run carries on.
+//!
+//! The rejects file holds one line per invalid line, as `<offset>\t<reason>\t<line>`, in no
+//! particular order, as the consumers reject lines concurrently.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, PoisonError};
+
+use super::func::{self, InvalidLine};
+
+/// The rejects file, with the first error writing to it, if any.
+struct Sink {
+    file: BufWriter<File>,
+    error: Option<io::Error>,
+}
+
+/// The side file the invalid lines of the input are written to.
+pub struct Rejects {
+    path: PathBuf,
+    sink: Mutex<Sink>,
+    count: AtomicU64,
+}
+
+impl Rejects {
+    /// Create the rejects file at `path`, truncating it if it exists.
+    pub fn create(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        let file = BufWriter::new(File::create(&path)?);
+
+        Ok(Self {
+            path,
+            sink: Mutex::new(Sink { file, error: None }),
+            count: AtomicU64::default(),
+        })
+    }
+
+    /// The path of the rejects file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The number of lines rejected so far.
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    /// Reject the invalid `line` found at `offset` in the input.
+    ///
+    /// A failed write is kept for [`Rejects::finish`], so that the run is not interrupted.
+    fn reject(&self, offset: u64, reason: InvalidLine, line: &[u8]) {
+        self.count.fetch_add(1, Ordering::Relaxed);
+
+        let mut sink = self.sink.lock().unwrap_or_else(PoisonError::into_inner);
+        if sink.error.is_none() {
+            let written = write!(sink.file, "{}\t{}\t", offset, reason)
+                .and_then(|()| sink.file.write_all(line))
+                .and_then(|()| sink.file.write_all(b"\n"));
+            sink.error = written.err();
+        }
+    }
+
+    /// Reject the invalid lines of the chunk `bytes` starting at `offset` in the input, with
+    /// names of up to `max_name_length` bytes, returning the valid lines.
+    ///
+    /// The chunk is returned as is if every line is valid; otherwise its valid lines are
+    /// copied into `valid`, which is returned instead.
+    pub fn filter<'b>(
+        &self,
+        offset: u64,
+        bytes: &'b [u8],
+        max_name_length: usize,
+        valid: &'b mut Vec<u8>,
+    ) -> &'b [u8] {
+        if func::find_invalid_line(bytes, max_name_length).is_none() {
+            return bytes;
+        }
+
+        valid.clear();
+        let mut position = 0;
+
+        for line in bytes.split_inclusive(|&byte| byte == b'\n') {
+            let content = line.strip_suffix(b"\n").unwrap_or(line);
+
+            // Empty lines are skipped by the parsers, so are not rejected.
+            match func::check_line(content, max_name_length) {
+                Err(reason) if !content.is_empty() => {
+                    self.reject(offset + position as u64, reason, content)
+                }
+                _ => valid.extend_from_slice(line),
+            }
+
+            position += line.len();
+        }
+
+        valid
+    }
+
+    /// Flush the rejects file, returning the number of lines rejected, or the first error
+    /// writing to it.
+    pub fn finish(&self) -> io::Result<u64> {
+        let mut sink = self.sink.lock().unwrap_or_else(PoisonError::into_inner);
+
+        match sink.error.take() {
+            Some(error) => Err(error),
+            None => sink.file.flush().map(|()| self.count()),
+        }
+    }
+}
+
+impl std::fmt::Debug for Rejects {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Rejects")
+            .field("path", &self.path)
+            .field("count", &self.count())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::config;
+    use crate::testing::TestDir;
+
+    #[test]
+    fn filter_invalid_lines() {
+        let dir = TestDir::new("filter_invalid_lines");
+        let path = dir.join("rejects.txt");
+        let rejects = Rejects::create(&path).unwrap();
+        let mut valid = Vec::new();
+
+        let clean = b"jack;1.2\njill;-3.4\n";
+        assert_eq!(
+            rejects.filter(0, clean, config::MAX_NAME_LENGTH, &mut valid),
+            clean
+        );
+
+        let dirty = b"jack;1.2\njill\n\nbob;123.4\njack;5.6\nalice;x";
+        assert_eq!(
+            rejects.filter(100, dirty, config::MAX_NAME_LENGTH, &mut valid),
+            b"jack;1.2\n\njack;5.6\n"
+        );
+
+        assert_eq!(rejects.finish().unwrap(), 3);
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "109\tmissing `;`\tjill\n\
+            115\tvalue out of range\tbob;123.4\n\
+            134\tmalformed value\talice;x\n"
+        );
+    }
+}
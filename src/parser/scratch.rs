@@ -0,0 +1,37 @@
+//! The scratch buffers of a consumer, reused across every chunk it parses.
+
+/// The positions and values found by the stages of
+/// [`parse_bytes_batched_with`](super::sync::parse_bytes_batched_with).
+///
+/// Each consumer keeps one for the whole run, so that the buffers grow to the size of a window
+/// once, and are only cleared between windows and chunks rather than reallocated.
+#[derive(Debug, Default)]
+pub struct ScratchSpace {
+    pub(crate) separators: Vec<usize>,
+    pub(crate) names: Vec<(usize, usize)>,
+    pub(crate) starts: Vec<usize>,
+    pub(crate) values: Vec<i16>,
+}
+
+impl ScratchSpace {
+    /// Create an empty scratch space; the buffers are allocated by the first chunk.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Clear the buffers, keeping their capacity.
+    pub fn clear(&mut self) {
+        self.separators.clear();
+        self.names.clear();
+        self.starts.clear();
+        self.values.clear();
+    }
+
+    /// The number of bytes allocated by the buffers.
+    pub fn allocated_bytes(&self) -> usize {
+        self.separators.capacity() * std::mem::size_of::<usize>()
+            + self.names.capacity() * std::mem::size_of::<(usize, usize)>()
+            + self.starts.capacity() * std::mem::size_of::<usize>()
+            + self.values.capacity() * std::mem::size_of::<i16>()
+    }
+}
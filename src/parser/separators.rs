@@ -0,0 +1,255 @@
+//! Locate the separators (`;` and `\n`) in a chunk of 1BRC lines.
+//!
+//! Two implementations are provided:
+//!
+//! - [`find_separators_iter`] is the naive scalar scan, one byte at a time.
+//! - [`find_separators_simd`] scans the chunk in 64-byte lanes, building a bitmask of
+//!   matching bytes for each lane using SWAR (SIMD within a register) arithmetic on
+//!   [`u64`] words. This does not require any nightly features or CPU specific
+//!   instructions.
+//!
+//! Both functions append the positions of the separators to the provided [`Vec`] in
+//! ascending order, so that the caller can reuse the same allocation across chunks.
+
+/// The number of bytes processed in a single lane by [`find_separators_simd`].
+pub const LANE_SIZE: usize = 64;
+
+/// The number of bytes in a SWAR word.
+const WORD_SIZE: usize = std::mem::size_of::<u64>();
+
+/// Lowest 7 bits of every byte in a word.
+const LOW_BITS: u64 = 0x7F7F_7F7F_7F7F_7F7F;
+
+/// Magic multiplier to gather the high bit of every byte into the highest byte.
+const GATHER_MULTIPLIER: u64 = 0x0102_0408_1020_4080;
+
+/// Repeat a byte across all 8 bytes of a [`u64`].
+const fn splat(byte: u8) -> u64 {
+    u64::from_ne_bytes([byte; WORD_SIZE])
+}
+
+/// Whether a byte is a separator.
+#[inline]
+pub fn is_separator(byte: u8) -> bool {
+    byte == b';' || byte == b'\n'
+}
+
+/// Set the high bit of every byte in `word` that is zero, and clear every other bit.
+///
+/// Unlike the classic `(x - 0x01..) & !x & 0x80..` trick, this does not carry across
+/// bytes, so there are no false positives.
+#[inline]
+fn zero_bytes(word: u64) -> u64 {
+    !(((word & LOW_BITS).wrapping_add(LOW_BITS)) | word | LOW_BITS)
+}
+
+/// Compress the high bits of every byte in `mask` into the lowest 8 bits.
+///
+/// Bit `n` of the result corresponds to byte `n` of the little-endian word.
+#[inline]
+fn gather_high_bits(mask: u64) -> u64 {
+    ((mask >> 7).wrapping_mul(GATHER_MULTIPLIER)) >> 56
+}
+
+/// Build a bitmask of the separators in a 64-byte lane, one bit per byte.
+#[inline]
+fn lane_mask(lane: &[u8]) -> u64 {
+    debug_assert_eq!(lane.len(), LANE_SIZE);
+
+    lane.chunks_exact(WORD_SIZE)
+        .enumerate()
+        .fold(0, |acc, (index, word)| {
+            let word = u64::from_le_bytes(word.try_into().unwrap());
+            let matches = zero_bytes(word ^ splat(b';')) | zero_bytes(word ^ splat(b'\n'));
+
+            acc | (gather_high_bits(matches) << (index * WORD_SIZE))
+        })
+}
+
+/// Append the positions of the separators in `bytes`, offset by `offset`.
+#[inline]
+fn scan_scalar(bytes: &[u8], offset: usize, positions: &mut Vec<usize>) {
+    positions.extend(
+        bytes
+            .iter()
+            .enumerate()
+            .filter_map(|(pos, &byte)| is_separator(byte).then_some(offset + pos)),
+    )
+}
+
+/// Find the positions of all separators in the bytes, one byte at a time.
+pub fn find_separators_iter(bytes: &[u8], positions: &mut Vec<usize>) {
+    scan_scalar(bytes, 0, positions)
+}
+
+/// Find the positions of all separators in the bytes, 64 bytes at a time.
+///
+/// Any remaining bytes that do not fill a whole lane are scanned one byte at a time.
+pub fn find_separators_simd(bytes: &[u8], positions: &mut Vec<usize>) {
+    let lanes = bytes.chunks_exact(LANE_SIZE);
+    let remainder = lanes.remainder();
+
+    lanes.enumerate().for_each(|(index, lane)| {
+        let offset = index * LANE_SIZE;
+        let mut mask = lane_mask(lane);
+
+        while mask != 0 {
+            positions.push(offset + mask.trailing_zeros() as usize);
+            mask &= mask - 1;
+        }
+    });
+
+    scan_scalar(remainder, bytes.len() - remainder.len(), positions);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    macro_rules! expand_find_separators_tests {
+        ($((
+            $name:ident,
+            $input:expr
+        )),*$(,)?) => {
+            $(
+                #[test]
+                fn $name() {
+                    let bytes: Vec<u8> = $input.into();
+
+                    let expected = bytes
+                        .iter()
+                        .enumerate()
+                        .filter_map(|(pos, &byte)| (byte == b';' || byte == b'\n').then_some(pos))
+                        .collect::<Vec<_>>();
+
+                    let mut iter = Vec::new();
+                    find_separators_iter(&bytes, &mut iter);
+                    assert_eq!(iter, expected);
+
+                    let mut simd = Vec::new();
+                    find_separators_simd(&bytes, &mut simd);
+                    assert_eq!(simd, expected);
+                }
+            )*
+        };
+    }
+
+    expand_find_separators_tests!(
+        (find_separators_empty, ""),
+        (find_separators_single_line, "jack;1.2\n"),
+        (
+            find_separators_exactly_one_lane,
+            "Springfield;12.3\nHogwarts;-4.5\nSodor;0.0\nWhiterun;99.9\nFalador;1\n"
+        ),
+        (
+            find_separators_multiple_lanes,
+            "Springfield;12.3\nHogwarts;-4.5\nSodor;0.0\nWhiterun;99.9\nFalador;-99.9\n".repeat(7)
+        ),
+        (find_separators_all_separators, ";\n".repeat(97)),
+        (find_separators_no_separators, "x".repeat(200)),
+        (
+            find_separators_high_bytes,
+            "Zürich;1.0\nSão Paulo;-2.0\n\u{ff};\u{80}\n".repeat(5)
+        ),
+    );
+    mod prop {
+        use super::*;
+        use crate::parser::func;
+        use proptest::prelude::*;
+
+        /// A station name of 1 to 100 bytes, without any separators.
+        fn name() -> impl Strategy<Value = Vec<u8>> {
+            proptest::collection::vec(
+                any::<u8>().prop_filter("separator", |&byte| !is_separator(byte)),
+                1..=100,
+            )
+        }
+
+        /// A value in tenths, within the range allowed by the 1BRC rules.
+        fn value() -> impl Strategy<Value = i16> {
+            -999_i16..=999
+        }
+
+        /// Format a value in tenths as `-?\d{1,2}\.\d`.
+        fn format_value(value: i16) -> String {
+            format!(
+                "{}{}.{}",
+                if value < 0 { "-" } else { "" },
+                value.abs() / 10,
+                value.abs() % 10
+            )
+        }
+
+        /// A well-formed chunk of 1BRC lines, along with the lines it was generated from.
+        fn chunk() -> impl Strategy<Value = (Vec<u8>, Vec<(Vec<u8>, i16)>)> {
+            proptest::collection::vec((name(), value()), 0..64).prop_map(|lines| {
+                let bytes = lines
+                    .iter()
+                    .flat_map(|(name, value)| {
+                        [
+                            name.as_slice(),
+                            b";",
+                            format_value(*value).as_bytes(),
+                            b"\n",
+                        ]
+                        .concat()
+                    })
+                    .collect();
+
+                (bytes, lines)
+            })
+        }
+
+        proptest! {
+            #[test]
+            fn simd_equals_iter_on_chunks(
+                (bytes, _) in chunk(),
+                // Shift the lines against the lane boundaries.
+                shift in 0..LANE_SIZE,
+            ) {
+                let bytes = [vec![b'x'; shift], bytes].concat();
+                let (mut simd, mut iter) = (Vec::new(), Vec::new());
+
+                find_separators_simd(&bytes, &mut simd);
+                find_separators_iter(&bytes, &mut iter);
+
+                prop_assert_eq!(simd, iter);
+            }
+
+            #[test]
+            fn simd_equals_iter_on_arbitrary_bytes(
+                bytes in proptest::collection::vec(any::<u8>(), 0..LANE_SIZE * 8),
+            ) {
+                let (mut simd, mut iter) = (Vec::new(), Vec::new());
+
+                find_separators_simd(&bytes, &mut simd);
+                find_separators_iter(&bytes, &mut iter);
+
+                prop_assert_eq!(simd, iter);
+            }
+
+            #[test]
+            fn simd_separators_reproduce_lines((bytes, lines) in chunk()) {
+                let mut positions = Vec::new();
+                find_separators_simd(&bytes, &mut positions);
+
+                prop_assert_eq!(positions.len(), lines.len() * 2);
+
+                let mut start = 0;
+                let parsed = positions
+                    .chunks_exact(2)
+                    .map(|pair| {
+                        let (semicolon, newline) = (pair[0], pair[1]);
+                        let name = bytes[start..semicolon].to_vec();
+                        let value = func::parse_value_checked(&bytes[semicolon + 1..newline]);
+                        start = newline + 1;
+
+                        (name, value.unwrap())
+                    })
+                    .collect::<Vec<_>>();
+
+                prop_assert_eq!(parsed, lines);
+            }
+        }
+    }
+}
@@ -0,0 +1,210 @@
+//! Records partitioned into shards by the hash of the station names.
+//!
+//! Merging the records of the consumers one after the other is single-threaded, and shows up at
+//! high thread counts with many distinct stations. Once a consumer is done, its records are
+//! split into [`ShardedRecords`], so that a station is always in the same shard whichever
+//! consumer saw it; shard `i` of every consumer can then be merged in a task of its own, in
+//! parallel with the other shards, see [`merge_shards`](super::task::merge_shards).
+//!
+//! The shards are disjoint, so they are joined back into a single [`StationRecords`] without
+//! any merging, and exported in order by merging the sorted names of each shard.
+
+use std::hash::{BuildHasher, BuildHasherDefault, DefaultHasher};
+
+use itertools::Itertools;
+
+use super::aggregator::Aggregator;
+use super::func;
+use super::models::{StationRecords, StationStats};
+use super::LiteHashBuffer;
+
+/// The shard of `name` among `shards`, a power of two, by the top bits of its hash.
+///
+/// The shards are picked by a hash of their own, rather than the hash of the records, so that
+/// the names of a shard do not share the bits the maps pick their buckets with.
+#[inline]
+pub fn shard_of(name: &[u8], shards: usize) -> usize {
+    match shards.trailing_zeros() {
+        0 => 0,
+        bits => {
+            let hash = BuildHasherDefault::<DefaultHasher>::default().hash_one(name);
+            (hash >> (u64::BITS - bits)) as usize
+        }
+    }
+}
+
+/// Records of multiple stations, partitioned into a power of two of disjoint shards by
+/// [`shard_of`] the station names.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShardedRecords<A = StationStats> {
+    shards: Vec<StationRecords<A>>,
+}
+
+impl<A> ShardedRecords<A> {
+    /// Create empty records of `shards` shards, rounded up to a power of two.
+    pub fn new(shards: usize) -> Self {
+        Self {
+            shards: (0..shards.max(1).next_power_of_two())
+                .map(|_| StationRecords::default())
+                .collect(),
+        }
+    }
+
+    /// The number of shards.
+    pub fn shards(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Move the shards out, in order.
+    pub fn into_shards(self) -> Vec<StationRecords<A>> {
+        self.shards
+    }
+
+    /// Join the shards back into a single [`StationRecords`]; as the shards are disjoint, the
+    /// stations are moved over without being merged.
+    pub fn into_records(self) -> StationRecords<A> {
+        self.shards.into_iter().flatten().collect()
+    }
+}
+
+impl<A: Aggregator> ShardedRecords<A> {
+    /// Partition `records` into `shards` shards, rounded up to a power of two.
+    pub fn from_records(records: StationRecords<A>, shards: usize) -> Self {
+        let mut sharded = Self::new(shards);
+        let shards = sharded.shards();
+
+        for (name, stats) in records {
+            sharded.shards[shard_of(name.as_slice(), shards)].merge(name, stats);
+        }
+
+        sharded
+    }
+
+    /// Put together shards already partitioned by [`shard_of`], e.g. by
+    /// [`ShardedRecords::into_shards`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the number of shards is not a power of two.
+    pub fn from_shards(shards: Vec<StationRecords<A>>) -> Self {
+        assert!(
+            shards.len().is_power_of_two(),
+            "the number of shards must be a power of two, not {}",
+            shards.len()
+        );
+
+        Self { shards }
+    }
+
+    /// Merge the stats of a station aggregated elsewhere into its shard.
+    pub fn merge(&mut self, name: LiteHashBuffer, stats: A) {
+        let shard = shard_of(name.as_slice(), self.shards());
+        self.shards[shard].merge(name, stats);
+    }
+
+    /// The number of stations in the records.
+    pub fn stations(&self) -> usize {
+        self.shards.iter().map(StationRecords::stations).sum()
+    }
+
+    /// Iterate through the records in an alphabetical order of the station names, by merging
+    /// the names of each shard sorted on their own.
+    pub fn iter_sorted(&self) -> impl Iterator<Item = (&[u8], &A)> {
+        self.shards
+            .iter()
+            .map(StationRecords::iter_sorted)
+            .kmerge_by(|(lhs, _), (rhs, _)| lhs < rhs)
+    }
+
+    /// Export the results to a text in the 1BRC format, like
+    /// [`StationRecords::export_text`].
+    pub fn export_text(&self) -> String
+    where
+        A::Output: std::fmt::Display,
+    {
+        "{".to_owned()
+            + &itertools::join(
+                self.iter_sorted().map(|(name, stats)| {
+                    format!("{}={}", func::bytes_to_string(name), stats.emit())
+                }),
+                ", ",
+            )
+            + "}\n"
+    }
+}
+
+impl<A: Aggregator> std::ops::AddAssign for ShardedRecords<A> {
+    /// Merge `rhs` shard by shard, repartitioning it first if it has another number of shards.
+    fn add_assign(&mut self, rhs: Self) {
+        if rhs.shards() != self.shards() {
+            rhs.shards
+                .into_iter()
+                .flatten()
+                .for_each(|(name, stats)| self.merge(name, stats));
+            return;
+        }
+
+        self.shards
+            .iter_mut()
+            .zip(rhs.shards)
+            .for_each(|(shard, rhs_shard)| *shard += rhs_shard);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn records(lines: &[(&str, i16)]) -> StationRecords {
+        let mut records = StationRecords::default();
+        for &(name, value) in lines {
+            records.insert_borrowed(name.as_bytes(), value);
+        }
+        records
+    }
+
+    #[test]
+    fn partition_and_join() {
+        let lines: Vec<_> = (0..1000)
+            .map(|i| (format!("station{}", i % 300), (i % 100) as i16))
+            .collect();
+        let lines: Vec<_> = lines
+            .iter()
+            .map(|(name, value)| (name.as_str(), *value))
+            .collect();
+        let records = records(&lines);
+
+        let sharded = ShardedRecords::from_records(records.clone(), 6);
+        assert_eq!(sharded.shards(), 8);
+        assert_eq!(sharded.stations(), 300);
+        assert_eq!(sharded.export_text(), records.export_text());
+
+        // Every station is in the shard of its name.
+        for (index, shard) in sharded.clone().into_shards().into_iter().enumerate() {
+            assert!(shard.stations() > 0);
+            assert!(shard.iter().all(|(name, _)| shard_of(name, 8) == index));
+        }
+
+        assert_eq!(sharded.into_records(), records);
+    }
+
+    #[test]
+    fn add_shard_by_shard() {
+        let (lhs, rhs) = (
+            records(&[("jack", 12), ("jill", -34)]),
+            records(&[("jack", 56), ("bob", 0)]),
+        );
+
+        let mut sharded = ShardedRecords::from_records(lhs.clone(), 4);
+        sharded += ShardedRecords::from_records(rhs.clone(), 4);
+        assert_eq!(
+            sharded.export_text(),
+            (lhs.clone() + rhs.clone()).export_text()
+        );
+
+        // Records of another number of shards are repartitioned.
+        let mut sharded = ShardedRecords::from_records(lhs.clone(), 4);
+        sharded += ShardedRecords::from_records(rhs.clone(), 1);
+        assert_eq!(sharded.into_records(), lhs + rhs);
+    }
+}
@@ -0,0 +1,223 @@
+//! Save and load [`StationRecords`] as a binary snapshot.
+//!
+//! Unlike the 1BRC output, a snapshot keeps the exact integer statistics of each station,
+//! so that two runs can be compared without any rounding involved.
+//!
+//! The format is little-endian throughout:
+//!
+//! ```text
+//! magic: [u8; 8] = b"1BRCSNAP"
+//! version: u32
+//! stations: u64
+//! for each station, in order of name:
+//!     name length: u16
+//!     name: [u8; name length]
+//!     min: i16
+//!     max: i16
+//!     sum: i64
+//!     count: u64
+//! ```
+
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use super::models::{StationRecords, StationStats};
+use super::LiteHashBuffer;
+
+/// The bytes at the start of every snapshot.
+pub const SNAPSHOT_MAGIC: &[u8; 8] = b"1BRCSNAP";
+
+/// The version of the snapshot format written by [`StationRecords::write_snapshot`].
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// Create an error for a snapshot that cannot be decoded.
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+/// Read a fixed number of bytes.
+fn read_array<const N: usize>(reader: &mut impl Read) -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Decode the stations of a snapshot one at a time, in order of name, without loading the
+/// whole snapshot, e.g. to merge several of them.
+pub struct SnapshotReader<R> {
+    reader: R,
+    remaining: u64,
+}
+
+impl<R: Read> SnapshotReader<R> {
+    /// Decode the header of the snapshot from `reader`.
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        if &read_array::<8>(&mut reader)? != SNAPSHOT_MAGIC {
+            return Err(invalid("Not a snapshot of station records."));
+        }
+
+        let version = u32::from_le_bytes(read_array(&mut reader)?);
+        if version != SNAPSHOT_VERSION {
+            return Err(invalid(format!(
+                "Unsupported snapshot version {}; expected {}.",
+                version, SNAPSHOT_VERSION
+            )));
+        }
+
+        let remaining = u64::from_le_bytes(read_array(&mut reader)?);
+
+        Ok(Self { reader, remaining })
+    }
+
+    /// The number of stations left to decode.
+    pub fn remaining(&self) -> u64 {
+        self.remaining
+    }
+
+    /// Decode the next station.
+    fn station(&mut self) -> io::Result<(LiteHashBuffer, StationStats)> {
+        let reader = &mut self.reader;
+
+        let name_len = u16::from_le_bytes(read_array(reader)?) as usize;
+        let mut name = vec![0; name_len];
+        reader.read_exact(&mut name)?;
+
+        let min = i16::from_le_bytes(read_array(reader)?);
+        let max = i16::from_le_bytes(read_array(reader)?);
+        let sum = i64::from_le_bytes(read_array(reader)?);
+        let count = u64::from_le_bytes(read_array(reader)?);
+
+        let stats = StationStats {
+            min,
+            max,
+            sum: sum
+                .try_into()
+                .map_err(|_| invalid(format!("Sum {} is out of range.", sum)))?,
+            count: count
+                .try_into()
+                .map_err(|_| invalid(format!("Count {} is out of range.", count)))?,
+        };
+
+        Ok((LiteHashBuffer::from(name), stats))
+    }
+}
+
+impl<R: Read> Iterator for SnapshotReader<R> {
+    type Item = io::Result<(LiteHashBuffer, StationStats)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        let station = self.station();
+        // Stop after an error, as the rest of the snapshot cannot be decoded.
+        self.remaining = if station.is_ok() {
+            self.remaining - 1
+        } else {
+            0
+        };
+
+        Some(station)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, usize::try_from(self.remaining).ok())
+    }
+}
+
+impl StationRecords {
+    /// Encode the records as a snapshot into `writer`.
+    pub fn encode_snapshot(&self, writer: &mut impl Write) -> io::Result<()> {
+        let stations = self.iter_sorted().collect::<Vec<_>>();
+
+        writer.write_all(SNAPSHOT_MAGIC)?;
+        writer.write_all(&SNAPSHOT_VERSION.to_le_bytes())?;
+        writer.write_all(&(stations.len() as u64).to_le_bytes())?;
+
+        for (name, stats) in stations {
+            let name_len = u16::try_from(name.len()).map_err(|_| {
+                invalid(format!("Station name of {} bytes is too long.", name.len()))
+            })?;
+
+            writer.write_all(&name_len.to_le_bytes())?;
+            writer.write_all(name)?;
+            writer.write_all(&stats.min.to_le_bytes())?;
+            writer.write_all(&stats.max.to_le_bytes())?;
+            writer.write_all(&(stats.sum as i64).to_le_bytes())?;
+            writer.write_all(&(stats.count as u64).to_le_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    /// Decode a snapshot from `reader`.
+    pub fn decode_snapshot(reader: &mut impl Read) -> io::Result<Self> {
+        SnapshotReader::new(reader)?.collect()
+    }
+
+    /// Save the records as a snapshot file at `path`.
+    pub fn write_snapshot(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut file = io::BufWriter::new(std::fs::File::create(path)?);
+        self.encode_snapshot(&mut file)?;
+        file.flush()
+    }
+
+    /// Load the records from a snapshot file at `path`.
+    pub fn read_snapshot(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut file = io::BufReader::new(std::fs::File::open(path)?);
+        Self::decode_snapshot(&mut file)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::TestDir;
+
+    fn records() -> StationRecords {
+        let mut records = StationRecords::new();
+        records.insert("Zürich".as_bytes().into(), -123);
+        records.insert("Abha".as_bytes().into(), 999);
+        records.insert("Abha".as_bytes().into(), -999);
+        records
+    }
+
+    #[test]
+    fn snapshot_roundtrip() {
+        let records = records();
+
+        let mut bytes = Vec::new();
+        records.encode_snapshot(&mut bytes).unwrap();
+
+        assert!(bytes.starts_with(SNAPSHOT_MAGIC));
+        assert_eq!(
+            StationRecords::decode_snapshot(&mut &bytes[..]).unwrap(),
+            records
+        );
+    }
+
+    #[test]
+    fn snapshot_file_roundtrip() {
+        let dir = TestDir::new("snapshot_file_roundtrip");
+        let path = dir.join("snapshot.bin");
+        let records = records();
+
+        records.write_snapshot(&path).unwrap();
+        let loaded = StationRecords::read_snapshot(&path).unwrap();
+
+        assert_eq!(loaded, records);
+    }
+
+    #[test]
+    fn snapshot_invalid() {
+        let mut bytes = Vec::new();
+        records().encode_snapshot(&mut bytes).unwrap();
+
+        let error = StationRecords::decode_snapshot(&mut &b"NOTASNAP"[..]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+
+        let error = StationRecords::decode_snapshot(&mut &bytes[..bytes.len() - 1]).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
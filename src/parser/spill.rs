@@ -0,0 +1,400 @@
+//! Spill the records of the consumers to the disk, to keep the memory bounded however many
+//! distinct stations the input has.
+//!
+//! The 1BRC has about 400 stations, but other datasets may have millions of distinct keys,
+//! each kept by every consumer until the end of the run. With spilling, a consumer writes its
+//! records to a temporary file as a [snapshot](super::snapshot), i.e. sorted by name, and
+//! clears them whenever they grow past a threshold; the sorted files are merged at the end
+//! one station at a time, so that the merged results can be exported without loading them
+//! all back.
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::io::{self, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use super::atomic;
+use super::models::{StationRecords, StationStats};
+use super::snapshot::SnapshotReader;
+use super::units::Unit;
+use super::verify::{self, OutputError};
+use super::{func, LiteHashBuffer};
+
+/// The estimated size of each station in the records, i.e. its key and stats, and the control
+/// byte of the hash table.
+pub const STATION_SIZE: usize =
+    std::mem::size_of::<LiteHashBuffer>() + std::mem::size_of::<StationStats>() + 1;
+
+/// Tell apart the spill directories of the runs of a process.
+static SPILL_DIRS: AtomicUsize = AtomicUsize::new(0);
+
+/// When and where the records of the consumers are spilled.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpillOptions {
+    /// The estimated size in bytes of the records of a consumer above which they are spilled.
+    pub threshold: usize,
+
+    /// The directory the temporary files are created in.
+    pub dir: PathBuf,
+}
+
+impl SpillOptions {
+    /// Spill the records of a consumer above `threshold` bytes to the temporary directory of
+    /// the system.
+    pub fn new(threshold: usize) -> Self {
+        Self {
+            threshold,
+            dir: std::env::temp_dir(),
+        }
+    }
+
+    /// Create the temporary files in `dir` instead.
+    pub fn with_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.dir = dir.into();
+        self
+    }
+}
+
+/// The estimated size in bytes of `records`; the names too long to be kept inline by
+/// [`LiteHashBuffer`] take some more on the heap.
+pub fn estimated_size(records: &StationRecords) -> usize {
+    records.stations() * STATION_SIZE
+}
+
+/// A directory of the spill files of a single run, removed with the files once dropped.
+#[derive(Debug)]
+pub struct SpillDir {
+    path: PathBuf,
+    threshold: usize,
+}
+
+impl SpillDir {
+    /// Create a new directory for the spill files described by `options`.
+    pub fn create(options: &SpillOptions) -> io::Result<Self> {
+        let path = options.dir.join(format!(
+            "async_1brc_spill_{}_{}",
+            std::process::id(),
+            SPILL_DIRS.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(&path)?;
+
+        Ok(Self {
+            path,
+            threshold: options.threshold,
+        })
+    }
+
+    /// The path of the directory.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Create the [`Spiller`] of the consumer at `index`.
+    pub fn spiller(&self, index: usize) -> Spiller {
+        Spiller {
+            dir: self.path.clone(),
+            index,
+            threshold: self.threshold,
+            files: 0,
+            #[cfg(feature = "normalize")]
+            normalize_names: false,
+        }
+    }
+
+    /// The spill files written so far, in no particular order.
+    pub fn files(&self) -> io::Result<Vec<PathBuf>> {
+        std::fs::read_dir(&self.path)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect()
+    }
+
+    /// Merge every spill file, one station at a time in order of name.
+    pub fn merge(&self) -> io::Result<MergedSpills> {
+        MergedSpills::open(self.files()?)
+    }
+
+    /// Merge every spill file back into a single [`StationRecords`] in memory.
+    pub fn merge_records(&self) -> io::Result<StationRecords> {
+        self.merge()?.collect()
+    }
+
+    /// Merge every spill file, and export the results to `path` in the 1BRC format, without
+    /// loading them all in memory.
+    ///
+    /// The output is written through a temporary file, renamed over `path` once complete.
+    pub fn export_file(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        self.export_file_in(path, Unit::Celsius)
+    }
+
+    /// Merge every spill file, and export the results to `path` with the values in `unit`,
+    /// like [`SpillDir::export_file`].
+    pub fn export_file_in(&self, path: impl AsRef<Path>, unit: Unit) -> io::Result<()> {
+        let merged = self.merge()?;
+        atomic::write_blocking(path, |file| {
+            export_merged_in(merged, file, unit).map(|_| ())
+        })
+    }
+
+    /// Verify that the output file at `path` was exported from the merged spill files, again
+    /// without loading them all in memory; returns the number of stations.
+    pub fn verify_file(&self, path: impl AsRef<Path>) -> Result<usize, OutputError> {
+        self.verify_file_in(path, Unit::Celsius)
+    }
+
+    /// Verify that the output file at `path` was exported from the merged spill files in
+    /// `unit`, like [`SpillDir::verify_file`].
+    pub fn verify_file_in(&self, path: impl AsRef<Path>, unit: Unit) -> Result<usize, OutputError> {
+        verify::verify_file_in(path, self.merge()?, unit)
+    }
+}
+
+impl Drop for SpillDir {
+    fn drop(&mut self) {
+        // The files are temporary; a failure only leaves them behind in the temporary directory.
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
+/// Spill the records of a single consumer to the [`SpillDir`] it was created by.
+#[derive(Debug)]
+pub struct Spiller {
+    dir: PathBuf,
+    index: usize,
+    threshold: usize,
+    files: usize,
+    #[cfg(feature = "normalize")]
+    normalize_names: bool,
+}
+
+impl Spiller {
+    /// Merge the station names differing only by their Unicode normal form before spilling,
+    /// as the records of the consumers are then never normalized as a whole.
+    #[cfg(feature = "normalize")]
+    pub fn with_normalize_names(mut self, normalize_names: bool) -> Self {
+        self.normalize_names = normalize_names;
+        self
+    }
+
+    /// The number of files spilled so far.
+    pub fn files(&self) -> usize {
+        self.files
+    }
+
+    /// Spill `records` if their estimated size has reached the threshold.
+    pub fn spill_if_full(&mut self, records: &mut StationRecords) -> io::Result<()> {
+        if estimated_size(records) >= self.threshold {
+            self.spill(records)?;
+        }
+
+        Ok(())
+    }
+
+    /// Write `records` to a new spill file, and clear them; the records keep their capacity,
+    /// which is bounded by the threshold, for the next stations.
+    pub fn spill(&mut self, records: &mut StationRecords) -> io::Result<()> {
+        if records.stations() == 0 {
+            return Ok(());
+        }
+
+        #[cfg(feature = "normalize")]
+        if self.normalize_names {
+            records.normalize_names();
+        }
+
+        let path = self
+            .dir
+            .join(format!("consumer_{}_{}.bin", self.index, self.files));
+        records.write_snapshot(path)?;
+        records.drain();
+        self.files += 1;
+
+        Ok(())
+    }
+}
+
+/// The stations of several sorted spill files merged into one, in order of name.
+pub struct MergedSpills {
+    readers: Vec<SnapshotReader<BufReader<std::fs::File>>>,
+    /// The next name of each reader, with the index of the reader, smallest first.
+    heap: BinaryHeap<Reverse<(LiteHashBuffer, usize)>>,
+    /// The stats of the next name of each reader.
+    heads: Vec<Option<StationStats>>,
+}
+
+impl MergedSpills {
+    /// Open the spill files at `paths` to be merged.
+    pub fn open(paths: impl IntoIterator<Item = impl AsRef<Path>>) -> io::Result<Self> {
+        let readers = paths
+            .into_iter()
+            .map(|path| SnapshotReader::new(BufReader::new(std::fs::File::open(path)?)))
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let mut merged = Self {
+            heap: BinaryHeap::with_capacity(readers.len()),
+            heads: vec![None; readers.len()],
+            readers,
+        };
+        for index in 0..merged.readers.len() {
+            merged.advance(index)?;
+        }
+
+        Ok(merged)
+    }
+
+    /// Move the reader at `index` to its next station, if any.
+    fn advance(&mut self, index: usize) -> io::Result<()> {
+        if let Some(station) = self.readers[index].next() {
+            let (name, stats) = station?;
+            self.heads[index] = Some(stats);
+            self.heap.push(Reverse((name, index)));
+        }
+
+        Ok(())
+    }
+
+    /// Merge the stats of the smallest name of every reader.
+    fn merge_next(&mut self) -> io::Result<Option<(LiteHashBuffer, StationStats)>> {
+        let Some(Reverse((name, index))) = self.heap.pop() else {
+            return Ok(None);
+        };
+        let mut stats = self.heads[index].take().unwrap_or_default();
+        self.advance(index)?;
+
+        while let Some(Reverse((next, _))) = self.heap.peek() {
+            if *next != name {
+                break;
+            }
+
+            let Some(Reverse((_, index))) = self.heap.pop() else {
+                break;
+            };
+            stats += self.heads[index].take();
+            self.advance(index)?;
+        }
+
+        Ok(Some((name, stats)))
+    }
+}
+
+impl Iterator for MergedSpills {
+    type Item = io::Result<(LiteHashBuffer, StationStats)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.merge_next() {
+            Ok(station) => station.map(Ok),
+            Err(error) => {
+                // The files cannot be merged past an error.
+                self.heap.clear();
+                Some(Err(error))
+            }
+        }
+    }
+}
+
+/// Export the stations of `merged` to `writer` in the 1BRC format, as
+/// [`StationRecords::export_text`] would; returns the number of stations.
+pub fn export_merged(
+    merged: impl Iterator<Item = io::Result<(LiteHashBuffer, StationStats)>>,
+    writer: &mut impl Write,
+) -> io::Result<usize> {
+    export_merged_in(merged, writer, Unit::Celsius)
+}
+
+/// Export the stations of `merged` to `writer` with the values in `unit`, as
+/// [`StationRecords::export_text_in`] would; returns the number of stations.
+pub fn export_merged_in(
+    merged: impl Iterator<Item = io::Result<(LiteHashBuffer, StationStats)>>,
+    writer: &mut impl Write,
+    unit: Unit,
+) -> io::Result<usize> {
+    let mut stations = 0;
+
+    writer.write_all(b"{")?;
+    for station in merged {
+        let (name, stats) = station?;
+        let Some(summary) = unit.summarize(&stats) else {
+            continue;
+        };
+
+        if stations > 0 {
+            writer.write_all(b", ")?;
+        }
+        write!(writer, "{}={}", func::bytes_to_string(&name), summary)?;
+        stations += 1;
+    }
+    writer.write_all(b"}\n")?;
+
+    Ok(stations)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::sync;
+
+    #[test]
+    fn spill_and_merge() {
+        let spills = SpillDir::create(&SpillOptions::new(STATION_SIZE)).unwrap();
+        let input = "jack;1.2\njill;-3.4\nbob;0.0\namy;7.0\njack;5.6\nzed;-1.0\njill;2.0\n";
+
+        let mut expected = StationRecords::new();
+        sync::parse_bytes(input.as_bytes(), &mut expected);
+
+        // Two consumers taking turns with the lines, spilling after each one.
+        let mut spillers = [spills.spiller(0), spills.spiller(1)];
+        for (index, line) in input.lines().enumerate() {
+            let mut records = StationRecords::new();
+            sync::parse_bytes(format!("{}\n", line).as_bytes(), &mut records);
+
+            let spiller = &mut spillers[index % 2];
+            spiller.spill(&mut records).unwrap();
+            assert_eq!(records.stations(), 0);
+        }
+        assert_eq!(spills.files().unwrap().len(), 7);
+
+        assert_eq!(spills.merge_records().unwrap(), expected);
+
+        let mut exported = Vec::new();
+        let stations = export_merged(spills.merge().unwrap(), &mut exported).unwrap();
+        assert_eq!(stations, 5);
+        assert_eq!(String::from_utf8(exported).unwrap(), expected.export_text());
+
+        let output = spills.path().with_extension("txt");
+        spills.export_file(&output).unwrap();
+        let verified = spills.verify_file(&output);
+        std::fs::remove_file(&output).unwrap();
+        assert_eq!(verified.unwrap(), 5);
+
+        let path = spills.path().to_owned();
+        drop(spills);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn spill_if_full() {
+        let spills = SpillDir::create(&SpillOptions::new(2 * STATION_SIZE)).unwrap();
+        let mut spiller = spills.spiller(0);
+        let mut records = StationRecords::new();
+
+        records.insert(b"jack".into(), 12);
+        spiller.spill_if_full(&mut records).unwrap();
+        assert_eq!((records.stations(), spiller.files()), (1, 0));
+
+        records.insert(b"jill".into(), -34);
+        spiller.spill_if_full(&mut records).unwrap();
+        assert_eq!((records.stations(), spiller.files()), (0, 1));
+    }
+
+    #[test]
+    fn merge_nothing() {
+        let mut exported = Vec::new();
+        export_merged(
+            MergedSpills::open(Vec::<PathBuf>::new()).unwrap(),
+            &mut exported,
+        )
+        .unwrap();
+
+        assert_eq!(exported, b"{}\n");
+    }
+}
@@ -1,6 +1,20 @@
 //! Parsing a 1BRC line, synchronously.
 
-use super::{func, models};
+use std::hash::BuildHasher;
+
+use super::{
+    aggregator::Aggregator, func, key::KeyExtractor, models, scratch::ScratchSpace, separators,
+    values,
+};
+
+/// The size of the windows of a chunk parsed at a time by [`parse_bytes_batched`], so that
+/// the positions of the separators stay in the cache between the stages.
+const BATCHED_WINDOW_SIZE: usize = 1 << 16;
+
+thread_local! {
+    /// The scratch space of [`parse_bytes_batched`], for callers without one of their own.
+    static BATCHED_SCRATCH: std::cell::RefCell<ScratchSpace> = Default::default();
+}
 
 /// Parse bytes into a [`models::StationRecords`].
 ///
@@ -10,13 +24,16 @@ use super::{func, models};
 /// These parsing functions expect perfect input; if the input is not perfect, the behavior is
 /// undefined.
 #[allow(unreachable_code, unused_variables, unused_mut)]
-pub fn parse_bytes(bytes: &[u8], records: &mut models::StationRecords) {
+pub fn parse_bytes<A: Aggregator, S: BuildHasher>(
+    bytes: &[u8],
+    records: &mut models::StationRecords<A, S>,
+) {
     #[cfg(feature = "debug")]
     let mut counter = 0;
 
     bytes
         .split(|&byte| byte == b'\n')
-        .filter(|bytes| bytes.len() > 0)
+        .filter(|bytes| !bytes.is_empty())
         .for_each(|line| {
             #[cfg(feature = "debug")]
             '_debug: {
@@ -31,7 +48,7 @@ pub fn parse_bytes(bytes: &[u8], records: &mut models::StationRecords) {
             if let (Some(name), Some(value_raw), None) =
                 (line_split.next(), line_split.next(), line_split.next())
             {
-                records.insert(name.into(), parse_value(value_raw));
+                records.insert_borrowed(name, parse_value(value_raw));
             } else {
                 panic!(
                     "parse_bytes() found an invalid line: {:?}",
@@ -41,11 +58,208 @@ pub fn parse_bytes(bytes: &[u8], records: &mut models::StationRecords) {
         });
 }
 
+/// Parse bytes into a [`models::StationRecords`], finding the separators with
+/// [`memchr::memchr2`] and [`memchr::memchr`] rather than splitting the bytes one at a time.
+///
+/// The search is vectorized by the `memchr` crate on stable, with runtime CPU detection; the
+/// names and values are borrowed from `bytes` like [`parse_bytes`].
+///
+/// Like [`parse_bytes`], this expects perfect input, and panics on a line without a `;`.
+///
+/// Returns the number of records parsed.
+#[allow(unreachable_code, unused_variables)]
+pub fn parse_bytes_memchr<A: Aggregator, S: BuildHasher>(
+    bytes: &[u8],
+    records: &mut models::StationRecords<A, S>,
+) -> usize {
+    #[cfg(feature = "noparse")]
+    {
+        // This will prevent any parsing from being done at all; all data will be discarded.
+        // This is just for testing purposes.
+        records.insert("some place".as_bytes().into(), 0);
+        return 1;
+    }
+
+    let mut count = 0;
+    let mut rest = bytes;
+
+    // The first separator of a line is its `;`, unless the line is empty or invalid.
+    while let Some(position) = memchr::memchr2(b';', b'\n', rest) {
+        if rest[position] == b'\n' {
+            if position > 0 {
+                panic!(
+                    "parse_bytes_memchr() found an invalid line: {:?}",
+                    func::bytes_to_string(&rest[..position])
+                );
+            }
+
+            rest = &rest[1..];
+            continue;
+        }
+
+        let (name, value) = (&rest[..position], &rest[position + 1..]);
+        let end = memchr::memchr(b'\n', value).unwrap_or(value.len());
+
+        records.insert_borrowed(name, parse_value(&value[..end]));
+        count += 1;
+
+        rest = value.get(end + 1..).unwrap_or_default();
+    }
+
+    if !rest.is_empty() {
+        panic!(
+            "parse_bytes_memchr() found an invalid line: {:?}",
+            func::bytes_to_string(rest)
+        );
+    }
+
+    count
+}
+
+/// Parse bytes into a [`models::StationRecords`] in stages: the separators are located with
+/// [`separators::find_separators_simd`], then the values of every line are converted in
+/// batches with [`values::parse_values_batched`], before the records are inserted.
+///
+/// The bytes are parsed 64 KiB at a time, split at the end of a line, with the scratch space of
+/// the current thread; see [`parse_bytes_batched_with`] to bring your own.
+///
+/// Like [`parse_bytes`], this expects perfect input, and panics on a line without a `;`.
+///
+/// Returns the number of records parsed.
+pub fn parse_bytes_batched<A: Aggregator, S: BuildHasher>(
+    bytes: &[u8],
+    records: &mut models::StationRecords<A, S>,
+) -> usize {
+    BATCHED_SCRATCH.with_borrow_mut(|scratch| parse_bytes_batched_with(bytes, records, scratch))
+}
+
+/// Parse bytes like [`parse_bytes_batched`], keeping the positions and values of each stage in
+/// `scratch`, which is reused by the caller across chunks.
+#[allow(unreachable_code, unused_variables)]
+pub fn parse_bytes_batched_with<A: Aggregator, S: BuildHasher>(
+    bytes: &[u8],
+    records: &mut models::StationRecords<A, S>,
+    scratch: &mut ScratchSpace,
+) -> usize {
+    #[cfg(feature = "noparse")]
+    {
+        // This will prevent any parsing from being done at all; all data will be discarded.
+        // This is just for testing purposes.
+        records.insert("some place".as_bytes().into(), 0);
+        return 1;
+    }
+
+    let mut count = 0;
+    let mut rest = bytes;
+
+    while !rest.is_empty() {
+        let window = match rest.len() > BATCHED_WINDOW_SIZE {
+            true => memchr::memrchr(b'\n', &rest[..BATCHED_WINDOW_SIZE])
+                .map_or(rest, |end| &rest[..=end]),
+            false => rest,
+        };
+
+        count += parse_window_batched(window, records, scratch);
+        rest = &rest[window.len()..];
+    }
+
+    count
+}
+
+/// Parse a window of complete lines for [`parse_bytes_batched`].
+fn parse_window_batched<A: Aggregator, S: BuildHasher>(
+    bytes: &[u8],
+    records: &mut models::StationRecords<A, S>,
+    scratch: &mut ScratchSpace,
+) -> usize {
+    scratch.clear();
+
+    let ScratchSpace {
+        separators,
+        names,
+        starts,
+        values,
+    } = scratch;
+
+    separators::find_separators_simd(bytes, separators);
+
+    // Pair the last `;` of each line with its start, skipping empty lines.
+    let invalid = |line: &[u8]| -> ! {
+        panic!(
+            "parse_bytes_batched() found an invalid line: {:?}",
+            func::bytes_to_string(line)
+        )
+    };
+    let (mut line_start, mut semicolon) = (0, None);
+
+    for &position in separators.iter() {
+        if bytes[position] == b';' {
+            semicolon = Some(position);
+            continue;
+        }
+
+        match semicolon.take() {
+            Some(semicolon) => names.push((line_start, semicolon)),
+            None if position == line_start => {}
+            None => invalid(&bytes[line_start..position]),
+        }
+        line_start = position + 1;
+    }
+
+    match semicolon {
+        Some(semicolon) => names.push((line_start, semicolon)),
+        None if line_start == bytes.len() => {}
+        None => invalid(&bytes[line_start..]),
+    }
+
+    starts.extend(names.iter().map(|&(_, semicolon)| semicolon + 1));
+    values::parse_values_batched(bytes, starts, values);
+
+    for (&(start, semicolon), &value) in names.iter().zip(values.iter()) {
+        records.insert_borrowed(&bytes[start..semicolon], value);
+    }
+
+    names.len()
+}
+
+/// Parse bytes into a [`models::StationRecords`], grouping the records by the key derived by
+/// `extractor` from the fields before the last `;` of each line.
+///
+/// Like [`parse_bytes`], this expects perfect input, and panics if the key cannot be derived.
+pub fn parse_bytes_with_key<A: Aggregator, S: BuildHasher>(
+    bytes: &[u8],
+    records: &mut models::StationRecords<A, S>,
+    extractor: &impl KeyExtractor,
+) {
+    bytes
+        .split(|&byte| byte == b'\n')
+        .filter(|bytes| !bytes.is_empty())
+        .for_each(|line| {
+            let record = func::find_semicolon(line).and_then(|position| {
+                Some((
+                    extractor.key(&line[..position])?,
+                    parse_value(&line[position + 1..]),
+                ))
+            });
+
+            match record {
+                Some((key, value)) => records.insert(key, value),
+                None => panic!(
+                    "parse_bytes_with_key() found an invalid line: {:?}",
+                    func::bytes_to_string(line)
+                ),
+            }
+        });
+}
+
 /// Parse value.
+///
+/// An empty value, e.g. at the end of a truncated chunk, is parsed as `0` rather than
+/// panicking.
 pub fn parse_value(bytes: &[u8]) -> i16 {
     let mut multiplier: i16 = 1;
 
-    if bytes[0] == b'-' {
+    if bytes.first() == Some(&b'-') {
         multiplier = -1;
     }
 
@@ -0,0 +1,198 @@
+//! A hash table of the stations built directly on [`hashbrown::HashTable`], backing
+//! [`StationRecords`](super::models::StationRecords) with the `raw-table` feature.
+//!
+//! Unlike a [`HashMap`](std::collections::HashMap), the table is handed the hash of each name
+//! by the caller: a name is hashed once, whether the station is found or inserted, and a hash
+//! computed ahead of time, e.g. while scanning a batch of names, can be reused as is through
+//! [`StationRecords::insert_hashed`](super::models::StationRecords::insert_hashed).
+
+use std::hash::BuildHasher;
+
+use hashbrown::hash_table;
+
+use super::hasher::StationHasher;
+use super::LiteHashBuffer;
+
+/// The stations and their aggregates, keyed by the hash of the name from `S`.
+#[derive(Clone)]
+pub struct StationTable<A, S = StationHasher> {
+    table: hashbrown::HashTable<(LiteHashBuffer, A)>,
+    hasher: S,
+}
+
+/// An iterator over the names of a [`StationTable`].
+pub type Keys<'a, A> = std::iter::Map<
+    hash_table::Iter<'a, (LiteHashBuffer, A)>,
+    fn(&'a (LiteHashBuffer, A)) -> &'a LiteHashBuffer,
+>;
+
+/// A draining iterator over the entries of a [`StationTable`].
+pub type Drain<'a, A> = hash_table::Drain<'a, (LiteHashBuffer, A)>;
+
+/// An owning iterator over the entries of a [`StationTable`].
+pub type IntoIter<A> = hash_table::IntoIter<(LiteHashBuffer, A)>;
+
+impl<A, S: BuildHasher> StationTable<A, S> {
+    /// Create an empty table with room for `capacity` stations.
+    pub fn with_capacity_and_hasher(capacity: usize, hasher: S) -> Self {
+        Self {
+            table: hashbrown::HashTable::with_capacity(capacity),
+            hasher,
+        }
+    }
+
+    /// Hash a name the same way as the keys of the table.
+    #[inline]
+    pub fn hash(&self, name: &[u8]) -> u64 {
+        self.hasher.hash_one(name)
+    }
+
+    /// The number of stations.
+    pub fn len(&self) -> usize {
+        self.table.len()
+    }
+
+    /// Check if there is no station.
+    pub fn is_empty(&self) -> bool {
+        self.table.is_empty()
+    }
+
+    /// Get the aggregate of the station `name` of the given `hash`.
+    #[inline]
+    pub fn find(&self, hash: u64, name: &[u8]) -> Option<&A> {
+        self.table
+            .find(hash, |(key, _)| key.as_slice() == name)
+            .map(|(_, value)| value)
+    }
+
+    /// Get the aggregate of the station `name` of the given `hash` mutably.
+    #[inline]
+    pub fn find_mut(&mut self, hash: u64, name: &[u8]) -> Option<&mut A> {
+        self.table
+            .find_mut(hash, |(key, _)| key.as_slice() == name)
+            .map(|(_, value)| value)
+    }
+
+    /// Insert a station known to be absent from the table.
+    #[inline]
+    pub fn insert_unique(&mut self, hash: u64, name: LiteHashBuffer, value: A) {
+        let hasher = &self.hasher;
+
+        self.table.insert_unique(hash, (name, value), |(key, _)| {
+            hasher.hash_one(key.as_slice())
+        });
+    }
+
+    /// Get the aggregate of the station `name`.
+    pub fn get(&self, name: &[u8]) -> Option<&A> {
+        self.find(self.hash(name), name)
+    }
+
+    /// Insert a station, replacing its aggregate if it is already in the table.
+    pub fn insert(&mut self, name: LiteHashBuffer, value: A) {
+        let hash = self.hash(name.as_slice());
+
+        match self.find_mut(hash, name.as_slice()) {
+            Some(existing) => *existing = value,
+            None => self.insert_unique(hash, name, value),
+        }
+    }
+
+    /// Iterate through the names in an arbitrary order.
+    pub fn keys(&self) -> Keys<'_, A> {
+        self.table.iter().map(|(key, _)| key)
+    }
+
+    /// Iterate through the aggregates in an arbitrary order.
+    pub fn values(&self) -> impl Iterator<Item = &A> {
+        self.table.iter().map(|(_, value)| value)
+    }
+
+    /// Iterate through the aggregates mutably, in an arbitrary order.
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut A> {
+        self.table.iter_mut().map(|(_, value)| value)
+    }
+
+    /// Keep only the stations for which `keep` returns `true`.
+    pub fn retain(&mut self, mut keep: impl FnMut(&LiteHashBuffer, &mut A) -> bool) {
+        self.table.retain(|(key, value)| keep(key, value));
+    }
+
+    /// Remove every station, moving them out in an arbitrary order.
+    pub fn drain(&mut self) -> Drain<'_, A> {
+        self.table.drain()
+    }
+}
+
+impl<A, S: BuildHasher> Extend<(LiteHashBuffer, A)> for StationTable<A, S> {
+    fn extend<I: IntoIterator<Item = (LiteHashBuffer, A)>>(&mut self, iter: I) {
+        iter.into_iter()
+            .for_each(|(name, value)| self.insert(name, value));
+    }
+}
+
+impl<A, S> IntoIterator for StationTable<A, S> {
+    type Item = (LiteHashBuffer, A);
+    type IntoIter = IntoIter<A>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.table.into_iter()
+    }
+}
+
+impl<A: std::fmt::Debug, S> std::fmt::Debug for StationTable<A, S> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_map()
+            .entries(self.table.iter().map(|(key, value)| (key, value)))
+            .finish()
+    }
+}
+
+impl<A: PartialEq, S: BuildHasher> PartialEq for StationTable<A, S> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len()
+            && self
+                .table
+                .iter()
+                .all(|(key, value)| other.get(key.as_slice()) == Some(value))
+    }
+}
+
+impl<A: Eq, S: BuildHasher> Eq for StationTable<A, S> {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn find_and_insert() {
+        let mut table = StationTable::with_capacity_and_hasher(4, StationHasher::default());
+
+        for (index, name) in ["jack", "jill", "jack", "Petropavlovsk-Kamchatsky"]
+            .iter()
+            .enumerate()
+        {
+            let hash = table.hash(name.as_bytes());
+
+            match table.find_mut(hash, name.as_bytes()) {
+                Some(count) => *count += index,
+                None => table.insert_unique(hash, name.as_bytes().into(), index),
+            }
+        }
+
+        assert_eq!(table.len(), 3);
+        assert_eq!(table.get(b"jack"), Some(&2));
+        assert_eq!(table.get(b"bob"), None);
+
+        let mut names = table.keys().map(|name| name.as_slice()).collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(
+            names,
+            [b"Petropavlovsk-Kamchatsky".as_slice(), b"jack", b"jill"]
+        );
+
+        let other = table.clone().into_iter().collect::<Vec<_>>();
+        assert_eq!(other.len(), 3);
+        assert_eq!(table.clone(), table);
+    }
+}
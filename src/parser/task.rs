@@ -1,47 +1,386 @@
 //! Task to create a number of threads to read from the same [`RowsReader`].
 
 use super::super::reader::RowsReader;
+use super::aggregator::Aggregator;
+use super::engine::ParserEngine;
 use super::models::StationRecords;
+use super::sharded::ShardedRecords;
+use super::spill::{SpillDir, Spiller};
 use std::sync::Arc;
 
+#[cfg(feature = "mem-stats")]
+use super::super::mem_stats::{self, Stage};
+
+use super::super::timed;
+
+/// Spawn a consumer task, named after its index so that it can be told apart in
+/// `tokio-console` with the `console` feature.
+fn spawn_consumer<F>(_index: usize, future: F) -> tokio::task::JoinHandle<F::Output>
+where
+    F: std::future::Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    #[cfg(feature = "console")]
+    let future =
+        tracing::Instrument::instrument(future, tracing::info_span!("consumer", index = _index));
+
+    #[cfg(all(feature = "console", tokio_unstable))]
+    return tokio::task::Builder::new()
+        .name(&format!("consumer #{}", _index))
+        .spawn(future)
+        .expect("Failed to spawn the consumer task.");
+
+    #[cfg(not(all(feature = "console", tokio_unstable)))]
+    tokio::spawn(future)
+}
+
+/// Run `read_task` alongside `consumers`, e.g. [`read_from_reader`], consuming the chunks it
+/// reads.
+///
+/// Returns the result of the read and of the consumers. If every consumer fails, nothing
+/// takes the chunks off the reader any more, so `read_task` is dropped instead of awaited,
+/// and only the failure of the consumers is reported.
+pub async fn read_and_consume<T>(
+    read_task: impl std::future::Future<Output = std::io::Result<()>>,
+    consumers: impl std::future::Future<Output = Result<T, tokio::task::JoinError>>,
+) -> (std::io::Result<()>, Result<T, tokio::task::JoinError>) {
+    tokio::pin!(read_task, consumers);
+
+    tokio::select! {
+        biased;
+
+        read = &mut read_task => (read, consumers.await),
+        consumed = &mut consumers => match consumed {
+            Ok(consumed) => (read_task.await, Ok(consumed)),
+            Err(error) => (Ok(()), Err(error)),
+        },
+    }
+}
+
+/// Spawn `threads` consumers, at least one, each running the future returned by `consumer`
+/// with its index.
+fn spawn_consumers<F, C>(threads: usize, consumer: C) -> Vec<tokio::task::JoinHandle<F::Output>>
+where
+    C: Fn(usize) -> F,
+    F: std::future::Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    (0..threads.max(1))
+        .map(|_i| {
+            let consumer = timed::with_consumer(_i, consumer(_i));
+
+            #[cfg(feature = "mem-stats")]
+            let consumer = mem_stats::in_stage(Stage::Parser, consumer);
+
+            spawn_consumer(_i, consumer)
+        })
+        .collect()
+}
+
 /// Create X number of concurrent consumers to read from the same [`RowsReader`].
-pub async fn read_from_reader(
+///
+/// Each chunk is parsed by `engine`, and the records are aggregated by `A`, i.e.
+/// [`StationStats`](super::models::StationStats) for the 1BRC output.
+///
+/// Returns the error of the first consumer that panicked, e.g. on an invalid line in strict
+/// mode, once every consumer has finished.
+pub async fn read_from_reader<A>(
     reader: Arc<RowsReader>,
     threads: usize,
     max_chunk_size: usize,
-) -> StationRecords {
-    // If there is only one thread, we can just read from the reader directly.
-    if threads <= 1 {
-        // Somehow changing this to just awaiting the inner function call makes the code slower??
-        // This may be because tokio will spawn a new thread for the inner function call, leaving
-        // the main thread to continue with the rest of the code.
-        return tokio::spawn(async move {
-            StationRecords::read_from_reader(&reader, max_chunk_size).await
-        })
-        .await
-        .unwrap();
-    }
+    engine: ParserEngine,
+) -> Result<StationRecords<A>, tokio::task::JoinError>
+where
+    A: Aggregator + Send + 'static,
+{
+    read_from_reader_into(
+        reader,
+        threads,
+        max_chunk_size,
+        engine,
+        StationRecords::default(),
+    )
+    .await
+}
 
-    let mut handles = Vec::with_capacity(threads);
+/// Create X number of concurrent consumers to read from the same [`RowsReader`] like
+/// [`read_from_reader`], merging their records into `records` instead of empty ones, e.g. the
+/// [`reset`](StationRecords::reset) records of a previous run.
+///
+/// Returns the error of the first consumer that panicked once every consumer has finished.
+pub async fn read_from_reader_into<A>(
+    reader: Arc<RowsReader>,
+    threads: usize,
+    max_chunk_size: usize,
+    engine: ParserEngine,
+    mut records: StationRecords<A>,
+) -> Result<StationRecords<A>, tokio::task::JoinError>
+where
+    A: Aggregator + Send + 'static,
+{
+    let handles = spawn_consumers(threads, |_i| {
+        let reader = Arc::clone(&reader);
 
-    for _i in 0..threads {
-        let local_reader = Arc::clone(&reader);
-        handles.push(tokio::spawn(async move {
+        async move {
             #[cfg(feature = "debug")]
             println!("task::read_from_reader() spawned consumer #{}", _i);
 
-            StationRecords::read_from_reader(&local_reader, max_chunk_size).await
-        }));
+            StationRecords::read_from_reader(&reader, max_chunk_size, engine).await
+        }
+    });
+
+    // If there is only one thread, its records are returned as they are.
+    if matches!(handles.as_slice(), [_]) && records.stations() == 0 {
+        return handles.into_iter().next().unwrap().await;
     }
 
-    let mut records = StationRecords::new();
+    let mut failure = None;
     #[allow(clippy::unused_enumerate_index)]
     for (_i, handle) in handles.into_iter().enumerate() {
-        records += handle.await.unwrap();
+        let consumer_records = match handle.await {
+            Ok(consumer_records) => consumer_records,
+            // The other consumers carry on with the chunks of a failed one, so they are all
+            // awaited before the error is returned.
+            Err(error) => {
+                failure.get_or_insert(error);
+                continue;
+            }
+        };
+
+        #[cfg(feature = "mem-stats")]
+        let _stage = Stage::Merge.enter();
+
+        records += consumer_records;
 
         #[cfg(feature = "debug")]
         println!("task::read_from_reader() consumer #{} finished.", _i);
     }
 
-    records
+    match failure {
+        Some(error) => Err(error),
+        None => Ok(records),
+    }
+}
+
+/// Create X number of concurrent consumers to read from the same [`RowsReader`] like
+/// [`read_from_reader`], each partitioning its records into `shards` shards once done, which
+/// are then merged in parallel by [`merge_shards`].
+///
+/// Returns the error of the first consumer that panicked once every consumer has finished.
+pub async fn read_from_reader_sharded<A>(
+    reader: Arc<RowsReader>,
+    threads: usize,
+    max_chunk_size: usize,
+    engine: ParserEngine,
+    shards: usize,
+) -> Result<ShardedRecords<A>, tokio::task::JoinError>
+where
+    A: Aggregator + Send + 'static,
+{
+    let handles = spawn_consumers(threads, |_i| {
+        let reader = Arc::clone(&reader);
+
+        async move {
+            let records = StationRecords::read_from_reader(&reader, max_chunk_size, engine).await;
+            ShardedRecords::from_records(records, shards)
+        }
+    });
+
+    let mut consumers = Vec::with_capacity(handles.len());
+    let mut failure = None;
+    for handle in handles {
+        match handle.await {
+            Ok(records) => consumers.push(records),
+            Err(error) => {
+                failure.get_or_insert(error);
+            }
+        }
+    }
+
+    match failure {
+        Some(error) => Err(error),
+        None => merge_shards(consumers).await,
+    }
+}
+
+/// Merge the records of every consumer shard by shard, each shard in a task of its own.
+///
+/// The records are repartitioned into the shards of the first ones if their numbers of shards
+/// differ.
+pub async fn merge_shards<A>(
+    consumers: Vec<ShardedRecords<A>>,
+) -> Result<ShardedRecords<A>, tokio::task::JoinError>
+where
+    A: Aggregator + Send + 'static,
+{
+    let mut consumers = consumers.into_iter();
+    let Some(first) = consumers.next() else {
+        return Ok(ShardedRecords::new(1));
+    };
+
+    // The columns of the shards: `columns[i]` holds shard `i` of every consumer.
+    let shards = first.shards();
+    let mut columns: Vec<Vec<_>> = first
+        .into_shards()
+        .into_iter()
+        .map(|shard| vec![shard])
+        .collect();
+    for records in consumers {
+        let records = if records.shards() == shards {
+            records
+        } else {
+            let mut repartitioned = ShardedRecords::new(shards);
+            repartitioned += records;
+            repartitioned
+        };
+
+        for (column, shard) in columns.iter_mut().zip(records.into_shards()) {
+            column.push(shard);
+        }
+    }
+
+    let handles: Vec<_> = columns
+        .into_iter()
+        .map(|column| {
+            tokio::spawn(async move {
+                #[cfg(feature = "mem-stats")]
+                let _stage = Stage::Merge.enter();
+
+                column.into_iter().sum::<StationRecords<A>>()
+            })
+        })
+        .collect();
+
+    let mut merged = Vec::with_capacity(handles.len());
+    for handle in handles {
+        merged.push(handle.await?);
+    }
+
+    Ok(ShardedRecords::from_shards(merged))
+}
+
+/// Create X number of concurrent consumers to read from the same [`RowsReader`] like
+/// [`read_from_reader`], each merging its records into the
+/// [`Checkpoints`](super::checkpoint::Checkpoints) of the reader after every chunk, so that
+/// the reader can save them every so often.
+///
+/// Returns the records merged, including those the checkpoints resumed from, or the error of
+/// the first consumer that panicked.
+///
+/// # Panics
+///
+/// Panics if the reader has no checkpoints.
+pub async fn checkpoint_from_reader(
+    reader: Arc<RowsReader>,
+    threads: usize,
+    max_chunk_size: usize,
+    engine: ParserEngine,
+) -> Result<StationRecords, tokio::task::JoinError> {
+    assert!(
+        reader.checkpoints().is_some(),
+        "checkpoint_from_reader() requires a reader with checkpoints"
+    );
+
+    let handles = spawn_consumers(threads, |_i| {
+        let reader = Arc::clone(&reader);
+
+        async move {
+            let checkpoints = reader.checkpoints().expect("the reader has checkpoints");
+            let consumer = checkpoints.consumer();
+
+            StationRecords::read_from_reader_with(&reader, max_chunk_size, engine, |records| {
+                checkpoints.merge(records)
+            })
+            .await;
+
+            consumer.finish();
+        }
+    });
+
+    let mut failure = None;
+    for handle in handles {
+        if let Err(error) = handle.await {
+            failure.get_or_insert(error);
+        }
+    }
+
+    let checkpoints = reader.checkpoints().expect("the reader has checkpoints");
+    match failure {
+        Some(error) => Err(error),
+        None => {
+            let records = checkpoints.take_records();
+
+            // The consumers only normalize the names of the records they still hold, which
+            // are merged after every chunk.
+            #[cfg(feature = "normalize")]
+            let records = {
+                let mut records = records;
+                if reader.normalize_names() {
+                    records.normalize_names();
+                }
+                records
+            };
+
+            Ok(records)
+        }
+    }
+}
+
+/// Create X number of concurrent consumers to read from the same [`RowsReader`] like
+/// [`read_from_reader`], each spilling its records to `spills` whenever they grow past the
+/// threshold, and once more at the end.
+///
+/// The records are left in `spills` to be merged, instead of being returned; returns the
+/// number of files spilled, or the error of the first consumer that panicked, e.g. as it
+/// could not write a spill file.
+pub async fn spill_from_reader(
+    reader: Arc<RowsReader>,
+    threads: usize,
+    max_chunk_size: usize,
+    engine: ParserEngine,
+    spills: &SpillDir,
+) -> Result<usize, tokio::task::JoinError> {
+    let handles = spawn_consumers(threads, |index| {
+        let reader = Arc::clone(&reader);
+        let spiller = spills.spiller(index);
+
+        #[cfg(feature = "normalize")]
+        let spiller = spiller.with_normalize_names(reader.normalize_names());
+
+        async move {
+            let mut spiller = spiller;
+            let spill = |spiller: &mut Spiller, records: &mut StationRecords| {
+                spiller
+                    .spill_if_full(records)
+                    .unwrap_or_else(|err| panic!("Could not spill the records: {}", err))
+            };
+
+            let mut records =
+                StationRecords::read_from_reader_with(&reader, max_chunk_size, engine, |records| {
+                    spill(&mut spiller, records)
+                })
+                .await;
+
+            spiller
+                .spill(&mut records)
+                .unwrap_or_else(|err| panic!("Could not spill the records: {}", err));
+            spiller.files()
+        }
+    });
+
+    let mut files = 0;
+    let mut failure = None;
+    for handle in handles {
+        match handle.await {
+            Ok(spilled) => files += spilled,
+            Err(error) => {
+                failure.get_or_insert(error);
+            }
+        }
+    }
+
+    match failure {
+        Some(error) => Err(error),
+        None => Ok(files),
+    }
 }
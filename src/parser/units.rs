@@ -0,0 +1,181 @@
+//! Export the results in another unit than degrees Celsius.
+//!
+//! The values are kept in tenths of a degree Celsius throughout the parsing, and only
+//! converted when exported. Each conversion into hundredths of a degree is exact in integers,
+//! e.g. `tenths * 18 + 3200` into Fahrenheit, so the values are only rounded once, to the tenth
+//! of a degree of the output, the same way for the minimums, maximums and means.
+
+use super::aggregator::{Aggregator, StationSummary};
+use super::func;
+use super::models::{StationRecords, StationStats};
+
+/// The unit of the exported values.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Unit {
+    /// Degrees Celsius, as in the input.
+    #[default]
+    Celsius,
+
+    /// Degrees Fahrenheit.
+    Fahrenheit,
+
+    /// Kelvin.
+    Kelvin,
+}
+
+impl Unit {
+    /// The factor and the offset converting tenths of a degree Celsius into hundredths of the
+    /// unit.
+    fn hundredths_per_tenth(&self) -> (i64, i64) {
+        match self {
+            Self::Celsius => (10, 0),
+            Self::Fahrenheit => (18, 3200),
+            Self::Kelvin => (10, 27315),
+        }
+    }
+
+    /// Convert `sum` of `count` values in tenths of a degree Celsius into their sum in
+    /// hundredths of the unit, exactly.
+    pub fn hundredths(&self, sum: i64, count: usize) -> i64 {
+        let (factor, offset) = self.hundredths_per_tenth();
+        sum * factor + offset * count as i64
+    }
+
+    /// Convert a value in tenths of a degree Celsius into tenths of the unit, rounded half up.
+    pub fn tenths(&self, value: i16) -> i64 {
+        (self.hundredths(value as i64, 1) + 5).div_euclid(10)
+    }
+
+    /// The exact mean of `stats` in tenths of the unit, or `None` without any value.
+    pub fn mean(&self, stats: &StationStats) -> Option<f64> {
+        (stats.count > 0).then(|| {
+            self.hundredths(stats.sum as i64, stats.count) as f64 / stats.count as f64 / 10.0
+        })
+    }
+
+    /// The min, mean and max of `stats` in the unit; the same as [`Aggregator::emit`] in
+    /// degrees Celsius. `None` without any value, e.g. for a station left behind by
+    /// [`StationRecords::reset`].
+    pub fn summarize(&self, stats: &StationStats) -> Option<StationSummary> {
+        if stats.count == 0 {
+            return None;
+        } else if *self == Self::Celsius {
+            return Some(stats.emit());
+        }
+
+        let count = stats.count as i64;
+        let mean =
+            (self.hundredths(stats.sum as i64, stats.count) + 5 * count).div_euclid(10 * count);
+
+        Some(StationSummary {
+            min: self.tenths(stats.min) as f32 / 10.0,
+            mean: mean as f32 / 10.0,
+            max: self.tenths(stats.max) as f32 / 10.0,
+        })
+    }
+}
+
+impl std::fmt::Display for Unit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Celsius => write!(f, "celsius"),
+            Self::Fahrenheit => write!(f, "fahrenheit"),
+            Self::Kelvin => write!(f, "kelvin"),
+        }
+    }
+}
+
+impl StationRecords {
+    /// Export the results to a text in the 1BRC format like [`StationRecords::export_text`],
+    /// with the values in `unit`; the stations without any value are left out.
+    pub fn export_text_in(&self, unit: Unit) -> String {
+        "{".to_owned()
+            + &itertools::join(
+                self.iter_sorted().filter_map(|(name, stats)| {
+                    let summary = unit.summarize(stats)?;
+                    Some(format!("{}={}", func::bytes_to_string(name), summary))
+                }),
+                ", ",
+            )
+            + "}\n"
+    }
+
+    /// Export the results to a file in the 1BRC format with the values in `unit`, through a
+    /// temporary file renamed over `path` once complete, like
+    /// [`StationRecords::try_export_file`].
+    #[cfg(feature = "runtime")]
+    pub async fn try_export_file_in(
+        &self,
+        path: impl AsRef<std::path::Path>,
+        unit: Unit,
+    ) -> std::io::Result<()> {
+        super::atomic::write(path, self.export_text_in(unit).as_bytes()).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::sync;
+
+    #[test]
+    fn convert_values() {
+        assert_eq!(Unit::Fahrenheit.tenths(0), 320);
+        assert_eq!(Unit::Fahrenheit.tenths(-400), -400);
+        assert_eq!(Unit::Fahrenheit.tenths(999), 2118);
+        // -1.2 °C is 29.84 °F, and 1.3 °C is 34.34 °F.
+        assert_eq!(Unit::Fahrenheit.tenths(-12), 298);
+        assert_eq!(Unit::Fahrenheit.tenths(13), 343);
+        // 0.0 °C is 273.15 K, rounded half up.
+        assert_eq!(Unit::Kelvin.tenths(0), 2732);
+        assert_eq!(Unit::Kelvin.tenths(-999), 1733);
+        assert_eq!(Unit::Celsius.tenths(-12), -12);
+    }
+
+    #[test]
+    fn export_in_units() {
+        let mut records = StationRecords::new();
+        sync::parse_bytes(b"jack;1.2\njill;-3.4\njack;5.6\n", &mut records);
+
+        assert_eq!(records.export_text_in(Unit::Celsius), records.export_text());
+        assert_eq!(
+            records.export_text_in(Unit::Fahrenheit),
+            "{jack=34.2/38.1/42.1, jill=25.9/25.9/25.9}\n"
+        );
+        assert_eq!(
+            records.export_text_in(Unit::Kelvin),
+            "{jack=274.4/276.6/278.8, jill=269.8/269.8/269.8}\n"
+        );
+    }
+
+    #[test]
+    fn skip_reset_stations() {
+        let mut records = StationRecords::new();
+        sync::parse_bytes(b"jack;1.2\njill;-3.4\n", &mut records);
+        records.reset();
+        sync::parse_bytes(b"jack;5.6\n", &mut records);
+
+        let jill = records.get(&b"jill".into()).unwrap();
+        for unit in [Unit::Celsius, Unit::Fahrenheit, Unit::Kelvin] {
+            assert_eq!(unit.summarize(jill), None);
+            assert_eq!(unit.mean(jill), None);
+        }
+        assert_eq!(
+            records.export_text_in(Unit::Celsius),
+            "{jack=5.6/5.6/5.6}\n"
+        );
+        assert_eq!(
+            records.export_text_in(Unit::Fahrenheit),
+            "{jack=42.1/42.1/42.1}\n"
+        );
+
+        let expected = records
+            .iter_sorted()
+            .map(|(name, stats)| Ok((name, *stats)));
+        let text = records.export_text_in(Unit::Kelvin);
+        assert_eq!(
+            crate::parser::verify::verify_output_in(&text, expected, Unit::Kelvin).unwrap(),
+            1
+        );
+    }
+}
@@ -0,0 +1,132 @@
+//! Convert the values of 1BRC lines into tenths of a degree, without branching on the
+//! digits.
+//!
+//! A value, i.e. `-?\d{1,2}\.\d`, is at most 5 bytes long, so it fits in a single [`u64`]
+//! word together with the bytes following it. [`parse_value_swar`] finds the decimal point
+//! and the sign from the bits of the word, aligns the digits and combines them with a single
+//! multiply (SWAR, i.e. SIMD within a register).
+//!
+//! [`parse_values_batched`] applies the same arithmetic to [`BATCH_SIZE`] words at a time,
+//! once the starts of the values are known from the separator scan, so that the compiler can
+//! vectorize the conversion across lines.
+
+/// The number of values converted together by [`parse_values_batched`].
+pub const BATCH_SIZE: usize = 8;
+
+/// The bit which is set in every ASCII digit, but not in `.`, at the bytes where the decimal
+/// point can be, i.e. the 2nd to the 4th byte.
+const DIGIT_BITS: u64 = 0x1010_1000;
+
+/// The digits of `\d\d\.\d` once aligned, i.e. the tens, the units and the tenths.
+const DIGITS_MASK: u64 = 0x0F_000F_0F00;
+
+/// Multiply the aligned digits by 100, 10 and 1 into the same byte.
+const MAGIC_MULTIPLIER: u64 = 100 * 0x100_0000 + 10 * 0x1_0000 + 1;
+
+/// Read the 8 bytes at `offset` as a little-endian word, padded with zeros past the end of
+/// `bytes`.
+#[inline]
+fn load(bytes: &[u8], offset: usize) -> u64 {
+    match bytes.get(offset..offset + 8) {
+        Some(word) => u64::from_le_bytes(word.try_into().unwrap()),
+        None => {
+            let rest = &bytes[offset..];
+            let mut word = [0; 8];
+            word[..rest.len()].copy_from_slice(rest);
+            u64::from_le_bytes(word)
+        }
+    }
+}
+
+/// Convert a word starting with a value into tenths of a degree.
+#[inline(always)]
+fn convert(word: u64) -> i16 {
+    // The first byte without the digit bit among the 2nd to the 4th is the decimal point.
+    let decimal_point = (!word & DIGIT_BITS).trailing_zeros();
+
+    // All ones if the first byte is `-`, which lacks the digit bit, or zero otherwise.
+    let sign = ((!word << 59) as i64 >> 63) as u64;
+
+    // Drop the sign, and shift the digits so that the decimal point is at the 4th byte.
+    let digits = ((word & !(sign & 0xFF)) << (28 - decimal_point)) & DIGITS_MASK;
+    let absolute = (digits.wrapping_mul(MAGIC_MULTIPLIER) >> 32) & 0x3FF;
+
+    ((absolute ^ sign).wrapping_sub(sign)) as i16
+}
+
+/// Parse the value starting at `start` in `bytes` into tenths of a degree.
+///
+/// The value must match `-?\d{1,2}\.\d`, followed by the end of the line or of `bytes`;
+/// otherwise the result is meaningless.
+#[inline]
+pub fn parse_value_swar(bytes: &[u8], start: usize) -> i16 {
+    convert(load(bytes, start))
+}
+
+/// Parse the values starting at each of `starts` in `bytes`, appending them to `values` in
+/// order.
+///
+/// The values are converted [`BATCH_SIZE`] at a time; the same rules as
+/// [`parse_value_swar`] apply to each value.
+pub fn parse_values_batched(bytes: &[u8], starts: &[usize], values: &mut Vec<i16>) {
+    let batches = starts.chunks_exact(BATCH_SIZE);
+    let remainder = batches.remainder();
+
+    values.reserve(starts.len());
+
+    for batch in batches {
+        let words: [u64; BATCH_SIZE] = std::array::from_fn(|index| load(bytes, batch[index]));
+        values.extend(words.map(convert));
+    }
+
+    values.extend(
+        remainder
+            .iter()
+            .map(|&start| parse_value_swar(bytes, start)),
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Format tenths of a degree as a 1BRC value, e.g. `-12.3`.
+    fn format_value(tenths: i16) -> String {
+        let sign = if tenths < 0 { "-" } else { "" };
+        format!("{}{}.{}", sign, tenths.abs() / 10, tenths.abs() % 10)
+    }
+
+    #[test]
+    fn parse_every_value() {
+        for tenths in -999..=999 {
+            let value = format_value(tenths);
+
+            assert_eq!(parse_value_swar(value.as_bytes(), 0), tenths, "{}", value);
+            assert_eq!(
+                parse_value_swar(format!("{}\nAbha;1.0\n", value).as_bytes(), 0),
+                tenths,
+                "{}",
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn parse_batches() {
+        let (mut bytes, mut starts, mut expected) = (Vec::new(), Vec::new(), Vec::new());
+
+        for tenths in (-999..=999).step_by(7) {
+            bytes.extend_from_slice(b"Abha;");
+            starts.push(bytes.len());
+            bytes.extend_from_slice(format_value(tenths).as_bytes());
+            bytes.push(b'\n');
+            expected.push(tenths);
+        }
+
+        let mut values = Vec::new();
+        parse_values_batched(&bytes, &starts, &mut values);
+
+        assert_ne!(starts.len() % BATCH_SIZE, 0);
+        assert_eq!(values, expected);
+    }
+}
@@ -0,0 +1,412 @@
+//! Verify an exported output against the records it was exported from.
+//!
+//! The output is parsed back independently of the formatting of [`StationStats`], and walked
+//! along the expected stations in order of name, so that a bug in the export, e.g. a
+//! mis-rounded mean, a station missing, or an unsorted output, is caught before the output is
+//! compared against a baseline.
+
+use std::io;
+use std::ops::Deref;
+use std::path::Path;
+
+use super::func;
+use super::models::{StationRecords, StationStats};
+use super::units::Unit;
+
+/// The difference allowed between a mean in the output and the exact mean, in tenths of a
+/// degree: half a tenth for the rounding, and some more for the `f32` arithmetic.
+const MEAN_TOLERANCE: f64 = 0.5 + 1e-3;
+
+/// Why an output does not match the records.
+#[derive(Debug)]
+pub enum OutputError {
+    /// The output could not be read.
+    Io(io::Error),
+
+    /// The output is not in the 1BRC format.
+    Malformed { offset: usize, reason: &'static str },
+
+    /// The output does not end with a newline.
+    MissingNewline,
+
+    /// A station does not sort after the previous one.
+    Unordered { previous: String, name: String },
+
+    /// A station of the records is missing from the output.
+    Missing(String),
+
+    /// A station of the output is not in the records.
+    Unexpected(String),
+
+    /// A statistic of a station differs from the records, in degrees.
+    Value {
+        name: String,
+        statistic: &'static str,
+        expected: f64,
+        found: f64,
+    },
+}
+
+impl std::fmt::Display for OutputError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(error) => write!(f, "Could not read the output: {}", error),
+            Self::Malformed { offset, reason } => {
+                write!(f, "The output is malformed at byte {}: {}", offset, reason)
+            }
+            Self::MissingNewline => write!(f, "The output does not end with a newline"),
+            Self::Unordered { previous, name } => {
+                write!(f, "{:?} is not sorted after {:?}", name, previous)
+            }
+            Self::Missing(name) => write!(f, "{:?} is missing from the output", name),
+            Self::Unexpected(name) => write!(f, "{:?} is not in the records", name),
+            Self::Value {
+                name,
+                statistic,
+                expected,
+                found,
+            } => write!(
+                f,
+                "{:?}: {} is {:.1} in the output but {} in the records",
+                name, statistic, found, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for OutputError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for OutputError {
+    fn from(error: io::Error) -> Self {
+        Self::Io(error)
+    }
+}
+
+/// Parse a value with a single decimal, such as `-12.3`, at the start of `text`, into tenths;
+/// returns the value and its length.
+fn parse_value(text: &str) -> Option<(i64, usize)> {
+    let bytes = text.as_bytes();
+    let negative = bytes.first() == Some(&b'-');
+    let start = negative as usize;
+
+    let integer = bytes[start..]
+        .iter()
+        .take_while(|byte| byte.is_ascii_digit())
+        .count();
+    let decimal = *bytes.get(start + integer + 1)?;
+    if integer == 0 || bytes[start + integer] != b'.' || !decimal.is_ascii_digit() {
+        return None;
+    }
+
+    let tenths = text[start..start + integer].parse::<i64>().ok()? * 10 + (decimal - b'0') as i64;
+    Some((if negative { -tenths } else { tenths }, start + integer + 2))
+}
+
+/// Parse `min/mean/max` at the start of `text`, if followed by the end of the station; returns
+/// the values and their length.
+fn parse_values(text: &str) -> Option<([i64; 3], usize)> {
+    let mut values = [0; 3];
+    let mut length = 0;
+
+    for (index, value) in values.iter_mut().enumerate() {
+        if index > 0 {
+            length += text[length..].strip_prefix('/').map(|_| 1)?;
+        }
+
+        let (parsed, parsed_length) = parse_value(&text[length..])?;
+        *value = parsed;
+        length += parsed_length;
+    }
+
+    let rest = &text[length..];
+    (rest.starts_with(", ") || rest.starts_with('}')).then_some((values, length))
+}
+
+/// A station parsed from the output.
+struct OutputStation<'t> {
+    name: &'t str,
+    /// The min, mean and max in tenths of a degree.
+    values: [i64; 3],
+}
+
+/// Parse the stations of a 1BRC output in order.
+///
+/// Station names may contain `=` or `, `, as each station ends at the first `=` followed by
+/// three values and the end of the station.
+fn parse_stations(text: &str) -> Result<Vec<OutputStation<'_>>, OutputError> {
+    let malformed = |rest: &str, reason| OutputError::Malformed {
+        offset: text.len() - rest.len(),
+        reason,
+    };
+
+    let mut rest = text
+        .strip_prefix('{')
+        .ok_or_else(|| malformed(text, "expected `{`"))?;
+    let mut stations = Vec::new();
+
+    while !rest.starts_with('}') {
+        if !stations.is_empty() {
+            rest = rest
+                .strip_prefix(", ")
+                .ok_or_else(|| malformed(rest, "expected `, `"))?;
+        }
+
+        let (name, values, length) = rest
+            .match_indices('=')
+            .find_map(|(index, _)| {
+                parse_values(&rest[index + 1..])
+                    .map(|(values, length)| (&rest[..index], values, index + 1 + length))
+            })
+            .ok_or_else(|| malformed(rest, "expected a station with `name=min/mean/max`"))?;
+
+        stations.push(OutputStation { name, values });
+        rest = &rest[length..];
+    }
+
+    match &rest[1..] {
+        "\n" => Ok(stations),
+        "" => Err(OutputError::MissingNewline),
+        trailing => Err(malformed(trailing, "expected the end of the output")),
+    }
+}
+
+/// Check the values of a station against its stats, converted into `unit`.
+fn verify_values(
+    station: &OutputStation,
+    stats: &StationStats,
+    unit: Unit,
+) -> Result<(), OutputError> {
+    let mismatch = |statistic, expected: f64, found: i64| OutputError::Value {
+        name: station.name.to_owned(),
+        statistic,
+        expected,
+        found: found as f64 / 10.0,
+    };
+    let [min, mean, max] = station.values;
+    let (expected_min, expected_max) = (unit.tenths(stats.min), unit.tenths(stats.max));
+    // A station without any value is never exported.
+    let Some(exact_mean) = unit.mean(stats) else {
+        return Err(OutputError::Unexpected(station.name.to_owned()));
+    };
+
+    if min != expected_min {
+        Err(mismatch("min", expected_min as f64 / 10.0, min))
+    } else if max != expected_max {
+        Err(mismatch("max", expected_max as f64 / 10.0, max))
+    } else if (mean as f64 - exact_mean).abs() > MEAN_TOLERANCE {
+        Err(mismatch("mean", exact_mean / 10.0, mean))
+    } else {
+        Ok(())
+    }
+}
+
+/// Verify an output in the 1BRC format against the `expected` stations in order of name;
+/// returns the number of stations.
+///
+/// The minimums and maximums must be exact, and each mean must be a rounding of the exact mean
+/// to a tenth of a degree.
+pub fn verify_output<N: Deref<Target = [u8]>>(
+    text: &str,
+    expected: impl IntoIterator<Item = io::Result<(N, StationStats)>>,
+) -> Result<usize, OutputError> {
+    verify_output_in(text, expected, Unit::Celsius)
+}
+
+/// Verify an output in the 1BRC format with the values in `unit` against the `expected`
+/// stations in order of name, like [`verify_output`]; the expected stations without any value
+/// are left out, as [`StationRecords::export_text_in`] does.
+pub fn verify_output_in<N: Deref<Target = [u8]>>(
+    text: &str,
+    expected: impl IntoIterator<Item = io::Result<(N, StationStats)>>,
+    unit: Unit,
+) -> Result<usize, OutputError> {
+    let stations = parse_stations(text)?;
+
+    for pair in stations.windows(2) {
+        if pair[0].name >= pair[1].name {
+            return Err(OutputError::Unordered {
+                previous: pair[0].name.to_owned(),
+                name: pair[1].name.to_owned(),
+            });
+        }
+    }
+
+    let mut expected = expected
+        .into_iter()
+        .filter(|station| !matches!(station, Ok((_, stats)) if stats.count == 0));
+    for station in &stations {
+        let (name, stats) = expected
+            .next()
+            .ok_or_else(|| OutputError::Unexpected(station.name.to_owned()))??;
+        let name = func::bytes_to_string(&name);
+
+        // Both are sorted, so the first of the two names is missing from the other.
+        match station.name.cmp(&name) {
+            std::cmp::Ordering::Less => {
+                return Err(OutputError::Unexpected(station.name.to_owned()))
+            }
+            std::cmp::Ordering::Greater => return Err(OutputError::Missing(name.into_owned())),
+            std::cmp::Ordering::Equal => verify_values(station, &stats, unit)?,
+        }
+    }
+
+    match expected.next() {
+        Some(station) => Err(OutputError::Missing(
+            func::bytes_to_string(&station?.0).into_owned(),
+        )),
+        None => Ok(stations.len()),
+    }
+}
+
+/// Verify the output file at `path` against the `expected` stations in order of name; returns
+/// the number of stations.
+pub fn verify_file<N: Deref<Target = [u8]>>(
+    path: impl AsRef<Path>,
+    expected: impl IntoIterator<Item = io::Result<(N, StationStats)>>,
+) -> Result<usize, OutputError> {
+    verify_file_in(path, expected, Unit::Celsius)
+}
+
+/// Verify the output file at `path` with the values in `unit` against the `expected` stations
+/// in order of name; returns the number of stations.
+pub fn verify_file_in<N: Deref<Target = [u8]>>(
+    path: impl AsRef<Path>,
+    expected: impl IntoIterator<Item = io::Result<(N, StationStats)>>,
+    unit: Unit,
+) -> Result<usize, OutputError> {
+    verify_output_in(&std::fs::read_to_string(path)?, expected, unit)
+}
+
+impl StationRecords {
+    /// Verify that the output file at `path` was exported from these records; returns the
+    /// number of stations.
+    pub fn verify_file(&self, path: impl AsRef<Path>) -> Result<usize, OutputError> {
+        self.verify_file_in(path, Unit::Celsius)
+    }
+
+    /// Verify that the output file at `path` was exported from these records in `unit`;
+    /// returns the number of stations.
+    pub fn verify_file_in(&self, path: impl AsRef<Path>, unit: Unit) -> Result<usize, OutputError> {
+        verify_file_in(
+            path,
+            self.iter_sorted().map(|(name, stats)| Ok((name, *stats))),
+            unit,
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::parser::sync;
+
+    fn records(input: &str) -> StationRecords {
+        let mut records = StationRecords::new();
+        sync::parse_bytes(input.as_bytes(), &mut records);
+        records
+    }
+
+    fn verify(text: &str, records: &StationRecords) -> Result<usize, OutputError> {
+        verify_output(
+            text,
+            records
+                .iter_sorted()
+                .map(|(name, stats)| Ok((name, *stats))),
+        )
+    }
+
+    #[test]
+    fn verify_exported() {
+        let records = records("jack;1.2\njill;-3.4\njack;5.6\na=b, c;0.0\nz;-0.1\nz;0.0\n");
+
+        assert_eq!(verify(&records.export_text(), &records).unwrap(), 4);
+        assert_eq!(verify("{}\n", &StationRecords::new()).unwrap(), 0);
+    }
+
+    #[test]
+    fn verify_rounding() {
+        let records = records("jack;1.2\njack;1.3\n");
+
+        // The exact mean of 1.25 may be rounded either way.
+        assert!(verify("{jack=1.2/1.2/1.3}\n", &records).is_ok());
+        assert!(verify("{jack=1.2/1.3/1.3}\n", &records).is_ok());
+        assert!(matches!(
+            verify("{jack=1.2/1.4/1.3}\n", &records),
+            Err(OutputError::Value {
+                statistic: "mean",
+                ..
+            })
+        ));
+        assert!(matches!(
+            verify("{jack=1.1/1.2/1.3}\n", &records),
+            Err(OutputError::Value {
+                statistic: "min",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn verify_in_units() {
+        let records = records("jack;1.2\njill;-3.4\njack;5.6\n");
+        let expected = || {
+            records
+                .iter_sorted()
+                .map(|(name, stats)| Ok((name, *stats)))
+        };
+
+        for unit in [Unit::Celsius, Unit::Fahrenheit, Unit::Kelvin] {
+            let text = records.export_text_in(unit);
+            assert_eq!(verify_output_in(&text, expected(), unit).unwrap(), 2);
+        }
+        assert!(matches!(
+            verify_output_in(&records.export_text(), expected(), Unit::Kelvin),
+            Err(OutputError::Value { .. })
+        ));
+    }
+
+    #[test]
+    fn verify_errors() {
+        let records = records("a;1.0\nb;2.0\n");
+
+        assert!(matches!(
+            verify("{a=1.0/1.0/1.0, b=2.0/2.0/2.0}", &records),
+            Err(OutputError::MissingNewline)
+        ));
+        assert!(matches!(
+            verify("{b=2.0/2.0/2.0, a=1.0/1.0/1.0}\n", &records),
+            Err(OutputError::Unordered { .. })
+        ));
+        assert!(matches!(
+            verify("{a=1.0/1.0/1.0}\n", &records),
+            Err(OutputError::Missing(name)) if name == "b"
+        ));
+        assert!(matches!(
+            verify("{a=1.0/1.0/1.0, b=2.0/2.0/2.0, c=3.0/3.0/3.0}\n", &records),
+            Err(OutputError::Unexpected(name)) if name == "c"
+        ));
+        assert!(matches!(
+            verify("{a=1.0/1.0/1.0, ab=1.0/1.0/1.0, b=2.0/2.0/2.0}\n", &records),
+            Err(OutputError::Unexpected(name)) if name == "ab"
+        ));
+        assert!(matches!(
+            verify("{a=1/1.0/1.0}\n", &records),
+            Err(OutputError::Malformed { offset: 1, .. })
+        ));
+        assert!(matches!(
+            verify("{a=1.0/1.0/1.0, b=2.0/2.0/2.00}\n", &records),
+            Err(OutputError::Malformed { offset: 16, .. })
+        ));
+        assert!(matches!(
+            verify("{a=1.0/1.0/1.0, b=2.0/2.0/2.0}\n\n", &records),
+            Err(OutputError::Malformed { .. })
+        ));
+    }
+}
@@ -0,0 +1,524 @@
+//! Aggregate an input with timestamps into time windows, e.g. the stats of each station per day.
+//!
+//! Besides the `station;temp` lines of the 1BRC, real datasets usually record when each value
+//! was measured, as `station;timestamp;temp`. The timestamp is either a number of seconds since
+//! the Unix epoch, or an ISO 8601 date and time, e.g. `2024-01-31T12:34:56Z`, with an optional
+//! fraction of a second and an offset from UTC; a date alone is taken as its midnight in UTC.
+//!
+//! [`WindowedRecords`] keep the records of each station per [`Window`], in a map of the
+//! windows, keyed by their start, nested in a map of the stations; any [`Aggregator`] can be
+//! kept per window, as in [`StationRecords`](super::models::StationRecords). The windows of the
+//! default [`StationStats`] are exported as CSV:
+//!
+//! ```text
+//! station,window,min,mean,max,count
+//! Abha,2024-01-31T00:00:00Z,-1.2,18.0,45.6,24
+//! ```
+
+use std::collections::BTreeMap;
+use std::io::{self, Read, Write};
+use std::path::Path;
+
+use super::aggregator::Aggregator;
+use super::models::StationStats;
+use super::sync::parse_value;
+use super::{atomic, func};
+use crate::config;
+
+/// The length of the time windows the records are aggregated in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Window {
+    /// From the start of each hour, in UTC.
+    Hour,
+
+    /// From midnight of each day, in UTC.
+    #[default]
+    Day,
+}
+
+impl Window {
+    /// The length of the window in seconds.
+    pub fn seconds(&self) -> i64 {
+        match self {
+            Self::Hour => 3600,
+            Self::Day => 86400,
+        }
+    }
+
+    /// The start of the window containing `timestamp`, in seconds since the Unix epoch.
+    pub fn start(&self, timestamp: i64) -> i64 {
+        timestamp - timestamp.rem_euclid(self.seconds())
+    }
+}
+
+impl std::fmt::Display for Window {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Hour => write!(f, "hour"),
+            Self::Day => write!(f, "day"),
+        }
+    }
+}
+
+/// The number of days from the Unix epoch to a date of the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    era * 146097 + day_of_era - 719468
+}
+
+/// The date of the proleptic Gregorian calendar a number of days from the Unix epoch.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+
+    (year_of_era + era * 400 + i64::from(month <= 2), month, day)
+}
+
+/// Parse the unsigned decimal digits of `bytes`, if they are all digits.
+fn digits(bytes: &[u8]) -> Option<i64> {
+    if bytes.is_empty() || !bytes.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+
+    Some(
+        bytes
+            .iter()
+            .fold(0, |acc, &digit| acc * 10 + func::u8_to_digit(digit) as i64),
+    )
+}
+
+/// Parse a timestamp, either in seconds since the Unix epoch, or in ISO 8601, into seconds
+/// since the Unix epoch; see the [module](self) documentation.
+pub fn parse_timestamp(bytes: &[u8]) -> Option<i64> {
+    match bytes {
+        [b'-', seconds @ ..] => return digits(seconds).map(|seconds| -seconds),
+        _ if !bytes.contains(&b'-') => return digits(bytes),
+        _ => {}
+    }
+
+    // `YYYY-MM-DD`, then an optional `THH:MM[:SS[.fff]]` and offset.
+    let (date, rest) = bytes.split_at(bytes.len().min(10));
+    let [year @ .., b'-', m1, m2, b'-', d1, d2] = date else {
+        return None;
+    };
+    let (year, month, day) = (digits(year)?, digits(&[*m1, *m2])?, digits(&[*d1, *d2])?);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    let mut seconds = days_from_civil(year, month, day) * 86400;
+    let rest = match rest {
+        [] => return Some(seconds),
+        [b'T' | b' ', time @ ..] => time,
+        _ => return None,
+    };
+
+    // The offset from UTC, if any, starts at the first `Z`, `+` or `-` after the time.
+    let end = rest
+        .iter()
+        .position(|byte| matches!(byte, b'Z' | b'+' | b'-'))
+        .unwrap_or(rest.len());
+    let (time, offset) = rest.split_at(end);
+
+    // The fraction of a second is dropped.
+    let time = time.split(|&byte| byte == b'.').next().unwrap_or_default();
+    let mut fields = time.split(|&byte| byte == b':');
+    let (hours, minutes) = (digits(fields.next()?)?, digits(fields.next()?)?);
+    let secs = fields.next().map_or(Some(0), digits)?;
+    if fields.next().is_some() || hours > 23 || minutes > 59 || secs > 60 {
+        return None;
+    }
+    seconds += hours * 3600 + minutes * 60 + secs;
+
+    match offset {
+        [] | [b'Z'] => Some(seconds),
+        [sign @ (b'+' | b'-'), h1, h2, rest @ ..] => {
+            let minutes = match rest {
+                [] => 0,
+                [b':', m1, m2] | [m1, m2] => digits(&[*m1, *m2])?,
+                _ => return None,
+            };
+            let offset = digits(&[*h1, *h2])? * 3600 + minutes * 60;
+
+            Some(if *sign == b'+' {
+                seconds - offset
+            } else {
+                seconds + offset
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Format a timestamp in seconds since the Unix epoch as ISO 8601 in UTC.
+pub fn format_timestamp(timestamp: i64) -> String {
+    let (days, seconds) = (timestamp.div_euclid(86400), timestamp.rem_euclid(86400));
+    let (year, month, day) = civil_from_days(days);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    )
+}
+
+/// Records of multiple stations per time window, aggregated by `A`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WindowedRecords<A = StationStats> {
+    window: Window,
+    /// The records of each station, keyed by the start of their windows; the names are kept as
+    /// plain bytes, which unlike a `LiteHashBuffer` can be looked up by a slice with any hasher.
+    stations: BTreeMap<Vec<u8>, BTreeMap<i64, A>>,
+}
+
+impl<A> WindowedRecords<A> {
+    /// Create empty records, aggregating in windows of `window`.
+    pub fn new(window: Window) -> Self {
+        Self {
+            window,
+            stations: BTreeMap::new(),
+        }
+    }
+
+    /// The length of the windows.
+    pub fn window(&self) -> Window {
+        self.window
+    }
+
+    /// The number of stations.
+    pub fn stations(&self) -> usize {
+        self.stations.len()
+    }
+
+    /// The number of windows with records, over every station.
+    pub fn windows(&self) -> usize {
+        self.stations.values().map(BTreeMap::len).sum()
+    }
+
+    /// The records of `name`, keyed by the start of their windows, if any.
+    pub fn get(&self, name: &[u8]) -> Option<&BTreeMap<i64, A>> {
+        self.stations.get(name)
+    }
+
+    /// Iterate over the stations in order of name, with their windows in order of time.
+    pub fn iter(&self) -> impl Iterator<Item = (&[u8], &BTreeMap<i64, A>)> {
+        self.stations
+            .iter()
+            .map(|(name, windows)| (name.as_slice(), windows))
+    }
+}
+
+impl<A: Aggregator> WindowedRecords<A> {
+    /// Insert a value of `name` measured at `timestamp`, in seconds since the Unix epoch.
+    pub fn insert(&mut self, name: &[u8], timestamp: i64, value: i16) {
+        let start = self.window.start(timestamp);
+        let windows = match self.stations.get_mut(name) {
+            Some(windows) => windows,
+            None => self.stations.entry(name.to_vec()).or_default(),
+        };
+
+        windows
+            .entry(start)
+            .and_modify(|aggregator| aggregator.observe(value))
+            .or_insert_with(|| A::from_value(value));
+    }
+
+    /// Parse the `station;timestamp;temp` lines of `bytes` into the records, returning the
+    /// number of lines parsed.
+    ///
+    /// Like [`super::sync::parse_bytes_with_key`], this expects valid input, and panics on a
+    /// line without a timestamp, or with a timestamp which cannot be parsed.
+    pub fn parse_bytes(&mut self, bytes: &[u8]) -> usize {
+        let mut lines = 0;
+
+        for line in bytes
+            .split(|&byte| byte == b'\n')
+            .filter(|line| !line.is_empty())
+        {
+            let record = func::find_semicolon(line).and_then(|value| {
+                let fields = &line[..value];
+                let name = memchr::memchr(b';', fields)?;
+
+                Some((
+                    &fields[..name],
+                    parse_timestamp(&fields[name + 1..])?,
+                    parse_value(&line[value + 1..]),
+                ))
+            });
+
+            match record {
+                Some((name, timestamp, value)) => self.insert(name, timestamp, value),
+                None => panic!(
+                    "WindowedRecords::parse_bytes() found an invalid line: {:?}",
+                    func::bytes_to_string(line)
+                ),
+            }
+            lines += 1;
+        }
+
+        lines
+    }
+
+    /// Parse the complete lines of `reader` into the records until the end of its data,
+    /// returning the number of bytes consumed; an incomplete line at the end is not parsed, as
+    /// in [`StationRecords::ingest`](super::models::StationRecords::ingest).
+    pub fn ingest(&mut self, mut reader: impl Read) -> io::Result<u64> {
+        let mut buffer = Vec::with_capacity(config::CHUNK_SIZE + config::MAX_LINE_LENGTH);
+        let mut consumed = 0;
+
+        loop {
+            let filled = buffer.len();
+            buffer.resize(filled + config::CHUNK_SIZE, 0);
+
+            let read = loop {
+                match reader.read(&mut buffer[filled..]) {
+                    Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                    read => break read?,
+                }
+            };
+
+            buffer.truncate(filled + read);
+            if read == 0 {
+                return Ok(consumed);
+            }
+
+            if let Some(end) = memchr::memrchr(b'\n', &buffer) {
+                self.parse_bytes(&buffer[..=end]);
+
+                consumed += end as u64 + 1;
+                buffer.drain(..=end);
+            }
+        }
+    }
+}
+
+impl<A: Aggregator> std::ops::AddAssign for WindowedRecords<A> {
+    /// Merge the records of `rhs` into these, in the windows of these records; both should
+    /// have the same windows.
+    fn add_assign(&mut self, rhs: Self) {
+        for (name, windows) in rhs.stations {
+            let merged = self.stations.entry(name).or_default();
+
+            for (start, aggregator) in windows {
+                match merged.entry(self.window.start(start)) {
+                    std::collections::btree_map::Entry::Occupied(mut entry) => {
+                        entry.get_mut().merge(aggregator)
+                    }
+                    std::collections::btree_map::Entry::Vacant(entry) => {
+                        entry.insert(aggregator);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl WindowedRecords {
+    /// Write the windows as CSV to `writer`, with a header: the station, the start of the
+    /// window in ISO 8601, then its minimum, mean, maximum and count, by station then time.
+    pub fn write_csv(&self, mut writer: impl Write) -> io::Result<()> {
+        writeln!(writer, "station,window,min,mean,max,count")?;
+
+        for (name, windows) in &self.stations {
+            let name = func::bytes_to_string(name);
+            let name = csv_field(&name);
+
+            for (&start, stats) in windows {
+                let summary = stats.emit();
+                writeln!(
+                    writer,
+                    "{},{},{:.1},{:.1},{:.1},{}",
+                    name,
+                    format_timestamp(start),
+                    summary.min,
+                    summary.mean,
+                    summary.max,
+                    stats.count
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Export the windows as CSV to `path`, replacing it atomically; see [`Self::write_csv`].
+    pub fn export_csv(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        atomic::write_blocking(path, |writer| self.write_csv(writer))
+    }
+}
+
+/// Quote a CSV field if it has a comma, a quote or a line break.
+fn csv_field(field: &str) -> std::borrow::Cow<'_, str> {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\"")).into()
+    } else {
+        field.into()
+    }
+}
+
+/// Aggregate the file at `path` into windows of `window`, splitting it at the lines into one
+/// range per thread, each parsed on its own thread.
+#[cfg(feature = "runtime")]
+pub fn read_file(
+    path: impl AsRef<Path>,
+    window: Window,
+    threads: usize,
+) -> io::Result<WindowedRecords> {
+    use std::io::{Seek, SeekFrom};
+
+    use crate::distributed::align_to_line;
+
+    let path = path.as_ref();
+    let mut file = std::fs::File::open(path)?;
+    let len = file.metadata()?.len();
+    let threads = threads.max(1) as u64;
+
+    let mut bounds = Vec::new();
+    for thread in 0..=threads {
+        bounds.push(align_to_line(&mut file, len * thread / threads, len)?);
+    }
+
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = bounds
+            .windows(2)
+            .filter(|range| range[0] < range[1])
+            .map(|range| {
+                let (start, end) = (range[0], range[1]);
+
+                scope.spawn(move || {
+                    let mut file = std::fs::File::open(path)?;
+                    file.seek(SeekFrom::Start(start))?;
+
+                    let mut records = WindowedRecords::new(window);
+                    records.ingest(io::BufReader::new(file.take(end - start)))?;
+                    Ok::<_, io::Error>(records)
+                })
+            })
+            .collect();
+
+        handles.into_iter().try_fold(
+            WindowedRecords::new(window),
+            |mut total, handle| -> io::Result<_> {
+                total += handle.join().expect("a parsing thread panicked")?;
+                Ok(total)
+            },
+        )
+    })
+}
+
+/// The options of the `windows` subcommand of `main`.
+#[cfg(feature = "runtime")]
+#[derive(clap::Args, Debug, Clone)]
+pub struct WindowArgs {
+    /// The length of the time windows.
+    #[arg(long, value_enum, default_value_t = Window::default())]
+    pub window: Window,
+
+    /// The CSV file the windows are exported to.
+    #[arg(long, default_value_t = config::WINDOWS_OUTPUT_PATH.to_owned())]
+    pub csv: String,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_timestamps() {
+        assert_eq!(parse_timestamp(b"0"), Some(0));
+        assert_eq!(parse_timestamp(b"1706704496"), Some(1706704496));
+        assert_eq!(parse_timestamp(b"-86400"), Some(-86400));
+        assert_eq!(parse_timestamp(b"2024-01-31T12:34:56Z"), Some(1706704496));
+        assert_eq!(
+            parse_timestamp(b"2024-01-31 12:34:56.789"),
+            Some(1706704496)
+        );
+        assert_eq!(
+            parse_timestamp(b"2024-01-31T14:34:56+02:00"),
+            Some(1706704496)
+        );
+        assert_eq!(
+            parse_timestamp(b"2024-01-31T07:04:56-0530"),
+            Some(1706704496)
+        );
+        assert_eq!(parse_timestamp(b"2024-01-31T12:34"), Some(1706704440));
+        assert_eq!(parse_timestamp(b"2024-02-29"), Some(1709164800));
+        assert_eq!(parse_timestamp(b"1969-12-31T23:59:59Z"), Some(-1));
+
+        for invalid in [
+            &b""[..],
+            b"12a",
+            b"2024-13-01",
+            b"2024-01-31T25:00",
+            b"2024-01-31X",
+        ] {
+            assert_eq!(parse_timestamp(invalid), None, "{:?}", invalid);
+        }
+    }
+
+    #[test]
+    fn format_timestamps() {
+        for timestamp in [0, -1, 1706704496, 1709164800, 951782400, -2208988800] {
+            let formatted = format_timestamp(timestamp);
+            assert_eq!(parse_timestamp(formatted.as_bytes()), Some(timestamp));
+        }
+        assert_eq!(format_timestamp(1706704496), "2024-01-31T12:34:56Z");
+    }
+
+    #[test]
+    fn aggregate_windows() {
+        let input = b"Abha;2024-01-31T00:10:00Z;1.2\n\
+            Abha;2024-01-31T23:50:00Z;-3.4\n\
+            Abha;2024-02-01T00:00:00Z;5.6\n\
+            Zug;1706704496;10.0\n";
+
+        let mut days = WindowedRecords::new(Window::Day);
+        assert_eq!(days.parse_bytes(input), 4);
+        assert_eq!((days.stations(), days.windows()), (2, 3));
+
+        let mut csv = Vec::new();
+        days.write_csv(&mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "station,window,min,mean,max,count\n\
+            Abha,2024-01-31T00:00:00Z,-3.4,-1.1,1.2,2\n\
+            Abha,2024-02-01T00:00:00Z,5.6,5.6,5.6,1\n\
+            Zug,2024-01-31T00:00:00Z,10.0,10.0,10.0,1\n"
+        );
+
+        let mut hours = WindowedRecords::<StationStats>::new(Window::Hour);
+        hours.parse_bytes(input);
+        assert_eq!(hours.windows(), 4);
+
+        // Merging the records of two halves gives the records of the whole.
+        let (first, second) = input.split_at(61);
+        let mut merged = WindowedRecords::new(Window::Day);
+        merged.parse_bytes(first);
+        let mut rest = WindowedRecords::new(Window::Day);
+        rest.ingest(second).unwrap();
+        merged += rest;
+        assert_eq!(merged, days);
+    }
+
+    #[test]
+    #[should_panic]
+    fn missing_timestamp() {
+        WindowedRecords::<StationStats>::new(Window::Day).parse_bytes(b"Abha;1.2\n");
+    }
+}
@@ -0,0 +1,29 @@
+//! The types a typical embedder needs, without reaching into the modules:
+//!
+//! ```
+//! use async_1brc::prelude::*;
+//!
+//! let mut records = StationRecords::new();
+//! records.insert(LiteHashBuffer::from(b"Abha"), 123);
+//! ```
+
+pub use crate::parser::{
+    aggregator::Aggregator,
+    incremental::Incremental,
+    key::KeyExtractor,
+    models::{StationRecords, StationStats},
+    LiteHashBuffer,
+};
+
+#[cfg(feature = "runtime")]
+pub use crate::{
+    parser::engine::ParserEngine,
+    reader::{ChunkStream, RowsReader},
+    run, run_from, RunError, RunOptions, RunReport,
+};
+
+#[cfg(feature = "sync")]
+pub use crate::reader::MmapReader;
+
+#[cfg(feature = "assert")]
+pub use crate::assertion::MismatchReport;
@@ -0,0 +1,285 @@
+//! Control the page cache of the input file, to separate cold-I/O from hot-cache
+//! performance when benchmarking, and hint the kernel to read the file ahead.
+
+use std::io::Read;
+use std::path::Path;
+
+/// The size of the buffer used to read through the file when prewarming.
+const PREWARM_BUFFER_SIZE: usize = 1 << 20;
+
+/// Evict the pages of the file from the page cache, so that the next read comes from disk.
+///
+/// This uses `posix_fadvise(POSIX_FADV_DONTNEED)`, which only evicts clean pages; pages
+/// of a file still being written to may stay cached. This returns
+/// [`std::io::ErrorKind::Unsupported`] on platforms without `posix_fadvise`.
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd"))]
+pub fn drop_caches(path: impl AsRef<Path>) -> std::io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    let file = std::fs::File::open(path)?;
+
+    // SAFETY: the file descriptor is valid for the lifetime of `file`.
+    match unsafe { libc::posix_fadvise(file.as_raw_fd(), 0, 0, libc::POSIX_FADV_DONTNEED) } {
+        0 => Ok(()),
+        errno => Err(std::io::Error::from_raw_os_error(errno)),
+    }
+}
+
+/// Evict the pages of the file from the page cache, so that the next read comes from disk.
+///
+/// This returns [`std::io::ErrorKind::Unsupported`] on platforms without `posix_fadvise`,
+/// such as macOS, where `sudo purge` can be used instead.
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "freebsd")))]
+pub fn drop_caches(_path: impl AsRef<Path>) -> std::io::Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "dropping the page cache of a single file is not supported on this platform",
+    ))
+}
+
+/// The hint given to the kernel about how the input file is going to be read.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Readahead {
+    /// Do not give any hint, leaving the default readahead of the kernel.
+    Off,
+
+    /// Hint that the file is read sequentially, so that the kernel reads further ahead and
+    /// drops the pages already read sooner.
+    #[default]
+    Sequential,
+
+    /// Also ask the kernel to start reading the whole file into the page cache right away.
+    WillNeed,
+}
+
+/// Hint the kernel to read ahead the file opened as `file`.
+///
+/// This uses `posix_fadvise` with `POSIX_FADV_SEQUENTIAL`, and `POSIX_FADV_WILLNEED` for
+/// [`Readahead::WillNeed`]. The sequential hint only applies to reads through this open file,
+/// so `file` must be the one being read.
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd"))]
+pub fn advise_file(file: &std::fs::File, readahead: Readahead) -> std::io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    let advices: &[libc::c_int] = match readahead {
+        Readahead::Off => &[],
+        Readahead::Sequential => &[libc::POSIX_FADV_SEQUENTIAL],
+        Readahead::WillNeed => &[libc::POSIX_FADV_SEQUENTIAL, libc::POSIX_FADV_WILLNEED],
+    };
+
+    advices.iter().try_for_each(|&advice| {
+        // SAFETY: the file descriptor is valid for the lifetime of `file`.
+        match unsafe { libc::posix_fadvise(file.as_raw_fd(), 0, 0, advice) } {
+            0 => Ok(()),
+            errno => Err(std::io::Error::from_raw_os_error(errno)),
+        }
+    })
+}
+
+/// Hint the kernel to read ahead the file opened as `file`.
+///
+/// macOS has no `posix_fadvise`; this enables the readahead of the file with
+/// `fcntl(F_RDAHEAD)`, and for [`Readahead::WillNeed`] also issues `fcntl(F_RDADVISE)` over
+/// the file, up to the first 2 GiB.
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+pub fn advise_file(file: &std::fs::File, readahead: Readahead) -> std::io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    let check = |result: libc::c_int| match result {
+        -1 => Err(std::io::Error::last_os_error()),
+        _ => Ok(()),
+    };
+
+    if readahead == Readahead::Off {
+        return Ok(());
+    }
+
+    // SAFETY: the file descriptor is valid for the lifetime of `file`.
+    check(unsafe { libc::fcntl(file.as_raw_fd(), libc::F_RDAHEAD, 1) })?;
+
+    if readahead == Readahead::WillNeed {
+        let advisory = libc::radvisory {
+            ra_offset: 0,
+            ra_count: file.metadata()?.len().min(libc::c_int::MAX as u64) as libc::c_int,
+        };
+
+        // SAFETY: as above, and `advisory` outlives the call.
+        check(unsafe { libc::fcntl(file.as_raw_fd(), libc::F_RDADVISE, &advisory) })?;
+    }
+
+    Ok(())
+}
+
+/// Hint the kernel to read ahead the file opened as `file`.
+///
+/// Windows only takes the sequential hint when the file is opened, which [`open`] does; this
+/// returns [`std::io::ErrorKind::Unsupported`] for [`Readahead::WillNeed`].
+#[cfg(windows)]
+pub fn advise_file(_file: &std::fs::File, readahead: Readahead) -> std::io::Result<()> {
+    match readahead {
+        Readahead::Off | Readahead::Sequential => Ok(()),
+        Readahead::WillNeed => Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "reading the whole file ahead is not supported on Windows",
+        )),
+    }
+}
+
+/// Hint the kernel to read ahead the file opened as `file`.
+///
+/// This returns [`std::io::ErrorKind::Unsupported`] for any hint on this platform.
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "freebsd",
+    target_os = "macos",
+    target_os = "ios",
+    windows
+)))]
+pub fn advise_file(_file: &std::fs::File, readahead: Readahead) -> std::io::Result<()> {
+    match readahead {
+        Readahead::Off => Ok(()),
+        _ => Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "readahead hints are not supported on this platform",
+        )),
+    }
+}
+
+/// Open the input file for reading, with the hints which can only be given when opening it.
+///
+/// On Windows, the file is opened with `FILE_FLAG_SEQUENTIAL_SCAN` unless the `readahead` is
+/// [`Readahead::Off`]; elsewhere, the hints are given to the open file by [`advise_file`].
+pub fn open(path: impl AsRef<Path>, readahead: Readahead) -> std::io::Result<std::fs::File> {
+    #[cfg(windows)]
+    if readahead != Readahead::Off {
+        use std::os::windows::fs::OpenOptionsExt;
+
+        /// The `FILE_FLAG_SEQUENTIAL_SCAN` of `CreateFileW`.
+        const FILE_FLAG_SEQUENTIAL_SCAN: u32 = 0x0800_0000;
+
+        return std::fs::OpenOptions::new()
+            .read(true)
+            .custom_flags(FILE_FLAG_SEQUENTIAL_SCAN)
+            .open(path);
+    }
+
+    #[cfg(not(windows))]
+    let _ = readahead;
+
+    std::fs::File::open(path)
+}
+
+/// Hint the kernel to read ahead a memory-mapped file, with `madvise(MADV_SEQUENTIAL)`, and
+/// `madvise(MADV_WILLNEED)` for [`Readahead::WillNeed`].
+///
+/// `mapping` must start at the start of the mapping, which is aligned to a page.
+#[cfg(unix)]
+pub fn advise_mapping(mapping: &[u8], readahead: Readahead) -> std::io::Result<()> {
+    let advices: &[libc::c_int] = match readahead {
+        _ if mapping.is_empty() => &[],
+        Readahead::Off => &[],
+        Readahead::Sequential => &[libc::MADV_SEQUENTIAL],
+        Readahead::WillNeed => &[libc::MADV_SEQUENTIAL, libc::MADV_WILLNEED],
+    };
+
+    advices.iter().try_for_each(|&advice| {
+        // SAFETY: the range is mapped for the lifetime of `mapping`, and the advices do not
+        // change its contents.
+        match unsafe { libc::madvise(mapping.as_ptr() as *mut libc::c_void, mapping.len(), advice) }
+        {
+            0 => Ok(()),
+            _ => Err(std::io::Error::last_os_error()),
+        }
+    })
+}
+
+/// Hint the kernel to read ahead a memory-mapped file.
+///
+/// This returns [`std::io::ErrorKind::Unsupported`] for any hint on this platform.
+#[cfg(not(unix))]
+pub fn advise_mapping(_mapping: &[u8], readahead: Readahead) -> std::io::Result<()> {
+    match readahead {
+        Readahead::Off => Ok(()),
+        _ => Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "readahead hints are not supported on this platform",
+        )),
+    }
+}
+
+/// Read through the whole file once, discarding the bytes, so that the next read is served
+/// from the page cache as far as memory allows.
+///
+/// Returns the number of bytes read.
+pub fn prewarm(path: impl AsRef<Path>) -> std::io::Result<u64> {
+    let mut file = std::fs::File::open(path)?;
+    let mut buffer = vec![0; PREWARM_BUFFER_SIZE];
+    let mut total = 0;
+
+    loop {
+        match file.read(&mut buffer)? {
+            0 => break Ok(total),
+            bytes_read => total += bytes_read as u64,
+        }
+    }
+}
+
+/// Prepare the page cache of the file before a trial, as requested by the flags.
+///
+/// Failures are reported but do not abort the run, as the benchmark itself is still valid;
+/// only the caching conditions differ from what was requested.
+pub fn prepare(path: impl AsRef<Path>, drop_caches: bool, prewarm: bool) {
+    if drop_caches {
+        match self::drop_caches(&path) {
+            Ok(()) => println!("Dropped the page cache of {:?}.", path.as_ref()),
+            Err(err) => println!("Could not drop the page cache: {}", err),
+        }
+    }
+
+    if prewarm {
+        match self::prewarm(&path) {
+            Ok(bytes) => println!("Prewarmed the page cache with {} bytes.", bytes),
+            Err(err) => println!("Could not prewarm the page cache: {}", err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::TestDir;
+
+    #[test]
+    fn prewarm_reads_whole_file() {
+        let dir = TestDir::new("prewarm_reads_whole_file");
+        let path = dir.join("input.txt");
+        std::fs::write(&path, vec![b'x'; PREWARM_BUFFER_SIZE + 7]).unwrap();
+
+        assert_eq!(prewarm(&path).unwrap(), PREWARM_BUFFER_SIZE as u64 + 7);
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    #[test]
+    fn advise_readahead() {
+        let dir = TestDir::new("advise_readahead");
+        let path = dir.join("input.txt");
+        std::fs::write(&path, vec![b'x'; PREWARM_BUFFER_SIZE]).unwrap();
+
+        let file = open(&path, Readahead::Sequential).unwrap();
+        for readahead in [Readahead::Off, Readahead::Sequential, Readahead::WillNeed] {
+            assert!(advise_file(&file, readahead).is_ok());
+        }
+
+        #[cfg(feature = "sync")]
+        {
+            let mut reader = super::super::MmapReader::from_file(file);
+            assert!(reader.advise(Readahead::WillNeed).is_ok());
+        }
+    }
+
+    #[test]
+    fn drop_caches_missing_file() {
+        assert!(drop_caches("/this/path/does/not/exist").is_err());
+    }
+}
@@ -1,51 +1,89 @@
 //! Helper functions for the reader.
 
-use super::super::config;
-
-#[cfg(feature = "timed")]
 use super::super::timed::TimedOperation;
 
-#[cfg(feature = "timed")]
-pub static CLONE_BUFFER_TIMED: std::sync::OnceLock<std::sync::Arc<TimedOperation>> =
-    std::sync::OnceLock::new();
-
-#[cfg(feature = "timed")]
 pub static MEM_SWAP_TIMED: std::sync::OnceLock<std::sync::Arc<TimedOperation>> =
     std::sync::OnceLock::new();
 
-/// Transfer the buffer from the read buffer to the export buffer.
+/// Allocate an empty chunk buffer of `capacity` bytes.
 ///
-/// This will leave the read buffer empty.
-pub fn transfer_buffer(buffer_read: &mut Vec<u8>, buffer_export: &mut Vec<u8>) {
-    buffer_export.append(buffer_read);
+/// With the `hugepages` feature, the buffer is backed by transparent huge pages where
+/// possible.
+pub fn allocate_buffer(capacity: usize) -> Vec<u8> {
+    #[cfg(feature = "hugepages")]
+    return super::huge_pages::with_capacity(capacity);
+
+    #[cfg(not(feature = "hugepages"))]
+    Vec::with_capacity(capacity)
 }
 
-/// Shift the buffer from the read buffer to the export buffer.
-pub fn clone_buffer(buffer_read: &mut [u8], buffer_export: &mut Vec<u8>) {
-    #[cfg(feature = "timed")]
-    let _counter = CLONE_BUFFER_TIMED
-        .get_or_init(|| TimedOperation::new("clone_buffer"))
-        .start();
+/// Run `future` to completion on a dedicated thread with its own single-threaded runtime,
+/// such as the reader, so that its system calls never hold up the tasks on the worker threads
+/// of the current runtime.
+///
+/// The current task only waits for the thread to finish, without blocking its worker thread;
+/// dropping the returned future cancels `future` on the thread.
+///
+/// # Panics
+///
+/// Panics if the thread cannot be spawned, or if `future` panics.
+pub async fn run_isolated<F>(future: F) -> F::Output
+where
+    F: std::future::Future + Send + 'static,
+    F::Output: Send + 'static,
+{
+    let (mut sender, receiver) = tokio::sync::oneshot::channel();
 
-    buffer_export.extend_from_slice(buffer_read);
-}
+    std::thread::Builder::new()
+        .name("reader".to_owned())
+        .spawn(move || {
+            let runtime = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .expect("Failed to build the runtime of the isolated thread.");
 
-/// Check if the buffer is full.
-pub fn buffer_full(buffer_export: &Vec<u8>, chunk_size: usize) -> bool {
-    #[cfg(not(feature = "debug"))]
-    {
-        buffer_export.len() >= buffer_export.capacity() - chunk_size - config::MAX_LINE_LENGTH
-    }
+            runtime.block_on(async move {
+                tokio::select! {
+                    output = future => {
+                        let _ = sender.send(output);
+                    }
+                    _ = sender.closed() => {}
+                }
+            });
+        })
+        .expect("Failed to spawn the isolated thread.");
 
-    #[cfg(feature = "debug")]
-    {
-        let _result =
-            buffer_export.len() >= buffer_export.capacity() - chunk_size - config::MAX_LINE_LENGTH;
+    receiver
+        .await
+        .expect("The isolated thread panicked before completing.")
+}
 
-        if _result {
-            println!("RowsReader: buffer_full() buffer full: {}", _result);
+/// Drop the bytes of `buffer_export` up to and including its first newline if `resync` is set,
+/// i.e. the rest of a line whose start was dropped; `resync` is cleared once it is found.
+pub fn resync(buffer_export: &mut Vec<u8>, resync: &mut bool) {
+    if *resync {
+        match memchr::memchr(b'\n', buffer_export) {
+            Some(end) => {
+                buffer_export.drain(..=end);
+                *resync = false;
+            }
+            None => buffer_export.clear(),
         }
+    }
+}
+
+/// Check if the buffer is full, i.e. if another chunk and the end of its last line, of up to
+/// `max_line_length` bytes, may not fit in its capacity.
+pub fn buffer_full(buffer_export: &Vec<u8>, chunk_size: usize, max_line_length: usize) -> bool {
+    let result = buffer_export.len()
+        >= buffer_export
+            .capacity()
+            .saturating_sub(chunk_size + max_line_length);
 
-        _result
+    #[cfg(feature = "debug")]
+    if result {
+        println!("RowsReader: buffer_full() buffer full: {}", result);
     }
+
+    result
 }
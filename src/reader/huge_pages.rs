@@ -0,0 +1,174 @@
+//! Back the chunk buffers and the memory map with transparent huge pages, to reduce the TLB
+//! misses while scanning billions of bytes.
+//!
+//! The buffers are allocated as usual, then the part of each allocation covering whole huge
+//! pages is advised with `madvise(MADV_HUGEPAGE)` before it is first written to, so that the
+//! kernel faults it in as huge pages. The chunks of `max_chunk_size` bytes span several huge
+//! pages each, so only the partial pages at either end of a buffer are left out.
+//!
+//! This requires transparent huge pages to be enabled in `always` or `madvise` mode, in
+//! `/sys/kernel/mm/transparent_hugepage/enabled`. File-backed memory maps are only collapsed
+//! into huge pages by kernels built with `CONFIG_READ_ONLY_THP_FOR_FS`. Whether huge pages
+//! were actually obtained is reported by [`usage`].
+
+/// The size of a transparent huge page on x86-64 and most aarch64 kernels.
+pub const HUGE_PAGE_SIZE: usize = 2 << 20;
+
+/// The part of the range of `len` bytes from `start` covering whole huge pages, as the start
+/// and the length.
+#[cfg(any(target_os = "linux", target_os = "android", test))]
+fn aligned_range(start: usize, len: usize) -> (usize, usize) {
+    let aligned_start = start.next_multiple_of(HUGE_PAGE_SIZE);
+    let aligned_end = (start + len) / HUGE_PAGE_SIZE * HUGE_PAGE_SIZE;
+
+    (aligned_start, aligned_end.saturating_sub(aligned_start))
+}
+
+/// Advise the whole huge pages within the range of `len` bytes from `start`.
+///
+/// Returns the number of bytes advised.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn advise_range(start: *const u8, len: usize) -> std::io::Result<usize> {
+    let (aligned_start, aligned_len) = aligned_range(start as usize, len);
+
+    if aligned_len == 0 {
+        return Ok(0);
+    }
+
+    // SAFETY: the range lies within memory owned by the caller, and the advice does not change
+    // its contents.
+    match unsafe {
+        libc::madvise(
+            aligned_start as *mut libc::c_void,
+            aligned_len,
+            libc::MADV_HUGEPAGE,
+        )
+    } {
+        0 => Ok(aligned_len),
+        _ => Err(std::io::Error::last_os_error()),
+    }
+}
+
+/// Advise the whole huge pages within the range of `len` bytes from `start`.
+///
+/// This returns [`std::io::ErrorKind::Unsupported`] on platforms without transparent huge
+/// pages.
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn advise_range(_start: *const u8, _len: usize) -> std::io::Result<usize> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "transparent huge pages are not supported on this platform",
+    ))
+}
+
+/// Allocate a buffer of `capacity` bytes, advising its whole huge pages before they are
+/// first written to.
+///
+/// The buffer is still usable if the advice fails, only backed by normal pages.
+pub fn with_capacity(capacity: usize) -> Vec<u8> {
+    let buffer = Vec::with_capacity(capacity);
+    let _ = advise_range(buffer.as_ptr(), buffer.capacity());
+
+    buffer
+}
+
+/// Advise the whole huge pages of a memory-mapped file.
+///
+/// Returns the number of bytes advised.
+pub fn advise_mapping(mapping: &[u8]) -> std::io::Result<usize> {
+    advise_range(mapping.as_ptr(), mapping.len())
+}
+
+/// The memory of the process backed by transparent huge pages.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HugePagesUsage {
+    /// The bytes of anonymous memory, i.e. the buffers, backed by huge pages.
+    pub anonymous: u64,
+
+    /// The bytes of file-backed memory, i.e. the memory map, backed by huge pages.
+    pub file: u64,
+}
+
+impl HugePagesUsage {
+    /// Parse the `AnonHugePages` and `FilePmdMapped` fields of `/proc/self/smaps_rollup`.
+    fn from_smaps(smaps: &str) -> Self {
+        let field = |name: &str| {
+            smaps
+                .lines()
+                .find_map(|line| line.strip_prefix(name)?.strip_prefix(':'))
+                .and_then(|value| value.trim().strip_suffix("kB")?.trim().parse::<u64>().ok())
+                .map_or(0, |kilobytes| kilobytes * 1024)
+        };
+
+        Self {
+            anonymous: field("AnonHugePages"),
+            file: field("FilePmdMapped"),
+        }
+    }
+}
+
+impl std::fmt::Display for HugePagesUsage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} MiB of anonymous memory and {} MiB of file-backed memory in huge pages",
+            self.anonymous >> 20,
+            self.file >> 20
+        )
+    }
+}
+
+/// Get the memory of the process currently backed by transparent huge pages.
+pub fn usage() -> std::io::Result<HugePagesUsage> {
+    std::fs::read_to_string("/proc/self/smaps_rollup")
+        .map(|smaps| HugePagesUsage::from_smaps(&smaps))
+}
+
+/// Report whether huge pages were obtained, e.g. at the end of a run.
+pub fn report() {
+    match usage() {
+        Ok(usage) if usage == HugePagesUsage::default() => {
+            println!("No huge pages were obtained; check that transparent huge pages are enabled.")
+        }
+        Ok(usage) => println!("Huge pages: {}.", usage),
+        Err(err) => println!("Could not read the huge pages usage: {}", err),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn align_range() {
+        assert_eq!(aligned_range(1, HUGE_PAGE_SIZE), (HUGE_PAGE_SIZE, 0));
+        assert_eq!(
+            aligned_range(HUGE_PAGE_SIZE - 16, HUGE_PAGE_SIZE * 3),
+            (HUGE_PAGE_SIZE, HUGE_PAGE_SIZE * 2)
+        );
+        assert_eq!(aligned_range(0, HUGE_PAGE_SIZE), (0, HUGE_PAGE_SIZE));
+    }
+
+    #[test]
+    fn parse_smaps() {
+        let smaps = "Rss:                1412 kB\n\
+            AnonHugePages:      4096 kB\n\
+            ShmemPmdMapped:        0 kB\n\
+            FilePmdMapped:      2048 kB\n";
+
+        assert_eq!(
+            HugePagesUsage::from_smaps(smaps),
+            HugePagesUsage {
+                anonymous: 4 << 20,
+                file: 2 << 20
+            }
+        );
+        assert_eq!(HugePagesUsage::from_smaps(""), HugePagesUsage::default());
+    }
+
+    #[test]
+    fn allocate_buffer() {
+        let buffer = with_capacity(HUGE_PAGE_SIZE * 3);
+        assert!(buffer.capacity() >= HUGE_PAGE_SIZE * 3);
+    }
+}
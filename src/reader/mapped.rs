@@ -0,0 +1,431 @@
+//! Read-only memory maps of files, either whole or in windows sliced into chunks along a
+//! separator.
+//!
+//! Mapping a file is `unsafe`, as the bytes of the mapping are those of the file itself: if
+//! another process writes to the file while it is mapped, the slices handed out change under
+//! the parser, breaking the guarantees of `&[u8]`, and if it truncates the file, touching the
+//! pages past the new end raises `SIGBUS`. No mapping can guard against that; the input must
+//! simply not be modified while it is read, as for any benchmark.
+//!
+//! This module is the only place the crate maps a file, so that the hazard is documented and
+//! justified once. Every slice borrows the mapping it points into, either through the
+//! [`MappedFile`] or through the window shared by a [`MappedChunk`], so that no slice can
+//! outlive it.
+
+use std::fs::File;
+use std::io;
+use std::ops::{Deref, Range};
+use std::path::Path;
+use std::sync::Arc;
+
+use memmap2::{Mmap, MmapOptions};
+
+use crate::config;
+
+use super::cache::{self, Readahead};
+
+/// Map the part of `file` described by `options`.
+fn map(options: &MmapOptions, file: &File) -> io::Result<Mmap> {
+    // SAFETY: the mapping is read-only, and only ever borrowed by the slices handed out by its
+    // owner, which cannot outlive it. The file must not be modified or truncated while it is
+    // mapped, as documented in the module; this cannot be enforced.
+    unsafe { options.map(file) }
+}
+
+/// A read-only memory map of a whole file.
+pub struct MappedFile {
+    /// The memory map of the file, or [`None`] for an empty file, which cannot be mapped.
+    mmap: Option<Mmap>,
+}
+
+impl MappedFile {
+    /// Map the whole of the open `file`.
+    ///
+    /// An empty file is not mapped at all, as a mapping cannot be empty. The file can be closed
+    /// afterwards, as the mapping keeps its own reference to it.
+    pub fn map(file: &File) -> io::Result<Self> {
+        let mmap = match file.metadata()?.len() {
+            0 => None,
+            _ => Some(map(&MmapOptions::new(), file)?),
+        };
+
+        Ok(Self { mmap })
+    }
+
+    /// Map the whole file at `path`.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::map(&File::open(path)?)
+    }
+
+    /// The bytes of the whole file, empty if the file is.
+    pub fn bytes(&self) -> &[u8] {
+        self.mmap.as_deref().unwrap_or_default()
+    }
+}
+
+/// A chunk of a file, keeping the window of the file it lies in mapped while it is alive.
+#[derive(Clone)]
+pub struct MappedChunk {
+    window: Arc<Mmap>,
+    range: Range<usize>,
+}
+
+impl Deref for MappedChunk {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.window[self.range.clone()]
+    }
+}
+
+impl std::borrow::Borrow<[u8]> for MappedChunk {
+    fn borrow(&self) -> &[u8] {
+        self
+    }
+}
+
+/// A read-only memory map of a file in windows, iterated over in chunks ending on a
+/// separator.
+///
+/// Rather than one mapping of the whole file, the file is mapped a window of
+/// `window_size` bytes at a time as the chunks are iterated over, and each window is unmapped
+/// once the iterator has moved past it and its chunks are dropped. This keeps huge files
+/// readable on 32-bit targets, and spares the kernel the page tables of the whole file.
+pub struct MappedChunks {
+    file: File,
+    len: u64,
+    chunk_size: usize,
+    window_size: usize,
+    readahead: Readahead,
+    #[cfg(feature = "hugepages")]
+    huge_pages: bool,
+}
+
+impl MappedChunks {
+    /// Read the open `file` in windows; nothing is mapped until the chunks are iterated over.
+    pub fn new(file: File) -> io::Result<Self> {
+        Ok(Self {
+            len: file.metadata()?.len(),
+            file,
+            chunk_size: config::CHUNK_SIZE,
+            window_size: config::MMAP_WINDOW_SIZE,
+            readahead: Readahead::Off,
+            #[cfg(feature = "hugepages")]
+            huge_pages: false,
+        })
+    }
+
+    /// Read the file at `path` in windows.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::new(File::open(path)?)
+    }
+
+    /// Read the open `file` in windows, panicking if its metadata cannot be read.
+    pub fn from_file(file: File) -> Self {
+        let description = format!("{:?}", file);
+        Self::new(file)
+            .unwrap_or_else(|err| panic!("Could not read the file at {}: {}", description, err))
+    }
+
+    /// Read the file at `path` in windows, panicking if it cannot be opened.
+    pub fn from_path(path: &str) -> Self {
+        let file =
+            File::open(path).unwrap_or_else(|_| panic!("Could not open file at path: {}", path));
+        Self::from_file(file)
+    }
+
+    /// Set the size of the chunks, before extending each to the next separator.
+    ///
+    /// A chunk never spans two windows, so chunks are at most the size of a window.
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    /// Set the chunk size to split the file evenly into the given number of chunks.
+    ///
+    /// The chunk size is at least 1 byte, even for an empty file.
+    pub fn with_chunks(mut self, chunks: usize) -> Self {
+        let chunk_size = self.len.div_ceil(chunks as u64).max(1);
+        self.chunk_size = usize::try_from(chunk_size).unwrap_or(usize::MAX);
+        self
+    }
+
+    /// Set the size of the windows mapped at a time.
+    ///
+    /// A window is mapped larger if a single line does not fit in it.
+    pub fn with_window_size(mut self, window_size: usize) -> Self {
+        self.window_size = window_size.max(1);
+        self
+    }
+
+    /// Advise the kernel to back each window with transparent huge pages as it is mapped.
+    ///
+    /// The windows are still readable if the advice fails, only backed by normal pages.
+    #[cfg(feature = "hugepages")]
+    pub fn with_huge_pages(mut self) -> Self {
+        self.huge_pages = true;
+        self
+    }
+
+    /// The size of the chunks, before extending each to the next separator.
+    pub fn chunk_size(&self) -> usize {
+        self.chunk_size.min(self.window_size)
+    }
+
+    /// The size of the windows mapped at a time.
+    pub fn window_size(&self) -> usize {
+        self.window_size
+    }
+
+    /// Hint the kernel to read ahead the file, e.g. [`Readahead::Sequential`] as the chunks
+    /// are iterated over in order.
+    ///
+    /// The hint is given for the file at once, then for each window as it is mapped.
+    pub fn advise(&mut self, readahead: Readahead) -> io::Result<()> {
+        cache::advise_file(&self.file, readahead)?;
+        self.readahead = readahead;
+        Ok(())
+    }
+
+    /// Map the window of up to `window_size` bytes of the file from `offset`.
+    ///
+    /// Panics if the window cannot be mapped, as the chunks cannot be read at all.
+    fn map_window(&self, offset: u64, window_size: usize) -> Window {
+        let len = (self.len - offset).min(window_size as u64) as usize;
+        let mmap =
+            map(MmapOptions::new().offset(offset).len(len), &self.file).unwrap_or_else(|err| {
+                panic!(
+                    "Could not memory-map {} bytes at offset {} of the file at {:?}: {}",
+                    len, offset, self.file, err
+                )
+            });
+
+        // The hints are best-effort; a failing hint was already reported by `advise`.
+        let _ = cache::advise_mapping(&mmap, self.readahead);
+        #[cfg(feature = "hugepages")]
+        if self.huge_pages {
+            let _ = super::huge_pages::advise_mapping(&mmap);
+        }
+
+        Window {
+            mmap: Arc::new(mmap),
+            offset,
+        }
+    }
+
+    /// Iterate over the chunks of the file, each ending on `SEP` or at the end of the file.
+    pub fn iter<const SEP: u8>(&self) -> IterMappedChunks<'_, SEP> {
+        IterMappedChunks {
+            chunks: self,
+            window: None,
+            cursor: 0,
+        }
+    }
+
+    /// The length of the file.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Whether the file is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// The number of chunks of the file before extending each to the next separator.
+    pub fn chunks_count(&self) -> usize {
+        self.len.div_ceil(self.chunk_size() as u64) as usize
+    }
+}
+
+/// A window of the file, mapped from `offset`.
+struct Window {
+    mmap: Arc<Mmap>,
+    offset: u64,
+}
+
+/// An iterator over the chunks of a [`MappedChunks`], mapping its windows in turn.
+pub struct IterMappedChunks<'m, const SEP: u8> {
+    chunks: &'m MappedChunks,
+    window: Option<Window>,
+    cursor: u64,
+}
+
+impl<const SEP: u8> Iterator for IterMappedChunks<'_, SEP> {
+    type Item = MappedChunk;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.cursor >= self.chunks.len {
+            return None;
+        }
+
+        let chunk_size = self.chunks.chunk_size();
+        let mut window_size = self.chunks.window_size;
+
+        loop {
+            let window = match &self.window {
+                Some(window) => window,
+                None => self
+                    .window
+                    .insert(self.chunks.map_window(self.cursor, window_size)),
+            };
+            let start = (self.cursor - window.offset) as usize;
+            let bytes = &window.mmap[start..];
+            let at_end = window.offset + window.mmap.len() as u64 == self.chunks.len;
+
+            // End is either the next matching byte after the chunk, or the end of the file; if
+            // the window ends first, the chunk is cut at its last line if it starts the window.
+            let end = bytes
+                .get(chunk_size..)
+                .and_then(|rest| memchr::memchr(SEP, rest))
+                .map(|offset| chunk_size + offset + 1)
+                .or_else(|| at_end.then_some(bytes.len()))
+                .or_else(|| {
+                    (start == 0)
+                        .then(|| memchr::memrchr(SEP, bytes))
+                        .flatten()
+                        .map(|offset| offset + 1)
+                });
+
+            match end {
+                Some(end) => {
+                    let chunk = MappedChunk {
+                        window: Arc::clone(&window.mmap),
+                        range: start..start + end,
+                    };
+                    self.cursor += end as u64;
+
+                    return Some(chunk);
+                }
+                None => {
+                    // Map the next window from the cursor, larger if a single line does not
+                    // fit in the window.
+                    if start == 0 {
+                        window_size = window_size.saturating_mul(2);
+                    }
+                    self.window = None;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::TestDir;
+
+    /// Write `contents` to a file named after `name` in `dir`, returning its path.
+    fn write(dir: &TestDir, name: &str, contents: &[u8]) -> std::path::PathBuf {
+        let path = dir.join(format!("{}.txt", name));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn read_tiny_files() {
+        let dir = TestDir::new("read_tiny_files");
+        for (name, contents) in [
+            ("empty", ""),
+            ("line", "Abha;1.2\n"),
+            ("unterminated", "Abha;1.2"),
+        ] {
+            let path = write(&dir, name, contents.as_bytes());
+
+            let mut chunks = MappedChunks::from_path(path.to_str().unwrap()).with_chunks(4);
+            // The files can only be advised to be read ahead on Unix.
+            assert!(chunks.advise(Readahead::Sequential).is_ok() || !cfg!(unix));
+            assert_eq!(chunks.is_empty(), contents.is_empty());
+            assert_eq!(
+                chunks.iter::<b'\n'>().collect::<Vec<_>>().concat(),
+                contents.as_bytes()
+            );
+        }
+    }
+
+    #[test]
+    fn chunks_end_on_separator() {
+        let contents = "jack;1.2\njill;-3.4\nbob;0.0\n".repeat(100);
+        let dir = TestDir::new("chunks_end_on_separator");
+        let path = write(&dir, "separator", contents.as_bytes());
+
+        for window_size in [4, 64, 1000, config::MMAP_WINDOW_SIZE] {
+            for chunk_size in [1, 7, 64, 1000, contents.len()] {
+                let chunks = MappedChunks::open(&path)
+                    .unwrap()
+                    .with_chunk_size(chunk_size)
+                    .with_window_size(window_size);
+
+                let iterated = chunks.iter::<b'\n'>().collect::<Vec<_>>();
+                assert!(iterated.iter().all(|chunk| chunk.ends_with(b"\n")));
+                assert_eq!(iterated.concat(), contents.as_bytes());
+            }
+        }
+    }
+
+    #[test]
+    fn unmap_behind_the_cursor() {
+        let contents = "jack;1.2\njill;-3.4\nbob;0.0\n".repeat(100);
+        let dir = TestDir::new("unmap_behind_the_cursor");
+        let path = write(&dir, "windows", contents.as_bytes());
+
+        let chunks = MappedChunks::open(&path)
+            .unwrap()
+            .with_chunk_size(64)
+            .with_window_size(256);
+        let mut iter = chunks.iter::<b'\n'>();
+
+        let first = iter.next().unwrap();
+        let rest = iter.collect::<Vec<_>>();
+
+        // The iterator has moved past the first window, so only its chunks still map it.
+        let sharing = rest
+            .iter()
+            .filter(|chunk| Arc::ptr_eq(&chunk.window, &first.window))
+            .count();
+        assert_eq!(Arc::strong_count(&first.window), 1 + sharing);
+        assert!(sharing < rest.len());
+        assert!(first.window.len() <= 256);
+        assert_eq!([&first[..], &rest.concat()].concat(), contents.as_bytes());
+    }
+
+    #[test]
+    fn read_lines_longer_than_a_window() {
+        let contents = format!("{};1.2\nbob;0.0\n", "x".repeat(100));
+        let dir = TestDir::new("read_lines_longer_than_a_window");
+        let path = write(&dir, "long-line", contents.as_bytes());
+
+        let chunks = MappedChunks::open(&path)
+            .unwrap()
+            .with_chunk_size(1)
+            .with_window_size(16);
+        assert_eq!(
+            chunks
+                .iter::<b'\n'>()
+                .map(|chunk| chunk.to_vec())
+                .collect::<Vec<_>>(),
+            [format!("{};1.2\n", "x".repeat(100)), "bob;0.0\n".to_owned()].map(String::into_bytes)
+        );
+    }
+
+    #[test]
+    fn outlive_the_file() {
+        let dir = TestDir::new("outlive_the_file");
+        let path = write(&dir, "closed", b"Abha;1.2\n");
+
+        // The mapping stays valid once the file it was mapped from is closed.
+        let mapped = MappedFile::map(&File::open(&path).unwrap()).unwrap();
+        assert_eq!(mapped.bytes(), b"Abha;1.2\n");
+    }
+
+    #[test]
+    fn open_missing_file() {
+        for err in [
+            MappedFile::open("/this/path/does/not/exist").err(),
+            MappedChunks::open("/this/path/does/not/exist").err(),
+        ] {
+            assert_eq!(err.map(|err| err.kind()), Some(io::ErrorKind::NotFound));
+        }
+    }
+}
@@ -1,9 +1,37 @@
 //! The reader coroutine.
 
+pub mod cache;
+
+#[cfg(feature = "runtime")]
 pub mod func;
 
+#[cfg(feature = "hugepages")]
+pub mod huge_pages;
+
+#[cfg(feature = "sync")]
+pub mod mapped;
+
+#[cfg(feature = "runtime")]
 mod models;
+#[cfg(feature = "runtime")]
 pub use models::*;
 
+#[cfg(feature = "runtime")]
+pub mod ring;
+
+pub mod recovery;
+
+pub mod storage;
+
+#[cfg(feature = "runtime")]
+pub mod sample;
+
+#[cfg(feature = "runtime")]
+mod stream;
+#[cfg(feature = "runtime")]
+pub use stream::ChunkStream;
+
 #[cfg(feature = "sync")]
 pub mod sync;
+#[cfg(feature = "sync")]
+pub use sync::MmapReader;
@@ -1,37 +1,77 @@
 //! The reader model.
 
-use deadqueue::unlimited::Queue;
-use std::sync::atomic::{AtomicBool, Ordering};
-use tokio::{
-    io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt},
-    sync::watch,
-};
+use std::ops::ControlFlow;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt};
+use tokio::time::Instant;
 
 use super::super::config;
+use super::super::parser::{checkpoint::Checkpoints, rejects::Rejects};
+
+#[cfg(feature = "filter")]
+use super::super::parser::filter::StationFilter;
 use super::func;
+use super::recovery::{ErrorPolicy, RetryPolicy};
+use super::ring::{CachePadded, Ring};
 
-#[cfg(feature = "timed")]
-use super::super::timed::TimedOperation;
+use super::super::timed::{self, ThroughputCounter, TimedOperation};
 
-#[cfg(feature = "timed")]
 pub static READER_LOCK_TIMED: std::sync::OnceLock<std::sync::Arc<TimedOperation>> =
     std::sync::OnceLock::new();
 
-#[cfg(feature = "timed")]
+pub static FILL_CHUNK_WAIT_TIMED: std::sync::OnceLock<std::sync::Arc<TimedOperation>> =
+    std::sync::OnceLock::new();
+
+pub static FILL_CLOSED_WAIT_TIMED: std::sync::OnceLock<std::sync::Arc<TimedOperation>> =
+    std::sync::OnceLock::new();
+
+pub static EXPORT_BUFFER_WAIT_TIMED: std::sync::OnceLock<std::sync::Arc<TimedOperation>> =
+    std::sync::OnceLock::new();
+
 pub static READER_READ_TIMED: std::sync::OnceLock<std::sync::Arc<TimedOperation>> =
     std::sync::OnceLock::new();
 
-#[cfg(feature = "timed")]
 pub static READER_LINE_TIMED: std::sync::OnceLock<std::sync::Arc<TimedOperation>> =
     std::sync::OnceLock::new();
 
+pub static READER_THROUGHPUT: std::sync::OnceLock<std::sync::Arc<ThroughputCounter>> =
+    std::sync::OnceLock::new();
+
 pub struct RowsReader {
-    output_queue: Queue<Vec<u8>>,
-    input_queue: Queue<Vec<u8>>,
+    /// The chunks read, with the offset of their first byte in the input, from the reader to
+    /// the consumers; closed at the end of the input.
+    output_queue: Ring<(u64, Vec<u8>)>,
+    /// The spent buffers, from the consumers back to the reader.
+    input_queue: Ring<Vec<u8>>,
     chunk_size: usize,
     max_chunk_size: usize,
+    /// The longest station name expected, from which the end of the last line of each chunk
+    /// is kept room for.
+    max_name_length: usize,
+    /// Whether the consumers validate every line of the chunks, with names of up to
+    /// `max_name_length` bytes.
+    strict: bool,
+    /// Where the consumers route the invalid lines to, instead of parsing them, if anywhere.
+    rejects: Option<Rejects>,
+    /// Where the records merged by the consumers are checkpointed to, if anywhere.
+    checkpoints: Option<Checkpoints>,
+    /// Whether the consumers merge the station names differing only by their normal form.
+    #[cfg(feature = "normalize")]
+    normalize_names: bool,
+    /// The stations the consumers aggregate, if not every station.
+    #[cfg(feature = "filter")]
+    filter: Option<StationFilter>,
+    retry_policy: RetryPolicy,
+    error_policy: ErrorPolicy,
     in_progress: AtomicBool,
-    closed: watch::Sender<bool>,
+    bytes_read: AtomicU64,
+    chunks_exported: AtomicUsize,
+    read_retries: AtomicUsize,
+    chunks_skipped: AtomicUsize,
+    /// Whether the reader stopped before the end of the input.
+    partial: AtomicBool,
+    /// Written by every consumer, so kept apart from the counters written by the reader.
+    records_parsed: CachePadded<AtomicU64>,
 }
 
 #[allow(dead_code)]
@@ -43,80 +83,291 @@ impl Default for RowsReader {
 
 impl RowsReader {
     pub fn new() -> Self {
-        let (closed, _) = watch::channel(false);
-
         Self {
-            output_queue: Queue::new(),
-            input_queue: Queue::new(),
+            output_queue: Ring::with_capacity(config::RING_CAPACITY),
+            input_queue: Ring::with_capacity(config::RING_CAPACITY),
             chunk_size: config::CHUNK_SIZE,
             max_chunk_size: config::MAX_CHUNK_SIZE,
+            max_name_length: config::MAX_NAME_LENGTH,
+            strict: false,
+            rejects: None,
+            checkpoints: None,
+            #[cfg(feature = "normalize")]
+            normalize_names: false,
+            #[cfg(feature = "filter")]
+            filter: None,
+            retry_policy: RetryPolicy::default(),
+            error_policy: ErrorPolicy::default(),
             in_progress: AtomicBool::new(false),
-            closed,
+            bytes_read: AtomicU64::default(),
+            chunks_exported: AtomicUsize::default(),
+            read_retries: AtomicUsize::default(),
+            chunks_skipped: AtomicUsize::default(),
+            partial: AtomicBool::new(false),
+            records_parsed: CachePadded::default(),
         }
     }
 
     /// Create a new instance with custom chunk sizes.
     pub fn with_chunk_sizes(chunk_size: usize, max_chunk_size: usize) -> Self {
-        let (closed, _) = watch::channel(false);
-
         Self {
-            output_queue: Queue::new(),
-            input_queue: Queue::new(),
+            output_queue: Ring::with_capacity(config::RING_CAPACITY),
+            input_queue: Ring::with_capacity(config::RING_CAPACITY),
             chunk_size: usize::max(config::MAX_LINE_LENGTH, chunk_size),
             max_chunk_size,
+            max_name_length: config::MAX_NAME_LENGTH,
+            strict: false,
+            rejects: None,
+            checkpoints: None,
+            #[cfg(feature = "normalize")]
+            normalize_names: false,
+            #[cfg(feature = "filter")]
+            filter: None,
+            retry_policy: RetryPolicy::default(),
+            error_policy: ErrorPolicy::default(),
             in_progress: AtomicBool::new(false),
-            closed,
+            bytes_read: AtomicU64::default(),
+            chunks_exported: AtomicUsize::default(),
+            read_retries: AtomicUsize::default(),
+            chunks_skipped: AtomicUsize::default(),
+            partial: AtomicBool::new(false),
+            records_parsed: CachePadded::default(),
         }
     }
 
-    /// Add additional buffers to the queue.
+    /// Add additional buffers to the queue, up to the capacity of the queue.
     pub fn with_additional_buffers(self, additional_buffers: usize) -> Self {
-        for _ in 0..additional_buffers {
-            self.input_queue
-                .push(Vec::with_capacity(self.max_chunk_size));
+        for _ in 0..additional_buffers.min(self.input_queue.capacity()) {
+            let _ = self
+                .input_queue
+                .try_push(func::allocate_buffer(self.max_chunk_size));
         }
 
         self
     }
 
+    /// Take over the spent buffers of the `previous` reader, once its run is over, up to the
+    /// capacity of the queue; another run over the same kind of input then starts with warm
+    /// buffers instead of allocating new ones.
+    pub fn with_buffers_of(self, previous: &RowsReader) -> Self {
+        while let Some(buffer) = previous.input_queue.try_pop() {
+            if buffer.capacity() < self.max_chunk_size || self.input_queue.try_push(buffer).is_err()
+            {
+                break;
+            }
+        }
+
+        self
+    }
+
+    /// Set the longest station name expected, [`config::MAX_NAME_LENGTH`] by default.
+    ///
+    /// Each chunk keeps room for the end of its last line with such a name, so that the buffer
+    /// is never reallocated while the line is completed.
+    pub fn with_max_name_length(mut self, max_name_length: usize) -> Self {
+        self.max_name_length = max_name_length;
+        self
+    }
+
+    /// Make the consumers validate every line with [`check_line`](crate::parser::func::check_line)
+    /// before parsing a chunk, panicking with the reason and the line on the first invalid one,
+    /// e.g. with a station name longer than the maximum length, or a malformed or out of range
+    /// value, instead of aggregating corrupted statistics.
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Make the consumers check every line with
+    /// [`check_line`](crate::parser::func::check_line) like [`RowsReader::with_strict`], but
+    /// write the invalid ones to `rejects` with their byte offsets and carry on, only parsing
+    /// the valid lines.
+    pub fn with_rejects(mut self, rejects: Rejects) -> Self {
+        self.rejects = Some(rejects);
+        self
+    }
+
+    /// Pause every so often until the consumers have merged the records of every chunk handed
+    /// out into `checkpoints`, and save them along with the offset of the input they cover.
+    ///
+    /// The bytes are counted from the offset the checkpoints resume from, if any, which the
+    /// input must already be at; see [`Checkpoints::resume_from`].
+    pub fn with_checkpoints(mut self, checkpoints: Checkpoints) -> Self {
+        self.bytes_read = AtomicU64::new(checkpoints.start());
+        self.checkpoints = Some(checkpoints);
+        self
+    }
+
+    /// Make the consumers merge the station names which only differ by their Unicode normal
+    /// form under their NFC form, with
+    /// [`StationRecords::normalize_names`](crate::parser::models::StationRecords::normalize_names)
+    /// once every chunk is parsed.
+    #[cfg(feature = "normalize")]
+    pub fn with_normalize_names(mut self, normalize_names: bool) -> Self {
+        self.normalize_names = normalize_names;
+        self
+    }
+
+    /// Make the consumers only aggregate the stations matching `filter`, skipping the values
+    /// of the others as they parse each chunk with
+    /// [`StationFilter::parse_chunk`] instead of their engine.
+    #[cfg(feature = "filter")]
+    pub fn with_filter(mut self, filter: StationFilter) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+
+    /// Set how many times, and after how long, a failed read of the input is retried.
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Set what happens once a read of the input has failed every retry; see [`ErrorPolicy`].
+    pub fn with_error_policy(mut self, error_policy: ErrorPolicy) -> Self {
+        self.error_policy = error_policy;
+        self
+    }
+
+    /// The maximum size of a chunk, which is the capacity of the buffers allocated upfront.
+    pub fn max_chunk_size(&self) -> usize {
+        self.max_chunk_size
+    }
+
+    /// The longest station name expected.
+    pub fn max_name_length(&self) -> usize {
+        self.max_name_length
+    }
+
+    /// The longest station name allowed in strict mode, or [`None`] if the lines are not
+    /// validated.
+    pub fn strict_name_length(&self) -> Option<usize> {
+        self.strict.then_some(self.max_name_length)
+    }
+
+    /// Where the consumers route the invalid lines to, if anywhere.
+    pub fn rejects(&self) -> Option<&Rejects> {
+        self.rejects.as_ref()
+    }
+
+    /// Where the records merged by the consumers are checkpointed to, if anywhere.
+    pub fn checkpoints(&self) -> Option<&Checkpoints> {
+        self.checkpoints.as_ref()
+    }
+
+    /// Get the number of invalid lines rejected by the consumers so far.
+    pub fn lines_rejected(&self) -> u64 {
+        self.rejects.as_ref().map_or(0, Rejects::count)
+    }
+
+    /// Whether the consumers merge the station names differing only by their normal form.
+    #[cfg(feature = "normalize")]
+    pub fn normalize_names(&self) -> bool {
+        self.normalize_names
+    }
+
+    /// The stations the consumers aggregate, if not every station.
+    #[cfg(feature = "filter")]
+    pub fn filter(&self) -> Option<&StationFilter> {
+        self.filter.as_ref()
+    }
+
     /// Check if the reader is in progress.
     pub fn in_progress(&self) -> bool {
         self.in_progress.load(Ordering::Relaxed)
     }
 
-    /// Return when the reader will no longer yield any more data.
-    pub async fn closed(&self) -> Result<(), tokio::sync::watch::error::RecvError> {
-        let mut rx = self.closed.subscribe();
+    /// Get the total number of bytes read from the input so far.
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes_read.load(Ordering::Relaxed)
+    }
 
-        rx.wait_for(|v| *v).await?;
+    /// Get the total number of chunks pushed to the queue so far.
+    pub fn chunks_exported(&self) -> usize {
+        self.chunks_exported.load(Ordering::Relaxed)
+    }
 
-        loop {
-            if self.output_queue.is_empty() {
-                break;
-            }
+    /// Get the number of failed reads of the input retried so far.
+    pub fn read_retries(&self) -> usize {
+        self.read_retries.load(Ordering::Relaxed)
+    }
 
-            tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
-        }
+    /// Get the number of chunks dropped by [`ErrorPolicy::SkipChunk`] so far.
+    pub fn chunks_skipped(&self) -> usize {
+        self.chunks_skipped.load(Ordering::Relaxed)
+    }
 
-        Ok(())
+    /// Check if the reader stopped before the end of the input, or skipped some of it, so that
+    /// the results only cover part of the input.
+    pub fn is_partial(&self) -> bool {
+        self.partial.load(Ordering::Relaxed) || self.chunks_skipped() > 0
     }
 
-    /// Pop the next buffer from the queue.
-    pub async fn fill(&self, mut buffer: Vec<u8>) -> Option<Vec<u8>> {
-        #[cfg(feature = "timed")]
+    /// Get the number of chunks waiting in the queue for a consumer.
+    pub fn queue_depth(&self) -> usize {
+        self.output_queue.len()
+    }
+
+    /// Get the total number of records the consumers reported to have parsed so far.
+    pub fn records_parsed(&self) -> u64 {
+        self.records_parsed.load(Ordering::Relaxed)
+    }
+
+    /// Report that a consumer has parsed a number of records from a chunk.
+    pub fn add_records_parsed(&self, records: usize) {
+        self.records_parsed
+            .fetch_add(records as u64, Ordering::Relaxed);
+    }
+
+    /// Return when the reader will no longer yield any more data.
+    pub async fn closed(&self) {
+        self.output_queue.drained().await
+    }
+
+    /// Swap a spent buffer for the next chunk from the queue.
+    ///
+    /// `buffer` is cleared and handed back to the reader to be filled again, so that the
+    /// buffers are allocated once upfront and recycled between the reader and the consumers;
+    /// the reader stalls if the consumers hold on to every buffer. The buffer is dropped
+    /// instead if the reader already has as many spare buffers as the queue holds. Returns
+    /// [`None`] once the reader is closed and every chunk has been taken.
+    ///
+    /// The time spent waiting is recorded separately depending on whether a chunk arrived,
+    /// or the reader was closed, so that starved consumers can be told apart from ones
+    /// waiting for the end of the input.
+    pub async fn fill(&self, buffer: Vec<u8>) -> Option<Vec<u8>> {
+        self.fill_with_offset(buffer)
+            .await
+            .map(|(_offset, bytes)| bytes)
+    }
+
+    /// Like [`RowsReader::fill`], also returning the offset of the first byte of the chunk in
+    /// the input.
+    ///
+    /// The offset is exact unless chunks were skipped after a failed read, as the bytes never
+    /// read are not accounted for.
+    pub async fn fill_with_offset(&self, mut buffer: Vec<u8>) -> Option<(u64, Vec<u8>)> {
         let _counter = READER_LOCK_TIMED
             .get_or_init(|| TimedOperation::new("RowsReader::fill()"))
             .start();
 
         buffer.clear();
-        self.input_queue.push(buffer);
+        let _ = self.input_queue.try_push(buffer);
 
-        let result = tokio::select! {
-            _ = self.closed() => None,
-            bytes = self.output_queue.pop() => {
-                Some(bytes)
-            }
-        };
+        let waiting = timed::is_enabled().then(Instant::now);
+
+        let result = self.output_queue.pop().await;
+
+        if let Some(waiting) = waiting {
+            let operation = match result {
+                Some(_) => FILL_CHUNK_WAIT_TIMED
+                    .get_or_init(|| TimedOperation::new("RowsReader::fill()[waiting for chunk]")),
+                None => FILL_CLOSED_WAIT_TIMED
+                    .get_or_init(|| TimedOperation::new("RowsReader::fill()[waiting for close]")),
+            };
+
+            operation.record_duration(waiting.elapsed());
+        }
 
         result
     }
@@ -127,7 +378,19 @@ impl RowsReader {
             #[cfg(feature = "debug")]
             println!("RowsReader: export_buffer() waiting for available buffer from input_queue.");
 
-            let mut buffer_new = self.input_queue.pop().await;
+            let mut buffer_new = {
+                // The reader is blocked here if every buffer is held by a consumer.
+                let _counter = EXPORT_BUFFER_WAIT_TIMED
+                    .get_or_init(|| {
+                        TimedOperation::new("RowsReader::export_buffer()[waiting for buffer]")
+                    })
+                    .start();
+
+                self.input_queue
+                    .pop()
+                    .await
+                    .expect("the input queue of the reader is never closed")
+            };
 
             #[cfg(feature = "debug")]
             println!(
@@ -136,15 +399,19 @@ impl RowsReader {
             );
 
             {
-                #[cfg(feature = "timed")]
                 let _counter = func::MEM_SWAP_TIMED
                     .get_or_init(|| TimedOperation::new("mem_swap"))
                     .start();
                 std::mem::swap(&mut buffer_new, buffer_export);
             }
 
+            // Every byte read so far is either in this chunk, or before it.
             let len = buffer_new.len();
-            self.output_queue.push(buffer_new);
+            let offset = self.bytes_read() - len as u64;
+
+            // The reader is blocked here if the consumers fall behind by a full queue.
+            self.output_queue.push((offset, buffer_new)).await;
+            self.chunks_exported.fetch_add(1, Ordering::Relaxed);
             len
         } else {
             #[cfg(feature = "debug")]
@@ -154,7 +421,13 @@ impl RowsReader {
     }
 
     /// Read the file and push the chunks to the queue.
-    pub async fn read(&self, mut buffer: impl AsyncReadExt + AsyncBufRead + std::marker::Unpin) {
+    ///
+    /// Returns the error of a read that failed every retry with [`ErrorPolicy::Abort`]; the
+    /// queue is closed all the same, so that the consumers finish the chunks already pushed.
+    pub async fn read(
+        &self,
+        mut buffer: impl AsyncReadExt + AsyncBufRead + std::marker::Unpin,
+    ) -> std::io::Result<()> {
         if self
             .in_progress
             .compare_exchange(false, true, Ordering::Relaxed, Ordering::Relaxed)
@@ -165,47 +438,106 @@ impl RowsReader {
             )
         }
 
-        let mut buffer_read = vec![0; self.chunk_size];
-        let mut buffer_export = Vec::<u8>::with_capacity(self.max_chunk_size);
+        let mut buffer_export = func::allocate_buffer(self.max_chunk_size);
+        let max_line_length = config::max_line_length(self.max_name_length);
 
-        let mut buffer_line = Vec::<u8>::with_capacity(config::MAX_LINE_LENGTH);
+        let mut throughput = READER_THROUGHPUT
+            .get_or_init(|| ThroughputCounter::new("RowsReader::read()"))
+            .start();
+
+        // After a skipped chunk, the input resumes in the middle of a line, which is dropped.
+        let mut resync = false;
+        // Whether the input has failed again since the last skipped chunk without progress.
+        let mut stalled = false;
 
         loop {
             let bytes_read = {
-                #[cfg(feature = "timed")]
                 let _counter = READER_READ_TIMED
-                    .get_or_init(|| TimedOperation::new("RowsReader::read()[fixed length]"))
+                    .get_or_init(|| {
+                        TimedOperation::with_outliers(
+                            "RowsReader::read()[fixed length]",
+                            config::TIMED_OUTLIERS,
+                        )
+                    })
                     .start();
 
-                buffer.read(&mut buffer_read).await.unwrap()
+                // Read straight into the spare capacity of the chunk, instead of copying it over
+                // from a separate read buffer.
+                self.read_chunk(&mut buffer, &mut buffer_export).await
+            };
+
+            let bytes_read = match bytes_read {
+                Ok(bytes_read) => bytes_read,
+                Err(error) => {
+                    match self.recover(error, &mut buffer_export, &mut resync, &mut stalled)? {
+                        ControlFlow::Continue(()) => continue,
+                        ControlFlow::Break(()) => {
+                            break self.flush_partial(&mut buffer_export).await
+                        }
+                    }
+                }
             };
 
             #[cfg(feature = "debug")]
             println!("RowsReader: read() read {bytes_read} bytes.");
 
-            func::clone_buffer(&mut buffer_read[..bytes_read], &mut buffer_export);
+            self.bytes_read
+                .fetch_add(bytes_read as u64, Ordering::Relaxed);
+            throughput.add(bytes_read, 0);
+            stalled &= bytes_read == 0;
+            func::resync(&mut buffer_export, &mut resync);
 
             if bytes_read == 0 // if nothing is read
-                || func::buffer_full(&buffer_export, self.chunk_size) // if the buffer is full
+                || func::buffer_full(&buffer_export, self.chunk_size, max_line_length) // if the buffer is full
                 || !self.input_queue.is_empty()
             // if something is waiting
             {
                 // Read until the end of line anyway
                 let bytes_read = {
-                    #[cfg(feature = "timed")]
                     let _counter = READER_LINE_TIMED
-                        .get_or_init(|| TimedOperation::new("RowsReader::read()[line]"))
+                        .get_or_init(|| {
+                            TimedOperation::with_outliers(
+                                "RowsReader::read()[line]",
+                                config::TIMED_OUTLIERS,
+                            )
+                        })
                         .start();
 
-                    buffer.read_until(b'\n', &mut buffer_line).await.unwrap()
+                    // The rest of the line is appended to the chunk in place as well; the
+                    // buffer keeps room for a line with the longest name expected.
+                    self.read_line(&mut buffer, &mut buffer_export).await
+                };
+
+                let bytes_read = match bytes_read {
+                    Ok(bytes_read) => bytes_read,
+                    Err(error) => {
+                        match self.recover(error, &mut buffer_export, &mut resync, &mut stalled)? {
+                            ControlFlow::Continue(()) => continue,
+                            ControlFlow::Break(()) => {
+                                break self.flush_partial(&mut buffer_export).await
+                            }
+                        }
+                    }
                 };
 
                 #[cfg(feature = "debug")]
                 println!("RowsReader: read() read {bytes_read} bytes up to a new line.");
 
-                func::transfer_buffer(&mut buffer_line, &mut buffer_export);
+                self.bytes_read
+                    .fetch_add(bytes_read as u64, Ordering::Relaxed);
+                throughput.add(bytes_read, 0);
+                stalled &= bytes_read == 0;
+                func::resync(&mut buffer_export, &mut resync);
+
                 let _bytes_pushed = self.export_buffer(&mut buffer_export).await;
 
+                // Every byte read so far has been handed out at this point.
+                if let Some(checkpoints) = self.checkpoints.as_ref().filter(|_| bytes_read > 0) {
+                    checkpoints
+                        .checkpoint_if_due(self.bytes_read(), self.chunks_exported())
+                        .await;
+                }
+
                 #[cfg(feature = "debug")]
                 println!("RowsReader: read() flushed {_bytes_pushed} bytes to queue.");
 
@@ -213,11 +545,317 @@ impl RowsReader {
                     #[cfg(feature = "debug")]
                     println!("RowsReader: read() finished.");
 
-                    self.closed.send_replace(true);
+                    self.output_queue.close();
 
                     break;
                 }
             }
         }
+
+        Ok(())
+    }
+
+    /// Read up to a chunk from `input` into `buffer_export`, retrying the failed reads.
+    async fn read_chunk(
+        &self,
+        input: &mut (impl AsyncRead + Unpin),
+        buffer_export: &mut Vec<u8>,
+    ) -> std::io::Result<usize> {
+        let mut attempt = 0;
+
+        loop {
+            match (&mut *input)
+                .take(self.chunk_size as u64)
+                .read_buf(buffer_export)
+                .await
+            {
+                Ok(bytes_read) => return Ok(bytes_read),
+                Err(error) => self.back_off(&mut attempt, error).await?,
+            }
+        }
+    }
+
+    /// Read the rest of the line from `input` into `buffer_export`, retrying the failed reads.
+    async fn read_line(
+        &self,
+        input: &mut (impl AsyncBufRead + Unpin),
+        buffer_export: &mut Vec<u8>,
+    ) -> std::io::Result<usize> {
+        let mut attempt = 0;
+        let mut bytes_read = 0;
+
+        loop {
+            // The bytes read before an error are kept in the buffer, and the retry continues
+            // after them.
+            let start = buffer_export.len();
+
+            match input.read_until(b'\n', buffer_export).await {
+                Ok(read) => return Ok(bytes_read + read),
+                Err(error) => {
+                    bytes_read += buffer_export.len() - start;
+                    self.back_off(&mut attempt, error).await?
+                }
+            }
+        }
+    }
+
+    /// Wait before retrying a read that failed `attempt` times before, or hand the `error`
+    /// back if it should not be retried.
+    async fn back_off(&self, attempt: &mut u32, error: std::io::Error) -> std::io::Result<()> {
+        let delay = self.retry_policy.delay(*attempt, &error).ok_or(error)?;
+
+        #[cfg(feature = "debug")]
+        println!("RowsReader: retrying a failed read #{attempt} in {delay:?}.");
+
+        *attempt += 1;
+        self.read_retries.fetch_add(1, Ordering::Relaxed);
+        tokio::time::sleep(delay).await;
+
+        Ok(())
+    }
+
+    /// Apply the [`ErrorPolicy`] to a read that failed every retry, breaking out of the reading
+    /// loop if the reader should stop, or handing the error back to abort.
+    fn recover(
+        &self,
+        error: std::io::Error,
+        buffer_export: &mut Vec<u8>,
+        resync: &mut bool,
+        stalled: &mut bool,
+    ) -> std::io::Result<ControlFlow<()>> {
+        match self.error_policy {
+            ErrorPolicy::Abort => {
+                self.partial.store(true, Ordering::Relaxed);
+                self.output_queue.close();
+
+                Err(error)
+            }
+            ErrorPolicy::SkipChunk if !*stalled => {
+                #[cfg(feature = "debug")]
+                println!("RowsReader: read() skipped a chunk after an error: {error}.");
+
+                // Unless the chunk ends with a complete line, the input resumes in the middle
+                // of one.
+                *resync = buffer_export.last().map_or(*resync, |&byte| byte != b'\n');
+                *stalled = true;
+                buffer_export.clear();
+                self.chunks_skipped.fetch_add(1, Ordering::Relaxed);
+
+                Ok(ControlFlow::Continue(()))
+            }
+            _ => {
+                #[cfg(feature = "debug")]
+                println!("RowsReader: read() stopped after an error: {error}.");
+
+                Ok(ControlFlow::Break(()))
+            }
+        }
+    }
+
+    /// Hand the complete lines of `buffer_export` to the consumers, and close the queue before
+    /// the end of the input.
+    async fn flush_partial(&self, buffer_export: &mut Vec<u8>) {
+        let complete = memchr::memrchr(b'\n', buffer_export).map_or(0, |end| end + 1);
+        buffer_export.truncate(complete);
+
+        self.export_buffer(buffer_export).await;
+        self.partial.store(true, Ordering::Relaxed);
+        self.output_queue.close();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn fill_recycles_buffers() {
+        let input = "jack;1.2\njill;-3.4\njack;56.7\nbob;0.0\n".repeat(100);
+
+        // Without any additional buffers, the reader can only continue with the buffer
+        // recycled by the consumer.
+        let reader = RowsReader::with_chunk_sizes(64, 128);
+
+        let mut chunks = Vec::new();
+        let mut allocations = std::collections::HashSet::new();
+
+        let (result, _) = tokio::join!(reader.read(input.as_bytes()), async {
+            let mut buffer = Vec::with_capacity(128);
+
+            while let Some(bytes) = reader.fill(buffer).await {
+                allocations.insert(bytes.as_ptr());
+                chunks.push(String::from_utf8(bytes.clone()).unwrap());
+                buffer = bytes;
+            }
+        });
+
+        assert!(result.is_ok());
+        assert!(chunks.len() > 1);
+        assert!(allocations.len() <= 2);
+        assert_eq!(chunks.concat(), input);
+    }
+
+    #[tokio::test]
+    async fn chunks_fit_their_buffers() {
+        let input = "jack;1.2\njill;-3.4\njack;56.7\nbob;0.0\n".repeat(1000);
+        let max_chunk_size = 4 * config::MAX_LINE_LENGTH;
+        let reader = RowsReader::with_chunk_sizes(config::MAX_LINE_LENGTH, max_chunk_size)
+            .with_additional_buffers(2);
+
+        let mut chunks = Vec::new();
+        let (result, _) = tokio::join!(reader.read(input.as_bytes()), async {
+            let mut buffer = Vec::with_capacity(max_chunk_size);
+
+            while let Some(bytes) = reader.fill(buffer).await {
+                // The reads and the rest of the last line never outgrow the buffer.
+                assert!(bytes.len() <= max_chunk_size && bytes.ends_with(b"\n"));
+                chunks.push(String::from_utf8(bytes.clone()).unwrap());
+                buffer = bytes;
+            }
+        });
+
+        assert!(result.is_ok());
+        assert!(chunks.len() > 1);
+        assert_eq!(chunks.concat(), input);
+    }
+
+    #[tokio::test]
+    async fn closed_once_drained() {
+        let reader = RowsReader::with_chunk_sizes(64, 128).with_additional_buffers(4);
+        reader
+            .read("jack;1.2\njill;-3.4\n".as_bytes())
+            .await
+            .unwrap();
+
+        // The reader is closed, but a chunk is still waiting for a consumer.
+        let pending = tokio::time::timeout(std::time::Duration::from_millis(10), reader.closed());
+        assert!(pending.await.is_err());
+
+        let mut buffer = Vec::with_capacity(128);
+        while let Some(bytes) = reader.fill(buffer).await {
+            buffer = bytes;
+        }
+
+        // Without any polling, this returns as soon as the last chunk has been taken.
+        let start = std::time::Instant::now();
+        reader.closed().await;
+        assert!(start.elapsed() < std::time::Duration::from_millis(10));
+    }
+
+    /// An input failing `failures` times once `fail_at` bytes have been read.
+    struct FlakyInput {
+        data: &'static [u8],
+        position: usize,
+        fail_at: usize,
+        failures: usize,
+    }
+
+    impl AsyncRead for FlakyInput {
+        fn poll_read(
+            mut self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+            buf: &mut tokio::io::ReadBuf<'_>,
+        ) -> std::task::Poll<std::io::Result<()>> {
+            if self.position >= self.fail_at && self.failures > 0 {
+                self.failures -= 1;
+                return std::task::Poll::Ready(Err(std::io::ErrorKind::TimedOut.into()));
+            }
+
+            // Stop at the failure, as a disk would at a bad sector.
+            let end = match self.position < self.fail_at {
+                true => self.fail_at,
+                false => self.data.len(),
+            }
+            .min(self.data.len())
+            .min(self.position + buf.remaining());
+
+            buf.put_slice(&self.data[self.position..end]);
+            self.position = end;
+
+            std::task::Poll::Ready(Ok(()))
+        }
+    }
+
+    /// Read `input` failing `failures` times at `fail_at`, returning what the consumers got and
+    /// the result of the reader.
+    async fn read_flaky(
+        reader: RowsReader,
+        fail_at: usize,
+        failures: usize,
+    ) -> (RowsReader, String, std::io::Result<()>) {
+        const INPUT: &str = "jack;1.2\njill;-3.4\njack;56.7\nbob;0.0\n";
+
+        let input = FlakyInput {
+            data: INPUT.as_bytes(),
+            position: 0,
+            fail_at,
+            failures,
+        };
+
+        let mut chunks = Vec::new();
+        let (result, _) = tokio::join!(
+            reader.read(tokio::io::BufReader::with_capacity(8, input)),
+            async {
+                let mut buffer = Vec::with_capacity(128);
+
+                while let Some(bytes) = reader.fill(buffer).await {
+                    chunks.push(String::from_utf8(bytes.clone()).unwrap());
+                    buffer = bytes;
+                }
+            }
+        );
+
+        (reader, chunks.concat(), result)
+    }
+
+    #[tokio::test]
+    async fn retry_failed_reads() {
+        let reader = RowsReader::with_chunk_sizes(16, 128).with_retry_policy(RetryPolicy {
+            retries: 2,
+            backoff: std::time::Duration::from_millis(1),
+        });
+        let (reader, read, result) = read_flaky(reader, 12, 2).await;
+        assert!(result.is_ok());
+
+        assert_eq!(read, "jack;1.2\njill;-3.4\njack;56.7\nbob;0.0\n");
+        assert_eq!(reader.read_retries(), 2);
+        assert!(!reader.is_partial());
+    }
+
+    #[tokio::test]
+    async fn skip_failed_chunk() {
+        let reader = RowsReader::with_chunk_sizes(16, 128)
+            .with_retry_policy(RetryPolicy::none())
+            .with_error_policy(ErrorPolicy::SkipChunk);
+        let (reader, read, result) = read_flaky(reader, 12, 1).await;
+        assert!(result.is_ok());
+
+        // The chunk being read is dropped, along with the rest of its last line.
+        assert_eq!(read, "jack;56.7\nbob;0.0\n");
+        assert_eq!(reader.chunks_skipped(), 1);
+        assert!(reader.is_partial());
+    }
+
+    #[tokio::test]
+    async fn flush_partial_chunk() {
+        let reader = RowsReader::with_chunk_sizes(16, 128)
+            .with_retry_policy(RetryPolicy::none())
+            .with_error_policy(ErrorPolicy::FlushPartial);
+        let (reader, read, result) = read_flaky(reader, 12, usize::MAX).await;
+        assert!(result.is_ok());
+
+        assert_eq!(read, "jack;1.2\n");
+        assert!(reader.is_partial());
+    }
+
+    #[tokio::test]
+    async fn abort_on_failed_read() {
+        let reader = RowsReader::with_chunk_sizes(16, 128).with_retry_policy(RetryPolicy::none());
+        let (reader, read, result) = read_flaky(reader, 12, 1).await;
+
+        assert_eq!(result.unwrap_err().kind(), std::io::ErrorKind::TimedOut);
+        // Unlike with `FlushPartial`, the chunk being read is lost.
+        assert_eq!(read, "");
+        assert!(reader.is_partial());
     }
 }
@@ -0,0 +1,111 @@
+//! Recover from the errors of the input in the middle of a run, e.g. a network mount briefly
+//! timing out.
+//!
+//! A failed read is first retried by the [`RetryPolicy`], waiting longer after each attempt;
+//! if it still fails, the [`ErrorPolicy`] decides whether the run is lost, or continues with
+//! some of the input missing.
+
+use std::time::Duration;
+
+use crate::config;
+
+/// What the reader does once a read has failed every retry.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ErrorPolicy {
+    /// Fail the read with the error, losing the whole run.
+    #[default]
+    Abort,
+
+    /// Drop the chunk being read, and continue from the next line after it; stops like
+    /// [`ErrorPolicy::FlushPartial`] if the input fails again before anything is read.
+    SkipChunk,
+
+    /// Stop reading, handing the complete lines read so far to the consumers, so that the
+    /// partial results are still exported.
+    FlushPartial,
+}
+
+impl std::fmt::Display for ErrorPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Abort => write!(f, "abort"),
+            Self::SkipChunk => write!(f, "skip-chunk"),
+            Self::FlushPartial => write!(f, "flush-partial"),
+        }
+    }
+}
+
+/// How many times, and after how long, a failed read is retried.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// The number of retries of a failed read.
+    pub retries: u32,
+
+    /// The wait before the first retry, doubled after every attempt.
+    pub backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            retries: config::READ_RETRIES,
+            backoff: Duration::from_millis(config::READ_RETRY_BACKOFF_MS),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Never retry a failed read.
+    pub fn none() -> Self {
+        Self {
+            retries: 0,
+            backoff: Duration::ZERO,
+        }
+    }
+
+    /// The wait before retrying a read that failed `attempt` times before with `error`, or
+    /// [`None`] if it should not be retried.
+    ///
+    /// An interrupted read is retried at once, and does not count towards the retries.
+    pub fn delay(&self, attempt: u32, error: &std::io::Error) -> Option<Duration> {
+        match error.kind() {
+            std::io::ErrorKind::Interrupted => Some(Duration::ZERO),
+            _ if attempt >= self.retries => None,
+            _ => Some(self.backoff.saturating_mul(1 << attempt.min(16))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::{Error, ErrorKind};
+
+    #[test]
+    fn back_off_exponentially() {
+        let policy = RetryPolicy {
+            retries: 3,
+            backoff: Duration::from_millis(10),
+        };
+        let error = Error::new(ErrorKind::TimedOut, "timed out");
+
+        let delays = (0..4)
+            .map(|attempt| policy.delay(attempt, &error))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            delays,
+            [
+                Some(Duration::from_millis(10)),
+                Some(Duration::from_millis(20)),
+                Some(Duration::from_millis(40)),
+                None
+            ]
+        );
+        assert_eq!(
+            policy.delay(3, &Error::from(ErrorKind::Interrupted)),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(RetryPolicy::none().delay(0, &error), None);
+    }
+}
@@ -0,0 +1,347 @@
+//! A bounded lock-free ring of slots, handing the chunks over from the reader to the
+//! consumers, and the spent buffers back.
+//!
+//! Each slot carries a sequence number telling whether it is ready to be written or read at
+//! the current lap of the ring, so that pushing and popping only take a compare-and-swap on
+//! the position, and never a lock (Dmitry Vyukov's bounded queue). The reader is the single
+//! producer of the chunks, popped by many consumers, while the consumers push the spent
+//! buffers back to the single reader.
+//!
+//! Waiting for a slot or an item, and closing the ring, go through [`Notify`], which is only
+//! touched by the tasks that actually have to wait.
+//!
+//! The positions and the notifications are written from different cores, so each is kept on
+//! its own cache line by [`CachePadded`], lest every push invalidates the line read by every
+//! pop.
+
+use std::cell::UnsafeCell;
+use std::mem::MaybeUninit;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use tokio::sync::Notify;
+
+/// Align a value to its own cache line, i.e. two 64-byte lines on x86-64 as the adjacent line
+/// is prefetched together, and 128 bytes on Apple silicon.
+#[derive(Debug, Default)]
+#[repr(align(128))]
+pub struct CachePadded<T>(pub T);
+
+impl<T> std::ops::Deref for CachePadded<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+/// A slot of the ring.
+struct Slot<T> {
+    /// `position` if the slot is free to be written at `position`, or `position + 1` if it
+    /// holds the item pushed at `position`.
+    sequence: AtomicUsize,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+/// A bounded lock-free ring, which can be closed once nothing more is pushed.
+pub struct Ring<T> {
+    slots: Box<[Slot<T>]>,
+    mask: usize,
+    push_position: CachePadded<AtomicUsize>,
+    pop_position: CachePadded<AtomicUsize>,
+    closed: AtomicBool,
+    readable: CachePadded<Notify>,
+    writable: CachePadded<Notify>,
+    drained: Notify,
+}
+
+// SAFETY: every value is moved in and out of its slot by exactly one thread, as ordered by the
+// sequence of the slot.
+unsafe impl<T: Send> Send for Ring<T> {}
+unsafe impl<T: Send> Sync for Ring<T> {}
+
+impl<T> Ring<T> {
+    /// Create an empty ring of at least `capacity` slots, rounded up to a power of two.
+    pub fn with_capacity(capacity: usize) -> Self {
+        let capacity = capacity.max(2).next_power_of_two();
+
+        Self {
+            slots: (0..capacity)
+                .map(|position| Slot {
+                    sequence: AtomicUsize::new(position),
+                    value: UnsafeCell::new(MaybeUninit::uninit()),
+                })
+                .collect(),
+            mask: capacity - 1,
+            push_position: CachePadded(AtomicUsize::new(0)),
+            pop_position: CachePadded(AtomicUsize::new(0)),
+            closed: AtomicBool::new(false),
+            readable: CachePadded(Notify::new()),
+            writable: CachePadded(Notify::new()),
+            drained: Notify::new(),
+        }
+    }
+
+    /// The number of slots of the ring.
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// The number of items in the ring, which may be outdated as soon as it is returned.
+    pub fn len(&self) -> usize {
+        let popped = self.pop_position.load(Ordering::Relaxed);
+        let pushed = self.push_position.load(Ordering::Relaxed);
+
+        pushed.saturating_sub(popped)
+    }
+
+    /// Check if the ring is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Check if the ring has been closed; it may still hold items to be popped.
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Acquire)
+    }
+
+    /// Push an item without waiting, handing it back if the ring is full.
+    pub fn try_push(&self, value: T) -> Result<(), T> {
+        let mut position = self.push_position.load(Ordering::Relaxed);
+
+        loop {
+            let slot = &self.slots[position & self.mask];
+            let sequence = slot.sequence.load(Ordering::Acquire);
+
+            match (sequence as isize).wrapping_sub(position as isize) {
+                0 => match self.push_position.compare_exchange_weak(
+                    position,
+                    position + 1,
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        // SAFETY: winning the position grants exclusive access to the free slot.
+                        unsafe { (*slot.value.get()).write(value) };
+                        slot.sequence.store(position + 1, Ordering::Release);
+                        self.readable.notify_one();
+
+                        return Ok(());
+                    }
+                    Err(current) => position = current,
+                },
+                // The slot still holds the item of the previous lap.
+                diff if diff < 0 => return Err(value),
+                _ => position = self.push_position.load(Ordering::Relaxed),
+            }
+        }
+    }
+
+    /// Pop an item without waiting, if any.
+    pub fn try_pop(&self) -> Option<T> {
+        let mut position = self.pop_position.load(Ordering::Relaxed);
+
+        loop {
+            let slot = &self.slots[position & self.mask];
+            let sequence = slot.sequence.load(Ordering::Acquire);
+
+            match (sequence as isize).wrapping_sub(position as isize + 1) {
+                0 => match self.pop_position.compare_exchange_weak(
+                    position,
+                    position + 1,
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        // SAFETY: winning the position grants exclusive access to the item,
+                        // which was written before the sequence was released.
+                        let value = unsafe { (*slot.value.get()).assume_init_read() };
+                        slot.sequence
+                            .store(position + self.slots.len(), Ordering::Release);
+                        self.writable.notify_one();
+
+                        if self.is_closed() && self.is_empty() {
+                            self.drained.notify_waiters();
+                        }
+
+                        return Some(value);
+                    }
+                    Err(current) => position = current,
+                },
+                // The slot has not been written at this lap yet.
+                diff if diff < 0 => return None,
+                _ => position = self.pop_position.load(Ordering::Relaxed),
+            }
+        }
+    }
+
+    /// Push an item, waiting for a free slot if the ring is full.
+    ///
+    /// The slot is tried first without registering for a wakeup, which is only needed when
+    /// the ring is full; the same goes for [`Ring::pop`].
+    pub async fn push(&self, mut value: T) {
+        match self.try_push(value) {
+            Ok(()) => return,
+            Err(rejected) => value = rejected,
+        }
+
+        loop {
+            let notified = self.writable.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+
+            match self.try_push(value) {
+                Ok(()) => return,
+                Err(rejected) => value = rejected,
+            }
+
+            notified.await;
+        }
+    }
+
+    /// Pop an item, waiting for one to be pushed; returns [`None`] once the ring is closed and
+    /// empty.
+    pub async fn pop(&self) -> Option<T> {
+        if let Some(value) = self.try_pop() {
+            return Some(value);
+        }
+
+        loop {
+            let notified = self.readable.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+
+            if let Some(value) = self.try_pop() {
+                return Some(value);
+            }
+
+            // Check the items again, as they may have been pushed right before closing.
+            if self.is_closed() {
+                return self.try_pop();
+            }
+
+            notified.await;
+        }
+    }
+
+    /// Close the ring, waking every task waiting for an item; the items already pushed can
+    /// still be popped.
+    pub fn close(&self) {
+        self.closed.store(true, Ordering::Release);
+        self.readable.notify_waiters();
+        self.writable.notify_waiters();
+        self.drained.notify_waiters();
+    }
+
+    /// Return once the ring is closed and every item has been popped.
+    pub async fn drained(&self) {
+        loop {
+            let notified = self.drained.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+
+            if self.is_closed() && self.is_empty() {
+                return;
+            }
+
+            notified.await;
+        }
+    }
+}
+
+impl<T> Drop for Ring<T> {
+    fn drop(&mut self) {
+        while self.try_pop().is_some() {}
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::Arc;
+
+    #[test]
+    fn push_and_pop_in_order() {
+        let ring = Ring::with_capacity(3);
+        assert_eq!(ring.capacity(), 4);
+
+        for lap in 0..3 {
+            for value in 0..4 {
+                assert!(ring.try_push(lap * 10 + value).is_ok());
+            }
+            assert_eq!(ring.try_push(99), Err(99));
+            assert_eq!(ring.len(), 4);
+
+            for value in 0..4 {
+                assert_eq!(ring.try_pop(), Some(lap * 10 + value));
+            }
+            assert_eq!(ring.try_pop(), None);
+        }
+    }
+
+    #[test]
+    fn pad_positions() {
+        let ring = Ring::<u8>::with_capacity(4);
+        let push = &ring.push_position as *const _ as usize;
+        let pop = &ring.pop_position as *const _ as usize;
+
+        assert_eq!(std::mem::align_of::<CachePadded<AtomicUsize>>(), 128);
+        assert!(push.abs_diff(pop) >= 128);
+    }
+
+    #[test]
+    fn drop_remaining_items() {
+        let item = Arc::new(());
+        let ring = Ring::with_capacity(4);
+
+        ring.try_push(Arc::clone(&item)).unwrap();
+        ring.try_push(Arc::clone(&item)).unwrap();
+        drop(ring);
+
+        assert_eq!(Arc::strong_count(&item), 1);
+    }
+
+    #[tokio::test]
+    async fn pop_until_closed() {
+        let ring = Ring::with_capacity(4);
+
+        ring.push(1).await;
+        ring.close();
+
+        assert_eq!(ring.pop().await, Some(1));
+        assert_eq!(ring.pop().await, None);
+        ring.drained().await;
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn hand_off_to_many_consumers() {
+        const ITEMS: usize = 10_000;
+
+        let ring = Arc::new(Ring::with_capacity(8));
+
+        let consumers = (0..4)
+            .map(|_| {
+                let ring = Arc::clone(&ring);
+                tokio::spawn(async move {
+                    let mut items = Vec::new();
+                    while let Some(item) = ring.pop().await {
+                        items.push(item);
+                    }
+                    items
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for item in 0..ITEMS {
+            ring.push(item).await;
+        }
+        ring.close();
+
+        let mut items = Vec::with_capacity(ITEMS);
+        for consumer in consumers {
+            items.extend(consumer.await.unwrap());
+        }
+        items.sort_unstable();
+
+        assert_eq!(items, (0..ITEMS).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,154 @@
+//! Read a deterministic sample of a file, for approximate results in a fraction of the time.
+//!
+//! The file is divided into windows of a chunk each, and one window out of every `1 / rate`
+//! is read, starting with the first, each aligned to the lines on both ends so that only
+//! complete lines are read. The same rate over the same file always reads the same windows,
+//! so that two configurations can be compared on the same sample.
+
+use std::io::{self, SeekFrom};
+use std::ops::Range;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+use tokio::io::{AsyncRead, AsyncSeek, ReadBuf};
+
+use crate::distributed::align_to_line;
+
+/// The ranges of the windows of `window` bytes sampled at `rate` from `file` of `len` bytes,
+/// aligned to the lines; the whole file if `rate` is 1 or more.
+pub fn sample_ranges(
+    file: &mut std::fs::File,
+    len: u64,
+    window: u64,
+    rate: f64,
+) -> io::Result<Vec<Range<u64>>> {
+    let window = window.max(1);
+    let period = (1.0 / rate).round().max(1.0) as u64;
+    if period == 1 {
+        return Ok(std::iter::once(0..len).collect());
+    }
+
+    let mut ranges = Vec::new();
+    for index in (0..len.div_ceil(window)).step_by(period as usize) {
+        let start = align_to_line(file, index * window, len)?;
+        let end = align_to_line(file, ((index + 1) * window).min(len), len)?;
+
+        if start < end {
+            ranges.push(start..end);
+        }
+    }
+
+    Ok(ranges)
+}
+
+/// Reads the given ranges of `inner` one after the other, seeking over the bytes between them.
+pub struct SampledReader<R> {
+    inner: R,
+    /// The ranges still to be read, in reverse order.
+    ranges: Vec<Range<u64>>,
+    /// The bytes left in the range being read.
+    remaining: u64,
+    /// Whether a seek to the next range has been started, but not completed.
+    seeking: bool,
+}
+
+impl<R> SampledReader<R> {
+    /// Read `ranges` of `inner`, in the order given.
+    pub fn new(inner: R, mut ranges: Vec<Range<u64>>) -> Self {
+        ranges.reverse();
+
+        Self {
+            inner,
+            ranges,
+            remaining: 0,
+            seeking: false,
+        }
+    }
+}
+
+impl<R: AsyncRead + AsyncSeek + Unpin> AsyncRead for SampledReader<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+
+        loop {
+            if this.seeking {
+                ready!(Pin::new(&mut this.inner).poll_complete(cx))?;
+                this.seeking = false;
+            }
+
+            if this.remaining > 0 {
+                let limit = usize::try_from(this.remaining).unwrap_or(usize::MAX);
+                let mut limited =
+                    ReadBuf::new(buf.initialize_unfilled_to(limit.min(buf.remaining())));
+                ready!(Pin::new(&mut this.inner).poll_read(cx, &mut limited))?;
+
+                let read = limited.filled().len();
+                if read == 0 {
+                    return Poll::Ready(Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        "the input ended before the sampled range",
+                    )));
+                }
+
+                buf.advance(read);
+                this.remaining -= read as u64;
+                return Poll::Ready(Ok(()));
+            }
+
+            let Some(range) = this.ranges.pop() else {
+                return Poll::Ready(Ok(()));
+            };
+
+            Pin::new(&mut this.inner).start_seek(SeekFrom::Start(range.start))?;
+            this.seeking = true;
+            this.remaining = range.end - range.start;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::TestDir;
+    use tokio::io::AsyncReadExt;
+
+    #[tokio::test]
+    async fn read_sampled_lines() {
+        let dir = TestDir::new("read_sampled_lines");
+        let path = dir.join("input.txt");
+        let lines: String = (0..100)
+            .map(|line| format!("s{};{}.0\n", line, line))
+            .collect();
+        std::fs::write(&path, &lines).unwrap();
+
+        let mut file = std::fs::File::open(&path).unwrap();
+        let len = lines.len() as u64;
+        let ranges = sample_ranges(&mut file, len, 64, 0.25).unwrap();
+        assert_eq!(ranges.first().map(|range| range.start), Some(0));
+        assert_eq!(ranges, sample_ranges(&mut file, len, 64, 0.25).unwrap());
+        let whole = sample_ranges(&mut file, len, 64, 1.0).unwrap();
+        assert_eq!((whole.len(), whole.first()), (1, Some(&(0..len))));
+
+        let mut sampled = String::new();
+        SampledReader::new(tokio::fs::File::from_std(file), ranges.clone())
+            .read_to_string(&mut sampled)
+            .await
+            .unwrap();
+
+        // Only complete lines, about a quarter of them.
+        let expected: String = ranges
+            .iter()
+            .map(|range| &lines[range.start as usize..range.end as usize])
+            .collect();
+        assert_eq!(sampled, expected);
+        assert!(ranges.iter().all(|range| {
+            (range.start == 0 || lines.as_bytes()[range.start as usize - 1] == b'\n')
+                && lines.as_bytes()[range.end as usize - 1] == b'\n'
+        }));
+        assert!((15..=35).contains(&sampled.lines().count()));
+    }
+}
@@ -0,0 +1,244 @@
+//! Detect the kind of storage the input file resides on, to pick the read size and the
+//! number of consumers suited to it.
+//!
+//! The defaults in [`config`] were tuned for a RAM disk, where the consumers are the
+//! bottleneck. On slower storage the reader is the bottleneck instead: larger reads cut the
+//! number of requests, and fewer consumers leave fewer idle threads contending for the chunks.
+//!
+//! On Linux, the file system type is read with `statfs`, then the block device holding the
+//! file is looked up in `/sys/dev/block` for its name and `queue/rotational` flag. On macOS,
+//! only network file systems are recognized. Anything else is [`StorageKind::Unknown`], which
+//! keeps the defaults.
+
+use std::path::Path;
+
+use crate::config;
+use crate::tune::Tuning;
+
+/// The kind of storage an input file resides on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum StorageKind {
+    /// A file system in memory, such as `tmpfs` or a `zram` device.
+    RamDisk,
+
+    /// An NVMe solid state drive.
+    Nvme,
+
+    /// Any other non-rotational drive, such as a SATA solid state drive.
+    Ssd,
+
+    /// A spinning hard drive.
+    Rotational,
+
+    /// A network file system, such as NFS or SMB.
+    Network,
+
+    /// The storage could not be detected.
+    Unknown,
+}
+
+impl StorageKind {
+    /// The number of consumers and the chunk size suggested for this storage, or [`None`] to
+    /// keep the defaults of [`config`].
+    pub fn suggested_tuning(self) -> Option<Tuning> {
+        match self {
+            Self::RamDisk | Self::Unknown => None,
+            // Keep the consumers, but halve the number of read requests.
+            Self::Nvme => Some(Tuning::new(
+                config::NUMBER_OF_THREADS,
+                config::CHUNK_SIZE * 2,
+            )),
+            // About 500 MB/s, which half of the consumers can keep up with.
+            Self::Ssd => Some(Tuning::new(
+                config::NUMBER_OF_THREADS / 2,
+                config::CHUNK_SIZE * 2,
+            )),
+            // Large sequential reads, to keep the head from seeking between requests.
+            Self::Rotational => Some(Tuning::new(2, config::CHUNK_SIZE * 4)),
+            // Fewer, larger requests to hide the latency of each round trip.
+            Self::Network => Some(Tuning::new(
+                config::NUMBER_OF_THREADS / 2,
+                config::CHUNK_SIZE * 4,
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for StorageKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::RamDisk => write!(f, "RAM disk"),
+            Self::Nvme => write!(f, "NVMe"),
+            Self::Ssd => write!(f, "SSD"),
+            Self::Rotational => write!(f, "rotational disk"),
+            Self::Network => write!(f, "network file system"),
+            Self::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+/// Detect the kind of storage the file at `path` resides on.
+///
+/// Returns [`StorageKind::Unknown`] if the file cannot be inspected, or on unsupported
+/// platforms.
+pub fn detect(path: impl AsRef<Path>) -> StorageKind {
+    platform::detect(path.as_ref()).unwrap_or(StorageKind::Unknown)
+}
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod platform {
+    use std::os::unix::{ffi::OsStrExt, fs::MetadataExt};
+    use std::path::Path;
+
+    use super::StorageKind;
+
+    /// The `statfs` magic numbers of the file systems kept in memory.
+    const RAM_FILE_SYSTEMS: [u32; 2] = [
+        0x0102_1994, // tmpfs
+        0x8584_58F6, // ramfs
+    ];
+
+    /// The `statfs` magic numbers of the network file systems.
+    const NETWORK_FILE_SYSTEMS: [u32; 7] = [
+        0x0000_6969, // nfs
+        0x0000_517B, // smb
+        0xFF53_4D42, // cifs
+        0xFE53_4D42, // smb2
+        0x00C3_6400, // ceph
+        0x5346_414F, // afs
+        0x0102_1997, // 9p
+    ];
+
+    pub fn detect(path: &Path) -> Option<StorageKind> {
+        let c_path = std::ffi::CString::new(path.as_os_str().as_bytes()).ok()?;
+        // SAFETY: `statfs` is plain old data, filled in by the call below.
+        let mut stats: libc::statfs = unsafe { std::mem::zeroed() };
+
+        // SAFETY: the path is a valid C string, and `stats` is valid for writes.
+        if unsafe { libc::statfs(c_path.as_ptr(), &mut stats) } != 0 {
+            return None;
+        }
+
+        // The type of `f_type` depends on the architecture; the magic numbers fit in 32 bits.
+        let file_system = stats.f_type as u32;
+
+        if RAM_FILE_SYSTEMS.contains(&file_system) {
+            return Some(StorageKind::RamDisk);
+        }
+        if NETWORK_FILE_SYSTEMS.contains(&file_system) {
+            return Some(StorageKind::Network);
+        }
+
+        detect_block_device(std::fs::metadata(path).ok()?.dev())
+    }
+
+    /// Look up the block device `dev` in `/sys/dev/block`.
+    fn detect_block_device(dev: u64) -> Option<StorageKind> {
+        let major = ((dev >> 8) & 0xFFF) | ((dev >> 32) & !0xFFF);
+        let minor = (dev & 0xFF) | ((dev >> 12) & !0xFF);
+
+        let mut device =
+            std::fs::canonicalize(format!("/sys/dev/block/{}:{}", major, minor)).ok()?;
+
+        // The queue of a partition is that of its disk, one level up.
+        if device.join("partition").exists() {
+            device.pop();
+        }
+
+        let name = device.file_name()?.to_string_lossy().into_owned();
+
+        if name.starts_with("nvme") {
+            return Some(StorageKind::Nvme);
+        }
+        if ["zram", "ram", "pmem"]
+            .iter()
+            .any(|prefix| name.starts_with(prefix))
+        {
+            return Some(StorageKind::RamDisk);
+        }
+
+        match std::fs::read_to_string(device.join("queue/rotational"))
+            .ok()?
+            .trim()
+        {
+            "1" => Some(StorageKind::Rotational),
+            _ => Some(StorageKind::Ssd),
+        }
+    }
+}
+
+#[cfg(any(target_os = "macos", target_os = "ios"))]
+mod platform {
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+
+    use super::StorageKind;
+
+    /// The names of the network file systems in `f_fstypename`.
+    const NETWORK_FILE_SYSTEMS: [&str; 4] = ["nfs", "smbfs", "afpfs", "webdav"];
+
+    pub fn detect(path: &Path) -> Option<StorageKind> {
+        let c_path = std::ffi::CString::new(path.as_os_str().as_bytes()).ok()?;
+        // SAFETY: `statfs` is plain old data, filled in by the call below.
+        let mut stats: libc::statfs = unsafe { std::mem::zeroed() };
+
+        // SAFETY: the path is a valid C string, and `stats` is valid for writes.
+        if unsafe { libc::statfs(c_path.as_ptr(), &mut stats) } != 0 {
+            return None;
+        }
+
+        // SAFETY: `f_fstypename` is a NUL-terminated string within the array.
+        let name = unsafe { std::ffi::CStr::from_ptr(stats.f_fstypename.as_ptr()) };
+
+        NETWORK_FILE_SYSTEMS
+            .contains(&name.to_str().ok()?)
+            .then_some(StorageKind::Network)
+    }
+}
+
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "android",
+    target_os = "macos",
+    target_os = "ios"
+)))]
+mod platform {
+    use std::path::Path;
+
+    use super::StorageKind;
+
+    pub fn detect(_path: &Path) -> Option<StorageKind> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn detect_missing_file() {
+        assert_eq!(
+            detect("/nonexistent/measurements.txt"),
+            StorageKind::Unknown
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn detect_shared_memory() {
+        if Path::new("/dev/shm").is_dir() {
+            assert_eq!(detect("/dev/shm"), StorageKind::RamDisk);
+        }
+    }
+
+    #[test]
+    fn suggest_tunings() {
+        assert_eq!(StorageKind::RamDisk.suggested_tuning(), None);
+        assert_eq!(StorageKind::Unknown.suggested_tuning(), None);
+
+        let rotational = StorageKind::Rotational.suggested_tuning().unwrap();
+        assert_eq!(rotational.threads, 2);
+        assert!(rotational.chunk_size > config::CHUNK_SIZE);
+    }
+}
@@ -0,0 +1,114 @@
+//! Consume the chunks of a [`RowsReader`] as a [`Stream`].
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use futures_core::Stream;
+
+use super::RowsReader;
+
+/// The future of a single [`RowsReader::fill`] call.
+type Fill = Pin<Box<dyn Future<Output = Option<Vec<u8>>> + Send>>;
+
+/// A [`Stream`] of the chunks exported by a [`RowsReader`], ending when the reader closes.
+///
+/// This is the consumption side of [`RowsReader::fill`], so that the standard stream
+/// combinators can be applied to the chunks. Each chunk is owned by the caller; to avoid
+/// allocating a new buffer for every chunk, hand the chunks back with
+/// [`ChunkStream::recycle`] once they are processed.
+///
+/// ```no_run
+/// # use std::sync::Arc;
+/// # use async_1brc::reader::{ChunkStream, RowsReader};
+/// use futures::StreamExt;
+///
+/// # async fn example(reader: Arc<RowsReader>) {
+/// let mut chunks = ChunkStream::new(reader);
+///
+/// while let Some(chunk) = chunks.next().await {
+///     println!("Found {} bytes.", chunk.len());
+///     chunks.recycle(chunk);
+/// }
+/// # }
+/// ```
+pub struct ChunkStream {
+    reader: Arc<RowsReader>,
+    spare: Option<Vec<u8>>,
+    pending: Option<Fill>,
+    closed: bool,
+}
+
+impl ChunkStream {
+    /// Create a new [`ChunkStream`] over the chunks of `reader`.
+    pub fn new(reader: Arc<RowsReader>) -> Self {
+        Self {
+            reader,
+            spare: None,
+            pending: None,
+            closed: false,
+        }
+    }
+
+    /// Hand a processed chunk back, to be reused by the reader for the next chunk.
+    pub fn recycle(&mut self, chunk: Vec<u8>) {
+        self.spare = Some(chunk);
+    }
+}
+
+impl Stream for ChunkStream {
+    type Item = Vec<u8>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        if this.closed {
+            return Poll::Ready(None);
+        }
+
+        let pending = this.pending.get_or_insert_with(|| {
+            let reader = Arc::clone(&this.reader);
+            let buffer = this
+                .spare
+                .take()
+                .unwrap_or_else(|| Vec::with_capacity(reader.max_chunk_size()));
+
+            Box::pin(async move { reader.fill(buffer).await })
+        });
+
+        let chunk = std::task::ready!(pending.as_mut().poll(cx));
+        this.pending = None;
+        this.closed = chunk.is_none();
+
+        Poll::Ready(chunk)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures::StreamExt;
+
+    #[tokio::test]
+    async fn stream_chunks() {
+        let input = "jack;1.2\njill;-3.4\njack;56.7\nbob;0.0\n".repeat(100);
+        let reader = Arc::new(RowsReader::with_chunk_sizes(64, 128).with_additional_buffers(2));
+
+        let read_task = {
+            let reader = Arc::clone(&reader);
+            let input = input.clone();
+            tokio::spawn(async move { reader.read(input.as_bytes()).await })
+        };
+
+        let chunks = ChunkStream::new(Arc::clone(&reader))
+            .map(|chunk| String::from_utf8(chunk).unwrap())
+            .collect::<Vec<_>>()
+            .await;
+        read_task.await.unwrap().unwrap();
+
+        assert!(chunks.len() > 1);
+        assert!(chunks.iter().all(|chunk| chunk.ends_with('\n')));
+        assert_eq!(chunks.concat(), input);
+    }
+}
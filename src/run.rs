@@ -0,0 +1,1431 @@
+//! Aggregate a 1BRC input in a single call.
+//!
+//! This wires the [`RowsReader`] to the consumers for the `main` binary, and for embedding the
+//! aggregator in other crates:
+//!
+//! ```no_run
+//! # async fn example() -> std::io::Result<()> {
+//! let options = async_1brc::RunOptions::new("measurements.txt").with_output("output.txt");
+//! let report = async_1brc::run(options).await?;
+//! println!("{} bytes in {:?}", report.bytes_read, report.elapsed);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Every failure of the run is returned as an [`RunError`], along with the [`RunReport`] of how far
+//! the run went where there is one.
+
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tokio::io::AsyncBufRead;
+
+use crate::config;
+use crate::parser::{
+    self,
+    checkpoint::Checkpoints,
+    engine::ParserEngine,
+    models::StationRecords,
+    rejects::Rejects,
+    spill::{SpillDir, SpillOptions},
+    units::Unit,
+    verify::OutputError,
+};
+use crate::reader::{
+    cache::Readahead,
+    func::run_isolated,
+    recovery::{ErrorPolicy, RetryPolicy},
+    sample::{sample_ranges, SampledReader},
+    RowsReader,
+};
+
+#[cfg(feature = "assert")]
+use crate::assertion::{self, MismatchReport};
+
+#[cfg(feature = "mem-stats")]
+use crate::mem_stats::{self, Stage};
+
+/// A function called with the reader of a run once it is created, before the input is read,
+/// e.g. to report its metrics while the run goes on; see [`RunOptions::with_reader_hook`].
+#[derive(Clone)]
+pub struct ReaderHook(Arc<ReaderHookFn>);
+
+/// The function of a [`ReaderHook`].
+type ReaderHookFn = dyn Fn(&Arc<RowsReader>) + Send + Sync;
+
+impl std::fmt::Debug for ReaderHook {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ReaderHook(..)")
+    }
+}
+
+impl PartialEq for ReaderHook {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+/// The options of a single run of [`run`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunOptions {
+    /// The path of the input file.
+    pub file: PathBuf,
+
+    /// The path to export the results to in the 1BRC format, if any.
+    pub output: Option<PathBuf>,
+
+    /// The number of consumers parsing the chunks.
+    pub threads: usize,
+
+    /// The size of the chunks read from the file.
+    pub chunk_size: usize,
+
+    /// The maximum size of a chunk, including the end of its last line.
+    pub max_chunk_size: usize,
+
+    /// The parser used by the consumers.
+    pub engine: ParserEngine,
+
+    /// The number of shards the records of the consumers are merged in, in parallel.
+    pub merge_shards: usize,
+
+    /// The longest station name expected, which the reader keeps room for at the end of each
+    /// chunk.
+    pub max_name_length: usize,
+
+    /// Validate every line, by panicking in the consumers on the first invalid one, e.g. with a
+    /// name longer than `max_name_length` or a malformed value.
+    pub strict: bool,
+
+    /// Write the invalid lines to this file with their byte offsets, instead of parsing them,
+    /// and carry on with the run.
+    pub rejects: Option<PathBuf>,
+
+    /// Merge the station names which only differ by their Unicode normal form.
+    #[cfg(feature = "normalize")]
+    pub normalize_names: bool,
+
+    /// Only aggregate the stations matching this filter, if any.
+    #[cfg(feature = "filter")]
+    pub filter: Option<parser::filter::StationFilter>,
+
+    /// The hint given to the kernel to read ahead the input file.
+    pub readahead: Readahead,
+
+    /// Read the input on a dedicated thread with its own runtime, instead of the current
+    /// runtime shared with the consumers.
+    pub isolated_reader: bool,
+
+    /// How many times, and after how long, a failed read of the input is retried.
+    pub retry_policy: RetryPolicy,
+
+    /// What happens once a read of the input has failed every retry.
+    pub error_policy: ErrorPolicy,
+
+    /// When and where the records of the consumers are spilled to the disk, if ever.
+    pub spill: Option<SpillOptions>,
+
+    /// Save the records aggregated so far to this file every `checkpoint_interval` bytes of
+    /// input, if anywhere.
+    pub checkpoint: Option<PathBuf>,
+
+    /// The bytes of input read between two checkpoints.
+    pub checkpoint_interval: u64,
+
+    /// Resume from the checkpoint at this path, and keep checkpointing to it unless
+    /// `checkpoint` is set.
+    pub resume: Option<PathBuf>,
+
+    /// Read the exported results back, and verify them against the records.
+    pub verify_output: bool,
+
+    /// Aggregate only about this fraction of the input, e.g. `0.01`, in windows of a chunk
+    /// spread evenly across it, and scale the counts of the records back up to the whole
+    /// input; the results are approximate.
+    pub sample: Option<f64>,
+
+    /// Group the records by the first this many `;`-separated fields before the value of each
+    /// line, instead of by all of them; see [`parser::key::Fields`].
+    pub group_by: Option<usize>,
+
+    /// The unit the results are exported in.
+    pub unit: Unit,
+
+    /// The expected output to match the exported results against, if any.
+    #[cfg(feature = "assert")]
+    pub baseline: Option<PathBuf>,
+
+    /// Called with the reader once it is created, if set.
+    pub reader_hook: Option<ReaderHook>,
+}
+
+impl RunOptions {
+    /// Create a new [`RunOptions`] reading from `file`, with the default settings of the
+    /// `main` binary and without exporting the results.
+    pub fn new(file: impl Into<PathBuf>) -> Self {
+        Self {
+            file: file.into(),
+            output: None,
+            threads: config::NUMBER_OF_THREADS,
+            chunk_size: config::CHUNK_SIZE,
+            max_chunk_size: config::MAX_CHUNK_SIZE,
+            engine: ParserEngine::default(),
+            merge_shards: config::MERGE_SHARDS,
+            max_name_length: config::MAX_NAME_LENGTH,
+            strict: false,
+            rejects: None,
+            #[cfg(feature = "normalize")]
+            normalize_names: false,
+            #[cfg(feature = "filter")]
+            filter: None,
+            readahead: Readahead::default(),
+            isolated_reader: false,
+            retry_policy: RetryPolicy::default(),
+            error_policy: ErrorPolicy::default(),
+            spill: None,
+            checkpoint: None,
+            checkpoint_interval: config::CHECKPOINT_INTERVAL,
+            resume: None,
+            verify_output: false,
+            sample: None,
+            group_by: None,
+            unit: Unit::default(),
+            #[cfg(feature = "assert")]
+            baseline: None,
+            reader_hook: None,
+        }
+    }
+
+    /// Export the results to `output` in the 1BRC format.
+    pub fn with_output(mut self, output: impl Into<PathBuf>) -> Self {
+        self.output = Some(output.into());
+        self
+    }
+
+    /// Set the number of consumers parsing the chunks.
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    /// Set the size of the chunks, and the maximum size of a chunk.
+    pub fn with_chunk_sizes(mut self, chunk_size: usize, max_chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size;
+        self.max_chunk_size = max_chunk_size;
+        self
+    }
+
+    /// Set the parser used by the consumers.
+    pub fn with_engine(mut self, engine: ParserEngine) -> Self {
+        self.engine = engine;
+        self
+    }
+
+    /// Partition the records of each consumer into `merge_shards` shards by the hash of the
+    /// names, rounded up to a power of two, so that the shards are merged in parallel instead
+    /// of one consumer after the other; see [`parser::sharded`].
+    pub fn with_merge_shards(mut self, merge_shards: usize) -> Self {
+        self.merge_shards = merge_shards;
+        self
+    }
+
+    /// Set the longest station name expected, [`config::MAX_NAME_LENGTH`] by default.
+    pub fn with_max_name_length(mut self, max_name_length: usize) -> Self {
+        self.max_name_length = max_name_length;
+        self
+    }
+
+    /// Validate every line, rejecting the names longer than the maximum length and the
+    /// malformed or out of range values, instead of aggregating them.
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Save the records aggregated so far to `checkpoint` every `checkpoint_interval` bytes of
+    /// input, so that an interrupted run can be resumed with [`RunOptions::with_resume`].
+    ///
+    /// The checkpoints cannot be combined with spilling.
+    pub fn with_checkpoints(mut self, checkpoint: impl Into<PathBuf>) -> Self {
+        self.checkpoint = Some(checkpoint.into());
+        self
+    }
+
+    /// Set the bytes of input read between two checkpoints.
+    pub fn with_checkpoint_interval(mut self, checkpoint_interval: u64) -> Self {
+        self.checkpoint_interval = checkpoint_interval;
+        self
+    }
+
+    /// Resume from the checkpoint at `resume`, seeking the input to its offset and starting
+    /// from its records, then keep checkpointing to it.
+    pub fn with_resume(mut self, resume: impl Into<PathBuf>) -> Self {
+        self.resume = Some(resume.into());
+        self
+    }
+
+    /// Write the invalid lines to `rejects` with their byte offsets, only aggregating the valid
+    /// ones, instead of aggregating whatever the invalid lines parse into.
+    pub fn with_rejects(mut self, rejects: impl Into<PathBuf>) -> Self {
+        self.rejects = Some(rejects.into());
+        self
+    }
+
+    /// Merge the station names which only differ by their Unicode normal form, e.g. `é` as one
+    /// code point or as `e` and a combining accent, under their NFC form.
+    #[cfg(feature = "normalize")]
+    pub fn with_normalize_names(mut self, normalize_names: bool) -> Self {
+        self.normalize_names = normalize_names;
+        self
+    }
+
+    /// Only aggregate the stations matching `filter`, skipping the values of the others right
+    /// after their names are found; see [`parser::filter`].
+    #[cfg(feature = "filter")]
+    pub fn with_filter(mut self, filter: parser::filter::StationFilter) -> Self {
+        self.filter = Some(filter);
+        self
+    }
+
+    /// Set the hint given to the kernel to read ahead the input file.
+    pub fn with_readahead(mut self, readahead: Readahead) -> Self {
+        self.readahead = readahead;
+        self
+    }
+
+    /// Read the input on a dedicated thread with its own runtime, so that the system calls of
+    /// the reader do not compete with the consumers for the worker threads.
+    pub fn with_isolated_reader(mut self, isolated_reader: bool) -> Self {
+        self.isolated_reader = isolated_reader;
+        self
+    }
+
+    /// Set how many times, and after how long, a failed read of the input is retried.
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
+    }
+
+    /// Set what happens once a read of the input has failed every retry; with
+    /// [`ErrorPolicy::SkipChunk`] or [`ErrorPolicy::FlushPartial`], the records returned may
+    /// only cover part of the input.
+    pub fn with_error_policy(mut self, error_policy: ErrorPolicy) -> Self {
+        self.error_policy = error_policy;
+        self
+    }
+
+    /// Spill the records of each consumer to the disk whenever they grow past the threshold of
+    /// `spill`, and merge the spills at the end, keeping the memory bounded on inputs with
+    /// many more distinct stations than the 1BRC.
+    ///
+    /// The results are then exported straight from the spills to the output, without being
+    /// kept in the [`RunReport`]; without an output, they are merged back into its records.
+    pub fn with_spill(mut self, spill: SpillOptions) -> Self {
+        self.spill = Some(spill);
+        self
+    }
+
+    /// Read the exported results back, and verify that they match the records, i.e. every
+    /// station in order with the same values, before the run succeeds; this requires an output
+    /// to be set with [`RunOptions::with_output`].
+    pub fn with_verify_output(mut self, verify_output: bool) -> Self {
+        self.verify_output = verify_output;
+        self
+    }
+
+    /// Aggregate only about `rate` of the input, e.g. `0.01`, for approximate results; see
+    /// [`crate::reader::sample`]. This cannot be combined with the checkpoints.
+    pub fn with_sample(mut self, rate: f64) -> Self {
+        self.sample = Some(rate);
+        self
+    }
+
+    /// Group the records by the first `fields` fields of each line, joined by their `;`, e.g.
+    /// `station;sensor` of `station;sensor;reading;12.3` with `2`. The records are regrouped
+    /// once the input is aggregated, so this cannot be combined with spilling.
+    pub fn with_group_by(mut self, fields: usize) -> Self {
+        self.group_by = Some(fields);
+        self
+    }
+
+    /// Export the results in `unit` rather than in degrees Celsius; the records of the
+    /// [`RunReport`] are still kept in tenths of a degree Celsius.
+    pub fn with_unit(mut self, unit: Unit) -> Self {
+        self.unit = unit;
+        self
+    }
+
+    /// Match the exported results against the expected output at `baseline`; this requires an
+    /// output to be set with [`RunOptions::with_output`].
+    #[cfg(feature = "assert")]
+    pub fn with_baseline(mut self, baseline: impl Into<PathBuf>) -> Self {
+        self.baseline = Some(baseline.into());
+        self
+    }
+
+    /// Call `hook` with the reader of the run once it is created, before the input is read,
+    /// e.g. to serve or record its metrics while the run goes on; the reader can be kept past
+    /// the end of the run.
+    pub fn with_reader_hook(
+        mut self,
+        hook: impl Fn(&Arc<RowsReader>) + Send + Sync + 'static,
+    ) -> Self {
+        self.reader_hook = Some(ReaderHook(Arc::new(hook)));
+        self
+    }
+}
+
+/// The summary of a run, also attached to its [`RunError`]s as far as the run went.
+#[derive(Debug, Clone, Default)]
+pub struct RunReport {
+    /// The records aggregated; only covering part of the input if `partial`, and empty if a
+    /// consumer failed, or if the records were spilled and exported from the spills.
+    pub records: StationRecords,
+
+    /// The time taken to read, aggregate, and export or match the results.
+    pub elapsed: Duration,
+
+    /// The number of bytes read from the input.
+    pub bytes_read: u64,
+
+    /// The number of chunks handed to the consumers.
+    pub chunks: usize,
+
+    /// The number of records parsed by the consumers.
+    pub records_parsed: u64,
+
+    /// The number of failed reads of the input retried.
+    pub read_retries: usize,
+
+    /// The number of chunks dropped after a failed read.
+    pub chunks_skipped: usize,
+
+    /// The number of invalid lines written to the rejects file instead of being aggregated.
+    pub lines_rejected: u64,
+
+    /// The offset of the input the run resumed from, 0 unless it was resumed; the bytes read
+    /// are counted from it.
+    pub resumed_from: u64,
+
+    /// The number of checkpoints saved.
+    pub checkpoints: usize,
+
+    /// Whether some of the input is missing from the records.
+    pub partial: bool,
+
+    /// The fraction of the input aggregated if it was sampled, the counts of the records being
+    /// scaled up by its inverse.
+    pub sampled: Option<f64>,
+
+    /// The number of times the records of a consumer were spilled to the disk.
+    pub spills: usize,
+
+    /// The number of stations of the exported results verified against the records, if
+    /// verified.
+    pub verified: Option<usize>,
+}
+
+impl RunReport {
+    /// Summarize the run of `reader` started at `start`.
+    fn new(reader: &RowsReader, records: StationRecords, start: Instant) -> Self {
+        Self {
+            records,
+            elapsed: start.elapsed(),
+            bytes_read: reader.bytes_read(),
+            chunks: reader.chunks_exported(),
+            records_parsed: reader.records_parsed(),
+            read_retries: reader.read_retries(),
+            chunks_skipped: reader.chunks_skipped(),
+            lines_rejected: reader.lines_rejected(),
+            resumed_from: reader.checkpoints().map_or(0, Checkpoints::start),
+            checkpoints: reader.checkpoints().map_or(0, Checkpoints::written),
+            partial: reader.is_partial(),
+            sampled: None,
+            spills: 0,
+            verified: None,
+        }
+    }
+
+    /// The throughput of the run in bytes read per second.
+    pub fn throughput(&self) -> f64 {
+        self.bytes_read as f64 / self.elapsed.as_secs_f64()
+    }
+}
+
+/// Why a run failed.
+#[derive(Debug)]
+pub enum RunError {
+    /// The input could not be opened.
+    Open(std::io::Error),
+
+    /// The checkpoints could not be set up, e.g. as the checkpoint to resume from could not be
+    /// read, or was taken over another input.
+    Checkpoints(std::io::Error),
+
+    /// A read of the input failed every retry with [`ErrorPolicy::Abort`]; the report holds the
+    /// records of the chunks read before it.
+    Read {
+        error: std::io::Error,
+        report: Box<RunReport>,
+    },
+
+    /// A consumer panicked, e.g. on an invalid line in strict mode.
+    Consumer {
+        message: String,
+        report: Box<RunReport>,
+    },
+
+    /// The directory of the spills could not be created, e.g. as spilling was combined with
+    /// the grouping, or the spills could not be merged back into the records.
+    Spill {
+        error: std::io::Error,
+        report: Box<RunReport>,
+    },
+
+    /// The rejects file could not be created or written to.
+    Rejects {
+        error: std::io::Error,
+        report: Box<RunReport>,
+    },
+
+    /// A checkpoint could not be saved; the run went on without it.
+    Checkpoint {
+        error: std::io::Error,
+        report: Box<RunReport>,
+    },
+
+    /// The results could not be exported.
+    Export {
+        error: std::io::Error,
+        report: Box<RunReport>,
+    },
+
+    /// The exported results do not match the records they were exported from.
+    Verify {
+        error: OutputError,
+        report: Box<RunReport>,
+    },
+
+    /// The exported results do not match the baseline.
+    #[cfg(feature = "assert")]
+    Mismatch {
+        mismatch: MismatchReport,
+        report: Box<RunReport>,
+    },
+}
+
+impl RunError {
+    /// The report of the run as far as it went, unless the input could not even be opened.
+    pub fn report(&self) -> Option<&RunReport> {
+        match self {
+            Self::Open(_) | Self::Checkpoints(_) => None,
+            Self::Read { report, .. }
+            | Self::Consumer { report, .. }
+            | Self::Spill { report, .. }
+            | Self::Rejects { report, .. }
+            | Self::Checkpoint { report, .. }
+            | Self::Export { report, .. }
+            | Self::Verify { report, .. } => Some(report),
+            #[cfg(feature = "assert")]
+            Self::Mismatch { report, .. } => Some(report),
+        }
+    }
+
+    /// The exit code of a command line run failing with this error:
+    /// [`config::MISMATCH_EXIT_CODE`] if the results do not match the baseline, and
+    /// [`config::FAILURE_EXIT_CODE`] otherwise.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            #[cfg(feature = "assert")]
+            Self::Mismatch { .. } => config::MISMATCH_EXIT_CODE,
+            _ => config::FAILURE_EXIT_CODE,
+        }
+    }
+}
+
+impl std::fmt::Display for RunError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Open(error) => write!(f, "Could not open the input: {}", error),
+            Self::Checkpoints(error) => write!(f, "Could not set up the checkpoints: {}", error),
+            Self::Read { error, .. } => write!(f, "Could not read the input: {}", error),
+            Self::Consumer { message, .. } => write!(f, "A consumer failed: {}", message),
+            Self::Spill { error, .. } => write!(f, "Could not spill the records: {}", error),
+            Self::Rejects { error, .. } => {
+                write!(f, "Could not write the rejected lines: {}", error)
+            }
+            Self::Checkpoint { error, .. } => write!(f, "Could not save a checkpoint: {}", error),
+            Self::Export { error, .. } => write!(f, "Could not export the results: {}", error),
+            Self::Verify { error, .. } => write!(f, "The exported results are wrong: {}", error),
+            #[cfg(feature = "assert")]
+            Self::Mismatch { mismatch, .. } => write!(f, "{}", mismatch),
+        }
+    }
+}
+
+impl std::error::Error for RunError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Open(error)
+            | Self::Checkpoints(error)
+            | Self::Read { error, .. }
+            | Self::Spill { error, .. }
+            | Self::Rejects { error, .. }
+            | Self::Checkpoint { error, .. }
+            | Self::Export { error, .. } => Some(error),
+            Self::Verify { error, .. } => Some(error),
+            Self::Consumer { .. } => None,
+            #[cfg(feature = "assert")]
+            Self::Mismatch { mismatch, .. } => Some(mismatch),
+        }
+    }
+}
+
+impl From<RunError> for std::io::Error {
+    fn from(error: RunError) -> Self {
+        match error {
+            RunError::Open(error)
+            | RunError::Checkpoints(error)
+            | RunError::Read { error, .. }
+            | RunError::Spill { error, .. }
+            | RunError::Rejects { error, .. }
+            | RunError::Checkpoint { error, .. }
+            | RunError::Export { error, .. } => error,
+            error => std::io::Error::other(error),
+        }
+    }
+}
+
+/// The message of a consumer that panicked, or why it did not finish otherwise.
+pub(crate) fn consumer_failure(error: tokio::task::JoinError) -> String {
+    match error.try_into_panic() {
+        Ok(panic) => panic
+            .downcast_ref::<String>()
+            .cloned()
+            .or_else(|| {
+                panic
+                    .downcast_ref::<&str>()
+                    .map(|message| message.to_string())
+            })
+            .unwrap_or_else(|| "the consumer panicked".to_owned()),
+        Err(error) => error.to_string(),
+    }
+}
+
+/// What a run leaves behind for the next run over a similar input, so that it starts warm.
+#[derive(Default)]
+pub(crate) struct Warm {
+    /// The reader of the previous run, holding its spent buffers.
+    pub(crate) reader: Option<Arc<RowsReader>>,
+
+    /// The records of the previous run, whose table and names are reused.
+    pub(crate) records: StationRecords,
+}
+
+/// Read and aggregate the input described by `options`, exporting the results if requested.
+///
+/// The consumers are spawned onto the current tokio runtime, which should be multi-threaded
+/// for them to run in parallel.
+pub async fn run(options: RunOptions) -> Result<RunReport, RunError> {
+    run_warm(options, &mut Warm::default()).await
+}
+
+/// Like [`run`], reusing the buffers and the records of the previous run in `warm`, if any,
+/// and leaving those of this run in it for the next.
+///
+/// The records are only reused without checkpoints, spilling or merge shards.
+pub(crate) async fn run_warm(options: RunOptions, warm: &mut Warm) -> Result<RunReport, RunError> {
+    let start = Instant::now();
+    let mut file =
+        crate::reader::cache::open(&options.file, options.readahead).map_err(RunError::Open)?;
+
+    // The hint is only an optimization, and the run is valid without it.
+    let _ = crate::reader::cache::advise_file(&file, options.readahead);
+
+    let checkpoints = checkpoints(&options, &mut file).map_err(RunError::Checkpoints)?;
+
+    if let Some(rate) = options.sample {
+        return run_sampled(file, rate, options, warm, start).await;
+    }
+
+    let file = tokio::fs::File::from_std(file);
+    let input = tokio::io::BufReader::with_capacity(options.chunk_size, file);
+
+    read_warm(input, checkpoints, options, warm, start).await
+}
+
+/// Read a sample of `file` at `rate`, and scale the counts of the records up to the whole
+/// file.
+async fn run_sampled(
+    mut file: std::fs::File,
+    rate: f64,
+    options: RunOptions,
+    warm: &mut Warm,
+    start: Instant,
+) -> Result<RunReport, RunError> {
+    let ranges = file.metadata().and_then(|metadata| {
+        sample_ranges(&mut file, metadata.len(), options.chunk_size as u64, rate)
+            .map(|ranges| (metadata.len(), ranges))
+    });
+    let (len, ranges) = ranges.map_err(RunError::Open)?;
+    let sampled: u64 = ranges.iter().map(|range| range.end - range.start).sum();
+
+    let input = SampledReader::new(tokio::fs::File::from_std(file), ranges);
+    let input = tokio::io::BufReader::with_capacity(options.chunk_size, input);
+
+    let mut report = read_warm(input, None, options, warm, start).await?;
+    if sampled > 0 {
+        report.records.scale(len as f64 / sampled as f64);
+        report.sampled = Some(sampled as f64 / len as f64);
+    }
+
+    Ok(report)
+}
+
+/// Read and aggregate `input` with `options` and `checkpoints`, starting warm.
+async fn read_warm(
+    input: impl AsyncBufRead + Unpin + Send + 'static,
+    checkpoints: Option<Checkpoints>,
+    options: RunOptions,
+    warm: &mut Warm,
+    start: Instant,
+) -> Result<RunReport, RunError> {
+    let reader = new_reader(&options, checkpoints, warm.reader.take().as_deref(), start)?;
+    warm.reader = Some(Arc::clone(&reader));
+
+    let mut records = std::mem::take(&mut warm.records);
+    records.reset();
+
+    let read_task = {
+        let reader = Arc::clone(&reader);
+        async move { reader.read(input).await }
+    };
+
+    #[cfg(feature = "mem-stats")]
+    let read_task = mem_stats::in_stage(Stage::Reader, read_task);
+
+    #[cfg(feature = "console")]
+    let read_task = tracing::Instrument::instrument(read_task, tracing::info_span!("reader"));
+
+    if options.isolated_reader {
+        aggregate(reader, run_isolated(read_task), &options, records, start).await
+    } else {
+        aggregate(reader, read_task, &options, records, start).await
+    }
+}
+
+/// Read and aggregate any `input` in the 1BRC format, such as a socket or a decompressor,
+/// instead of the file of `options`, exporting the results if requested.
+///
+/// The rest of `options` applies as in [`run`]; `options.file` is ignored, and so is
+/// `options.isolated_reader`, since `input` may borrow from the current task, and so are the
+/// checkpoints and the sampling, which need a file to seek.
+pub async fn run_from(
+    input: impl AsyncBufRead + Unpin,
+    options: RunOptions,
+) -> Result<RunReport, RunError> {
+    let start = Instant::now();
+    let reader = new_reader(&options, None, None, start)?;
+
+    aggregate(
+        Arc::clone(&reader),
+        reader.read(input),
+        &options,
+        StationRecords::default(),
+        start,
+    )
+    .await
+}
+
+/// The checkpoints described by `options` of a run over `file`, seeking it to the checkpoint
+/// resumed from, if any.
+fn checkpoints(
+    options: &RunOptions,
+    file: &mut std::fs::File,
+) -> std::io::Result<Option<Checkpoints>> {
+    let Some(path) = options.checkpoint.as_ref().or(options.resume.as_ref()) else {
+        return Ok(None);
+    };
+
+    if options.spill.is_some() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "the checkpoints cannot be combined with spilling",
+        ));
+    } else if options.sample.is_some() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "the checkpoints cannot be combined with sampling",
+        ));
+    }
+
+    let checkpoints = Checkpoints::new(path, options.checkpoint_interval, file.metadata()?.len());
+    match &options.resume {
+        Some(resume) => checkpoints.resume_from(resume, file).map(Some),
+        None => Ok(Some(checkpoints)),
+    }
+}
+
+/// Create the reader described by `options`, with `checkpoints` and its rejects file if any,
+/// taking over the buffers of the `previous` reader if any.
+fn new_reader(
+    options: &RunOptions,
+    checkpoints: Option<Checkpoints>,
+    previous: Option<&RowsReader>,
+    start: Instant,
+) -> Result<Arc<RowsReader>, RunError> {
+    #[cfg(feature = "mem-stats")]
+    let _stage = Stage::Reader.enter();
+
+    let reader = RowsReader::with_chunk_sizes(options.chunk_size, options.max_chunk_size)
+        .with_max_name_length(options.max_name_length)
+        .with_strict(options.strict)
+        .with_retry_policy(options.retry_policy)
+        .with_error_policy(options.error_policy);
+
+    #[cfg(feature = "normalize")]
+    let reader = reader.with_normalize_names(options.normalize_names);
+
+    #[cfg(feature = "filter")]
+    let reader = match &options.filter {
+        Some(filter) => reader.with_filter(filter.clone()),
+        None => reader,
+    };
+
+    let reader = match checkpoints {
+        Some(checkpoints) => reader.with_checkpoints(checkpoints),
+        None => reader,
+    };
+
+    let reader = match &options.rejects {
+        Some(path) => match Rejects::create(path) {
+            Ok(rejects) => reader.with_rejects(rejects),
+            Err(error) => {
+                let report = Box::new(RunReport::new(&reader, StationRecords::default(), start));
+                return Err(RunError::Rejects { error, report });
+            }
+        },
+        None => reader,
+    };
+
+    let reader = Arc::new(match previous {
+        Some(previous) => reader.with_buffers_of(previous),
+        None => reader.with_additional_buffers(config::ADDITIONAL_BUFFERS),
+    });
+
+    if let Some(ReaderHook(hook)) = &options.reader_hook {
+        hook(&reader);
+    }
+
+    Ok(reader)
+}
+
+/// Consume the chunks of `reader` while `read_task` fills it, merging them into `records`
+/// unless checkpointed, spilled or sharded, and exporting the results if requested.
+async fn aggregate(
+    reader: Arc<RowsReader>,
+    read_task: impl Future<Output = std::io::Result<()>>,
+    options: &RunOptions,
+    records: StationRecords,
+    start: Instant,
+) -> Result<RunReport, RunError> {
+    let spills = match &options.spill {
+        Some(_) if options.group_by.is_some() => {
+            let error = std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "the grouping cannot be combined with spilling",
+            );
+            let report = Box::new(RunReport::new(&reader, StationRecords::default(), start));
+            return Err(RunError::Spill { error, report });
+        }
+        Some(spill) => match SpillDir::create(spill) {
+            Ok(spills) => Some(spills),
+            Err(error) => {
+                let report = Box::new(RunReport::new(&reader, StationRecords::default(), start));
+                return Err(RunError::Spill { error, report });
+            }
+        },
+        None => None,
+    };
+
+    let consumers = async {
+        match &spills {
+            Some(spills) => parser::task::spill_from_reader(
+                Arc::clone(&reader),
+                options.threads,
+                options.max_chunk_size,
+                options.engine,
+                spills,
+            )
+            .await
+            .map(|files| (StationRecords::default(), files)),
+            None if reader.checkpoints().is_some() => parser::task::checkpoint_from_reader(
+                Arc::clone(&reader),
+                options.threads,
+                options.max_chunk_size,
+                options.engine,
+            )
+            .await
+            .map(|records| (records, 0)),
+            None if options.merge_shards > 1 => parser::task::read_from_reader_sharded(
+                Arc::clone(&reader),
+                options.threads,
+                options.max_chunk_size,
+                options.engine,
+                options.merge_shards,
+            )
+            .await
+            .map(|sharded| (sharded.into_records(), 0)),
+            None => parser::task::read_from_reader_into(
+                Arc::clone(&reader),
+                options.threads,
+                options.max_chunk_size,
+                options.engine,
+                records,
+            )
+            .await
+            .map(|mut records| {
+                records.remove_unobserved();
+                (records, 0)
+            }),
+        }
+    };
+    let (read, consumed) = parser::task::read_and_consume(read_task, consumers).await;
+
+    let ((records, files), failure) = match consumed {
+        Ok(consumed) => (consumed, None),
+        Err(error) => (Default::default(), Some(consumer_failure(error))),
+    };
+    let mut report = Box::new(RunReport::new(&reader, records, start));
+    report.spills = files;
+
+    if let Some(fields) = options.group_by {
+        report.records.regroup(&parser::key::Fields(fields));
+    }
+
+    // A failed read is reported first, as it may have caused the consumers to fail.
+    if let Err(error) = read {
+        return Err(RunError::Read { error, report });
+    } else if let Some(message) = failure {
+        return Err(RunError::Consumer { message, report });
+    }
+
+    if let Some(Err(error)) = reader.rejects().map(Rejects::finish) {
+        return Err(RunError::Rejects { error, report });
+    }
+    if let Some(Err(error)) = reader.checkpoints().map(Checkpoints::finish) {
+        return Err(RunError::Checkpoint { error, report });
+    }
+
+    if let (Some(spills), None) = (&spills, &options.output) {
+        match spills.merge_records() {
+            Ok(records) => report.records = records,
+            Err(error) => return Err(RunError::Spill { error, report }),
+        }
+    }
+
+    let mut report = export(report, spills.as_ref(), options).await?;
+    report.elapsed = start.elapsed();
+    Ok(*report)
+}
+
+/// Export the records of `report`, or the `spills` if any, to the output of `options`, then
+/// verify and match them as requested; nothing is done without an output.
+pub(crate) async fn export(
+    mut report: Box<RunReport>,
+    spills: Option<&SpillDir>,
+    options: &RunOptions,
+) -> Result<Box<RunReport>, RunError> {
+    let Some(output) = &options.output else {
+        return Ok(report);
+    };
+
+    let export_task = async {
+        match spills {
+            Some(spills) => spills.export_file_in(output, options.unit),
+            None => {
+                report
+                    .records
+                    .try_export_file_in(output, options.unit)
+                    .await
+            }
+        }
+    };
+
+    #[cfg(feature = "mem-stats")]
+    let export_task = mem_stats::in_stage(Stage::Export, export_task);
+
+    if let Err(error) = export_task.await {
+        return Err(RunError::Export { error, report });
+    }
+
+    if options.verify_output {
+        let verified = match spills {
+            Some(spills) => spills.verify_file_in(output, options.unit),
+            None => report.records.verify_file_in(output, options.unit),
+        };
+        match verified {
+            Ok(stations) => report.verified = Some(stations),
+            Err(error) => return Err(RunError::Verify { error, report }),
+        }
+    }
+
+    #[cfg(feature = "assert")]
+    if let Some(baseline) = &options.baseline {
+        if let Err(mismatch) = assertion::match_files(output, baseline).await {
+            return Err(RunError::Mismatch { mismatch, report });
+        }
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::TestDir;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn run_and_export() {
+        let dir = TestDir::new("run_and_export");
+        let (input, output) = (dir.join("input.txt"), dir.join("output.txt"));
+        std::fs::write(&input, "jack;1.2\njill;-3.4\njack;5.6\n".repeat(1000)).unwrap();
+
+        let report = run(RunOptions::new(&input)
+            .with_output(&output)
+            .with_verify_output(true)
+            .with_threads(4)
+            .with_chunk_sizes(64, 64 + config::MAX_LINE_LENGTH))
+        .await
+        .unwrap();
+        let exported = std::fs::read_to_string(&output).unwrap();
+
+        assert_eq!(exported, "{jack=1.2/3.4/5.6, jill=-3.4/-3.4/-3.4}\n");
+        assert_eq!(exported, report.records.export_text());
+        assert_eq!(report.bytes_read, 28_000);
+        assert_eq!(report.records_parsed, 3000);
+        assert!(!report.partial);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn checkpoint_and_resume() {
+        let dir = TestDir::new("checkpoint_and_resume");
+        let (input, checkpoint) = (dir.join("input.txt"), dir.join("checkpoint.ckpt"));
+        let bytes: String = (0..3000)
+            .map(|line| format!("station{};{}.{}\n", line % 7, line % 50, line % 10))
+            .collect();
+        std::fs::write(&input, &bytes).unwrap();
+
+        let options = RunOptions::new(&input)
+            .with_threads(4)
+            .with_chunk_sizes(256, 256 + config::MAX_LINE_LENGTH)
+            .with_checkpoint_interval(4096);
+
+        let report = run(options.clone().with_checkpoints(&checkpoint))
+            .await
+            .unwrap();
+        assert!(report.checkpoints > 1);
+        assert_eq!(report.resumed_from, 0);
+
+        // The last checkpoint holds the records of exactly the lines before its offset.
+        let saved = parser::checkpoint::Checkpoint::read(&checkpoint).unwrap();
+        let offset = saved.offset as usize;
+        assert!(offset > 0 && offset < bytes.len());
+        assert_eq!(bytes.as_bytes()[offset - 1], b'\n');
+        assert_eq!(saved.input_len, bytes.len() as u64);
+
+        let before = run_from(&bytes.as_bytes()[..offset], options.clone())
+            .await
+            .unwrap();
+        assert_eq!(saved.records.export_text(), before.records.export_text());
+
+        let resumed = run(options.with_resume(&checkpoint)).await.unwrap();
+
+        assert_eq!(resumed.resumed_from, saved.offset);
+        assert_eq!(resumed.records.export_text(), report.records.export_text());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn run_sharded_merge() {
+        let input: String = (0..5000)
+            .map(|line| format!("station{};{}.{}\n", line % 397, line % 50, line % 10))
+            .collect();
+        let options = RunOptions::new("/nonexistent/measurements.txt")
+            .with_threads(4)
+            .with_chunk_sizes(256, 256 + config::MAX_LINE_LENGTH);
+
+        let merged = run_from(input.as_bytes(), options.clone()).await.unwrap();
+        let sharded = run_from(input.as_bytes(), options.with_merge_shards(8))
+            .await
+            .unwrap();
+
+        assert_eq!(sharded.records.stations(), 397);
+        assert_eq!(sharded.records, merged.records);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn run_from_bytes() {
+        let input = "jack;1.2\njill;-3.4\njack;5.6\n".repeat(1000);
+
+        let report = run_from(
+            input.as_bytes(),
+            RunOptions::new("/nonexistent/measurements.txt")
+                .with_threads(4)
+                .with_chunk_sizes(64, 64 + config::MAX_LINE_LENGTH)
+                .with_engine(ParserEngine::Memchr),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            report.records.export_text(),
+            "{jack=1.2/3.4/5.6, jill=-3.4/-3.4/-3.4}\n"
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn run_reader_hook() {
+        let hooked = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let options = RunOptions::new("/nonexistent/measurements.txt")
+            .with_chunk_sizes(64, 64 + config::MAX_LINE_LENGTH)
+            .with_reader_hook({
+                let hooked = Arc::clone(&hooked);
+                move |reader| hooked.lock().unwrap().push(Arc::clone(reader))
+            });
+        assert_eq!(options.clone(), options);
+
+        let input = "jack;1.2\njill;-3.4\n".repeat(100);
+        let report = run_from(input.as_bytes(), options).await.unwrap();
+
+        // The reader is kept by the hook past the end of the run.
+        let hooked = hooked.lock().unwrap();
+        assert_eq!(hooked.len(), 1);
+        assert_eq!(hooked[0].bytes_read(), report.bytes_read);
+        assert_eq!(report.bytes_read, input.len() as u64);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn run_isolated_reader() {
+        let dir = TestDir::new("run_isolated_reader");
+        let input = dir.join("input.txt");
+        std::fs::write(&input, "jack;1.2\njill;-3.4\njack;5.6\n".repeat(1000)).unwrap();
+
+        let report = run(RunOptions::new(&input)
+            .with_threads(4)
+            .with_chunk_sizes(64, 64 + config::MAX_LINE_LENGTH)
+            .with_isolated_reader(true))
+        .await;
+
+        assert_eq!(
+            report.unwrap().records.export_text(),
+            "{jack=1.2/3.4/5.6, jill=-3.4/-3.4/-3.4}\n"
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn run_sampled() {
+        let dir = TestDir::new("run_sampled");
+        let input = dir.join("input.txt");
+        std::fs::write(&input, "jack;1.2\njill;-3.4\njack;5.6\n".repeat(3000)).unwrap();
+
+        let options = RunOptions::new(&input)
+            .with_threads(2)
+            .with_chunk_sizes(256, 256 + config::MAX_LINE_LENGTH)
+            .with_sample(0.1);
+        let report = run(options.clone()).await.unwrap();
+        let again = run(options.clone()).await.unwrap();
+        let checkpointed = run(options.with_checkpoints("checkpoint.bin")).await;
+
+        // About a tenth of the input is read, the same each time, and the counts scaled up.
+        let fraction = report.sampled.unwrap();
+        assert!((0.05..0.15).contains(&fraction));
+        assert!(report.bytes_read < 9000);
+        assert_eq!(report.records, again.records);
+
+        let jack = report.records.get(&b"jack".into()).unwrap();
+        assert!((5400..6600).contains(&jack.count));
+        assert_eq!((jack.min, jack.max), (12, 56));
+        assert_eq!(
+            report.records.export_text(),
+            "{jack=1.2/3.4/5.6, jill=-3.4/-3.4/-3.4}\n"
+        );
+
+        assert!(matches!(checkpointed, Err(RunError::Checkpoints(_))));
+    }
+
+    #[cfg(feature = "filter")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn run_filtered() {
+        use crate::parser::filter::StationFilter;
+
+        let dir = TestDir::new("run_filtered");
+        let input = dir.join("input.txt");
+        std::fs::write(
+            &input,
+            "jack;1.2\njill;-3.4\njack;5.6\nbob;0.0\n".repeat(1000),
+        )
+        .unwrap();
+
+        let filter = StationFilter::new(
+            vec!["prefix:j".parse().unwrap()],
+            vec!["jill".parse().unwrap()],
+        );
+        let report = run(RunOptions::new(&input)
+            .with_threads(3)
+            .with_chunk_sizes(128, 128 + config::MAX_LINE_LENGTH)
+            .with_filter(filter))
+        .await
+        .unwrap();
+
+        assert_eq!(report.records.export_text(), "{jack=1.2/3.4/5.6}\n");
+        assert_eq!(report.records_parsed, 2000);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn run_grouped() {
+        let dir = TestDir::new("run_grouped");
+        let input = dir.join("input.txt");
+        std::fs::write(
+            &input,
+            "jack;s1;a;1.2\njack;s1;b;5.6\njack;s2;a;-3.4\n".repeat(1000),
+        )
+        .unwrap();
+
+        let options = RunOptions::new(&input)
+            .with_threads(3)
+            .with_chunk_sizes(128, 128 + config::MAX_LINE_LENGTH)
+            .with_group_by(2);
+        let report = run(options.clone()).await;
+        let sharded = run(options.clone().with_merge_shards(4)).await;
+        let spilled = run(options.with_spill(SpillOptions::new(1024))).await;
+
+        let expected = "{jack;s1=1.2/3.4/5.6, jack;s2=-3.4/-3.4/-3.4}\n";
+        assert_eq!(report.unwrap().records.export_text(), expected);
+        assert_eq!(sharded.unwrap().records.export_text(), expected);
+        assert!(matches!(spilled, Err(RunError::Spill { .. })));
+    }
+
+    #[tokio::test]
+    async fn run_tiny_inputs() {
+        for engine in [
+            ParserEngine::Scalar,
+            ParserEngine::Memchr,
+            ParserEngine::Batched,
+        ] {
+            for (input, expected) in [
+                ("", "{}\n"),
+                ("\n", "{}\n"),
+                ("jack;1.2\n", "{jack=1.2/1.2/1.2}\n"),
+                ("jack;1.2", "{jack=1.2/1.2/1.2}\n"),
+            ] {
+                let report = run_from(
+                    input.as_bytes(),
+                    RunOptions::new("/nonexistent/measurements.txt")
+                        .with_threads(4)
+                        .with_engine(engine),
+                )
+                .await
+                .unwrap();
+
+                assert_eq!(
+                    report.records.export_text(),
+                    expected,
+                    "{:?} with {}",
+                    input,
+                    engine
+                );
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn run_long_names() {
+        let name = "a".repeat(config::MAX_NAME_LENGTH + 1);
+        let input = format!("jack;1.2\n{};-3.4\n", name).repeat(100);
+        let options = RunOptions::new("/nonexistent/measurements.txt")
+            .with_threads(2)
+            .with_chunk_sizes(64, 256);
+
+        let report = run_from(input.as_bytes(), options.clone()).await.unwrap();
+        assert_eq!(report.records.iter().count(), 2);
+
+        let report = run_from(
+            input.as_bytes(),
+            options
+                .clone()
+                .with_max_name_length(name.len())
+                .with_strict(true),
+        )
+        .await
+        .unwrap();
+        assert_eq!(report.records.iter().count(), 2);
+
+        let error = run_from(input.as_bytes(), options.with_strict(true))
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(&error, RunError::Consumer { message, .. } if message.contains("name too long")),
+            "{}",
+            error
+        );
+    }
+
+    #[tokio::test]
+    async fn run_strict_values() {
+        for (line, reason) in [
+            ("jack;", "empty value"),
+            ("jack;--1.2", "malformed value"),
+            ("jack;1234.5", "value out of range"),
+        ] {
+            let input = format!("jack;1.2\n{}\njill;-3.4\n", line);
+            let options = RunOptions::new("/nonexistent/measurements.txt").with_threads(2);
+
+            let error = run_from(input.as_bytes(), options.with_strict(true))
+                .await
+                .unwrap_err();
+            let RunError::Consumer { message, report } = &error else {
+                panic!("unexpected error: {}", error);
+            };
+
+            assert!(report.bytes_read > 0);
+            assert!(message.contains(reason), "{}", message);
+            assert!(message.contains("at line 2"), "{}", message);
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn run_rejects() {
+        let input = "jack;1.2\njill;--3.4\nbob;0.0\njack;1234.5\n".repeat(100);
+        let dir = TestDir::new("run_rejects");
+        let path = dir.join("rejects.txt");
+        let options = RunOptions::new("/nonexistent/measurements.txt")
+            .with_threads(4)
+            .with_chunk_sizes(128, 128 + config::MAX_LINE_LENGTH)
+            .with_rejects(&path);
+
+        let report = run_from(input.as_bytes(), options).await.unwrap();
+        assert_eq!(report.lines_rejected, 200);
+        assert_eq!(
+            report.records.export_text(),
+            "{bob=0.0/0.0/0.0, jack=1.2/1.2/1.2}\n"
+        );
+
+        // Every rejected line is found at its offset in the input.
+        let rejects = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(rejects.lines().count(), 200);
+        for reject in rejects.lines() {
+            let [offset, _reason, line] = reject.splitn(3, '\t').collect::<Vec<_>>()[..] else {
+                panic!("malformed reject: {:?}", reject);
+            };
+            let offset = offset.parse::<usize>().unwrap();
+            assert!(input[offset..].starts_with(&format!("{}\n", line)));
+        }
+    }
+
+    #[cfg(feature = "normalize")]
+    #[tokio::test]
+    async fn run_normalized_names() {
+        let input = "Zu\u{308}rich;1.0\nZ\u{fc}rich;3.0\n".repeat(100);
+        let options = RunOptions::new("/nonexistent/measurements.txt")
+            .with_threads(2)
+            .with_chunk_sizes(64, 64 + config::MAX_LINE_LENGTH);
+
+        let report = run_from(input.as_bytes(), options.clone()).await.unwrap();
+        assert_eq!(report.records.iter().count(), 2);
+
+        let report = run_from(input.as_bytes(), options.with_normalize_names(true))
+            .await
+            .unwrap();
+        assert_eq!(report.records.export_text(), "{Z\u{fc}rich=1.0/2.0/3.0}\n");
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn run_spilled() {
+        let input = (0..1000)
+            .map(|index| format!("station {};{}.{}\n", index % 300, index % 100, index % 10))
+            .collect::<String>();
+        let dir = TestDir::new("run_spilled");
+        let output = dir.join("output.txt");
+        let options = RunOptions::new("/nonexistent/measurements.txt")
+            .with_threads(3)
+            .with_chunk_sizes(256, 256 + config::MAX_LINE_LENGTH);
+
+        let expected = run_from(input.as_bytes(), options.clone()).await.unwrap();
+        let options = options.with_spill(SpillOptions::new(50 * parser::spill::STATION_SIZE));
+
+        let report = run_from(input.as_bytes(), options.clone()).await.unwrap();
+        assert!(report.spills > 3);
+        assert_eq!(report.records, expected.records);
+
+        let report = run_from(
+            input.as_bytes(),
+            options.with_output(&output).with_verify_output(true),
+        )
+        .await
+        .unwrap();
+        let exported = std::fs::read_to_string(&output).unwrap();
+
+        assert_eq!(report.records.stations(), 0);
+        assert_eq!(exported, expected.records.export_text());
+    }
+
+    #[tokio::test]
+    async fn run_missing_file() {
+        let error = run(RunOptions::new("/nonexistent/measurements.txt"))
+            .await
+            .unwrap_err();
+
+        assert!(error.report().is_none());
+        assert_eq!(error.exit_code(), config::FAILURE_EXIT_CODE);
+        assert!(
+            matches!(error, RunError::Open(error) if error.kind() == std::io::ErrorKind::NotFound)
+        );
+    }
+
+    /// An input which fails every read after the first `ok` bytes.
+    struct BrokenInput {
+        ok: &'static [u8],
+    }
+
+    impl tokio::io::AsyncRead for BrokenInput {
+        fn poll_read(
+            mut self: std::pin::Pin<&mut Self>,
+            _cx: &mut std::task::Context<'_>,
+            buf: &mut tokio::io::ReadBuf<'_>,
+        ) -> std::task::Poll<std::io::Result<()>> {
+            if self.ok.is_empty() {
+                return std::task::Poll::Ready(Err(std::io::ErrorKind::BrokenPipe.into()));
+            }
+
+            let len = self.ok.len().min(buf.remaining());
+            buf.put_slice(&self.ok[..len]);
+            self.ok = &self.ok[len..];
+            std::task::Poll::Ready(Ok(()))
+        }
+    }
+
+    #[tokio::test]
+    async fn run_failed_read() {
+        let input = tokio::io::BufReader::with_capacity(
+            16,
+            BrokenInput {
+                ok: b"jack;1.2\njill;-3.4\njack;5.6\n",
+            },
+        );
+        let options = RunOptions::new("/nonexistent/measurements.txt")
+            .with_threads(2)
+            .with_chunk_sizes(16, 16 + config::MAX_LINE_LENGTH)
+            .with_retry_policy(RetryPolicy::none());
+
+        let error = run_from(input, options).await.unwrap_err();
+        let RunError::Read { error, report } = error else {
+            panic!("unexpected error: {}", error);
+        };
+
+        assert_eq!(error.kind(), std::io::ErrorKind::BrokenPipe);
+        assert!(report.partial);
+        assert_eq!(report.bytes_read, 28);
+    }
+}
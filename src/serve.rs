@@ -0,0 +1,305 @@
+//! Serve the results and the metrics of a run over HTTP, for dashboards to poll.
+//!
+//! `--serve <ADDRESS>` binds a minimal HTTP/1.1 server before the run starts, answering `GET`
+//! requests with JSON, one request per connection:
+//!
+//! - `/metrics`: the counters of the reader so far, the time elapsed, and whether the run is
+//!   complete;
+//! - `/results`: the stations with their minimum, mean and maximum, once the run is complete.
+//!   While it runs with checkpoints, the consumers merge their records after every chunk, so
+//!   a live snapshot of the records merged so far is served instead, flagged as incomplete;
+//!   otherwise, `503 Service Unavailable` is returned until the run completes.
+//!
+//! The server keeps answering once the run is complete, until the process is interrupted. It
+//! is not meant to be exposed beyond a trusted network: there is no authentication, and no
+//! TLS.
+
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, ToSocketAddrs};
+use tokio::task::JoinHandle;
+
+use crate::parser::aggregator::Aggregator;
+use crate::parser::func;
+use crate::parser::models::StationRecords;
+use crate::reader::RowsReader;
+
+/// The largest request read, headers included; anything longer is refused.
+const MAX_REQUEST_SIZE: usize = 8192;
+
+/// The state of the run, as shared with the server.
+struct State {
+    start: Instant,
+    reader: OnceLock<Arc<RowsReader>>,
+    /// The time taken by the run, once complete.
+    elapsed: OnceLock<Duration>,
+    /// The results rendered to JSON, once the run is complete.
+    results: OnceLock<String>,
+}
+
+/// An HTTP server of the results and the metrics of a run, answering on the runtime it was
+/// bound on until the runtime shuts down.
+pub struct ResultsServer {
+    state: Arc<State>,
+    address: SocketAddr,
+    task: JoinHandle<()>,
+}
+
+impl ResultsServer {
+    /// Bind the server to `address`, and start answering requests on the current runtime.
+    pub async fn bind(address: impl ToSocketAddrs) -> io::Result<Self> {
+        let listener = TcpListener::bind(address).await?;
+        let address = listener.local_addr()?;
+        let state = Arc::new(State {
+            start: Instant::now(),
+            reader: OnceLock::new(),
+            elapsed: OnceLock::new(),
+            results: OnceLock::new(),
+        });
+
+        let task = tokio::spawn(accept(listener, Arc::clone(&state)));
+
+        Ok(Self {
+            state,
+            address,
+            task,
+        })
+    }
+
+    /// The address the server is bound to, e.g. to find the port picked for port 0.
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Report the metrics of `reader`, and its live records if it checkpoints them; only the
+    /// first reader watched is reported.
+    pub fn watch(&self, reader: Arc<RowsReader>) {
+        let _ = self.state.reader.set(reader);
+    }
+
+    /// Mark the run as complete, and serve `records` as its results from now on; only the
+    /// first results published are served.
+    pub fn publish(&self, records: &StationRecords) {
+        let _ = self.state.elapsed.set(self.state.start.elapsed());
+        let _ = self.state.results.set(render_results(records, true));
+    }
+
+    /// Keep answering requests until the process is interrupted.
+    pub async fn serve_forever(self) {
+        let _ = self.task.await;
+    }
+}
+
+/// Answer every connection to `listener` in a task of its own.
+async fn accept(listener: TcpListener, state: Arc<State>) {
+    loop {
+        // A failed connection does not concern the others.
+        let Ok((stream, _)) = listener.accept().await else {
+            continue;
+        };
+
+        tokio::spawn(answer(stream, Arc::clone(&state)));
+    }
+}
+
+/// Read a single request from `stream`, and write its response.
+async fn answer(mut stream: TcpStream, state: Arc<State>) {
+    let mut request = Vec::new();
+    let mut buffer = [0; 1024];
+
+    while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+        match stream.read(&mut buffer).await {
+            Ok(0) | Err(_) => return,
+            Ok(read) => request.extend_from_slice(&buffer[..read]),
+        }
+
+        if request.len() > MAX_REQUEST_SIZE {
+            let _ = respond(&mut stream, "431 Request Header Fields Too Large", "{}").await;
+            return;
+        }
+    }
+
+    let (status, body) = route(&request, &state);
+    let _ = respond(&mut stream, status, &body).await;
+}
+
+/// The status and the body of the response to `request`.
+fn route(request: &[u8], state: &State) -> (&'static str, String) {
+    let line = request
+        .split(|&byte| byte == b'\r')
+        .next()
+        .unwrap_or_default();
+    let mut parts = line.split(|&byte| byte == b' ');
+    let (method, target) = (parts.next().unwrap_or_default(), parts.next());
+
+    let path = target.map(|target| {
+        target
+            .split(|&byte| byte == b'?')
+            .next()
+            .unwrap_or_default()
+    });
+    if method != b"GET" {
+        return (
+            "405 Method Not Allowed",
+            error("Only GET requests are served."),
+        );
+    }
+
+    match path {
+        Some(b"/metrics") => ("200 OK", render_metrics(state)),
+        Some(b"/results") => match state.results.get() {
+            Some(results) => ("200 OK", results.clone()),
+            None => match state.reader.get().and_then(|reader| reader.checkpoints()) {
+                Some(checkpoints) => ("200 OK", render_results(&checkpoints.snapshot(), false)),
+                None => (
+                    "503 Service Unavailable",
+                    error("The run is still in progress."),
+                ),
+            },
+        },
+        _ => (
+            "404 Not Found",
+            error("Only /metrics and /results are served."),
+        ),
+    }
+}
+
+/// Write a JSON response with `status`, closing the connection after it.
+async fn respond(stream: &mut TcpStream, status: &str, body: &str) -> io::Result<()> {
+    let head = format!(
+        "HTTP/1.1 {}\r\n\
+        Content-Type: application/json\r\n\
+        Content-Length: {}\r\n\
+        Access-Control-Allow-Origin: *\r\n\
+        Connection: close\r\n\r\n",
+        status,
+        body.len()
+    );
+
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(body.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+fn error(message: &str) -> String {
+    serde_json::json!({ "error": message }).to_string()
+}
+
+/// Render the metrics of the run to JSON.
+fn render_metrics(state: &State) -> String {
+    let elapsed = state
+        .elapsed
+        .get()
+        .copied()
+        .unwrap_or_else(|| state.start.elapsed());
+    let mut metrics = serde_json::json!({
+        "complete": state.results.get().is_some(),
+        "elapsed_seconds": elapsed.as_secs_f64(),
+    });
+
+    if let Some(reader) = state.reader.get() {
+        let bytes_read = reader.bytes_read();
+
+        metrics["bytes_read"] = bytes_read.into();
+        metrics["chunks"] = reader.chunks_exported().into();
+        metrics["records_parsed"] = reader.records_parsed().into();
+        metrics["lines_rejected"] = reader.lines_rejected().into();
+        metrics["read_retries"] = reader.read_retries().into();
+        metrics["chunks_skipped"] = reader.chunks_skipped().into();
+        metrics["queue_depth"] = reader.queue_depth().into();
+        metrics["partial"] = reader.is_partial().into();
+        metrics["bytes_per_second"] = (bytes_read as f64 / elapsed.as_secs_f64()).into();
+    }
+
+    metrics.to_string()
+}
+
+/// Render `records` to JSON, keyed by the station names in alphabetical order.
+fn render_results(records: &StationRecords, complete: bool) -> String {
+    let stations: serde_json::Map<_, _> = records
+        .iter_sorted()
+        .map(|(name, stats)| {
+            // The mean is rounded as in the exported text, so that the two agree.
+            let mean = format!("{:.1}", stats.emit().mean).parse::<f64>().unwrap();
+
+            (
+                func::bytes_to_string(name).into_owned(),
+                serde_json::json!({
+                    "min": stats.min as f64 / 10.0,
+                    "mean": mean,
+                    "max": stats.max as f64 / 10.0,
+                    "count": stats.count,
+                }),
+            )
+        })
+        .collect();
+
+    serde_json::json!({
+        "complete": complete,
+        "stations": stations,
+    })
+    .to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Request `path` from the server at `address`, returning the status line and the body.
+    async fn get(address: SocketAddr, path: &str) -> (String, serde_json::Value) {
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        stream
+            .write_all(format!("GET {} HTTP/1.1\r\nHost: test\r\n\r\n", path).as_bytes())
+            .await
+            .unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+
+        (
+            head.lines().next().unwrap().to_owned(),
+            serde_json::from_str(body).unwrap(),
+        )
+    }
+
+    #[tokio::test]
+    async fn serve_metrics_and_results() {
+        let server = ResultsServer::bind("127.0.0.1:0").await.unwrap();
+        let address = server.address();
+
+        let reader = Arc::new(RowsReader::new());
+        reader.add_records_parsed(3);
+        server.watch(reader);
+
+        let (status, metrics) = get(address, "/metrics").await;
+        assert_eq!(status, "HTTP/1.1 200 OK");
+        assert_eq!(metrics["complete"], false);
+        assert_eq!(metrics["records_parsed"], 3);
+
+        let (status, _) = get(address, "/results").await;
+        assert_eq!(status, "HTTP/1.1 503 Service Unavailable");
+
+        let mut records = StationRecords::new();
+        for (name, value) in [("jack", 12), ("jill", -34), ("jack", 57)] {
+            records.insert_borrowed(name.as_bytes(), value);
+        }
+        server.publish(&records);
+
+        let (status, results) = get(address, "/results?pretty").await;
+        assert_eq!(status, "HTTP/1.1 200 OK");
+        assert_eq!(results["complete"], true);
+        assert_eq!(
+            results["stations"]["jack"],
+            serde_json::json!({"min": 1.2, "mean": 3.5, "max": 5.7, "count": 2})
+        );
+        assert_eq!(results["stations"]["jill"]["mean"], -3.4);
+
+        let (status, _) = get(address, "/").await;
+        assert_eq!(status, "HTTP/1.1 404 Not Found");
+    }
+}
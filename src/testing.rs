@@ -0,0 +1,40 @@
+//! Helpers shared by the unit tests.
+
+use std::path::{Path, PathBuf};
+
+/// A temporary directory of its own for a test, removed with its files once dropped.
+///
+/// The directory is named after the process and the test, so that neither the tests running
+/// at the same time, nor those of another checkout, share any file.
+pub(crate) struct TestDir(PathBuf);
+
+impl TestDir {
+    /// Create the empty directory of the test `name`.
+    pub(crate) fn new(name: &str) -> Self {
+        let path =
+            std::env::temp_dir().join(format!("async-1brc-test-{}-{}", std::process::id(), name));
+
+        // A directory left behind by an earlier process of the same id.
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).expect("Could not create the directory of the test");
+
+        Self(path)
+    }
+
+    /// The path of the file `name` in the directory.
+    pub(crate) fn join(&self, name: impl AsRef<Path>) -> PathBuf {
+        self.0.join(name)
+    }
+}
+
+impl AsRef<Path> for TestDir {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
@@ -0,0 +1,86 @@
+//! Attribute measurements to the consumer task that made them.
+
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+/// The maximum number of consumers tracked individually; measurements made by consumers
+/// with a higher index are only counted in the totals.
+pub const MAX_CONSUMERS: usize = 64;
+
+tokio::task_local! {
+    static CONSUMER: usize;
+}
+
+/// Run the future as the consumer with the given index, so that all measurements made
+/// within it are also attributed to that consumer.
+pub async fn with_consumer<F: Future>(index: usize, future: F) -> F::Output {
+    CONSUMER.scope(index, future).await
+}
+
+/// Get the index of the consumer running on the current task, if any.
+pub fn current_consumer() -> Option<usize> {
+    CONSUMER.try_with(|index| *index).ok()
+}
+
+/// The measurements of a single consumer.
+#[derive(Debug, Default)]
+pub(super) struct ConsumerCounters {
+    ns: AtomicU64,
+    count: AtomicUsize,
+}
+
+/// The breakdown of the measurements of an operation by consumer.
+#[derive(Debug)]
+pub(super) struct PerConsumer {
+    consumers: [ConsumerCounters; MAX_CONSUMERS],
+}
+
+impl Default for PerConsumer {
+    fn default() -> Self {
+        Self {
+            consumers: std::array::from_fn(|_| ConsumerCounters::default()),
+        }
+    }
+}
+
+impl PerConsumer {
+    /// Record a measurement against the current consumer, if any.
+    pub(super) fn record(&self, elapsed: u64) {
+        if let Some(counters) = current_consumer().and_then(|index| self.consumers.get(index)) {
+            counters.ns.fetch_add(elapsed, Ordering::Relaxed);
+            counters.count.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Get the `(index, count, ns)` of every consumer that has made any measurements.
+    pub(super) fn breakdown(&self) -> Vec<(usize, usize, u64)> {
+        self.consumers
+            .iter()
+            .enumerate()
+            .filter_map(|(index, counters)| {
+                let count = counters.count.load(Ordering::Relaxed);
+
+                (count > 0).then(|| (index, count, counters.ns.load(Ordering::Relaxed)))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn per_consumer_breakdown() {
+        let per_consumer = PerConsumer::default();
+
+        per_consumer.record(1);
+        with_consumer(2, async { per_consumer.record(10) }).await;
+        with_consumer(2, async { per_consumer.record(20) }).await;
+        with_consumer(0, async { per_consumer.record(5) }).await;
+        with_consumer(MAX_CONSUMERS, async { per_consumer.record(5) }).await;
+
+        assert_eq!(per_consumer.breakdown(), vec![(0, 1, 5), (2, 2, 30)]);
+        assert_eq!(current_consumer(), None);
+    }
+}
@@ -0,0 +1,104 @@
+//! Export the measurements of [`TimedOperation`]s in machine-readable formats.
+
+use std::path::Path;
+
+use super::TimedOperation;
+
+/// The header of the CSV export.
+pub const CSV_HEADER: &str = "name,count,total_ns,exclusive_ns,min_ns,max_ns,p50_ns,p95_ns,p99_ns";
+
+/// Serialize the operations into a JSON array.
+pub fn to_json<T: AsRef<TimedOperation>>(operations: impl IntoIterator<Item = T>) -> String {
+    let reports = operations
+        .into_iter()
+        .map(|op| op.as_ref().report_json())
+        .collect::<Vec<_>>();
+
+    serde_json::to_string_pretty(&reports).expect("Failed to serialize the timings.")
+}
+
+/// Serialize the operations into CSV, one row per operation, using the [`CSV_HEADER`].
+///
+/// Missing percentiles are left empty.
+pub fn to_csv<T: AsRef<TimedOperation>>(operations: impl IntoIterator<Item = T>) -> String {
+    operations
+        .into_iter()
+        .map(|op| {
+            let report = op.as_ref().report_json();
+            let field = |key: &str| {
+                report[key]
+                    .as_u64()
+                    .map(|value| value.to_string())
+                    .unwrap_or_default()
+            };
+
+            format!(
+                "\"{}\",{},{},{},{},{},{},{},{}",
+                op.as_ref().name().replace('"', "\"\""),
+                field("count"),
+                field("total_ns"),
+                field("exclusive_ns"),
+                field("min_ns"),
+                field("max_ns"),
+                field("p50_ns"),
+                field("p95_ns"),
+                field("p99_ns"),
+            )
+        })
+        .fold(CSV_HEADER.to_owned() + "\n", |csv, row| csv + &row + "\n")
+}
+
+/// Write the operations to a file; as CSV if the path ends with `.csv`, otherwise as JSON.
+pub fn export<T: AsRef<TimedOperation>>(
+    path: impl AsRef<Path>,
+    operations: impl IntoIterator<Item = T>,
+) {
+    let path = path.as_ref();
+    let is_csv = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("csv"));
+
+    let content = if is_csv {
+        to_csv(operations)
+    } else {
+        to_json(operations)
+    };
+
+    std::fs::write(path, content)
+        .unwrap_or_else(|_| panic!("Could not write the timings to {:?}.", path));
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn export_csv() {
+        crate::timed::enable();
+
+        let ops = [
+            TimedOperation::new("first"),
+            TimedOperation::new("the \"second\""),
+        ];
+        drop(ops[0].start());
+
+        let csv = to_csv(&ops);
+        let mut lines = csv.lines();
+
+        assert_eq!(lines.next(), Some(CSV_HEADER));
+        assert!(lines.next().unwrap().starts_with("\"first\",1,"));
+        assert_eq!(lines.next(), Some("\"the \"\"second\"\"\",0,0,0,,0,,,"));
+        assert_eq!(lines.next(), None);
+    }
+
+    #[test]
+    fn export_json() {
+        let ops = [TimedOperation::new("first")];
+
+        let json: serde_json::Value = serde_json::from_str(&to_json(&ops)).unwrap();
+
+        assert_eq!(json[0]["name"], "first");
+        assert_eq!(json[0]["count"], 0);
+        assert!(json[0]["p50_ns"].is_null());
+    }
+}
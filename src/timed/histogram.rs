@@ -0,0 +1,227 @@
+//! A lock-free, log-bucketed histogram of durations.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// The number of linear sub-buckets within each power of two.
+const SUB_BUCKETS: usize = 4;
+
+/// The number of bits needed to address a sub-bucket.
+const SUB_BUCKET_BITS: u32 = SUB_BUCKETS.trailing_zeros();
+
+/// The total number of buckets, enough to cover the whole range of [`u64`] nanoseconds.
+const BUCKETS: usize = u64::BITS as usize * SUB_BUCKETS;
+
+/// The width of the bars printed by [`LogHistogram::report`].
+const BAR_WIDTH: usize = 40;
+
+/// A histogram of durations in nanoseconds, bucketed logarithmically.
+///
+/// Each power of two is split into [`SUB_BUCKETS`] linear buckets, so any value reported
+/// by this histogram is within 25% of the actual value, while only needing a fixed number
+/// of counters regardless of the range of durations recorded.
+///
+/// Recording is a single relaxed atomic increment, so this can be shared across threads.
+#[derive(Debug)]
+pub struct LogHistogram {
+    buckets: [AtomicU64; BUCKETS],
+}
+
+impl Default for LogHistogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl LogHistogram {
+    /// Create a new empty histogram.
+    pub const fn new() -> Self {
+        Self {
+            buckets: [const { AtomicU64::new(0) }; BUCKETS],
+        }
+    }
+
+    /// The index of the bucket containing the value.
+    fn index_of(ns: u64) -> usize {
+        if ns < SUB_BUCKETS as u64 {
+            return ns as usize;
+        }
+
+        let exponent = u64::BITS - 1 - ns.leading_zeros();
+        let sub_bucket = (ns >> (exponent - SUB_BUCKET_BITS)) as usize & (SUB_BUCKETS - 1);
+
+        exponent as usize * SUB_BUCKETS + sub_bucket
+    }
+
+    /// The range of values in nanoseconds covered by a bucket, inclusive.
+    fn range_of(index: usize) -> (u64, u64) {
+        if index < SUB_BUCKETS {
+            return (index as u64, index as u64);
+        }
+
+        let exponent = (index / SUB_BUCKETS) as u32;
+        let sub_bucket = (index % SUB_BUCKETS) as u64;
+        let width = 1u64 << (exponent - SUB_BUCKET_BITS);
+        let lower = (SUB_BUCKETS as u64 + sub_bucket) * width;
+
+        (lower, lower + (width - 1))
+    }
+
+    /// Record a single duration in nanoseconds.
+    pub fn record(&self, ns: u64) {
+        self.buckets[Self::index_of(ns)].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// The total number of durations recorded.
+    pub fn count(&self) -> u64 {
+        self.buckets
+            .iter()
+            .map(|bucket| bucket.load(Ordering::Relaxed))
+            .sum()
+    }
+
+    /// Get the duration at the given percentile, between `0.0` and `100.0`.
+    ///
+    /// This returns the upper bound of the bucket containing the percentile, so it never
+    /// under-reports. Returns [`None`] if nothing has been recorded.
+    pub fn percentile(&self, percentile: f64) -> Option<Duration> {
+        let counts = self.counts();
+        let total: u64 = counts.iter().sum();
+
+        if total == 0 {
+            return None;
+        }
+
+        let target = ((percentile.clamp(0.0, 100.0) / 100.0 * total as f64).ceil() as u64).max(1);
+
+        counts
+            .iter()
+            .enumerate()
+            .scan(0, |cumulative, (index, &count)| {
+                *cumulative += count;
+                Some((index, *cumulative))
+            })
+            .find(|(_, cumulative)| *cumulative >= target)
+            .map(|(index, _)| Duration::from_nanos(Self::range_of(index).1))
+    }
+
+    /// The non-empty buckets grouped by powers of two, as `(lower, upper, count)` with
+    /// inclusive bounds.
+    pub fn distribution(&self) -> Vec<(Duration, Duration, u64)> {
+        self.counts()
+            .chunks(SUB_BUCKETS)
+            .enumerate()
+            .filter_map(|(group, counts)| {
+                let count: u64 = counts.iter().sum();
+
+                (count > 0).then(|| {
+                    let lower = Self::range_of(group * SUB_BUCKETS).0;
+                    let upper = Self::range_of((group + 1) * SUB_BUCKETS - 1).1;
+                    (
+                        Duration::from_nanos(lower),
+                        Duration::from_nanos(upper),
+                        count,
+                    )
+                })
+            })
+            .collect()
+    }
+
+    /// Print the distribution of durations as a text bar chart.
+    pub fn report(&self) {
+        let distribution = self.distribution();
+        let max = distribution
+            .iter()
+            .map(|(_, _, count)| *count)
+            .max()
+            .unwrap_or(0);
+
+        for (lower, upper, count) in distribution {
+            println!(
+                "    {:>12} - {:<12} {:>10} {}",
+                format!("{:?}", lower),
+                format!("{:?}", upper),
+                count,
+                "#".repeat(((count * BAR_WIDTH as u64).div_ceil(max)) as usize)
+            );
+        }
+    }
+
+    /// A snapshot of the counts of every bucket.
+    fn counts(&self) -> Vec<u64> {
+        self.buckets
+            .iter()
+            .map(|bucket| bucket.load(Ordering::Relaxed))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn buckets_are_contiguous() {
+        (0..BUCKETS)
+            // The buckets of the exponent 1 are unused, as the values are covered by exponent 0.
+            .filter(|index| !(SUB_BUCKETS..SUB_BUCKETS * 2).contains(index))
+            .map(LogHistogram::range_of)
+            .collect::<Vec<_>>()
+            .windows(2)
+            .for_each(|pair| assert_eq!(pair[0].1 + 1, pair[1].0));
+
+        assert_eq!(LogHistogram::range_of(BUCKETS - 1).1, u64::MAX);
+    }
+
+    #[test]
+    fn values_fall_within_their_bucket() {
+        [0, 1, 3, 4, 5, 7, 8, 100, 1_000, 123_456_789, u64::MAX]
+            .into_iter()
+            .for_each(|ns| {
+                let (lower, upper) = LogHistogram::range_of(LogHistogram::index_of(ns));
+                assert!(
+                    lower <= ns && ns <= upper,
+                    "{} not in {}..={}",
+                    ns,
+                    lower,
+                    upper
+                );
+            });
+    }
+
+    #[test]
+    fn percentiles() {
+        let histogram = LogHistogram::new();
+        assert_eq!(histogram.percentile(50.0), None);
+
+        (0..99).for_each(|_| histogram.record(1_000));
+        histogram.record(50_000_000);
+
+        assert_eq!(histogram.count(), 100);
+
+        let p50 = histogram.percentile(50.0).unwrap();
+        assert!(p50 >= Duration::from_nanos(1_000) && p50 < Duration::from_nanos(1_250));
+
+        let p99 = histogram.percentile(99.0).unwrap();
+        assert_eq!(p99, p50);
+
+        let p100 = histogram.percentile(100.0).unwrap();
+        assert!(p100 >= Duration::from_millis(50) && p100 < Duration::from_micros(62_500));
+    }
+
+    #[test]
+    fn distribution_groups_by_power_of_two() {
+        let histogram = LogHistogram::new();
+        histogram.record(1_000);
+        histogram.record(1_100);
+        histogram.record(2_000);
+
+        assert_eq!(
+            histogram.distribution(),
+            vec![
+                (Duration::from_nanos(512), Duration::from_nanos(1_023), 1),
+                (Duration::from_nanos(1_024), Duration::from_nanos(2_047), 2),
+            ]
+        );
+    }
+}
@@ -1,4 +1,45 @@
 //! This module is for the `timed` command.
+//!
+//! The instrumentation is always compiled in, but nothing is measured unless timing is
+//! enabled for the run with [`enable`], e.g. by `--timed`. When disabled, starting a
+//! counter or a span only loads the flag with a relaxed atomic load: it neither reads the
+//! clock nor clones the operation, whose `static` is only initialized once.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Whether the operations are being timed; enabled from the start with the `timed` feature.
+static ENABLED: AtomicBool = AtomicBool::new(cfg!(feature = "timed"));
+
+/// Start timing the operations for the rest of the run.
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// Whether the operations are being timed.
+#[inline]
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+mod consumer;
+pub use consumer::{current_consumer, with_consumer, MAX_CONSUMERS};
+
+mod histogram;
+pub use histogram::LogHistogram;
+
+mod outliers;
+pub use outliers::{epoch, Outlier};
 
 mod operation;
-pub use operation::TimedOperation;
+pub use operation::{TimedOperation, TimedOperationCounter};
+
+mod span;
+pub use span::TimedSpan;
+
+mod throughput;
+pub use throughput::{ThroughputCounter, ThroughputMeasurement};
+
+mod registry;
+pub use registry::{registered, registered_throughput, report_all};
+
+pub mod export;
@@ -1,11 +1,16 @@
 //! A timed
 
+use itertools::Itertools;
 use std::sync::{
     atomic::{AtomicU64, AtomicUsize, Ordering},
-    Arc,
+    Arc, Mutex, Weak,
 };
 use tokio::time::Instant;
 
+use super::{
+    consumer::PerConsumer, outliers::Outliers, registry, LogHistogram, Outlier, TimedSpan,
+};
+
 /// An operation that needs to be timed.
 ///
 /// This struct is used to measure the time spent in a particular operation,
@@ -18,8 +23,8 @@ use tokio::time::Instant;
 /// # Note
 /// When used with [`std::sync::OnceLock`] as a `static` variable, [`Drop`] will
 /// not be called, and the total time spent will not be printed. In such a case,
-/// use the [`TimedOperation::report`] method to print the total time spent before
-/// the program exits.
+/// use [`super::report_all`] to print every operation created so far before the
+/// program exits.
 ///
 /// # Limitations
 /// This has a limited resolution of 1 nanosecond, any time spent less than that
@@ -34,7 +39,24 @@ use tokio::time::Instant;
 /// a significant impact on the time spent in the operation itself. The performance
 /// penalty is due to the atomic operations used to update the counters.
 ///
-/// This also makes nested use of this struct inaccurate.
+/// This also makes nested use of this struct inaccurate; use [`TimedOperation::span`]
+/// and [`TimedSpan::child`] to measure nested operations, which tracks the time spent
+/// in the operation itself separately from the time spent in its children.
+///
+/// # Consumers
+/// Measurements made within [`super::with_consumer`] are also attributed to the
+/// index of that consumer, so that the report can expose any load imbalance between
+/// the consumer tasks.
+///
+/// # Distribution
+/// Every measurement is also recorded into a [`LogHistogram`], so that the report
+/// can show the percentiles and the shape of the distribution; a handful of slow
+/// calls would otherwise hide behind the total and the count.
+///
+/// # Outliers
+/// Operations created with [`TimedOperation::with_outliers`] also keep the slowest
+/// calls along with when they started relative to [`super::epoch`], so that a slow
+/// call can be correlated with a specific point in the run.
 ///
 /// # Example
 /// ```
@@ -44,6 +66,8 @@ use tokio::time::Instant;
 ///
 /// #[tokio::main]
 /// async fn main() {
+///     async_1brc::timed::enable();
+///
 ///     let op = TimedOperation::new("test");
 ///     let handles = (0..5).into_iter().map(
 ///         |_| {
@@ -66,32 +90,110 @@ use tokio::time::Instant;
 pub struct TimedOperation {
     name: String,
     ns: AtomicU64,
+    min: AtomicU64,
     max: AtomicU64,
     count: AtomicUsize,
+    exclusive_ns: AtomicU64,
+    histogram: LogHistogram,
+    per_consumer: PerConsumer,
+    outliers: Outliers,
+    children: Mutex<Vec<Weak<TimedOperation>>>,
 }
 
 #[allow(dead_code)]
 impl TimedOperation {
+    /// Create a new operation, and add it to the global registry so that it is included in
+    /// [`super::report_all`].
     pub fn new(name: impl AsRef<str>) -> Arc<Self> {
-        Arc::new(Self {
+        Self::with_outliers(name, 0)
+    }
+
+    /// Create a new operation like [`TimedOperation::new`], which also keeps the `capacity`
+    /// slowest calls made to it.
+    pub fn with_outliers(name: impl AsRef<str>, capacity: usize) -> Arc<Self> {
+        super::epoch();
+
+        let operation = Arc::new(Self {
             name: name.as_ref().to_string(),
             ns: AtomicU64::default(),
+            min: AtomicU64::new(u64::MAX),
             max: AtomicU64::default(),
             count: AtomicUsize::default(),
-        })
+            exclusive_ns: AtomicU64::default(),
+            histogram: LogHistogram::new(),
+            per_consumer: PerConsumer::default(),
+            outliers: Outliers::new(capacity),
+            children: Mutex::default(),
+        });
+
+        registry::register(&operation);
+
+        operation
     }
 
     /// Starts a new counter for the operation.
     ///
     /// The counter will be stopped when it goes out of scope,
-    /// or when the `drop` method is called.
+    /// or when the `drop` method is called. Nothing is measured unless timing is
+    /// enabled by [`super::enable`], in which case the operation is not even cloned.
     pub fn start(self: &Arc<Self>) -> TimedOperationCounter {
         TimedOperationCounter {
-            parent: Arc::clone(self),
-            start: Instant::now(),
+            measuring: super::is_enabled().then(|| (Arc::clone(self), Instant::now())),
+        }
+    }
+
+    /// Starts a new root span for the operation, under which child spans can be started.
+    ///
+    /// Like [`TimedOperationCounter`], the span will be stopped when it goes out of scope.
+    pub fn span(self: &Arc<Self>) -> TimedSpan<'static> {
+        TimedSpan::new(self, None)
+    }
+
+    /// Record a single call taking `elapsed` nanoseconds, of which `children_ns` nanoseconds
+    /// were spent in child spans.
+    pub(super) fn record(&self, elapsed: u64, children_ns: u64) {
+        self.ns.fetch_add(elapsed, Ordering::Relaxed);
+        self.min.fetch_min(elapsed, Ordering::Relaxed);
+        self.max.fetch_max(elapsed, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.exclusive_ns
+            .fetch_add(elapsed.saturating_sub(children_ns), Ordering::Relaxed);
+        self.histogram.record(elapsed);
+        self.per_consumer.record(elapsed);
+        self.outliers.record(elapsed);
+    }
+
+    /// Record a single call measured by the caller, e.g. when which operation a call belongs
+    /// to is only known after it finishes. Nothing is recorded unless timing is enabled by
+    /// [`super::enable`].
+    pub fn record_duration(&self, elapsed: tokio::time::Duration) {
+        if super::is_enabled() {
+            self.record(elapsed.as_nanos() as u64, 0);
+        }
+    }
+
+    /// Note that `child` has been measured as a child span of this operation.
+    pub(super) fn add_child(&self, child: &Arc<TimedOperation>) {
+        let mut children = self.children.lock().unwrap();
+
+        if !children
+            .iter()
+            .any(|existing| std::ptr::eq(existing.as_ptr(), Arc::as_ptr(child)))
+        {
+            children.push(Arc::downgrade(child));
         }
     }
 
+    /// Get the operations that have been measured as child spans of this operation.
+    pub fn children(&self) -> Vec<Arc<TimedOperation>> {
+        self.children
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(Weak::upgrade)
+            .collect()
+    }
+
     /// Get the total number of calls made to the operation.
     pub fn count(&self) -> usize {
         self.count.load(Ordering::Relaxed)
@@ -104,6 +206,43 @@ impl TimedOperation {
         self.ns.load(Ordering::Relaxed)
     }
 
+    /// Get the total time spent in the operation itself, excluding any child spans.
+    ///
+    /// This equals [`TimedOperation::ns`] if the operation has no children.
+    pub fn exclusive_ns(&self) -> u64 {
+        self.exclusive_ns.load(Ordering::Relaxed)
+    }
+
+    /// Get the total duration spent in the operation itself, excluding any child spans.
+    pub fn exclusive_duration(&self) -> tokio::time::Duration {
+        std::time::Duration::from_nanos(self.exclusive_ns())
+    }
+
+    /// Get the `(consumer index, number of calls, total ns)` of every consumer that has
+    /// called the operation, in order of the index.
+    pub fn per_consumer(&self) -> Vec<(usize, usize, u64)> {
+        self.per_consumer.breakdown()
+    }
+
+    /// Get the minimum time spent in the operation, or [`None`] if it has never been called.
+    pub fn min_ns(&self) -> Option<u64> {
+        Some(self.min.load(Ordering::Relaxed)).filter(|ns| *ns != u64::MAX)
+    }
+
+    /// Get the minimum duration spent in the operation, or [`None`] if it has never been
+    /// called.
+    pub fn min(&self) -> Option<tokio::time::Duration> {
+        self.min_ns().map(std::time::Duration::from_nanos)
+    }
+
+    /// Get the slowest calls made to the operation, slowest first.
+    ///
+    /// This is always empty unless the operation was created by
+    /// [`TimedOperation::with_outliers`].
+    pub fn outliers(&self) -> Vec<Outlier> {
+        self.outliers.slowest()
+    }
+
     /// Get the maximum time spent in the operation.
     pub fn max_ns(&self) -> u64 {
         self.max.load(Ordering::Relaxed)
@@ -119,21 +258,135 @@ impl TimedOperation {
         std::time::Duration::from_nanos(self.ns())
     }
 
+    /// Get the duration at the given percentile of all the calls, between `0.0` and `100.0`.
+    ///
+    /// This is accurate to within 25%, and never under-reports, but is capped at the
+    /// maximum duration actually recorded.
+    pub fn percentile(&self, percentile: f64) -> Option<tokio::time::Duration> {
+        self.histogram
+            .percentile(percentile)
+            .map(|duration| duration.min(self.max()))
+    }
+
+    /// Get the histogram of the durations of all the calls.
+    pub fn histogram(&self) -> &LogHistogram {
+        &self.histogram
+    }
+
+    /// Get the name of the operation.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Report the measurements of the operation as a JSON object.
+    ///
+    /// All durations are in nanoseconds; the percentiles are [`None`] if the operation
+    /// has never been called.
+    pub fn report_json(&self) -> serde_json::Value {
+        let percentile_ns = |percentile| {
+            self.percentile(percentile)
+                .map(|duration| duration.as_nanos() as u64)
+        };
+
+        serde_json::json!({
+            "name": self.name,
+            "count": self.count(),
+            "total_ns": self.ns(),
+            "exclusive_ns": self.exclusive_ns(),
+            "min_ns": self.min_ns(),
+            "max_ns": self.max_ns(),
+            "p50_ns": percentile_ns(50.0),
+            "p95_ns": percentile_ns(95.0),
+            "p99_ns": percentile_ns(99.0),
+            "per_consumer": self.per_consumer().iter().map(|(index, count, ns)| {
+                serde_json::json!({"consumer": index, "count": count, "total_ns": ns})
+            }).collect::<Vec<_>>(),
+            "outliers": self.outliers().iter().map(|outlier| {
+                serde_json::json!({
+                    "at_ns": outlier.at.as_nanos() as u64,
+                    "elapsed_ns": outlier.elapsed.as_nanos() as u64,
+                    "consumer": outlier.consumer,
+                })
+            }).collect::<Vec<_>>(),
+        })
+    }
+
     /// Report the total time spent in the operation.
     pub fn report(&self) {
         let duration = self.duration();
         let count = self.count();
         let max = self.max();
         println!(
-            "{} has had {} calls, totalling {:?}, with a maximum of {:?}.",
-            self.name, count, duration, max
+            "{} has had {} calls, totalling {:?}, with a minimum of {:?} and a maximum of {:?}.",
+            self.name,
+            count,
+            duration,
+            self.min().unwrap_or_default(),
+            max
         );
+
+        if let (Some(p50), Some(p95), Some(p99)) = (
+            self.percentile(50.0),
+            self.percentile(95.0),
+            self.percentile(99.0),
+        ) {
+            println!("    p50: {:?}, p95: {:?}, p99: {:?}", p50, p95, p99);
+            self.histogram.report();
+        }
+
+        for outlier in self.outliers() {
+            println!(
+                "    slow call of {:?} at {:?} into the run{}",
+                outlier.elapsed,
+                outlier.at,
+                outlier
+                    .consumer
+                    .map(|index| format!(" by consumer #{}", index))
+                    .unwrap_or_default()
+            );
+        }
+
+        let per_consumer = self.per_consumer();
+        if !per_consumer.is_empty() {
+            for (index, count, ns) in per_consumer.iter() {
+                println!(
+                    "    consumer #{}: {} calls, totalling {:?}",
+                    index,
+                    count,
+                    std::time::Duration::from_nanos(*ns)
+                );
+            }
+
+            let (min, max) = per_consumer
+                .iter()
+                .map(|(_, _, ns)| *ns)
+                .minmax()
+                .into_option()
+                .unwrap();
+            if min > 0 {
+                println!(
+                    "    busiest consumer spent {:.2}x as long as the least busy.",
+                    max as f64 / min as f64
+                );
+            }
+        }
+
+        let children = self.children();
+        if !children.is_empty() {
+            println!(
+                "    {:?} spent exclusively, the rest in: {}",
+                self.exclusive_duration(),
+                itertools::join(children.iter().map(|child| child.name()), ", ")
+            );
+        }
     }
 }
 
 impl Drop for TimedOperation {
     fn drop(&mut self) {
-        self.report()
+        if super::is_enabled() {
+            self.report()
+        }
     }
 }
 
@@ -144,19 +397,38 @@ impl Drop for TimedOperation {
 /// To add measurements to the operation, use [`TimedOperation::start`], which will
 /// instantiate a new [`TimedOperationCounter`] instance.
 ///
-/// Upon dropping this counter, the time spent in the operation will be added to the
-/// parent [`TimedOperation`] instance.
+/// Upon dropping this counter, or calling [`TimedOperationCounter::stop`], the time spent
+/// in the operation will be added to the parent [`TimedOperation`] instance.
 pub struct TimedOperationCounter {
-    parent: Arc<TimedOperation>,
-    start: Instant,
+    /// The operation measured and when the measurement started, if timing is enabled.
+    measuring: Option<(Arc<TimedOperation>, Instant)>,
+}
+
+impl TimedOperationCounter {
+    /// Stop the counter, and return the time measured.
+    ///
+    /// This records the measurement just like dropping the counter, but allows the caller to
+    /// act upon the measured time, e.g. to warn about a particularly slow call. Returns
+    /// [`Duration::ZERO`](std::time::Duration::ZERO) if timing is not enabled.
+    pub fn stop(mut self) -> tokio::time::Duration {
+        self.finish()
+    }
+
+    /// Record the measurement if it has not been recorded yet, and return the time measured.
+    fn finish(&mut self) -> tokio::time::Duration {
+        self.measuring
+            .take()
+            .map_or(Default::default(), |(parent, start)| {
+                let elapsed = start.elapsed();
+                parent.record(elapsed.as_nanos() as u64, 0);
+                elapsed
+            })
+    }
 }
 
 impl Drop for TimedOperationCounter {
     fn drop(&mut self) {
-        let elapsed = self.start.elapsed().as_nanos() as u64;
-        self.parent.ns.fetch_add(elapsed, Ordering::Relaxed);
-        self.parent.max.fetch_max(elapsed, Ordering::Relaxed);
-        self.parent.count.fetch_add(1, Ordering::Relaxed);
+        self.finish();
     }
 }
 
@@ -166,6 +438,8 @@ mod test {
 
     #[tokio::test]
     async fn single_call() {
+        crate::timed::enable();
+
         let op = TimedOperation::new("test");
         {
             let _counter = op.start();
@@ -176,8 +450,120 @@ mod test {
         assert!(op.ns() >= 100);
     }
 
+    #[tokio::test]
+    async fn stop_returns_elapsed() {
+        crate::timed::enable();
+
+        let op = TimedOperation::new("test");
+        let counter = op.start();
+        tokio::time::sleep(tokio::time::Duration::from_millis(10)).await;
+        let elapsed = counter.stop();
+
+        assert!(elapsed >= tokio::time::Duration::from_millis(10));
+        assert_eq!(op.count(), 1);
+        assert_eq!(op.ns(), elapsed.as_nanos() as u64);
+    }
+
+    #[test]
+    fn record_duration() {
+        crate::timed::enable();
+
+        let op = TimedOperation::new("test");
+        op.record_duration(tokio::time::Duration::from_micros(5));
+
+        assert_eq!(op.count(), 1);
+        assert_eq!(op.ns(), 5_000);
+    }
+
+    #[tokio::test]
+    async fn percentiles() {
+        crate::timed::enable();
+
+        let op = TimedOperation::new("test");
+
+        for millis in [1, 1, 1, 50] {
+            let _counter = op.start();
+            tokio::time::sleep(tokio::time::Duration::from_millis(millis)).await;
+        }
+
+        assert_eq!(op.histogram().count(), 4);
+        assert!(op.percentile(50.0).unwrap() < tokio::time::Duration::from_millis(50));
+        assert!(op.percentile(100.0).unwrap() >= tokio::time::Duration::from_millis(50));
+    }
+
+    #[tokio::test]
+    async fn min_and_outliers() {
+        crate::timed::enable();
+
+        let op = TimedOperation::with_outliers("test", 1);
+        assert_eq!(op.min(), None);
+
+        for millis in [20, 1, 5] {
+            let _counter = op.start();
+            tokio::time::sleep(tokio::time::Duration::from_millis(millis)).await;
+        }
+
+        let min = op.min().unwrap();
+        assert!(min >= tokio::time::Duration::from_millis(1) && min < op.max());
+
+        let outliers = op.outliers();
+        assert_eq!(outliers.len(), 1);
+        assert_eq!(outliers[0].elapsed, op.max());
+        assert_eq!(op.report_json()["outliers"][0]["elapsed_ns"], op.max_ns());
+    }
+
+    #[tokio::test]
+    async fn report_json() {
+        crate::timed::enable();
+
+        let op = TimedOperation::new("test");
+        {
+            let _counter = op.start();
+            tokio::time::sleep(tokio::time::Duration::from_millis(10)).await;
+        }
+
+        let report = op.report_json();
+        assert_eq!(report["name"], "test");
+        assert_eq!(report["count"], 1);
+        assert_eq!(report["total_ns"], op.ns());
+        assert_eq!(report["max_ns"], op.ns());
+        assert!(report["p99_ns"].as_u64().unwrap() <= op.ns());
+    }
+
+    #[tokio::test]
+    async fn per_consumer_calls() {
+        crate::timed::enable();
+
+        let op = TimedOperation::new("test");
+
+        let handles = (0..3).map(|index| {
+            let op = Arc::clone(&op);
+            tokio::spawn(super::super::with_consumer(index, async move {
+                for _ in 0..=index {
+                    let _counter = op.start();
+                }
+            }))
+        });
+
+        for handle in handles {
+            handle.await.unwrap();
+        }
+
+        let per_consumer = op.per_consumer();
+        assert_eq!(
+            per_consumer
+                .iter()
+                .map(|(index, count, _)| (*index, *count))
+                .collect::<Vec<_>>(),
+            vec![(0, 1), (1, 2), (2, 3)]
+        );
+        assert_eq!(op.report_json()["per_consumer"][2]["count"], 3);
+    }
+
     #[tokio::test]
     async fn sequential_calls() {
+        crate::timed::enable();
+
         let op = TimedOperation::new("test");
 
         const REPEAT: u64 = 5;
@@ -192,6 +578,8 @@ mod test {
 
     #[tokio::test]
     async fn concurrent_calls() {
+        crate::timed::enable();
+
         let op = TimedOperation::new("test");
 
         const REPEAT: u64 = 5;
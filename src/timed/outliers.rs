@@ -0,0 +1,152 @@
+//! Capture the slowest calls of a [`super::TimedOperation`] along with when they happened.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+use tokio::time::Instant;
+
+use super::current_consumer;
+
+/// The point in time that all outlier timestamps are relative to.
+static EPOCH: OnceLock<Instant> = OnceLock::new();
+
+/// Get the start of the run, i.e. the creation of the first [`super::TimedOperation`].
+pub fn epoch() -> Instant {
+    *EPOCH.get_or_init(Instant::now)
+}
+
+/// A single slow call of an operation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Outlier {
+    /// When the call started, relative to [`epoch`].
+    pub at: Duration,
+
+    /// How long the call took.
+    pub elapsed: Duration,
+
+    /// The consumer that made the call, if any.
+    pub consumer: Option<usize>,
+}
+
+/// The `capacity` slowest calls of an operation.
+///
+/// Calls faster than the slowest ones kept so far are rejected with a single atomic load,
+/// so the lock is only taken while the list is filling up or when a new outlier is found.
+#[derive(Debug)]
+pub(super) struct Outliers {
+    capacity: usize,
+    threshold: AtomicU64,
+    slowest: Mutex<Vec<Outlier>>,
+}
+
+impl Outliers {
+    /// Keep up to `capacity` of the slowest calls; a capacity of zero keeps nothing.
+    pub(super) fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            threshold: AtomicU64::default(),
+            slowest: Mutex::new(Vec::with_capacity(capacity)),
+        }
+    }
+
+    /// Consider a call that just finished after `elapsed` nanoseconds.
+    pub(super) fn record(&self, elapsed: u64) {
+        if self.capacity == 0 || elapsed <= self.threshold.load(Ordering::Relaxed) {
+            return;
+        }
+
+        let elapsed = Duration::from_nanos(elapsed);
+        let outlier = Outlier {
+            at: Instant::now()
+                .saturating_duration_since(epoch())
+                .saturating_sub(elapsed),
+            elapsed,
+            consumer: current_consumer(),
+        };
+
+        let mut slowest = self.slowest.lock().unwrap();
+
+        // Another call may have filled the list since the threshold was loaded, with slower
+        // calls than this one.
+        if slowest.len() == self.capacity {
+            if slowest.last().is_some_and(|last| last.elapsed >= elapsed) {
+                return;
+            }
+
+            slowest.pop();
+        }
+
+        let index = slowest.partition_point(|existing| existing.elapsed >= elapsed);
+        slowest.insert(index, outlier);
+
+        if slowest.len() == self.capacity {
+            self.threshold.store(
+                slowest.last().unwrap().elapsed.as_nanos() as u64,
+                Ordering::Relaxed,
+            );
+        }
+    }
+
+    /// Get the slowest calls, slowest first.
+    pub(super) fn slowest(&self) -> Vec<Outlier> {
+        self.slowest.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn keeps_the_slowest() {
+        let outliers = Outliers::new(3);
+
+        [5, 1, 9, 3, 7, 2]
+            .into_iter()
+            .for_each(|ns| outliers.record(ns));
+
+        assert_eq!(
+            outliers
+                .slowest()
+                .iter()
+                .map(|outlier| outlier.elapsed.as_nanos())
+                .collect::<Vec<_>>(),
+            vec![9, 7, 5]
+        );
+    }
+
+    #[test]
+    fn keeps_the_slowest_across_threads() {
+        let outliers = Outliers::new(4);
+
+        // Every thread records each duration once, in another order, so that faster calls race
+        // slower ones past the threshold.
+        std::thread::scope(|scope| {
+            for thread in 0..8u64 {
+                let outliers = &outliers;
+                scope.spawn(move || {
+                    for ns in 1..=1000 {
+                        outliers.record((ns * 7919 + thread * 131) % 1000 + 1);
+                    }
+                });
+            }
+        });
+
+        assert_eq!(
+            outliers
+                .slowest()
+                .iter()
+                .map(|outlier| outlier.elapsed.as_nanos())
+                .collect::<Vec<_>>(),
+            vec![1000; 4]
+        );
+    }
+
+    #[test]
+    fn zero_capacity() {
+        let outliers = Outliers::new(0);
+        outliers.record(100);
+
+        assert!(outliers.slowest().is_empty());
+    }
+}
@@ -0,0 +1,81 @@
+//! A global registry of all the [`TimedOperation`]s and [`ThroughputCounter`]s created in
+//! the process.
+
+use std::sync::{Arc, Mutex, Weak};
+
+use super::{ThroughputCounter, TimedOperation};
+
+/// All the operations created so far, in order of creation.
+///
+/// Only weak references are kept, so that registering does not keep an operation alive,
+/// and locally scoped operations still report upon being dropped.
+static REGISTRY: Mutex<Vec<Weak<TimedOperation>>> = Mutex::new(Vec::new());
+
+/// All the throughput counters created so far, in order of creation.
+static THROUGHPUT_REGISTRY: Mutex<Vec<Weak<ThroughputCounter>>> = Mutex::new(Vec::new());
+
+/// Add a weak reference to the registry, dropping any that are no longer alive.
+fn push<T>(registry: &Mutex<Vec<Weak<T>>>, item: &Arc<T>) {
+    let mut registry = registry.lock().unwrap();
+
+    registry.retain(|item| item.strong_count() > 0);
+    registry.push(Arc::downgrade(item));
+}
+
+/// Get all the items of the registry that are still alive.
+fn alive<T>(registry: &Mutex<Vec<Weak<T>>>) -> Vec<Arc<T>> {
+    registry
+        .lock()
+        .unwrap()
+        .iter()
+        .filter_map(Weak::upgrade)
+        .collect()
+}
+
+/// Add an operation to the registry; this is called by [`TimedOperation::new`].
+pub(super) fn register(operation: &Arc<TimedOperation>) {
+    push(&REGISTRY, operation)
+}
+
+/// Add a counter to the registry; this is called by [`ThroughputCounter::new`].
+pub(super) fn register_throughput(counter: &Arc<ThroughputCounter>) {
+    push(&THROUGHPUT_REGISTRY, counter)
+}
+
+/// Get all the operations that are still alive, in order of creation.
+pub fn registered() -> Vec<Arc<TimedOperation>> {
+    alive(&REGISTRY)
+}
+
+/// Get all the throughput counters that are still alive, in order of creation.
+pub fn registered_throughput() -> Vec<Arc<ThroughputCounter>> {
+    alive(&THROUGHPUT_REGISTRY)
+}
+
+/// Report all the operations, then all the throughput counters, that are still alive, in
+/// order of creation.
+pub fn report_all() {
+    registered().iter().for_each(|op| op.report());
+    registered_throughput()
+        .iter()
+        .for_each(|counter| counter.report());
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn registered_operations() {
+        let op = TimedOperation::new("registry test: alive");
+        drop(TimedOperation::new("registry test: dropped"));
+
+        let names = registered()
+            .iter()
+            .map(|op| op.name().to_owned())
+            .filter(|name| name.starts_with("registry test"))
+            .collect::<Vec<_>>();
+
+        assert_eq!(names, vec![op.name().to_owned()]);
+    }
+}
@@ -0,0 +1,116 @@
+//! Nested measurements of [`TimedOperation`]s.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::time::Instant;
+
+use super::TimedOperation;
+
+/// A measurement of a [`TimedOperation`] that can have child spans nested within it.
+///
+/// The time spent in the child spans is recorded against their own operations, and is
+/// subtracted from the exclusive time of this span's operation, so that the report can
+/// show both inclusive and exclusive times without double counting.
+///
+/// Since the parent is linked explicitly rather than through thread-local state, spans
+/// can be held across `.await` points even if the task moves between threads.
+///
+/// Like [`TimedOperation::start`], nothing is measured unless timing is enabled by
+/// [`super::enable`].
+///
+/// # Example
+/// ```
+/// use async_1brc::timed::{self, TimedOperation};
+///
+/// timed::enable();
+///
+/// let outer = TimedOperation::new("outer");
+/// let inner = TimedOperation::new("inner");
+///
+/// {
+///     let span = outer.span();
+///     let _child = span.child(&inner);
+///     std::thread::sleep(std::time::Duration::from_millis(10));
+/// }
+///
+/// assert!(outer.exclusive_ns() < inner.ns());
+/// assert_eq!(outer.children()[0].name(), "inner");
+/// ```
+pub struct TimedSpan<'p> {
+    /// The operation measured and when the span started, if timing is enabled.
+    measuring: Option<(Arc<TimedOperation>, Instant)>,
+    children_ns: AtomicU64,
+    parent: Option<&'p TimedSpan<'p>>,
+}
+
+impl<'p> TimedSpan<'p> {
+    /// Start a new span of the operation, optionally nested within a parent span.
+    pub(super) fn new(operation: &Arc<TimedOperation>, parent: Option<&'p TimedSpan<'p>>) -> Self {
+        Self {
+            measuring: super::is_enabled().then(|| (Arc::clone(operation), Instant::now())),
+            children_ns: AtomicU64::default(),
+            parent,
+        }
+    }
+
+    /// Start a child span of another operation, nested within this span.
+    pub fn child<'s>(&'s self, operation: &Arc<TimedOperation>) -> TimedSpan<'s> {
+        if let Some((parent, _)) = &self.measuring {
+            parent.add_child(operation);
+        }
+
+        TimedSpan {
+            measuring: self
+                .measuring
+                .as_ref()
+                .map(|_| (Arc::clone(operation), Instant::now())),
+            children_ns: AtomicU64::default(),
+            parent: Some(self),
+        }
+    }
+}
+
+impl Drop for TimedSpan<'_> {
+    fn drop(&mut self) {
+        let Some((operation, start)) = &self.measuring else {
+            return;
+        };
+
+        let elapsed = start.elapsed().as_nanos() as u64;
+
+        operation.record(elapsed, self.children_ns.load(Ordering::Relaxed));
+
+        if let Some(parent) = self.parent {
+            parent.children_ns.fetch_add(elapsed, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn nested_spans() {
+        crate::timed::enable();
+
+        let outer = TimedOperation::new("outer");
+        let inner = TimedOperation::new("inner");
+
+        {
+            let span = outer.span();
+            for _ in 0..2 {
+                let _child = span.child(&inner);
+                tokio::time::sleep(tokio::time::Duration::from_millis(20)).await;
+            }
+        }
+
+        assert_eq!(outer.count(), 1);
+        assert_eq!(inner.count(), 2);
+        assert!(outer.ns() >= inner.ns());
+        assert_eq!(outer.exclusive_ns(), outer.ns() - inner.ns());
+        assert_eq!(inner.exclusive_ns(), inner.ns());
+        assert_eq!(outer.children().len(), 1);
+        assert!(inner.children().is_empty());
+    }
+}
@@ -0,0 +1,240 @@
+//! Measure the rate at which bytes and records flow through an operation.
+
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+use std::time::Duration;
+use tokio::time::Instant;
+
+use super::registry;
+
+/// The number of bytes in a megabyte, as used in the reported rates.
+const BYTES_PER_MB: f64 = 1_000_000.0;
+
+/// The number of records in a million, as used in the reported rates.
+const RECORDS_PER_MILLION: f64 = 1_000_000.0;
+
+/// An operation whose throughput is measured in bytes and records per second.
+///
+/// Like [`super::TimedOperation`], this accumulates the time spent across every measurement,
+/// but also the bytes and records processed during them. Since measurements can overlap when
+/// made by concurrent consumers, the wall time from the start of the first measurement to
+/// the end of the last is tracked too, so that the report can state both the throughput of
+/// the whole pipeline and that of a single busy consumer.
+///
+/// Nothing is measured unless timing is enabled by [`super::enable`].
+///
+/// # Example
+/// ```
+/// use async_1brc::timed::{self, ThroughputCounter};
+///
+/// timed::enable();
+///
+/// let counter = ThroughputCounter::new("parse");
+/// {
+///     let mut measurement = counter.start();
+///     std::thread::sleep(std::time::Duration::from_millis(10));
+///     measurement.add(1_000, 10);
+/// }
+///
+/// assert_eq!(counter.bytes(), 1_000);
+/// assert_eq!(counter.records(), 10);
+/// assert!(counter.bytes_per_second().unwrap() <= 100_000.0);
+/// ```
+#[derive(Debug)]
+pub struct ThroughputCounter {
+    name: String,
+    bytes: AtomicU64,
+    records: AtomicU64,
+    ns: AtomicU64,
+    first_start_ns: AtomicU64,
+    last_end_ns: AtomicU64,
+}
+
+impl ThroughputCounter {
+    /// Create a new counter, and add it to the global registry so that it is included in
+    /// [`super::report_all`].
+    pub fn new(name: impl AsRef<str>) -> Arc<Self> {
+        super::epoch();
+
+        let counter = Arc::new(Self {
+            name: name.as_ref().to_string(),
+            bytes: AtomicU64::default(),
+            records: AtomicU64::default(),
+            ns: AtomicU64::default(),
+            first_start_ns: AtomicU64::new(u64::MAX),
+            last_end_ns: AtomicU64::default(),
+        });
+
+        registry::register_throughput(&counter);
+
+        counter
+    }
+
+    /// Starts a new measurement, which is recorded when it goes out of scope; the counter is
+    /// not cloned unless timing is enabled.
+    pub fn start(self: &Arc<Self>) -> ThroughputMeasurement {
+        ThroughputMeasurement {
+            measuring: super::is_enabled().then(|| (Arc::clone(self), Instant::now())),
+            bytes: 0,
+            records: 0,
+        }
+    }
+
+    /// Record a single measurement.
+    fn record(&self, start: Instant, bytes: u64, records: u64) {
+        let end = Instant::now();
+        let since_epoch =
+            |instant: Instant| instant.saturating_duration_since(super::epoch()).as_nanos() as u64;
+
+        self.bytes.fetch_add(bytes, Ordering::Relaxed);
+        self.records.fetch_add(records, Ordering::Relaxed);
+        self.ns
+            .fetch_add((end - start).as_nanos() as u64, Ordering::Relaxed);
+        self.first_start_ns
+            .fetch_min(since_epoch(start), Ordering::Relaxed);
+        self.last_end_ns
+            .fetch_max(since_epoch(end), Ordering::Relaxed);
+    }
+
+    /// Get the name of the counter.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get the total number of bytes processed.
+    pub fn bytes(&self) -> u64 {
+        self.bytes.load(Ordering::Relaxed)
+    }
+
+    /// Get the total number of records processed.
+    pub fn records(&self) -> u64 {
+        self.records.load(Ordering::Relaxed)
+    }
+
+    /// Get the total time spent across all measurements, summed over concurrent consumers.
+    pub fn busy_duration(&self) -> Duration {
+        Duration::from_nanos(self.ns.load(Ordering::Relaxed))
+    }
+
+    /// Get the wall time from the start of the first measurement to the end of the last.
+    pub fn wall_duration(&self) -> Duration {
+        Duration::from_nanos(
+            self.last_end_ns
+                .load(Ordering::Relaxed)
+                .saturating_sub(self.first_start_ns.load(Ordering::Relaxed)),
+        )
+    }
+
+    /// Get the number of bytes processed per second of wall time, or [`None`] if nothing
+    /// has been measured.
+    pub fn bytes_per_second(&self) -> Option<f64> {
+        Self::rate(self.bytes(), self.wall_duration())
+    }
+
+    /// Get the number of records processed per second of wall time, or [`None`] if nothing
+    /// has been measured.
+    pub fn records_per_second(&self) -> Option<f64> {
+        Self::rate(self.records(), self.wall_duration())
+    }
+
+    /// The rate of `amount` over `duration`, if the duration is not zero.
+    fn rate(amount: u64, duration: Duration) -> Option<f64> {
+        (!duration.is_zero()).then(|| amount as f64 / duration.as_secs_f64())
+    }
+
+    /// Report the throughput of the operation.
+    pub fn report(&self) {
+        println!(
+            "{} processed {} bytes and {} records in {:?}, over {:?} of busy time.",
+            self.name,
+            self.bytes(),
+            self.records(),
+            self.wall_duration(),
+            self.busy_duration()
+        );
+
+        let busy = self.busy_duration();
+        if let Some(bytes_per_second) = self.bytes_per_second().filter(|_| self.bytes() > 0) {
+            println!(
+                "    {:.2} MB/s, {:.2} MB/s per busy consumer",
+                bytes_per_second / BYTES_PER_MB,
+                Self::rate(self.bytes(), busy).unwrap_or_default() / BYTES_PER_MB
+            );
+        }
+        if let Some(records_per_second) = self.records_per_second().filter(|_| self.records() > 0) {
+            println!(
+                "    {:.2} million rows/s, {:.2} million rows/s per busy consumer",
+                records_per_second / RECORDS_PER_MILLION,
+                Self::rate(self.records(), busy).unwrap_or_default() / RECORDS_PER_MILLION
+            );
+        }
+    }
+}
+
+/// A measurement linked to a [`ThroughputCounter`], recorded upon being dropped.
+pub struct ThroughputMeasurement {
+    /// The counter measured and when the measurement started, if timing is enabled.
+    measuring: Option<(Arc<ThroughputCounter>, Instant)>,
+    bytes: u64,
+    records: u64,
+}
+
+impl ThroughputMeasurement {
+    /// Add the bytes and records processed during this measurement.
+    pub fn add(&mut self, bytes: usize, records: usize) {
+        self.bytes += bytes as u64;
+        self.records += records as u64;
+    }
+}
+
+impl Drop for ThroughputMeasurement {
+    fn drop(&mut self) {
+        if let Some((parent, start)) = &self.measuring {
+            parent.record(*start, self.bytes, self.records);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn concurrent_measurements() {
+        crate::timed::enable();
+
+        let counter = ThroughputCounter::new("test");
+
+        let handles = (0..4)
+            .map(|_| {
+                let counter = Arc::clone(&counter);
+                tokio::spawn(async move {
+                    let mut measurement = counter.start();
+                    tokio::time::sleep(tokio::time::Duration::from_millis(20)).await;
+                    measurement.add(1_000, 10);
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for handle in handles {
+            handle.await.unwrap();
+        }
+
+        assert_eq!(counter.bytes(), 4_000);
+        assert_eq!(counter.records(), 40);
+        assert!(counter.busy_duration() >= Duration::from_millis(80));
+        assert!(counter.wall_duration() >= Duration::from_millis(20));
+        assert!(counter.wall_duration() < counter.busy_duration());
+        assert!(counter.records_per_second().unwrap() <= 2_000.0);
+    }
+
+    #[test]
+    fn nothing_measured() {
+        let counter = ThroughputCounter::new("test");
+
+        assert_eq!(counter.bytes_per_second(), None);
+        assert_eq!(counter.records_per_second(), None);
+    }
+}
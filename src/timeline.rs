@@ -0,0 +1,145 @@
+//! Sample the state of the pipeline over the whole run into a CSV file for plotting.
+
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::{mpsc, Arc};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::reader::RowsReader;
+
+/// The header of the timeline CSV.
+pub const CSV_HEADER: &str =
+    "elapsed_ms,queue_depth,bytes_read,chunks_exported,records_parsed,rss_bytes";
+
+/// Get the current resident set size of the process in bytes, if supported on this platform.
+#[cfg(target_os = "linux")]
+pub fn current_rss() -> Option<u64> {
+    let statm = std::fs::read_to_string("/proc/self/statm").ok()?;
+    let pages = statm.split_whitespace().nth(1)?.parse::<u64>().ok()?;
+
+    // SAFETY: `sysconf` has no preconditions.
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+
+    u64::try_from(page_size)
+        .ok()
+        .map(|page_size| pages * page_size)
+}
+
+/// Get the current resident set size of the process in bytes, if supported on this platform.
+#[cfg(not(target_os = "linux"))]
+pub fn current_rss() -> Option<u64> {
+    None
+}
+
+/// A background thread sampling a [`RowsReader`] at a fixed interval.
+///
+/// A sample is taken as soon as the timeline starts, then every `interval`, and once more
+/// when [`Timeline::finish`] is called, so that the file always covers the whole run.
+///
+/// This runs on its own thread rather than as a task, so that the samples stay evenly
+/// spaced even when the consumers keep every worker thread of the runtime busy.
+pub struct Timeline {
+    stop: mpsc::Sender<()>,
+    handle: JoinHandle<std::io::Result<()>>,
+}
+
+impl Timeline {
+    /// Start sampling the reader into a new CSV file at `path`.
+    pub fn start(reader: Arc<RowsReader>, path: impl Into<PathBuf>, interval: Duration) -> Self {
+        let (stop, stopped) = mpsc::channel();
+        let path = path.into();
+        let handle = std::thread::spawn(move || Self::sample(&reader, path, interval, stopped));
+
+        Self { stop, handle }
+    }
+
+    /// Take a final sample, and wait for the file to be written.
+    pub fn finish(self) -> std::io::Result<()> {
+        // The thread may have already failed, in which case its error is returned below.
+        let _ = self.stop.send(());
+
+        self.handle.join().expect("The timeline thread panicked.")
+    }
+
+    /// Write a single row of the CSV.
+    fn write_row(
+        file: &mut impl Write,
+        reader: &RowsReader,
+        start: Instant,
+    ) -> std::io::Result<()> {
+        writeln!(
+            file,
+            "{},{},{},{},{},{}",
+            start.elapsed().as_millis(),
+            reader.queue_depth(),
+            reader.bytes_read(),
+            reader.chunks_exported(),
+            reader.records_parsed(),
+            current_rss().map(|rss| rss.to_string()).unwrap_or_default()
+        )
+    }
+
+    /// Write a row every `interval` until a message is received on `stopped`, or the
+    /// [`Timeline`] is dropped.
+    fn sample(
+        reader: &RowsReader,
+        path: PathBuf,
+        interval: Duration,
+        stopped: mpsc::Receiver<()>,
+    ) -> std::io::Result<()> {
+        let start = Instant::now();
+        let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+        writeln!(file, "{}", CSV_HEADER)?;
+
+        let mut next = start;
+        loop {
+            Self::write_row(&mut file, reader, start)?;
+
+            next += interval;
+            match stopped.recv_timeout(next.saturating_duration_since(Instant::now())) {
+                Err(mpsc::RecvTimeoutError::Timeout) => continue,
+                _ => break,
+            }
+        }
+
+        Self::write_row(&mut file, reader, start)?;
+        file.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::TestDir;
+
+    #[test]
+    fn timeline_rows() {
+        let dir = TestDir::new("timeline_rows");
+        let path = dir.join("timeline.csv");
+        let reader = Arc::new(RowsReader::new());
+
+        let timeline = Timeline::start(Arc::clone(&reader), &path, Duration::from_millis(10));
+        std::thread::sleep(Duration::from_millis(35));
+        reader.add_records_parsed(7);
+        timeline.finish().unwrap();
+
+        let csv = std::fs::read_to_string(&path).unwrap();
+
+        let mut lines = csv.lines();
+        assert_eq!(lines.next(), Some(CSV_HEADER));
+
+        let rows = lines.collect::<Vec<_>>();
+        assert!(rows.len() >= 3);
+        assert!(rows
+            .iter()
+            .all(|row| row.split(',').count() == CSV_HEADER.split(',').count()));
+        assert_eq!(rows.last().unwrap().split(',').nth(4), Some("7"));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn rss_is_reported() {
+        assert!(current_rss().unwrap() > 0);
+    }
+}
@@ -0,0 +1,225 @@
+//! Find the chunk sizes and the number of threads best suited to this machine.
+//!
+//! The defaults in [`config`] were tuned for a single machine. `main tune` runs short
+//! calibration passes over a prefix of the input, sweeping the chunk sizes and the thread
+//! counts, and saves the fastest configuration as a [`Tuning`] file; later runs load it in
+//! place of the defaults, unless the values are given on the command line:
+//!
+//! ```bash
+//! cargo run --release --bin main -- --file=measurements.txt tune
+//! cargo run --release --bin main -- --file=measurements.txt
+//! ```
+
+use std::io;
+use std::path::Path;
+
+use crate::config;
+
+/// The chunk sizes and the number of threads of a run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Tuning {
+    pub threads: usize,
+    pub chunk_size: usize,
+    pub max_chunk_size: usize,
+}
+
+impl Tuning {
+    /// Create a [`Tuning`] with the same maximum chunk size relative to the chunk size as the
+    /// defaults in [`config`].
+    pub fn new(threads: usize, chunk_size: usize) -> Self {
+        Self {
+            threads,
+            chunk_size,
+            max_chunk_size: chunk_size * (config::MAX_CHUNK_SIZE / config::CHUNK_SIZE)
+                + config::MAX_LINE_LENGTH,
+        }
+    }
+
+    /// Load a tuning file written by [`Tuning::write`].
+    pub fn read(path: impl AsRef<Path>) -> io::Result<Self> {
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message);
+
+        let value: serde_json::Value = serde_json::from_slice(&std::fs::read(path)?)
+            .map_err(|_| invalid("The tuning file is not valid JSON."))?;
+        let field = |name: &str| {
+            value[name]
+                .as_u64()
+                .map(|value| value as usize)
+                .ok_or_else(|| invalid(&format!("The tuning file has no valid `{}`.", name)))
+        };
+
+        Ok(Self {
+            threads: field("threads")?,
+            chunk_size: field("chunk_size")?,
+            max_chunk_size: field("max_chunk_size")?,
+        })
+    }
+
+    /// Save the tuning as JSON to `path`.
+    pub fn write(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let value = serde_json::json!({
+            "threads": self.threads,
+            "chunk_size": self.chunk_size,
+            "max_chunk_size": self.max_chunk_size,
+        });
+
+        std::fs::write(path, format!("{:#}\n", value))
+    }
+}
+
+impl std::fmt::Display for Tuning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} threads, chunk size {}, max chunk size {}",
+            self.threads, self.chunk_size, self.max_chunk_size
+        )
+    }
+}
+
+/// The options of the `tune` subcommand of `main`.
+#[derive(clap::Args, Debug, Clone)]
+pub struct TuneArgs {
+    /// The number of bytes at the start of the input to calibrate with.
+    #[arg(long, default_value_t = config::TUNE_PREFIX_SIZE)]
+    pub prefix_size: usize,
+
+    /// The chunk sizes to try, separated by commas.
+    #[arg(long, value_delimiter = ',', default_values_t = config::TUNE_CHUNK_SIZES)]
+    pub chunk_sizes: Vec<usize>,
+
+    /// The numbers of threads to try, separated by commas.
+    #[arg(long, value_delimiter = ',', default_values_t = config::TUNE_THREADS)]
+    pub thread_counts: Vec<usize>,
+
+    /// The number of passes for each configuration, keeping the fastest.
+    #[arg(long, default_value_t = config::TUNE_REPEATS)]
+    pub repeats: usize,
+}
+
+/// The fastest pass of a single configuration.
+#[derive(Debug, Clone, Copy)]
+pub struct Trial {
+    pub tuning: Tuning,
+    pub elapsed: std::time::Duration,
+}
+
+/// Read the first `prefix_size` bytes of the file, up to the end of the last complete line.
+#[cfg(feature = "runtime")]
+async fn read_prefix(path: impl AsRef<Path>, prefix_size: usize) -> io::Result<Vec<u8>> {
+    use tokio::io::AsyncReadExt;
+
+    let mut prefix = Vec::with_capacity(prefix_size);
+    tokio::fs::File::open(path)
+        .await?
+        .take(prefix_size as u64)
+        .read_to_end(&mut prefix)
+        .await?;
+
+    let end = prefix
+        .iter()
+        .rposition(|&byte| byte == b'\n')
+        .map_or(0, |position| position + 1);
+    prefix.truncate(end);
+
+    Ok(prefix)
+}
+
+/// Run every combination of `options` over a prefix of the file, returning the fastest pass
+/// of each in the order tried.
+///
+/// The prefix is read into memory once, so that the passes compare the hand-off and the
+/// parsing rather than the page cache; `on_trial` is called after each configuration, e.g.
+/// to report the progress.
+#[cfg(feature = "runtime")]
+pub async fn calibrate(
+    path: impl AsRef<Path>,
+    options: &TuneArgs,
+    mut on_trial: impl FnMut(&Trial),
+) -> io::Result<Vec<Trial>> {
+    let prefix = read_prefix(&path, options.prefix_size).await?;
+    let mut trials = Vec::new();
+
+    for &chunk_size in &options.chunk_sizes {
+        for &threads in &options.thread_counts {
+            let tuning = Tuning::new(threads, chunk_size);
+            let mut elapsed = std::time::Duration::MAX;
+
+            for _ in 0..options.repeats.max(1) {
+                let start = std::time::Instant::now();
+                crate::run_from(
+                    &prefix[..],
+                    crate::RunOptions::new(path.as_ref())
+                        .with_threads(tuning.threads)
+                        .with_chunk_sizes(tuning.chunk_size, tuning.max_chunk_size),
+                )
+                .await?;
+                elapsed = elapsed.min(start.elapsed());
+            }
+
+            let trial = Trial { tuning, elapsed };
+            on_trial(&trial);
+            trials.push(trial);
+        }
+    }
+
+    Ok(trials)
+}
+
+/// The fastest of the trials, if any.
+pub fn fastest(trials: &[Trial]) -> Option<Tuning> {
+    trials
+        .iter()
+        .min_by_key(|trial| trial.elapsed)
+        .map(|trial| trial.tuning)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::TestDir;
+
+    #[test]
+    fn tuning_roundtrip() {
+        let dir = TestDir::new("tuning_roundtrip");
+        let path = dir.join("tuning.json");
+        let tuning = Tuning::new(4, 1 << 16);
+
+        tuning.write(&path).unwrap();
+        let loaded = Tuning::read(&path);
+        std::fs::write(&path, "{\"threads\": 4}").unwrap();
+        let incomplete = Tuning::read(&path);
+
+        assert_eq!(loaded.unwrap(), tuning);
+        assert_eq!(
+            tuning.max_chunk_size,
+            (1 << 16) * 16 + config::MAX_LINE_LENGTH
+        );
+        assert_eq!(incomplete.unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[cfg(feature = "runtime")]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn calibrate_prefix() {
+        let dir = TestDir::new("calibrate_prefix");
+        let path = dir.join("input.txt");
+        std::fs::write(&path, "jack;1.2\njill;-3.4\njack;5.6\n".repeat(1000)).unwrap();
+
+        let options = TuneArgs {
+            // Cut the prefix in the middle of a line.
+            prefix_size: 1000,
+            chunk_sizes: vec![64, 256],
+            thread_counts: vec![1, 2],
+            repeats: 2,
+        };
+        let mut reported = 0;
+        let trials = calibrate(&path, &options, |_| reported += 1).await;
+
+        let trials = trials.unwrap();
+        assert_eq!(trials.len(), 4);
+        assert_eq!(reported, 4);
+        assert!(trials
+            .iter()
+            .any(|trial| Some(trial.tuning) == fastest(&trials)));
+    }
+}
@@ -0,0 +1,155 @@
+//! Re-run the aggregation whenever the input changes, for iterating on generated datasets.
+//!
+//! A [`Watcher`] monitors the directory of the input with [`notify`], rather than the input
+//! itself, so that an input replaced by a rename, as most generators and editors do, is still
+//! followed. Bursts of events, e.g. a file being written in several calls, are coalesced until
+//! the directory has been quiet for [`config::WATCH_DEBOUNCE_MS`].
+//!
+//! Each run of a [`WatchSession`] starts warm: its reader takes over the buffers of the previous
+//! run, and its consumers merge into the table of the previous results, reset, so that the
+//! names of the stations seen before are not copied into a new table again.
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use notify::{EventKind, RecursiveMode, Watcher as _};
+use tokio::sync::mpsc;
+
+use crate::config;
+use crate::run::{run_warm, Warm};
+use crate::{RunError, RunOptions, RunReport};
+
+/// Monitors a file for changes.
+pub struct Watcher {
+    path: PathBuf,
+    events: mpsc::UnboundedReceiver<notify::Result<notify::Event>>,
+    debounce: Duration,
+    // Stops watching once dropped.
+    _watcher: notify::RecommendedWatcher,
+}
+
+impl Watcher {
+    /// Start monitoring the file at `path`, which may not exist yet.
+    pub fn new(path: impl AsRef<Path>) -> notify::Result<Self> {
+        let path = std::path::absolute(path.as_ref())?;
+        let dir = path.parent().unwrap_or(Path::new("/"));
+
+        let (sender, events) = mpsc::unbounded_channel();
+        let mut watcher = notify::recommended_watcher(move |event| {
+            // The receiver only goes away along with the watcher.
+            let _ = sender.send(event);
+        })?;
+        watcher.watch(dir, RecursiveMode::NonRecursive)?;
+
+        Ok(Self {
+            path,
+            events,
+            debounce: Duration::from_millis(config::WATCH_DEBOUNCE_MS),
+            _watcher: watcher,
+        })
+    }
+
+    /// Set how long the directory must be quiet after a change before it is reported,
+    /// [`config::WATCH_DEBOUNCE_MS`] by default.
+    pub fn with_debounce(mut self, debounce: Duration) -> Self {
+        self.debounce = debounce;
+        self
+    }
+
+    /// Wait until the file is created, modified, renamed or removed, and the directory has
+    /// been quiet for the debounce since; fails if the directory can no longer be watched.
+    pub async fn changed(&mut self) -> notify::Result<()> {
+        loop {
+            if self.next_change().await? {
+                break;
+            }
+        }
+
+        // The changes of the file during the debounce are part of the same change.
+        while let Ok(event) = tokio::time::timeout(self.debounce, self.next_change()).await {
+            event?;
+        }
+
+        Ok(())
+    }
+
+    /// Wait for the next event of the directory, returning whether it changed the file.
+    async fn next_change(&mut self) -> notify::Result<bool> {
+        let event = match self.events.recv().await {
+            Some(event) => event?,
+            None => return Err(notify::Error::generic("the watcher stopped")),
+        };
+
+        Ok(!matches!(event.kind, EventKind::Access(_)) && event.paths.contains(&self.path))
+    }
+}
+
+/// Consecutive runs over the same input, each starting with the buffers and the records table
+/// of the previous one.
+pub struct WatchSession {
+    options: RunOptions,
+    warm: Warm,
+    report: Option<RunReport>,
+}
+
+impl WatchSession {
+    /// Create a session of runs with `options`.
+    pub fn new(options: RunOptions) -> Self {
+        Self {
+            options,
+            warm: Warm::default(),
+            report: None,
+        }
+    }
+
+    /// The options of the runs.
+    pub fn options(&self) -> &RunOptions {
+        &self.options
+    }
+
+    /// Read and aggregate the input again, exporting the results if requested.
+    ///
+    /// The report is kept until the next run, which reuses its records.
+    pub async fn run(&mut self) -> Result<&RunReport, RunError> {
+        if let Some(report) = self.report.take() {
+            self.warm.records = report.records;
+        }
+
+        let report = run_warm(self.options.clone(), &mut self.warm).await?;
+        Ok(self.report.insert(report))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::testing::TestDir;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn rerun_on_change() {
+        let dir = TestDir::new("rerun_on_change");
+        let input = dir.join("measurements.txt");
+        std::fs::write(&input, "jack;1.2\njill;-3.4\njack;5.6\n").unwrap();
+
+        let mut watcher = Watcher::new(&input)
+            .unwrap()
+            .with_debounce(Duration::from_millis(50));
+        let mut session = WatchSession::new(RunOptions::new(&input).with_threads(2));
+
+        let first = session.run().await.unwrap().records.export_text();
+
+        // A station missing from the new input is not left behind by the previous run.
+        let replacement = dir.join("measurements.txt.new");
+        std::fs::write(&replacement, "jack;7.0\nbob;0.0\n").unwrap();
+        std::fs::rename(&replacement, &input).unwrap();
+
+        tokio::time::timeout(Duration::from_secs(10), watcher.changed())
+            .await
+            .expect("the change was not reported")
+            .unwrap();
+        let second = session.run().await.unwrap().records.export_text();
+
+        assert_eq!(first, "{jack=1.2/3.4/5.6, jill=-3.4/-3.4/-3.4}\n");
+        assert_eq!(second, "{bob=0.0/0.0/0.0, jack=7.0/7.0/7.0}\n");
+    }
+}
@@ -0,0 +1,78 @@
+//! Deterministic inputs and temporary directories shared by the integration tests.
+
+// Each test binary only uses some of the helpers.
+#![allow(dead_code)]
+
+use std::path::{Path, PathBuf};
+
+/// A small xorshift generator, so that the inputs are the same on every run and platform.
+pub struct Rng(pub u64);
+
+impl Rng {
+    pub fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// A number within `range`.
+    pub fn range(&mut self, range: std::ops::RangeInclusive<usize>) -> usize {
+        range.start() + (self.next() % (range.end() - range.start() + 1) as u64) as usize
+    }
+
+    /// A value within `-max..=max` tenths of a degree.
+    pub fn value(&mut self, max: i16) -> i16 {
+        (self.next() % (2 * max as u64 + 1)) as i16 - max
+    }
+
+    pub fn pick<'a, T: ?Sized>(&mut self, items: &[&'a T]) -> &'a T {
+        items[(self.next() % items.len() as u64) as usize]
+    }
+}
+
+/// Format a value in tenths of a degree as in the input, e.g. `-0.3`.
+pub fn format_value(tenths: i16) -> String {
+    let sign = if tenths < 0 { "-" } else { "" };
+    format!("{}{}.{}", sign, tenths.abs() / 10, tenths.abs() % 10)
+}
+
+/// Generate `rows` lines of random values of random stations among `names`.
+pub fn generate(seed: u64, names: &[&str], rows: usize) -> String {
+    let mut rng = Rng(seed);
+
+    (0..rows)
+        .map(|_| {
+            let name = rng.pick(names);
+            format!("{};{}\n", name, format_value(rng.value(999)))
+        })
+        .collect()
+}
+
+/// A temporary directory of its own for a test, removed with its files once dropped, named
+/// as those of the unit tests.
+pub struct TestDir(PathBuf);
+
+impl TestDir {
+    /// Create the empty directory of the test `name`.
+    pub fn new(name: &str) -> Self {
+        let path =
+            std::env::temp_dir().join(format!("async-1brc-test-{}-{}", std::process::id(), name));
+
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).expect("Could not create the directory of the test");
+
+        Self(path)
+    }
+
+    /// The path of the file `name` in the directory.
+    pub fn join(&self, name: impl AsRef<Path>) -> PathBuf {
+        self.0.join(name)
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
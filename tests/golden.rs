@@ -0,0 +1,203 @@
+//! Golden-file tests of the whole pipeline.
+//!
+//! Each case generates a small deterministic input, covering an edge case of the 1BRC format,
+//! and aggregates it from a file with [`async_1brc::run`], as the `main` binary would, with
+//! every parser engine and a few thread counts and chunk sizes. The output must match
+//! `tests/golden/<case>.txt` byte for byte.
+//!
+//! After an intended change of the output, write the golden files again with
+//! `UPDATE_GOLDEN=1 cargo test --test golden`, and review their diff.
+
+mod common;
+
+use std::path::{Path, PathBuf};
+
+use async_1brc::config;
+use async_1brc::parser::engine::ParserEngine;
+use async_1brc::RunOptions;
+use common::{generate, TestDir};
+
+/// The input of each case.
+fn input(case: &str) -> String {
+    match case {
+        "basic" => generate(
+            1,
+            &[
+                "Abha",
+                "Accra",
+                "Addis Ababa",
+                "Adelaide",
+                "Aden",
+                "Ahvaz",
+                "Albuquerque",
+                "Alexandra",
+                "Alexandria",
+                "Algiers",
+                "Alice Springs",
+                "Almaty",
+                "Amsterdam",
+                "Anadyr",
+                "Anchorage",
+                "Andorra la Vella",
+                "Ankara",
+                "Antananarivo",
+                "Antsiranana",
+                "Arkhangelsk",
+                "Ashgabat",
+                "Asmara",
+                "Assab",
+                "Astana",
+                "Athens",
+                "Atlanta",
+            ],
+            5000,
+        ),
+        // `-0.0` is the same value as `0.0`, and a mean just below zero is still negative.
+        "negative_zero" => [
+            "Zero;-0.0",
+            "Zero;0.0",
+            "Zero;-0.0",
+            "Minus;-0.0",
+            "Tiny;-0.1",
+            "Tiny;0.0",
+            "Tiny;0.0",
+            "Half;-0.1",
+            "Half;0.0",
+            "Plus;0.1",
+            "Plus;-0.0",
+            "Plus;-0.0",
+        ]
+        .iter()
+        .map(|line| format!("{}\n", line))
+        .collect(),
+        "short_names" => generate(2, &["a", "Z", "1", "é", "日", "😀", "-", "."], 1000),
+        "long_names" => {
+            let max = config::MAX_NAME_LENGTH;
+            let ascii = "a".repeat(max);
+            let ascii_last = format!("{}b", "a".repeat(max - 1));
+            let umlauts = "ü".repeat(max / 2);
+            let mixed = format!("{}{}", "日本".repeat(max / 6), "x".repeat(max % 6));
+
+            generate(3, &[&ascii, &ascii_last, &umlauts, &mixed, "short"], 2000)
+        }
+        // Both normal forms of `Zürich` are distinct stations, unless normalized.
+        "unicode" => generate(
+            4,
+            &[
+                "Zürich",
+                "Zu\u{308}rich",
+                "São Paulo",
+                "Kraków",
+                "İzmir",
+                "Reykjavík",
+                "東京",
+                "Αθήνα",
+                "Москва",
+                "Đà Lạt",
+                "القاهرة",
+                "🏙️ City",
+            ],
+            3000,
+        ),
+        "extremes" => [
+            "Hot;99.9",
+            "Hot;99.8",
+            "Cold;-99.9",
+            "Cold;-99.8",
+            "Both;99.9",
+            "Both;-99.9",
+            "Single;1.0",
+            "Single;-1.0",
+            "Single;0.1",
+        ]
+        .iter()
+        .map(|line| format!("{}\n", line))
+        .collect(),
+        case => panic!("unknown case {:?}", case),
+    }
+}
+
+fn golden_path(case: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{}.txt", case))
+}
+
+/// Aggregate the input of `case` with every configuration, and compare each output against
+/// the golden file.
+async fn check(case: &str) {
+    let dir = TestDir::new(&format!("golden_{}", case));
+    let (input_path, output_path) = (dir.join("input.txt"), dir.join("output.txt"));
+    std::fs::write(&input_path, input(case)).unwrap();
+
+    let mut outputs = Vec::new();
+    for engine in [
+        ParserEngine::Scalar,
+        ParserEngine::Memchr,
+        ParserEngine::Batched,
+        #[cfg(feature = "gpu")]
+        ParserEngine::Gpu,
+    ] {
+        for threads in [1, 4] {
+            for chunk_size in [256, config::CHUNK_SIZE] {
+                let options = RunOptions::new(&input_path)
+                    .with_output(&output_path)
+                    .with_verify_output(true)
+                    .with_engine(engine)
+                    .with_threads(threads)
+                    .with_chunk_sizes(chunk_size, chunk_size + config::MAX_LINE_LENGTH);
+
+                async_1brc::run(options).await.unwrap();
+
+                let output = std::fs::read_to_string(&output_path).unwrap();
+                outputs.push((format!("{} x{} of {}", engine, threads, chunk_size), output));
+            }
+        }
+    }
+
+    let path = golden_path(case);
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, &outputs[0].1).unwrap();
+    }
+
+    let golden = std::fs::read_to_string(&path)
+        .unwrap_or_else(|err| panic!("Could not read the golden file {:?}: {}", path, err));
+    for (configuration, output) in outputs {
+        assert_eq!(
+            output, golden,
+            "{} with {} differs from {:?}",
+            case, configuration, path
+        );
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn golden_basic() {
+    check("basic").await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn golden_negative_zero() {
+    check("negative_zero").await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn golden_short_names() {
+    check("short_names").await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn golden_long_names() {
+    check("long_names").await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn golden_unicode() {
+    check("unicode").await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn golden_extremes() {
+    check("extremes").await;
+}
@@ -0,0 +1,90 @@
+//! Stress the concurrency of the pipeline.
+//!
+//! The same in-memory input is aggregated dozens of times with random chunk sizes, thread
+//! counts and engines, so that lines are split across chunks at many different offsets and
+//! the consumers race to merge their records; every run must give the exact same records as
+//! parsing the input in a single pass.
+
+mod common;
+
+use async_1brc::config;
+use async_1brc::parser::engine::ParserEngine;
+use async_1brc::parser::models::StationRecords;
+use async_1brc::parser::sync;
+use async_1brc::RunOptions;
+use common::{generate, Rng};
+
+/// The number of runs of each test.
+const RUNS: usize = 48;
+
+/// Many stations with names of every length up to the maximum, including multi-byte ones, so
+/// that chunks often end inside a name or a value.
+fn station_names() -> Vec<String> {
+    (1..=config::MAX_NAME_LENGTH)
+        .flat_map(|length| {
+            [
+                "x".repeat(length),
+                format!("{}é", "y".repeat(length.saturating_sub(2))),
+            ]
+        })
+        .filter(|name| name.len() <= config::MAX_NAME_LENGTH)
+        .collect()
+}
+
+/// Aggregate `input` [`RUNS`] times with random options drawn from `seed`, and compare the
+/// records of every run against `expected`.
+async fn stress(seed: u64, input: &str, expected: &StationRecords) {
+    let mut rng = Rng(seed);
+
+    for run in 0..RUNS {
+        let chunk_size = rng.range(config::MAX_LINE_LENGTH..=4096);
+        let threads = rng.range(1..=16);
+        let engine = *rng.pick(&[
+            &ParserEngine::Scalar,
+            &ParserEngine::Memchr,
+            &ParserEngine::Batched,
+        ]);
+        let configuration = format!("run {} with {} x{} of {}", run, engine, threads, chunk_size);
+
+        let report = async_1brc::run_from(
+            input.as_bytes(),
+            RunOptions::new("/nonexistent/measurements.txt")
+                .with_engine(engine)
+                .with_threads(threads)
+                .with_chunk_sizes(chunk_size, chunk_size + config::MAX_LINE_LENGTH),
+        )
+        .await
+        .unwrap_or_else(|err| panic!("{} failed: {}", configuration, err));
+
+        assert_eq!(report.records, *expected, "{} differs", configuration);
+        assert_eq!(
+            report.bytes_read,
+            input.len() as u64,
+            "{} differs",
+            configuration
+        );
+    }
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn stress_many_stations() {
+    let names = station_names();
+    let names = names.iter().map(String::as_str).collect::<Vec<_>>();
+    let input = generate(198, &names, 20_000);
+
+    let mut expected = StationRecords::new();
+    sync::parse_bytes(input.as_bytes(), &mut expected);
+
+    stress(1, &input, &expected).await;
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn stress_few_stations() {
+    // Every consumer holds every station, so that all of them are merged each time.
+    let input = generate(199, &["a", "b", "日本"], 20_000);
+
+    let mut expected = StationRecords::new();
+    sync::parse_bytes(input.as_bytes(), &mut expected);
+
+    stress(2, &input, &expected).await;
+}